@@ -0,0 +1,673 @@
+//! Reader for Compiled HTML Help (.chm) files.
+//!
+//! A CHM file is an ITSF container: a header, a directory of PMGL listing
+//! chunks, and two content sections. Section 0 is stored uncompressed;
+//! section 1 (`MSCompressed`) is a single LZX stream whose reset table
+//! allows decompressing from any reset point, so a topic is read without
+//! decompressing the whole file.
+//!
+//! Help contexts are resolved through `#IVB`, the compiled `[MAP]` section:
+//! pairs of context ID and offset into `#STRINGS` of the topic path.
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+use crate::error::Error;
+use crate::lzx;
+
+const ITSF_SIGNATURE: &[u8] = b"ITSF";
+const ITSP_SIGNATURE: &[u8] = b"ITSP";
+const PMGL_SIGNATURE: &[u8] = b"PMGL";
+const LZXC_SIGNATURE: &[u8] = b"LZXC";
+const PMGL_HEADER_SIZE: usize = 0x14;
+
+const CONTENT: &str = "::DataSpace/Storage/MSCompressed/Content";
+const CONTROL_DATA: &str = "::DataSpace/Storage/MSCompressed/ControlData";
+const RESET_TABLE: &str = "::DataSpace/Storage/MSCompressed/Transform/\
+{7FC28940-9D31-11D0-9B27-00A0C91E9C7C}/InstanceData/ResetTable";
+
+fn corrupt(what: &str) -> Error {
+    Error::InvalidChm(what.to_string())
+}
+
+/// A file listed in the CHM directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    /// Path of the file, such as `/topics/shape.htm`.
+    pub name: String,
+    /// 0 for the uncompressed section, 1 for `MSCompressed`.
+    pub section: u64,
+    /// Offset of the file in its section.
+    pub offset: u64,
+    /// Length of the file, uncompressed.
+    pub length: u64,
+}
+
+/// Parameters of the LZX-compressed content section.
+struct CompressedSection {
+    /// Offset of the compressed stream in the file.
+    start: usize,
+    length: usize,
+    window_bits: u32,
+    /// Frames between decoder resets.
+    reset_interval: usize,
+    uncompressed_length: u64,
+    /// Compressed offset of each frame.
+    frames: Vec<u64>,
+}
+
+/// An open help file, read into memory.
+pub struct Chm {
+    data: Vec<u8>,
+    content_offset: usize,
+    entries: Vec<Entry>,
+    /// Lowercased name to index into `entries`; CHM names are case-insensitive.
+    by_name: HashMap<String, usize>,
+    compressed: Option<CompressedSection>,
+    /// Help context ID to topic path, from `#IVB` and `#STRINGS`.
+    contexts: BTreeMap<u32, String>,
+}
+
+fn bytes(data: &[u8], offset: usize, len: usize) -> Result<&[u8], Error> {
+    offset
+        .checked_add(len)
+        .and_then(|end| data.get(offset..end))
+        .ok_or_else(|| {
+            corrupt(&format!(
+                "read of {} bytes at {:#x} out of range",
+                len, offset
+            ))
+        })
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Result<u32, Error> {
+    let b = bytes(data, offset, 4)?;
+    Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+fn u64_at(data: &[u8], offset: usize) -> Result<u64, Error> {
+    let b = bytes(data, offset, 8)?;
+    Ok(u64::from_le_bytes(b.try_into().unwrap()))
+}
+
+fn usize_at(data: &[u8], offset: usize) -> Result<usize, Error> {
+    usize::try_from(u64_at(data, offset)?).map_err(|_| corrupt("offset out of range"))
+}
+
+/// Reads a directory ENCINT: 7-bit groups, most significant first, with the
+/// high bit set on all but the last byte.
+fn encint(data: &[u8], pos: &mut usize) -> Result<u64, Error> {
+    let mut value = 0u64;
+    for _ in 0..10 {
+        let byte = *data
+            .get(*pos)
+            .ok_or_else(|| corrupt("directory entry runs past its chunk"))?;
+        *pos += 1;
+        value = (value << 7) | (byte & 0x7f) as u64;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(corrupt("directory number too long"))
+}
+
+/// Parses the entries of a PMGL (listing) chunk.
+fn parse_listing_chunk(chunk: &[u8], entries: &mut Vec<Entry>) -> Result<(), Error> {
+    let free_space = u32_at(chunk, 4)? as usize;
+    let end = chunk
+        .len()
+        .checked_sub(free_space)
+        .filter(|end| *end >= PMGL_HEADER_SIZE)
+        .ok_or_else(|| corrupt("listing chunk free space out of range"))?;
+    let area = &chunk[..end];
+    let mut pos = PMGL_HEADER_SIZE;
+    while pos < end {
+        let name_len = encint(area, &mut pos)? as usize;
+        let name = bytes(area, pos, name_len)?;
+        pos += name_len;
+        let name = String::from_utf8_lossy(name).into_owned();
+        let section = encint(area, &mut pos)?;
+        let offset = encint(area, &mut pos)?;
+        let length = encint(area, &mut pos)?;
+        entries.push(Entry {
+            name,
+            section,
+            offset,
+            length,
+        });
+    }
+    Ok(())
+}
+
+/// Normalizes a topic path from `#STRINGS` (relative, maybe with
+/// backslashes or an anchor) to a directory name.
+fn topic_path(path: &str) -> String {
+    let path = path
+        .split('#')
+        .next()
+        .unwrap_or_default()
+        .replace('\\', "/");
+    if path.starts_with('/') {
+        path
+    } else {
+        format!("/{}", path)
+    }
+}
+
+impl Chm {
+    /// Reads and parses the help file at `path`.
+    pub fn open(path: &Path) -> Result<Chm, Error> {
+        Chm::parse(std::fs::read(path).map_err(|e| Error::io(path, e))?)
+    }
+
+    /// Parses a help file already in memory.
+    pub fn parse(data: Vec<u8>) -> Result<Chm, Error> {
+        if bytes(&data, 0, 4)? != ITSF_SIGNATURE {
+            return Err(corrupt("missing ITSF signature"));
+        }
+        let version = u32_at(&data, 4)?;
+        let dir_offset = usize_at(&data, 0x48)?;
+        let dir_length = usize_at(&data, 0x50)?;
+        let content_offset = match version {
+            2 => dir_offset + dir_length,
+            3 => usize_at(&data, 0x58)?,
+            other => return Err(corrupt(&format!("unsupported ITSF version {}", other))),
+        };
+
+        let dir = bytes(&data, dir_offset, dir_length)?;
+        if bytes(dir, 0, 4)? != ITSP_SIGNATURE {
+            return Err(corrupt("missing ITSP signature"));
+        }
+        let header_len = u32_at(dir, 8)? as usize;
+        let chunk_size = u32_at(dir, 0x10)? as usize;
+        let num_chunks = u32_at(dir, 0x2c)? as usize;
+        if chunk_size < PMGL_HEADER_SIZE {
+            return Err(corrupt("directory chunk size too small"));
+        }
+        let mut entries = Vec::new();
+        for i in 0..num_chunks {
+            let chunk = bytes(dir, header_len + i * chunk_size, chunk_size)?;
+            // PMGI chunks only index the listing chunks.
+            if chunk.starts_with(PMGL_SIGNATURE) {
+                parse_listing_chunk(chunk, &mut entries)?;
+            }
+        }
+        let by_name = entries
+            .iter()
+            .enumerate()
+            .map(|(i, e)| (e.name.to_lowercase(), i))
+            .collect();
+
+        let mut chm = Chm {
+            data,
+            content_offset,
+            entries,
+            by_name,
+            compressed: None,
+            contexts: BTreeMap::new(),
+        };
+        if chm.entry(CONTENT).is_some() {
+            chm.compressed = Some(chm.read_compressed_section()?);
+        }
+        chm.contexts = chm.read_contexts()?;
+        Ok(chm)
+    }
+
+    fn read_compressed_section(&self) -> Result<CompressedSection, Error> {
+        let control = self.read_file(CONTROL_DATA)?;
+        if bytes(&control, 4, 4)? != LZXC_SIGNATURE {
+            return Err(corrupt("missing LZXC signature"));
+        }
+        let version = u32_at(&control, 8)?;
+        let (mut reset_interval, mut window_size) = (
+            u32_at(&control, 12)? as usize,
+            u32_at(&control, 16)? as usize,
+        );
+        if version == 2 {
+            reset_interval *= lzx::FRAME_SIZE;
+            window_size *= lzx::FRAME_SIZE;
+        }
+        if !window_size.is_power_of_two() {
+            return Err(corrupt(&format!("invalid LZX window size {}", window_size)));
+        }
+        if reset_interval == 0 || !reset_interval.is_multiple_of(lzx::FRAME_SIZE) {
+            return Err(corrupt(&format!(
+                "invalid LZX reset interval {}",
+                reset_interval
+            )));
+        }
+
+        let table = self.read_file(RESET_TABLE)?;
+        let count = u32_at(&table, 4)? as usize;
+        let entry_size = u32_at(&table, 8)? as usize;
+        let table_offset = u32_at(&table, 12)? as usize;
+        let uncompressed_length = u64_at(&table, 16)?;
+        if entry_size != 8 {
+            return Err(corrupt("unsupported reset table entry size"));
+        }
+        let frames = (0..count)
+            .map(|i| u64_at(&table, table_offset + i * 8))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let content = self.entry(CONTENT).unwrap();
+        if content.section != 0 {
+            return Err(corrupt("compressed content is not in section 0"));
+        }
+        Ok(CompressedSection {
+            start: self.content_offset + content.offset as usize,
+            length: content.length as usize,
+            window_bits: window_size.trailing_zeros(),
+            reset_interval: reset_interval / lzx::FRAME_SIZE,
+            uncompressed_length,
+            frames,
+        })
+    }
+
+    fn read_contexts(&self) -> Result<BTreeMap<u32, String>, Error> {
+        let mut contexts = BTreeMap::new();
+        if self.entry("/#IVB").is_none() || self.entry("/#STRINGS").is_none() {
+            return Ok(contexts);
+        }
+        let ivb = self.read_file("/#IVB")?;
+        let strings = self.read_file("/#STRINGS")?;
+        let len = (u32_at(&ivb, 0)? as usize).min(ivb.len() - 4);
+        for pair in ivb[4..4 + len].chunks_exact(8) {
+            let id = u32::from_le_bytes(pair[..4].try_into().unwrap());
+            let offset = u32::from_le_bytes(pair[4..].try_into().unwrap()) as usize;
+            let Some(tail) = strings.get(offset..) else {
+                continue;
+            };
+            let end = tail.iter().position(|b| *b == 0).unwrap_or(tail.len());
+            let path = String::from_utf8_lossy(&tail[..end]);
+            if !path.is_empty() {
+                contexts.insert(id, topic_path(&path));
+            }
+        }
+        Ok(contexts)
+    }
+
+    /// Looks a file up by its path, ignoring case.
+    pub fn entry(&self, name: &str) -> Option<&Entry> {
+        self.by_name
+            .get(&name.to_lowercase())
+            .map(|i| &self.entries[*i])
+    }
+
+    /// Reads a file from either content section.
+    pub fn read_file(&self, name: &str) -> Result<Vec<u8>, Error> {
+        let entry = self
+            .entry(name)
+            .ok_or_else(|| corrupt(&format!("no file {}", name)))?;
+        match entry.section {
+            0 => {
+                let offset = usize::try_from(entry.offset)
+                    .ok()
+                    .and_then(|o| o.checked_add(self.content_offset))
+                    .ok_or_else(|| corrupt("file offset out of range"))?;
+                Ok(bytes(&self.data, offset, entry.length as usize)?.to_vec())
+            }
+            1 => self.read_compressed(entry.offset, entry.length),
+            other => Err(corrupt(&format!(
+                "{} is in unknown section {}",
+                name, other
+            ))),
+        }
+    }
+
+    /// Decompresses `length` bytes at `offset` of section 1, starting from
+    /// the last reset point before `offset`.
+    fn read_compressed(&self, offset: u64, length: u64) -> Result<Vec<u8>, Error> {
+        let section = self
+            .compressed
+            .as_ref()
+            .ok_or_else(|| corrupt("no compressed section"))?;
+        let end = offset
+            .checked_add(length)
+            .filter(|end| *end <= section.uncompressed_length)
+            .ok_or_else(|| corrupt("file extends past the compressed section"))?;
+        let frame_size = lzx::FRAME_SIZE as u64;
+        let frame = (offset / frame_size) as usize;
+        let reset_frame = frame - frame % section.reset_interval;
+        let start = *section
+            .frames
+            .get(reset_frame)
+            .ok_or_else(|| corrupt("reset table too short"))? as usize;
+        let input = section
+            .length
+            .checked_sub(start)
+            .and_then(|len| bytes(&self.data, section.start + start, len).ok())
+            .ok_or_else(|| corrupt("reset table entry out of range"))?;
+
+        // Frames are decoded whole: a match may run past the end of the file.
+        let first = reset_frame as u64 * frame_size;
+        let last = end
+            .div_ceil(frame_size)
+            .saturating_mul(frame_size)
+            .min(section.uncompressed_length);
+        let out = lzx::decompress(
+            input,
+            section.window_bits,
+            section.reset_interval,
+            (last - first) as usize,
+        )?;
+        Ok(out[(offset - first) as usize..(end - first) as usize].to_vec())
+    }
+
+    /// Path of the topic mapped to `help_context`, if any.
+    pub fn topic_for_context(&self, help_context: u32) -> Option<&str> {
+        self.contexts.get(&help_context).map(String::as_str)
+    }
+
+    /// Text of the topic mapped to `help_context`, if any.
+    pub fn topic_text(&self, help_context: u32) -> Result<Option<String>, Error> {
+        let Some(path) = self.topic_for_context(help_context) else {
+            return Ok(None);
+        };
+        let html = self.read_file(path)?;
+        Ok(Some(html_to_text(&decode_text(&html))))
+    }
+}
+
+/// Decodes topic bytes: UTF-8 when valid, otherwise Windows-1252, the
+/// encoding HTML Help Workshop assumes for Western topics.
+pub fn decode_text(data: &[u8]) -> String {
+    if let Ok(text) = std::str::from_utf8(data) {
+        return text.trim_start_matches('\u{feff}').to_string();
+    }
+    const CP1252: [char; 32] = [
+        '€', '\u{81}', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\u{8d}', 'Ž',
+        '\u{8f}', '\u{90}', '‘', '’', '“', '”', '•', '–', '—', '˜', '™', 'š', '›', 'œ', '\u{9d}',
+        'ž', 'Ÿ',
+    ];
+    data.iter()
+        .map(|&b| match b {
+            0x80..=0x9f => CP1252[(b - 0x80) as usize],
+            _ => b as char,
+        })
+        .collect()
+}
+
+fn entity(name: &str) -> Option<char> {
+    if let Some(num) = name.strip_prefix('#') {
+        let code = match num.strip_prefix(['x', 'X']) {
+            Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+            None => num.parse().ok()?,
+        };
+        return char::from_u32(code);
+    }
+    Some(match name {
+        "amp" => '&',
+        "lt" => '<',
+        "gt" => '>',
+        "quot" => '"',
+        "apos" => '\'',
+        "nbsp" => ' ',
+        "copy" => '©',
+        "reg" => '®',
+        "trade" => '™',
+        "deg" => '°',
+        "middot" => '·',
+        "bull" => '•',
+        "hellip" => '…',
+        "ndash" => '–',
+        "mdash" => '—',
+        "lsquo" => '‘',
+        "rsquo" => '’',
+        "ldquo" => '“',
+        "rdquo" => '”',
+        _ => return None,
+    })
+}
+
+/// Renders an HTML topic as plain text: markup is dropped, block elements
+/// start new lines, list items get bullets and whitespace is collapsed
+/// outside `<pre>`.
+pub fn html_to_text(html: &str) -> String {
+    let mut out = String::new();
+    let mut skip_until: Option<&str> = None;
+    let mut pre = 0usize;
+    let mut rest = html;
+
+    // Appends text, collapsing whitespace unless inside <pre>.
+    fn push_text(out: &mut String, text: &str, pre: bool) {
+        for c in text.chars() {
+            if pre {
+                out.push(c);
+            } else if c.is_whitespace() {
+                if !out.is_empty() && !out.ends_with([' ', '\n']) {
+                    out.push(' ');
+                }
+            } else {
+                out.push(c);
+            }
+        }
+    }
+    fn newline(out: &mut String) {
+        while out.ends_with(' ') {
+            out.pop();
+        }
+        if !out.is_empty() && !out.ends_with('\n') {
+            out.push('\n');
+        }
+    }
+    fn blank_line(out: &mut String) {
+        newline(out);
+        if !out.is_empty() && !out.ends_with("\n\n") {
+            out.push('\n');
+        }
+    }
+
+    while !rest.is_empty() {
+        let Some(lt) = rest.find('<') else {
+            if skip_until.is_none() {
+                push_text(&mut out, &decode_entities(rest), pre > 0);
+            }
+            break;
+        };
+        if skip_until.is_none() {
+            push_text(&mut out, &decode_entities(&rest[..lt]), pre > 0);
+        }
+        rest = &rest[lt..];
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+            continue;
+        }
+        let Some(gt) = rest.find('>') else {
+            break;
+        };
+        let tag = &rest[1..gt];
+        rest = &rest[gt + 1..];
+
+        let closing = tag.starts_with('/');
+        let name = tag
+            .trim_start_matches('/')
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        if let Some(end) = skip_until {
+            if closing && name == end {
+                skip_until = None;
+            }
+            continue;
+        }
+        match name.as_str() {
+            "script" | "style" | "title" if !closing => {
+                skip_until = Some(match name.as_str() {
+                    "script" => "script",
+                    "style" => "style",
+                    _ => "title",
+                });
+            }
+            "br" => {
+                while out.ends_with(' ') {
+                    out.pop();
+                }
+                out.push('\n');
+            }
+            "p" | "div" | "table" | "ul" | "ol" | "dl" | "blockquote" | "h1" | "h2" | "h3"
+            | "h4" | "h5" | "h6" | "hr" => blank_line(&mut out),
+            "pre" => {
+                blank_line(&mut out);
+                pre = if closing {
+                    pre.saturating_sub(1)
+                } else {
+                    pre + 1
+                };
+            }
+            "tr" | "dt" => newline(&mut out),
+            "dd" if !closing => {
+                newline(&mut out);
+                out.push_str("    ");
+            }
+            "li" if !closing => {
+                newline(&mut out);
+                out.push_str("• ");
+            }
+            "td" | "th" if !closing && !out.is_empty() && !out.ends_with('\n') => {
+                while out.ends_with(' ') {
+                    out.pop();
+                }
+                out.push_str(" | ");
+            }
+            _ => {}
+        }
+    }
+
+    let mut text = String::new();
+    let mut blank = false;
+    for line in out.lines() {
+        let line = line.trim_end();
+        if line.is_empty() {
+            blank = !text.is_empty();
+            continue;
+        }
+        if blank {
+            text.push('\n');
+            blank = false;
+        }
+        text.push_str(line);
+        text.push('\n');
+    }
+    text
+}
+
+/// Replaces character references; unknown ones are kept verbatim.
+fn decode_entities(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(amp) = rest.find('&') {
+        out.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        let decoded = rest[1..]
+            .find(';')
+            .filter(|end| *end <= 10)
+            .and_then(|end| entity(&rest[1..1 + end]).map(|c| (c, end + 2)));
+        match decoded {
+            Some((c, len)) => {
+                out.push(c);
+                rest = &rest[len..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture() -> Chm {
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/help.chm");
+        Chm::open(&path).unwrap()
+    }
+
+    #[test]
+    fn lists_directory() {
+        let chm = fixture();
+        let names: Vec<_> = chm.entries.iter().map(|e| e.name.as_str()).collect();
+        assert!(names.contains(&CONTENT), "{:?}", names);
+        assert!(names.contains(&"/topics/shape.htm"), "{:?}", names);
+        assert_eq!(chm.entry("/TOPICS/Shape.HTM").unwrap().section, 1);
+    }
+
+    #[test]
+    fn reads_compressed_files_across_reset_points() {
+        let chm = fixture();
+        let filler = chm.read_file("/topics/filler.htm").unwrap();
+        assert!(filler.len() > 2 * lzx::FRAME_SIZE, "{}", filler.len());
+        assert!(filler.starts_with(b"<html>"));
+        assert!(filler.ends_with(b"</html>\n"));
+        // Past the first reset interval, decoded from a later reset point.
+        let late = String::from_utf8(chm.read_file("/topics/late.htm").unwrap()).unwrap();
+        assert!(late.contains("Topic after the filler"), "{}", late);
+    }
+
+    #[test]
+    fn maps_help_contexts_to_topics() {
+        let chm = fixture();
+        assert_eq!(chm.topic_for_context(100), Some("/topics/shape.htm"));
+        assert_eq!(chm.topic_for_context(999), None);
+        let text = chm.topic_text(110).unwrap().unwrap();
+        assert!(
+            text.starts_with("Move method\n\nMoves the shape by dx, dy."),
+            "{}",
+            text
+        );
+        assert!(
+            chm.topic_text(300)
+                .unwrap()
+                .unwrap()
+                .contains("after the filler")
+        );
+        assert_eq!(chm.topic_text(999).unwrap(), None);
+    }
+
+    /// The help file hhc.exe compiles from tests/fixtures/hhc/help.hhp, to
+    /// check the container and LZX readers against the real compiler.
+    #[test]
+    #[ignore = "needs tests/fixtures/hhc/help.chm, built by tests/fixtures/build_real.sh"]
+    fn reads_a_help_file_compiled_by_hhc() {
+        let path =
+            std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/hhc/help.chm");
+        let chm = Chm::open(&path).unwrap();
+        let shape = chm.topic_text(100).unwrap().unwrap();
+        assert!(shape.contains("A drawable shape."), "{}", shape);
+        let text = chm.topic_text(110).unwrap().unwrap();
+        assert!(
+            text.starts_with("Move method\n\nMoves the shape by dx, dy."),
+            "{}",
+            text
+        );
+        assert!(
+            chm.topic_text(200)
+                .unwrap()
+                .unwrap()
+                .contains("Shape colors.")
+        );
+        assert_eq!(chm.topic_text(999).unwrap(), None);
+
+        let filler = chm.read_file("/topics/filler.htm").unwrap();
+        assert!(filler.len() > 2 * lzx::FRAME_SIZE, "{}", filler.len());
+        assert!(filler.starts_with(b"<html>"));
+        let late = chm.topic_text(300).unwrap().unwrap();
+        assert!(late.contains("Topic after the filler."), "{}", late);
+    }
+
+    #[test]
+    fn renders_html_as_text() {
+        let html = "<html><head><title>T</title><style>p {}</style></head><body>\
+            <h1>Move  method</h1><p>Moves the&nbsp;shape<br>by &lt;dx&gt; &amp; dy.</p>\
+            <ul><li>one</li><li>two</li></ul><pre>a\n  b</pre><!-- note --></body></html>";
+        assert_eq!(
+            html_to_text(html),
+            "Move method\n\nMoves the shape\nby <dx> & dy.\n\n• one\n• two\n\na\n  b\n"
+        );
+        assert_eq!(decode_text(b"caf\xe9 \x93q\x94"), "café “q”");
+    }
+}
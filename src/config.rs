@@ -0,0 +1,306 @@
+//! `tlb_winmd_gen.toml`: settings applied to every loaded library, so the
+//! IDL, both .winmd paths, the dumps and the TUI see the same types.
+//!
+//! ```toml
+//! namespace = "Contoso.Shapes"
+//! winmd-version = "1.2.0.0"
+//! imports = ["ocidl.tlb"]
+//!
+//! [types]
+//! exclude = ["_*Events"]
+//!
+//! [members]
+//! exclude = ["Colors.Reserved*"]
+//!
+//! [renames]
+//! IShape = "IContosoShape"
+//! "IShape.Color" = "Colour"
+//!
+//! [flags]
+//! ShapeStyle = true
+//! ```
+//!
+//! Patterns and renames name types and members as the type library does,
+//! members as `Type.Member`; `*` and `?` are wildcards.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::error::Error;
+use crate::model::*;
+use crate::typelib::TYPEFLAG_FDUAL;
+
+/// File read from the current directory when `--config` is not given.
+pub const DEFAULT_FILE: &str = "tlb_winmd_gen.toml";
+
+/// The contents of a `tlb_winmd_gen.toml`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    /// Namespace of the generated metadata, instead of the library name.
+    pub namespace: Option<String>,
+    /// Assembly version of the .winmd (`WinmdVersion` of the dotnet build).
+    pub winmd_version: Option<Version>,
+    /// Types to keep.
+    pub types: Filter,
+    /// Members a library can do without: enum values, module members and
+    /// members of pure dispinterfaces. Vtable methods and record fields are
+    /// always kept, as leaving them out would change the layout.
+    pub members: Filter,
+    /// New names of types and members.
+    pub renames: HashMap<String, String>,
+    /// Enums to treat as bit flags, or not, whatever their values suggest.
+    pub flags: HashMap<String, bool>,
+    /// Libraries to import besides those the type library references.
+    pub imports: Vec<String>,
+}
+
+/// Glob patterns selecting names.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Filter {
+    /// Patterns of names to keep; everything when empty.
+    pub include: Vec<String>,
+    /// Patterns of names to leave out, even when included.
+    pub exclude: Vec<String>,
+}
+
+impl Filter {
+    fn compile(&self) -> Result<CompiledFilter, Error> {
+        let globs = |patterns: &[String]| -> Result<Vec<Regex>, Error> {
+            patterns.iter().map(|p| glob(p)).collect()
+        };
+        Ok(CompiledFilter {
+            include: globs(&self.include)?,
+            exclude: globs(&self.exclude)?,
+        })
+    }
+}
+
+struct CompiledFilter {
+    include: Vec<Regex>,
+    exclude: Vec<Regex>,
+}
+
+impl CompiledFilter {
+    fn keeps(&self, name: &str) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|r| r.is_match(name)))
+            && !self.exclude.iter().any(|r| r.is_match(name))
+    }
+}
+
+/// A glob as an anchored regex.
+fn glob(pattern: &str) -> Result<Regex, Error> {
+    let regex = regex::escape(pattern)
+        .replace(r"\*", ".*")
+        .replace(r"\?", ".");
+    Regex::new(&format!("^{}$", regex))
+        .map_err(|e| Error::InvalidConfig(format!("pattern `{}`: {}", pattern, e)))
+}
+
+/// A four-part assembly version; missing parts are 0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Version(pub [u16; 4]);
+
+impl TryFrom<String> for Version {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, String> {
+        let parts: Vec<&str> = s.split('.').collect();
+        let invalid = || format!("invalid version `{}`, expected up to four numbers", s);
+        if parts.len() > 4 {
+            return Err(invalid());
+        }
+        let mut version = [0; 4];
+        for (part, value) in parts.iter().zip(&mut version) {
+            *value = part.parse().map_err(|_| invalid())?;
+        }
+        Ok(Version(version))
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [a, b, c, d] = self.0;
+        write!(f, "{}.{}.{}.{}", a, b, c, d)
+    }
+}
+
+impl Config {
+    /// Reads `path`, or [`DEFAULT_FILE`] when there is one and no path is
+    /// given; without either, nothing is changed.
+    pub fn load(path: Option<&Path>) -> Result<Self, Error> {
+        let path = match path {
+            Some(path) => path,
+            None if Path::new(DEFAULT_FILE).is_file() => Path::new(DEFAULT_FILE),
+            None => return Ok(Config::default()),
+        };
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| Error::InvalidConfig(format!("{}: {}", path.display(), e)))?;
+        Self::parse(&text).map_err(|e| match e {
+            Error::InvalidConfig(what) => {
+                Error::InvalidConfig(format!("{}: {}", path.display(), what))
+            }
+            e => e,
+        })
+    }
+
+    /// Parses the contents of a configuration file.
+    pub fn parse(text: &str) -> Result<Self, Error> {
+        let config: Config =
+            toml::from_str(text).map_err(|e| Error::InvalidConfig(e.message().to_string()))?;
+        // Bad patterns are reported up front, not on first use.
+        config.types.compile()?;
+        config.members.compile()?;
+        Ok(config)
+    }
+
+    /// Loads a library and applies the configuration to it.
+    pub fn load_library(&self, path: &Path, resource: Option<u16>) -> Result<Library, Error> {
+        let mut lib = Library::load(path, resource)?;
+        self.apply(&mut lib)?;
+        Ok(lib)
+    }
+
+    /// Filters, renames and flags the types of `lib` and adds the extra
+    /// imports. Types referring to a type that is left out keep its name,
+    /// unresolved.
+    pub fn apply(&self, lib: &mut Library) -> Result<(), Error> {
+        let types = self.types.compile()?;
+        let members = self.members.compile()?;
+
+        lib.retain_types(|ty| types.keeps(&ty.name));
+        for ty in &mut lib.types {
+            let removable = matches!(ty.kind, TypeKind::Enum | TypeKind::Module)
+                || (ty.kind == TypeKind::Dispatch && !ty.has_flag(TYPEFLAG_FDUAL));
+            if removable {
+                let name = ty.name.clone();
+                let keeps = |member: &str| members.keeps(&format!("{}.{}", name, member));
+                ty.vars.retain(|v| keeps(&v.name));
+                ty.funcs.retain(|f| keeps(&f.name));
+            }
+            if let Some(&bit_flags) = self.flags.get(&ty.name) {
+                ty.bit_flags = Some(bit_flags);
+            }
+        }
+
+        for index in 0..lib.types.len() {
+            let ty = &mut lib.types[index];
+            let prefix = format!("{}.", ty.name);
+            let renamed = |name: &mut String| {
+                if let Some(new) = self.renames.get(&format!("{}{}", prefix, name)) {
+                    *name = new.clone();
+                }
+            };
+            ty.vars.iter_mut().for_each(|v| renamed(&mut v.name));
+            ty.funcs.iter_mut().for_each(|f| renamed(&mut f.name));
+            if let Some(new) = self.renames.get(&lib.types[index].name) {
+                lib.rename_type(index, new);
+            }
+        }
+
+        for file_name in &self.imports {
+            let known = lib
+                .imports
+                .iter()
+                .any(|i| i.file_name.eq_ignore_ascii_case(file_name));
+            if !known {
+                lib.imports.push(Import {
+                    guid: Default::default(),
+                    version: (0, 0),
+                    lcid: 0,
+                    file_name: file_name.clone(),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::typelib::Variant;
+
+    fn simple(config: &str) -> Library {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/simple.tlb");
+        Config::parse(config)
+            .unwrap()
+            .load_library(&path, None)
+            .unwrap()
+    }
+
+    #[test]
+    fn filters_types_and_members() {
+        let lib = simple(
+            r#"
+            [types]
+            include = ["I*", "C*", "_*"]
+            exclude = ["*Events"]
+
+            [members]
+            exclude = ["Colors.?????", "Constants.Big", "IShape.*"]
+            "#,
+        );
+        let names: Vec<_> = lib.types.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(names, ["Colors", "IShape", "Constants", "IRenderer"]);
+        let colors: Vec<_> = lib.types[0].vars.iter().map(|v| v.name.as_str()).collect();
+        assert_eq!(colors, ["Red", "Blue"]);
+        let constants = lib.find("Constants").unwrap();
+        assert!(constants.vars.iter().all(|v| v.name != "Big"));
+        // Vtable methods stay.
+        assert_eq!(lib.find("IShape").unwrap().funcs.len(), 5);
+    }
+
+    #[test]
+    fn renames_types_and_members() {
+        let lib = simple(
+            r#"
+            imports = ["ocidl.tlb", "STDOLE2.TLB"]
+
+            [renames]
+            Colors = "Colours"
+            "Colors.Red" = "Crimson"
+            "IShape.Color" = "Colour"
+
+            [flags]
+            Colors = true
+            "#,
+        );
+        let colours = lib.find("Colours").unwrap();
+        assert_eq!(colours.vars[0].name, "Crimson");
+        assert!(colours.is_bit_flags());
+        let shape = lib.find("IShape").unwrap();
+        let colour = shape.funcs.iter().find(|f| f.name == "Colour").unwrap();
+        assert_eq!(
+            crate::idlgen::type_to_string(&colour.params[0].ty),
+            "enum Colours*"
+        );
+        let imports: Vec<_> = lib.imports.iter().map(|i| i.file_name.as_str()).collect();
+        assert_eq!(imports, ["stdole2.tlb", "ocidl.tlb"]);
+        assert_eq!(
+            lib.find("Constants").unwrap().vars[0].value,
+            Some(Variant::I4(42))
+        );
+    }
+
+    #[test]
+    fn parses_versions() {
+        let config = Config::parse(r#"winmd-version = "1.2""#).unwrap();
+        assert_eq!(config.winmd_version, Some(Version([1, 2, 0, 0])));
+        assert_eq!(config.winmd_version.unwrap().to_string(), "1.2.0.0");
+        for bad in [
+            r#"winmd-version = "1.2.3.4.5""#,
+            r#"winmd-version = "1.x""#,
+            r#"namespaces = "Contoso""#,
+        ] {
+            let error = Config::parse(bad).unwrap_err();
+            assert!(matches!(error, Error::InvalidConfig(_)), "{:?}", error);
+        }
+    }
+}
@@ -0,0 +1,142 @@
+//! What the generators leave out of their output or write in a degraded
+//! form: unresolved types, VARTYPEs with no spelling in the target, values
+//! that cannot be expressed, and the like.
+//!
+//! Choices made for every library alike, such as leaving the IDispatch
+//! plumbing out of the IDL, are not reported.
+
+use std::io::Write;
+
+use crate::jsongen::Json;
+
+/// Version of the `--diagnostics-json` layout.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// An item an output leaves out or degrades.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// Output the item is missing from or degraded in: `idl` or `winmd`.
+    pub output: &'static str,
+    /// Name of the library the item belongs to.
+    pub library: String,
+    /// `Type`, `Type.Member` or `Type.Member(param)`.
+    pub path: String,
+    /// What was left out or degraded, and why.
+    pub message: String,
+}
+
+/// Diagnostics collected over a run, in the order they were found.
+#[derive(Debug, Clone, Default)]
+pub struct Diagnostics {
+    library: String,
+    entries: Vec<Diagnostic>,
+}
+
+impl Diagnostics {
+    /// An empty collection.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Attributes the diagnostics that follow to `library`.
+    pub fn set_library(&mut self, library: &str) {
+        self.library = library.to_string();
+    }
+
+    /// Records an item of `output`; the same item reported twice is kept
+    /// once.
+    pub fn warn(&mut self, output: &'static str, path: String, message: String) {
+        let diagnostic = Diagnostic {
+            output,
+            library: self.library.clone(),
+            path,
+            message,
+        };
+        if !self.entries.contains(&diagnostic) {
+            self.entries.push(diagnostic);
+        }
+    }
+
+    /// The diagnostics, in the order they were found.
+    pub fn entries(&self) -> &[Diagnostic] {
+        &self.entries
+    }
+
+    /// Whether nothing was reported.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Number of diagnostics.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Prints one line per diagnostic and their count; nothing when there
+    /// are none.
+    pub fn write_summary<W: Write>(&self, mut out: W) -> std::io::Result<()> {
+        if self.entries.is_empty() {
+            return Ok(());
+        }
+        for d in &self.entries {
+            writeln!(
+                out,
+                "warning: {}: {}.{}: {}",
+                d.output, d.library, d.path, d.message
+            )?;
+        }
+        writeln!(
+            out,
+            "{} warning{}",
+            self.entries.len(),
+            if self.entries.len() == 1 { "" } else { "s" }
+        )
+    }
+
+    /// The diagnostics as a JSON document.
+    pub fn to_json(&self) -> String {
+        Json::Object(vec![
+            ("schema_version", SCHEMA_VERSION.into()),
+            ("count", self.entries.len().into()),
+            (
+                "diagnostics",
+                Json::array(&self.entries, |d| {
+                    Json::Object(vec![
+                        ("output", d.output.into()),
+                        ("library", d.library.as_str().into()),
+                        ("path", d.path.as_str().into()),
+                        ("message", d.message.as_str().into()),
+                    ])
+                }),
+            ),
+        ])
+        .render()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summarizes_each_item_once() {
+        let mut diagnostics = Diagnostics::new();
+        diagnostics.write_summary(&mut Vec::new()).unwrap();
+        diagnostics.set_library("ShapesLib");
+        for _ in 0..2 {
+            diagnostics.warn("idl", "IShape.Draw(pen)".into(), "unresolved type".into());
+        }
+        diagnostics.warn("winmd", "Colors.Red".into(), "no value".into());
+        let mut summary = Vec::new();
+        diagnostics.write_summary(&mut summary).unwrap();
+        assert_eq!(
+            String::from_utf8(summary).unwrap(),
+            "warning: idl: ShapesLib.IShape.Draw(pen): unresolved type\n\
+             warning: winmd: ShapesLib.Colors.Red: no value\n\
+             2 warnings\n"
+        );
+        let json = diagnostics.to_json();
+        assert!(json.contains("\"count\": 2"));
+        assert!(json.contains("\"path\": \"IShape.Draw(pen)\""));
+    }
+}
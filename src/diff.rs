@@ -0,0 +1,974 @@
+//! Semantic comparison of two versions of a type library.
+//!
+//! Types are paired by GUID where they have one and by name otherwise, and
+//! members by name (plus invoke kind for properties). Every difference is
+//! classified by what it breaks for existing clients:
+//!
+//! - binary-breaking: compiled clients stop working — a GUID, DISPID or
+//!   vtable slot changed, a member was removed, a record layout changed;
+//! - source-breaking: clients keep working but no longer compile unchanged —
+//!   renames and parameter or return type changes;
+//! - additive: new types and members that nothing depended on before.
+
+use std::collections::HashSet;
+use std::io::Write;
+
+use crate::idlgen::{type_to_string, variant_to_string};
+use crate::jsongen::Json;
+use crate::model::*;
+
+/// Version of the `--format json` layout.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// How bad a change is, most severe first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    /// Breaks code compiled against the old library: a vtable, a layout or
+    /// an identity changed.
+    BinaryBreaking,
+    /// Breaks code written against the old library once it is recompiled,
+    /// such as a renamed type or member.
+    SourceBreaking,
+    /// Breaks nothing.
+    Additive,
+}
+
+impl Severity {
+    /// The name used in the text and JSON reports.
+    pub fn name(self) -> &'static str {
+        match self {
+            Severity::BinaryBreaking => "binary-breaking",
+            Severity::SourceBreaking => "source-breaking",
+            Severity::Additive => "additive",
+        }
+    }
+}
+
+/// Whether an item was added, removed or changed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    /// Only in the new library.
+    Added,
+    /// Only in the old library.
+    Removed,
+    /// In both, but different.
+    Changed,
+}
+
+impl ChangeKind {
+    /// The name used in the text and JSON reports.
+    pub fn name(self) -> &'static str {
+        match self {
+            ChangeKind::Added => "added",
+            ChangeKind::Removed => "removed",
+            ChangeKind::Changed => "changed",
+        }
+    }
+}
+
+/// A difference between two versions of a library.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    /// How bad the change is.
+    pub severity: Severity,
+    /// Whether the item was added, removed or changed.
+    pub kind: ChangeKind,
+    /// `Type`, `Type.Member` or `Type.Member(param)`.
+    pub path: String,
+    /// What changed, such as `DISPID changed from 1 to 2`.
+    pub message: String,
+}
+
+/// Compares two libraries; changes are listed in the order of the old
+/// library, followed by additions in the order of the new one.
+pub fn diff_libraries(old: &Library, new: &Library) -> Vec<Change> {
+    let mut d = Differ::default();
+    if old.guid != new.guid {
+        d.changed(
+            Severity::BinaryBreaking,
+            &new.name,
+            format!("library GUID changed from {} to {}", old.guid, new.guid),
+        );
+    }
+    if old.name != new.name {
+        d.changed(
+            Severity::SourceBreaking,
+            &new.name,
+            format!("library renamed from {} to {}", old.name, new.name),
+        );
+    }
+
+    let mut matched = HashSet::new();
+    for old_ty in &old.types {
+        let by_guid = (!old_ty.guid.is_nil())
+            .then(|| new.types.iter().position(|t| t.guid == old_ty.guid))
+            .flatten();
+        let found = by_guid.or_else(|| {
+            new.types
+                .iter()
+                .position(|t| t.name == old_ty.name && !matched.contains(&t.name))
+        });
+        match found {
+            Some(index) if matched.insert(new.types[index].name.clone()) => {
+                d.type_def(old_ty, &new.types[index]);
+            }
+            _ => d.removed(Severity::BinaryBreaking, &old_ty.name, "type removed"),
+        }
+    }
+    for new_ty in &new.types {
+        if !matched.contains(&new_ty.name) {
+            d.added(&new_ty.name, format!("{} added", new_ty.kind.name()));
+        }
+    }
+    d.changes
+}
+
+#[derive(Default)]
+struct Differ {
+    changes: Vec<Change>,
+}
+
+impl Differ {
+    fn push(&mut self, severity: Severity, kind: ChangeKind, path: &str, message: String) {
+        self.changes.push(Change {
+            severity,
+            kind,
+            path: path.to_string(),
+            message,
+        });
+    }
+
+    fn changed(&mut self, severity: Severity, path: &str, message: String) {
+        self.push(severity, ChangeKind::Changed, path, message);
+    }
+
+    fn added(&mut self, path: &str, message: String) {
+        self.push(Severity::Additive, ChangeKind::Added, path, message);
+    }
+
+    fn removed(&mut self, severity: Severity, path: &str, message: &str) {
+        self.push(severity, ChangeKind::Removed, path, message.to_string());
+    }
+
+    fn type_def(&mut self, old: &TypeDef, new: &TypeDef) {
+        let path = new.name.as_str();
+        if old.name != new.name {
+            self.changed(
+                Severity::SourceBreaking,
+                path,
+                format!("renamed from {}", old.name),
+            );
+        }
+        if old.guid != new.guid {
+            self.changed(
+                Severity::BinaryBreaking,
+                path,
+                format!("GUID changed from {} to {}", old.guid, new.guid),
+            );
+        }
+        if old.kind != new.kind {
+            self.changed(
+                Severity::BinaryBreaking,
+                path,
+                format!(
+                    "kind changed from {} to {}",
+                    old.kind.name(),
+                    new.kind.name()
+                ),
+            );
+            return;
+        }
+        match new.kind {
+            TypeKind::Enum => self.enum_values(path, old, new),
+            TypeKind::Record | TypeKind::Union => self.fields(path, old, new),
+            TypeKind::Alias => {
+                let (from, to) = (alias_string(old), alias_string(new));
+                if from != to {
+                    self.changed(
+                        Severity::SourceBreaking,
+                        path,
+                        format!("aliased type changed from {} to {}", from, to),
+                    );
+                }
+            }
+            TypeKind::Interface | TypeKind::Dispatch => {
+                let (from, to) = (old.base().map(|b| &b.name), new.base().map(|b| &b.name));
+                if from != to {
+                    self.changed(
+                        Severity::BinaryBreaking,
+                        path,
+                        format!(
+                            "base interface changed from {} to {}",
+                            from.map_or("(none)", |s| s.as_str()),
+                            to.map_or("(none)", |s| s.as_str())
+                        ),
+                    );
+                }
+                self.functions(path, old, new);
+                self.dispatch_properties(path, old, new);
+            }
+            TypeKind::CoClass => self.coclass_interfaces(path, old, new),
+            TypeKind::Module => {
+                self.constants(path, old, new);
+                self.functions(path, old, new);
+            }
+        }
+    }
+
+    fn enum_values(&mut self, path: &str, old: &TypeDef, new: &TypeDef) {
+        let value = |v: &Variable| v.value.as_ref().map_or(0, Variant::as_i32);
+        let mut matched = HashSet::new();
+        for old_var in &old.vars {
+            let member = format!("{}.{}", path, old_var.name);
+            if let Some(new_var) = new.vars.iter().find(|v| v.name == old_var.name) {
+                matched.insert(new_var.name.as_str());
+                if value(old_var) != value(new_var) {
+                    self.changed(
+                        Severity::BinaryBreaking,
+                        &member,
+                        format!(
+                            "value changed from {} to {}",
+                            value(old_var),
+                            value(new_var)
+                        ),
+                    );
+                }
+            } else if let Some(renamed) = new.vars.iter().find(|v| {
+                value(v) == value(old_var)
+                    && !old.vars.iter().any(|o| o.name == v.name)
+                    && !matched.contains(v.name.as_str())
+            }) {
+                matched.insert(renamed.name.as_str());
+                self.changed(
+                    Severity::SourceBreaking,
+                    &member,
+                    format!("renamed to {}", renamed.name),
+                );
+            } else {
+                self.removed(Severity::SourceBreaking, &member, "enum value removed");
+            }
+        }
+        for new_var in &new.vars {
+            if !matched.contains(new_var.name.as_str()) {
+                self.added(
+                    &format!("{}.{}", path, new_var.name),
+                    format!("enum value {} added", value(new_var)),
+                );
+            }
+        }
+    }
+
+    fn fields(&mut self, path: &str, old: &TypeDef, new: &TypeDef) {
+        if old.size != new.size {
+            self.changed(
+                Severity::BinaryBreaking,
+                path,
+                format!("size changed from {} to {} bytes", old.size, new.size),
+            );
+        }
+        let mut matched = HashSet::new();
+        for old_var in &old.vars {
+            let member = format!("{}.{}", path, old_var.name);
+            let old_type = type_to_string(&old_var.ty);
+            if let Some(new_var) = new.vars.iter().find(|v| v.name == old_var.name) {
+                matched.insert(new_var.name.as_str());
+                let new_type = type_to_string(&new_var.ty);
+                if old_type != new_type {
+                    self.changed(
+                        Severity::BinaryBreaking,
+                        &member,
+                        format!("field type changed from {} to {}", old_type, new_type),
+                    );
+                }
+                if old_var.offset != new_var.offset {
+                    self.changed(
+                        Severity::BinaryBreaking,
+                        &member,
+                        format!(
+                            "offset changed from {} to {}",
+                            old_var.offset, new_var.offset
+                        ),
+                    );
+                }
+            } else if let Some(renamed) = new.vars.iter().find(|v| {
+                v.offset == old_var.offset
+                    && type_to_string(&v.ty) == old_type
+                    && !old.vars.iter().any(|o| o.name == v.name)
+                    && !matched.contains(v.name.as_str())
+            }) {
+                matched.insert(renamed.name.as_str());
+                self.changed(
+                    Severity::SourceBreaking,
+                    &member,
+                    format!("renamed to {}", renamed.name),
+                );
+            } else {
+                self.removed(Severity::BinaryBreaking, &member, "field removed");
+            }
+        }
+        for new_var in &new.vars {
+            if !matched.contains(new_var.name.as_str()) {
+                // Any new field changes the layout clients were built against.
+                self.push(
+                    Severity::BinaryBreaking,
+                    ChangeKind::Added,
+                    &format!("{}.{}", path, new_var.name),
+                    format!("field added at offset {}", new_var.offset),
+                );
+            }
+        }
+    }
+
+    /// Constants compare as numbers in their declared type, so a library
+    /// storing a `long` as `VT_INT` still holds the same constant; a change
+    /// of VARTYPE is reported on its own.
+    fn constants(&mut self, path: &str, old: &TypeDef, new: &TypeDef) {
+        for old_var in &old.vars {
+            let member = format!("{}.{}", path, old_var.name);
+            let Some(new_var) = new.vars.iter().find(|v| v.name == old_var.name) else {
+                self.removed(Severity::SourceBreaking, &member, "constant removed");
+                continue;
+            };
+            let (old_value, new_value) = (old_var.typed_value(), new_var.typed_value());
+            let (from, to) = (constant_type(&old_value), constant_type(&new_value));
+            if from != to {
+                self.changed(
+                    Severity::SourceBreaking,
+                    &member,
+                    format!("type changed from {} to {}", from, to),
+                );
+            }
+            if !same_number(&old_value, &new_value) {
+                self.changed(
+                    Severity::BinaryBreaking,
+                    &member,
+                    format!(
+                        "value changed from {} to {}",
+                        constant_string(&old_value),
+                        constant_string(&new_value)
+                    ),
+                );
+            }
+        }
+        for new_var in &new.vars {
+            if !old.vars.iter().any(|v| v.name == new_var.name) {
+                self.added(
+                    &format!("{}.{}", path, new_var.name),
+                    "constant added".to_string(),
+                );
+            }
+        }
+    }
+
+    /// Properties of a pure dispinterface, reached by DISPID only.
+    fn dispatch_properties(&mut self, path: &str, old: &TypeDef, new: &TypeDef) {
+        for old_var in &old.vars {
+            let member = format!("{}.{}", path, old_var.name);
+            let Some(new_var) = new.vars.iter().find(|v| v.name == old_var.name) else {
+                self.removed(Severity::BinaryBreaking, &member, "property removed");
+                continue;
+            };
+            if old_var.memid != new_var.memid {
+                self.changed(
+                    Severity::BinaryBreaking,
+                    &member,
+                    format!("DISPID changed from {} to {}", old_var.memid, new_var.memid),
+                );
+            }
+            let (from, to) = (type_to_string(&old_var.ty), type_to_string(&new_var.ty));
+            if from != to {
+                self.changed(
+                    Severity::SourceBreaking,
+                    &member,
+                    format!("property type changed from {} to {}", from, to),
+                );
+            }
+        }
+        for new_var in &new.vars {
+            if !old.vars.iter().any(|v| v.name == new_var.name) {
+                self.added(
+                    &format!("{}.{}", path, new_var.name),
+                    format!("property added with DISPID {}", new_var.memid),
+                );
+            }
+        }
+    }
+
+    fn functions(&mut self, path: &str, old: &TypeDef, new: &TypeDef) {
+        let old_funcs: Vec<&Function> = old
+            .funcs
+            .iter()
+            .filter(|f| !f.is_dispatch_plumbing())
+            .collect();
+        let new_funcs: Vec<&Function> = new
+            .funcs
+            .iter()
+            .filter(|f| !f.is_dispatch_plumbing())
+            .collect();
+        let key = |f: &Function| (f.name.clone(), f.invoke_kind);
+        let old_keys: HashSet<_> = old_funcs.iter().map(|f| key(f)).collect();
+        let in_vtable = |f: &Function| f.func_kind != FuncKind::Dispatch;
+        let last_old_slot = old_funcs
+            .iter()
+            .filter(|f| in_vtable(f))
+            .map(|f| f.vtable_offset)
+            .max();
+
+        let mut matched = HashSet::new();
+        for old_func in &old_funcs {
+            let member = format!("{}.{}", path, member_name(old_func));
+            let found = new_funcs
+                .iter()
+                .position(|f| key(f) == key(old_func))
+                .or_else(|| {
+                    // Same slot, same signature, old name gone: a rename.
+                    new_funcs.iter().position(|f| {
+                        in_vtable(f)
+                            && in_vtable(old_func)
+                            && f.vtable_offset == old_func.vtable_offset
+                            && signature(f) == signature(old_func)
+                            && !old_keys.contains(&key(f))
+                    })
+                });
+            let Some(index) = found.filter(|i| !matched.contains(i)) else {
+                self.removed(Severity::BinaryBreaking, &member, "method removed");
+                continue;
+            };
+            matched.insert(index);
+            let new_func = new_funcs[index];
+            if new_func.name != old_func.name {
+                self.changed(
+                    Severity::SourceBreaking,
+                    &member,
+                    format!("renamed to {}", member_name(new_func)),
+                );
+            }
+            self.function(&member, old_func, new_func);
+        }
+        for (index, new_func) in new_funcs.iter().enumerate() {
+            if matched.contains(&index) {
+                continue;
+            }
+            let member = format!("{}.{}", path, member_name(new_func));
+            match last_old_slot {
+                Some(last) if in_vtable(new_func) && new_func.vtable_offset <= last => {
+                    self.push(
+                        Severity::BinaryBreaking,
+                        ChangeKind::Added,
+                        &member,
+                        "method inserted before existing vtable slots".to_string(),
+                    );
+                }
+                _ => self.added(&member, "method added".to_string()),
+            }
+        }
+    }
+
+    fn function(&mut self, member: &str, old: &Function, new: &Function) {
+        if old.func_kind != FuncKind::Dispatch
+            && new.func_kind != FuncKind::Dispatch
+            && old.vtable_offset != new.vtable_offset
+        {
+            self.changed(
+                Severity::BinaryBreaking,
+                member,
+                format!(
+                    "vtable offset changed from {} to {}",
+                    old.vtable_offset, new.vtable_offset
+                ),
+            );
+        }
+        if old.memid != new.memid {
+            self.changed(
+                Severity::BinaryBreaking,
+                member,
+                format!("DISPID changed from {} to {}", old.memid, new.memid),
+            );
+        }
+        if old.params.len() != new.params.len() {
+            self.changed(
+                Severity::BinaryBreaking,
+                member,
+                format!(
+                    "parameter count changed from {} to {}",
+                    old.params.len(),
+                    new.params.len()
+                ),
+            );
+        }
+        let (from, to) = (type_to_string(&old.ret), type_to_string(&new.ret));
+        if from != to {
+            self.changed(
+                Severity::SourceBreaking,
+                member,
+                format!("return type changed from {} to {}", from, to),
+            );
+        }
+        for (i, (old_param, new_param)) in old.params.iter().zip(&new.params).enumerate() {
+            let old_name = param_name(old_param, i);
+            let new_name = param_name(new_param, i);
+            let param = format!("{}({})", member, new_name);
+            if old_name != new_name {
+                self.changed(
+                    Severity::SourceBreaking,
+                    &param,
+                    format!("parameter {} renamed from {}", i, old_name),
+                );
+            }
+            let (from, to) = (type_to_string(&old_param.ty), type_to_string(&new_param.ty));
+            if from != to {
+                self.changed(
+                    Severity::SourceBreaking,
+                    &param,
+                    format!("type changed from {} to {}", from, to),
+                );
+            }
+            if old_param.flags != new_param.flags {
+                self.changed(
+                    Severity::SourceBreaking,
+                    &param,
+                    format!(
+                        "flags changed from {:#x} to {:#x}",
+                        old_param.flags, new_param.flags
+                    ),
+                );
+            }
+            if old_param.default_value != new_param.default_value {
+                let show = |v: &Option<Variant>| {
+                    v.as_ref().map_or("(none)".to_string(), variant_to_string)
+                };
+                self.changed(
+                    Severity::SourceBreaking,
+                    &param,
+                    format!(
+                        "default value changed from {} to {}",
+                        show(&old_param.default_value),
+                        show(&new_param.default_value)
+                    ),
+                );
+            }
+        }
+    }
+
+    fn coclass_interfaces(&mut self, path: &str, old: &TypeDef, new: &TypeDef) {
+        for old_impl in &old.impl_types {
+            if !new
+                .impl_types
+                .iter()
+                .any(|i| i.target.name == old_impl.target.name)
+            {
+                self.removed(
+                    Severity::BinaryBreaking,
+                    &format!("{}.{}", path, old_impl.target.name),
+                    "interface no longer implemented",
+                );
+            }
+        }
+        for new_impl in &new.impl_types {
+            if !old
+                .impl_types
+                .iter()
+                .any(|i| i.target.name == new_impl.target.name)
+            {
+                self.added(
+                    &format!("{}.{}", path, new_impl.target.name),
+                    "interface implemented".to_string(),
+                );
+            }
+        }
+    }
+}
+
+/// `Name`, or `Name[propget]` for property accessors.
+fn member_name(func: &Function) -> String {
+    match func.invoke_kind.idl_attribute() {
+        Some(attr) => format!("{}[{}]", func.name, attr),
+        None => func.name.clone(),
+    }
+}
+
+fn param_name(param: &Param, index: usize) -> String {
+    param
+        .name
+        .clone()
+        .unwrap_or_else(|| format!("arg{}", index))
+}
+
+fn signature(func: &Function) -> Vec<String> {
+    std::iter::once(&func.ret)
+        .chain(func.params.iter().map(|p| &p.ty))
+        .map(type_to_string)
+        .collect()
+}
+
+fn alias_string(ty: &TypeDef) -> String {
+    ty.alias_of
+        .as_ref()
+        .map_or("(none)".to_string(), type_to_string)
+}
+
+fn constant_string(value: &Option<Variant>) -> String {
+    value
+        .as_ref()
+        .map_or("(none)".to_string(), variant_to_string)
+}
+
+/// IDL name of the VARTYPE a constant holds.
+fn constant_type(value: &Option<Variant>) -> String {
+    value.as_ref().map_or("(none)".to_string(), |v| {
+        type_to_string(&Type::Base(v.vt()))
+    })
+}
+
+/// Whether two constants hold the same number, whatever their VARTYPEs, or
+/// are otherwise equal.
+fn same_number(old: &Option<Variant>, new: &Option<Variant>) -> bool {
+    let float = |v: &Variant| match v {
+        Variant::R4(v) => Some(*v as f64),
+        Variant::R8(v) => Some(*v),
+        v => v.as_i128().map(|v| v as f64),
+    };
+    match (old, new) {
+        (Some(old), Some(new)) => match (old.as_i128(), new.as_i128()) {
+            (Some(old), Some(new)) => old == new,
+            _ => match (float(old), float(new)) {
+                (Some(old), Some(new)) => old == new,
+                _ => old == new,
+            },
+        },
+        (old, new) => old == new,
+    }
+}
+
+fn count(changes: &[Change], severity: Severity) -> usize {
+    changes.iter().filter(|c| c.severity == severity).count()
+}
+
+fn library_label(lib: &Library) -> String {
+    format!("{} {}.{}", lib.name, lib.version.0, lib.version.1)
+}
+
+/// Prints the changes as aligned text, one per line, with a summary.
+pub fn write_text<W>(
+    old: &Library,
+    new: &Library,
+    changes: &[Change],
+    mut out: W,
+) -> std::io::Result<()>
+where
+    W: Write,
+{
+    writeln!(
+        out,
+        "Comparing {} -> {}",
+        library_label(old),
+        library_label(new)
+    )?;
+    if changes.is_empty() {
+        writeln!(out, "No changes.")?;
+        return Ok(());
+    }
+    writeln!(out)?;
+    let width = changes.iter().map(|c| c.path.len()).max().unwrap_or(0);
+    for change in changes {
+        writeln!(
+            out,
+            "{:<15}  {:<width$}  {}",
+            change.severity.name(),
+            change.path,
+            change.message,
+            width = width
+        )?;
+    }
+    writeln!(out)?;
+    writeln!(
+        out,
+        "{} binary-breaking, {} source-breaking, {} additive",
+        count(changes, Severity::BinaryBreaking),
+        count(changes, Severity::SourceBreaking),
+        count(changes, Severity::Additive)
+    )?;
+    Ok(())
+}
+
+/// The changes as a JSON document.
+pub fn to_json(old: &Library, new: &Library, changes: &[Change]) -> String {
+    let library = |lib: &Library| {
+        Json::Object(vec![
+            ("name", lib.name.as_str().into()),
+            ("guid", lib.guid.to_string().into()),
+            (
+                "version",
+                format!("{}.{}", lib.version.0, lib.version.1).into(),
+            ),
+        ])
+    };
+    Json::Object(vec![
+        ("schema_version", SCHEMA_VERSION.into()),
+        ("old", library(old)),
+        ("new", library(new)),
+        (
+            "summary",
+            Json::Object(vec![
+                (
+                    "binary_breaking",
+                    count(changes, Severity::BinaryBreaking).into(),
+                ),
+                (
+                    "source_breaking",
+                    count(changes, Severity::SourceBreaking).into(),
+                ),
+                ("additive", count(changes, Severity::Additive).into()),
+            ]),
+        ),
+        (
+            "changes",
+            Json::array(changes, |c| {
+                Json::Object(vec![
+                    ("severity", c.severity.name().into()),
+                    ("kind", c.kind.name().into()),
+                    ("path", c.path.as_str().into()),
+                    ("message", c.message.as_str().into()),
+                ])
+            }),
+        ),
+    ])
+    .render()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::fixture;
+
+    fn type_mut<'a>(lib: &'a mut Library, name: &str) -> &'a mut TypeDef {
+        lib.types.iter_mut().find(|t| t.name == name).unwrap()
+    }
+
+    fn func_mut<'a>(ty: &'a mut TypeDef, name: &str) -> &'a mut Function {
+        ty.funcs.iter_mut().find(|f| f.name == name).unwrap()
+    }
+
+    fn summary(changes: &[Change]) -> Vec<(Severity, ChangeKind, &str)> {
+        changes
+            .iter()
+            .map(|c| (c.severity, c.kind, c.path.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn identical_libraries_have_no_changes() {
+        let lib = fixture("simple.tlb");
+        assert!(diff_libraries(&lib, &lib).is_empty());
+        let lib = fixture("legacy_sltg.tlb");
+        assert!(diff_libraries(&lib, &lib).is_empty());
+    }
+
+    #[test]
+    fn classifies_interface_changes() {
+        let old = fixture("simple.tlb");
+        let mut new = old.clone();
+        let shape = type_mut(&mut new, "IShape");
+        func_mut(shape, "Move").params[0].name = Some("x".to_string());
+        func_mut(shape, "Center").memid = 9;
+        let mut scale = shape.funcs.last().unwrap().clone();
+        scale.name = "Scale".to_string();
+        scale.memid = 10;
+        scale.vtable_offset += 8;
+        shape.funcs.push(scale);
+        shape.funcs.retain(|f| f.name != "Color");
+
+        let changes = diff_libraries(&old, &new);
+        assert_eq!(
+            summary(&changes),
+            vec![
+                (
+                    Severity::SourceBreaking,
+                    ChangeKind::Changed,
+                    "IShape.Move(x)"
+                ),
+                (
+                    Severity::BinaryBreaking,
+                    ChangeKind::Removed,
+                    "IShape.Color[propget]"
+                ),
+                (
+                    Severity::BinaryBreaking,
+                    ChangeKind::Changed,
+                    "IShape.Center"
+                ),
+                (Severity::Additive, ChangeKind::Added, "IShape.Scale"),
+            ],
+            "{:#?}",
+            changes
+        );
+        assert_eq!(changes[0].message, "parameter 0 renamed from dx");
+        assert_eq!(changes[2].message, "DISPID changed from 5 to 9");
+    }
+
+    #[test]
+    fn detects_vtable_reordering_and_renames() {
+        let old = fixture("simple.tlb");
+        let mut new = old.clone();
+        let shape = type_mut(&mut new, "IShape");
+        shape.name = "IShape2".to_string();
+        let mut inserted = func_mut(shape, "Move").clone();
+        inserted.name = "Rotate".to_string();
+        for func in &mut shape.funcs {
+            if func.name == "Color" || func.name == "Center" {
+                func.vtable_offset += 8;
+            }
+        }
+        shape.funcs.push(inserted);
+        let point = type_mut(&mut new, "Point");
+        point.vars[1].name = "top".to_string();
+
+        let changes = diff_libraries(&old, &new);
+        assert_eq!(
+            summary(&changes),
+            vec![
+                (Severity::SourceBreaking, ChangeKind::Changed, "Point.y"),
+                (Severity::SourceBreaking, ChangeKind::Changed, "IShape2"),
+                (
+                    Severity::BinaryBreaking,
+                    ChangeKind::Changed,
+                    "IShape2.Color[propget]"
+                ),
+                (
+                    Severity::BinaryBreaking,
+                    ChangeKind::Changed,
+                    "IShape2.Center"
+                ),
+                (
+                    Severity::BinaryBreaking,
+                    ChangeKind::Added,
+                    "IShape2.Rotate"
+                ),
+            ],
+            "{:#?}",
+            changes
+        );
+        assert_eq!(changes[1].message, "renamed from IShape");
+    }
+
+    #[test]
+    fn classifies_guid_enum_and_constant_changes() {
+        let old = fixture("simple.tlb");
+        let mut new = old.clone();
+        let colors = type_mut(&mut new, "Colors");
+        colors.vars[2].value = Some(Variant::I4(7));
+        let mut purple = colors.vars[0].clone();
+        purple.name = "Purple".to_string();
+        purple.value = Some(Variant::I4(8));
+        colors.vars.push(purple);
+        type_mut(&mut new, "Handle").alias_of = Some(Type::Base(crate::typelib::VT_I8));
+        type_mut(&mut new, "Shape").guid = Guid::from_u128(42);
+        type_mut(&mut new, "Constants").vars[0].value = Some(Variant::I4(43));
+
+        let changes = diff_libraries(&old, &new);
+        assert_eq!(
+            summary(&changes),
+            vec![
+                (Severity::BinaryBreaking, ChangeKind::Changed, "Colors.Blue"),
+                (Severity::Additive, ChangeKind::Added, "Colors.Purple"),
+                (Severity::SourceBreaking, ChangeKind::Changed, "Handle"),
+                (Severity::BinaryBreaking, ChangeKind::Changed, "Shape"),
+                (
+                    Severity::BinaryBreaking,
+                    ChangeKind::Changed,
+                    "Constants.Answer"
+                ),
+            ],
+            "{:#?}",
+            changes
+        );
+        assert_eq!(
+            changes[2].message,
+            "aliased type changed from long to int64"
+        );
+        assert_eq!(changes[4].message, "value changed from 42 to 43");
+    }
+
+    #[test]
+    fn compares_constants_as_numbers() {
+        let old = fixture("simple.tlb");
+        let mut new = old.clone();
+        let constants = type_mut(&mut new, "Constants");
+        // SLTG stores integers as VT_INT, whatever their declared type.
+        constants.vars[0].value = Some(Variant::Int(42));
+        constants.vars[1].value = Some(Variant::R4(3.25));
+        let tiny = constants
+            .vars
+            .iter_mut()
+            .find(|v| v.name == "Tiny")
+            .unwrap();
+        tiny.ty = Type::Base(crate::typelib::VT_I2);
+        tiny.value = Some(Variant::I2(-56));
+
+        let changes: Vec<_> = diff_libraries(&old, &new)
+            .into_iter()
+            .map(|c| (c.severity, c.path, c.message))
+            .collect();
+        let change =
+            |severity, path: &str, message: &str| (severity, path.to_string(), message.to_string());
+        assert_eq!(
+            changes,
+            [
+                change(
+                    Severity::SourceBreaking,
+                    "Constants.Pi",
+                    "type changed from double to float"
+                ),
+                change(
+                    Severity::SourceBreaking,
+                    "Constants.Tiny",
+                    "type changed from unsigned char to short"
+                ),
+                change(
+                    Severity::BinaryBreaking,
+                    "Constants.Tiny",
+                    "value changed from 200 to -56"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_removed_and_added_types() {
+        let old = fixture("simple.tlb");
+        let new = fixture("legacy_sltg.tlb");
+        let changes = diff_libraries(&old, &new);
+        assert_eq!(changes[0].path, "LegacyLib");
+        assert!(
+            changes
+                .iter()
+                .any(|c| c.path == "Colors" && c.kind == ChangeKind::Removed)
+        );
+        assert!(
+            changes
+                .iter()
+                .any(|c| c.path == "ICounter" && c.kind == ChangeKind::Added)
+        );
+    }
+
+    #[test]
+    fn writes_text_and_json() {
+        let old = fixture("simple.tlb");
+        let mut new = old.clone();
+        new.version = (1, 3);
+        type_mut(&mut new, "Colors").vars.pop();
+
+        let changes = diff_libraries(&old, &new);
+        let mut text = Vec::new();
+        write_text(&old, &new, &changes, &mut text).unwrap();
+        assert_eq!(
+            String::from_utf8(text).unwrap(),
+            "Comparing SimpleLib 1.2 -> SimpleLib 1.3\n\n\
+             source-breaking  Colors.Unset  enum value removed\n\n\
+             0 binary-breaking, 1 source-breaking, 0 additive\n"
+        );
+        let json = to_json(&old, &new, &changes);
+        assert!(json.contains("\"source_breaking\": 1"), "{}", json);
+        assert!(json.contains("\"path\": \"Colors.Unset\""), "{}", json);
+    }
+}
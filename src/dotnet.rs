@@ -0,0 +1,177 @@
+//! The `dotnet build` path to a .winmd: an MSBuild project for the
+//! `Microsoft.Windows.WinmdGenerator` SDK, which compiles the IDL of each
+//! library with MIDL and scrapes the resulting headers with ClangSharp.
+//!
+//! The IDL files themselves come from [`idlgen`](crate::idlgen); this
+//! module writes the project and the translation unit that includes the
+//! headers, and runs the build.
+
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use crate::error::Error;
+
+/// The WinmdGenerator project: one `<Partition>` per library, each
+/// traversing the MIDL header of its library into its namespace.
+/// `partitions` pairs library names, which name the IDL and header files,
+/// with namespaces.
+pub fn generate_proj(
+    path: &Path,
+    partitions: &[(&str, &str)],
+    winmd_name: &str,
+    winmd_version: &str,
+    winmd_dir: &Path,
+) -> Result<(), Error> {
+    let winmd_abs_path = std::fs::canonicalize(winmd_dir).unwrap_or(winmd_dir.to_path_buf());
+    let winmd_file_path = winmd_abs_path.join(format!("{}.winmd", winmd_name));
+    let content = project(partitions, &winmd_file_path, winmd_version);
+
+    let mut file = File::create(path).map_err(|e| Error::io(path, e))?;
+    file.write_all(content.as_bytes())
+        .map_err(|e| Error::io(path, e))?;
+    Ok(())
+}
+
+fn project(partitions: &[(&str, &str)], winmd_file_path: &Path, winmd_version: &str) -> String {
+    let mut items = String::new();
+    for (lib_name, _) in partitions {
+        items.push_str(&format!(
+            "    <Idls Include=\"$(IdlsRoot)\\{}.idl\"/>\n",
+            lib_name
+        ));
+    }
+    for (lib_name, namespace) in partitions {
+        items.push_str(&format!(
+            r#"    <Partition Include="main.cpp">
+      <TraverseFiles>$(CompiledHeadersDir)\{}.h</TraverseFiles>
+      <Namespace>{}</Namespace>
+    </Partition>
+"#,
+            lib_name, namespace
+        ));
+    }
+
+    format!(
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<Project Sdk="Microsoft.Windows.WinmdGenerator/0.65.8-preview">
+  <PropertyGroup Label="Globals">
+    <OutputWinmd>{}</OutputWinmd>
+    <WinmdVersion>{}</WinmdVersion>
+    <IdlsRoot>$(MSBuildThisFileDirectory)</IdlsRoot>
+    <AdditionalIncludes>$(CompiledHeadersDir)</AdditionalIncludes>
+  </PropertyGroup>
+  <ItemGroup>
+{}  </ItemGroup>
+</Project>"#,
+        winmd_file_path.display(),
+        winmd_version,
+        items
+    )
+}
+
+/// The `main.cpp` every partition compiles: it includes the header of each
+/// library.
+pub fn generate_main_cpp(path: &Path, partitions: &[(&str, &str)]) -> Result<(), Error> {
+    let content = partitions
+        .iter()
+        .map(|(lib_name, _)| format!(r#"#include "{}.h""#, lib_name))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let mut file = File::create(path).map_err(|e| Error::io(path, e))?;
+    file.write_all(content.as_bytes())
+        .map_err(|e| Error::io(path, e))?;
+    Ok(())
+}
+
+/// Whether the `dotnet` command can be run.
+pub fn check_dotnet() -> bool {
+    Command::new("dotnet").arg("--version").output().is_ok()
+}
+
+/// Lines of the build output kept in the [`Error::ToolFailed`] of a failed
+/// build.
+const OUTPUT_TAIL: usize = 20;
+
+/// Runs `dotnet build` on the `generate.proj` in `proj_dir`. The build log
+/// is copied to standard output once the build ends; MSBuild and MIDL
+/// report their errors there, so the end of it, followed by standard
+/// error, makes the [`Error::ToolFailed`] of a failed build.
+pub fn run_dotnet_build(proj_dir: &Path) -> Result<(), Error> {
+    let tool = "dotnet build";
+    let output = Command::new("dotnet")
+        .arg("build")
+        .arg("generate.proj")
+        .current_dir(proj_dir)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .output()
+        .map_err(|e| Error::ToolFailed {
+            tool: tool.to_string(),
+            code: None,
+            output: e.to_string(),
+        })?;
+    std::io::stdout()
+        .write_all(&output.stdout)
+        .map_err(Error::IoError)?;
+
+    if !output.status.success() {
+        return Err(Error::ToolFailed {
+            tool: tool.to_string(),
+            code: output.status.code(),
+            output: tail(&output.stdout, &output.stderr),
+        });
+    }
+    Ok(())
+}
+
+/// The last [`OUTPUT_TAIL`] lines of `stdout`, then `stderr`.
+fn tail(stdout: &[u8], stderr: &[u8]) -> String {
+    let stdout = String::from_utf8_lossy(stdout);
+    let lines: Vec<_> = stdout.lines().collect();
+    let mut text = lines[lines.len().saturating_sub(OUTPUT_TAIL)..].join("\n");
+    let stderr = String::from_utf8_lossy(stderr);
+    if !stderr.trim().is_empty() {
+        if !text.is_empty() {
+            text.push('\n');
+        }
+        text.push_str(stderr.trim_end());
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_the_end_of_the_build_output() {
+        let log: String = (1..=30).map(|n| format!("line {}\n", n)).collect();
+        let text = tail(log.as_bytes(), b"MIDL2025: syntax error\n");
+        assert!(text.starts_with("line 11\n"), "{}", text);
+        assert!(
+            text.ends_with("line 30\nMIDL2025: syntax error"),
+            "{}",
+            text
+        );
+        assert_eq!(tail(b"", b"  \n"), "");
+    }
+
+    #[test]
+    fn lists_a_partition_per_library() {
+        let xml = project(
+            &[("Shapes", "Contoso.Shapes"), ("Canvas", "Contoso.Canvas")],
+            Path::new("out/Contoso.winmd"),
+            "1.2.0.0",
+        );
+        assert!(xml.contains("<OutputWinmd>out/Contoso.winmd</OutputWinmd>"));
+        assert!(xml.contains("<WinmdVersion>1.2.0.0</WinmdVersion>"));
+        assert!(xml.contains(
+            "    <Idls Include=\"$(IdlsRoot)\\Shapes.idl\"/>\n    <Idls Include=\"$(IdlsRoot)\\Canvas.idl\"/>\n"
+        ));
+        assert!(xml.contains(
+            "      <TraverseFiles>$(CompiledHeadersDir)\\Canvas.h</TraverseFiles>\n      <Namespace>Contoso.Canvas</Namespace>\n"
+        ));
+    }
+}
@@ -1,11 +1,173 @@
+//! The error type of every fallible operation of the crate.
+
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
 use thiserror::Error;
 
+// HRESULTs `LoadTypeLibEx` reports for the failures the built-in readers
+// run into.
+/// The file does not exist.
+pub const STG_E_FILENOTFOUND: u32 = 0x8003_0002;
+/// The file cannot be opened.
+pub const STG_E_ACCESSDENIED: u32 = 0x8003_0005;
+/// The file cannot be read.
+pub const STG_E_READFAULT: u32 = 0x8003_001e;
+/// The file is not in a type library format.
+pub const TYPE_E_UNSUPFORMAT: u32 = 0x8002_8019;
+/// The file is in a type library format but cannot be decoded.
+pub const TYPE_E_CANTLOADLIBRARY: u32 = 0x8002_9c4a;
+
+/// What went wrong, with the exit code of the command line tool chosen by
+/// the variant.
 #[derive(Error, Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum Error {
-    #[error("Failed to load type library")]
-    LoadTypeLibraryError(#[from] windows_core::Error),
+    /// A type library that could not be loaded, with the HRESULT OLE would
+    /// give for the same failure.
+    #[error("Cannot load {} (HRESULT {hresult:#010x}): {source}", path.display())]
+    LoadFailed {
+        /// The file given to load.
+        path: PathBuf,
+        /// One of the `STG_E_*` and `TYPE_E_*` constants.
+        hresult: u32,
+        /// What the reader ran into.
+        #[source]
+        source: Box<Error>,
+    },
+    /// A type library whose data the readers cannot decode.
+    #[error("Invalid type library: {0}")]
+    InvalidTypeLib(String),
+    /// A typeinfo whose `TYPEKIND` is none of the eight OLE knows.
+    #[error("Unsupported type kind {kind} of {name}")]
+    UnsupportedTypeKind {
+        /// Name of the typeinfo.
+        name: String,
+        /// Its `TYPEKIND`.
+        kind: u32,
+    },
+    /// A name given on the command line or in a file that nothing matches.
+    #[error("Unresolved reference to {name}: {reason}")]
+    UnresolvedReference {
+        /// The name as given.
+        name: String,
+        /// Why it does not resolve.
+        reason: String,
+    },
+    /// An external program that failed; `code` is `None` when it could not
+    /// be started or was killed by a signal, and `output` is the end of what
+    /// it printed, or why it could not be started.
+    #[error("{tool} failed with {}: {}", exit_status(*.code), .output.trim_end())]
+    ToolFailed {
+        /// Name of the program.
+        tool: String,
+        /// Its exit code.
+        code: Option<i32>,
+        /// The end of its output.
+        output: String,
+    },
+    /// A help file the CHM reader cannot decode.
+    #[error("Invalid CHM file: {0}")]
+    InvalidChm(String),
+    /// A .winmd that cannot be read as a reference.
+    #[error("Invalid .winmd file: {0}")]
+    InvalidWinmd(String),
+    /// A configuration file, manifest or set of arguments that does not
+    /// make sense.
+    #[error("Invalid configuration: {0}")]
+    InvalidConfig(String),
+    /// IDL the IDL reader cannot parse or resolve.
+    #[error("Invalid IDL at line {line}: {message}")]
+    InvalidIdl {
+        /// 1-based line of the offending token.
+        line: usize,
+        /// What is wrong there.
+        message: String,
+    },
+    /// Diagnostics of a run that was told to fail on any.
+    #[error("{0} warning(s) denied")]
+    WarningsDenied(usize),
+    /// Differences between a library and the IDL printed from it.
+    #[error("{0} discrepancy(ies) between the library and its IDL")]
+    VerificationFailed(usize),
+    /// A [`TypeLibInfo`](crate::idlgen::TypeLibInfo) asked for its library
+    /// before one was loaded.
     #[error("Type library not loaded")]
     TypeLibNotLoaded,
-    #[error("IO Error")]
-    IoError(#[from] std::io::Error),
+    /// A file that could not be read or written.
+    #[error("I/O error on {}: {source}", path.display())]
+    Io {
+        /// The file.
+        path: PathBuf,
+        /// What the operating system reported.
+        #[source]
+        source: std::io::Error,
+    },
+    /// An I/O error on a stream without a path, such as standard output.
+    #[error("I/O error: {0}")]
+    IoError(std::io::Error),
+}
+
+fn exit_status(code: Option<i32>) -> String {
+    match code {
+        Some(code) => format!("exit code {}", code),
+        None => "no exit code".to_string(),
+    }
+}
+
+impl Error {
+    /// Wraps a failure to read the type library at `path`.
+    pub fn load_failed(path: impl Into<PathBuf>, source: Error) -> Self {
+        let hresult = match &source {
+            Error::IoError(e) | Error::Io { source: e, .. } => match e.kind() {
+                ErrorKind::NotFound => STG_E_FILENOTFOUND,
+                ErrorKind::PermissionDenied => STG_E_ACCESSDENIED,
+                _ => STG_E_READFAULT,
+            },
+            Error::UnsupportedTypeKind { .. } => TYPE_E_UNSUPFORMAT,
+            _ => TYPE_E_CANTLOADLIBRARY,
+        };
+        Error::LoadFailed {
+            path: path.into(),
+            hresult,
+            source: Box::new(source),
+        }
+    }
+
+    /// Wraps a failure to read or write the file at `path`.
+    pub fn io(path: impl Into<PathBuf>, source: std::io::Error) -> Self {
+        Error::Io {
+            path: path.into(),
+            source,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages_include_the_source() {
+        let missing = std::io::Error::new(ErrorKind::NotFound, "no such file");
+        let error = Error::load_failed("shapes.tlb", Error::IoError(missing));
+        assert_eq!(
+            error.to_string(),
+            "Cannot load shapes.tlb (HRESULT 0x80030002): I/O error: no such file"
+        );
+        let denied = std::io::Error::new(ErrorKind::PermissionDenied, "access denied");
+        assert_eq!(
+            Error::io("out/Shapes.winmd", denied).to_string(),
+            "I/O error on out/Shapes.winmd: access denied"
+        );
+        let error = Error::ToolFailed {
+            tool: "dotnet build".to_string(),
+            code: Some(1),
+            output: "MIDL2025: syntax error\n".to_string(),
+        };
+        assert_eq!(
+            error.to_string(),
+            "dotnet build failed with exit code 1: MIDL2025: syntax error"
+        );
+    }
 }
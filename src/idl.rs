@@ -0,0 +1,2044 @@
+//! Reader for IDL source, the inverse of [`idlgen`](crate::idlgen).
+//!
+//! The `library` block is parsed into the [`model`](crate::model) a type
+//! library loads into, with what MIDL computes filled in the same way:
+//! DISPIDs of methods without an `id`, vtable offsets, the `dispatchable`
+//! and `cancreate` flags, and the member offsets of records at the
+//! `#pragma pack` in effect where they are defined. Base types are read in
+//! the spelling the printer writes and in the common Windows SDK synonyms
+//! (`DWORD`, `BOOL`, `LPOLESTR`, ...).
+//!
+//! [`load`] also reads the files an IDL file `import`s from its directory,
+//! keeping the declarations outside the library block the library refers
+//! to, and the libraries it `importlib`s other than stdole. Names still
+//! not defined resolve to the stdole2 type of that name when stdole2.tlb
+//! is imported, and stay unresolved otherwise.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+use crate::error::Error;
+use crate::idlgen::{FUNC_FLAG_ATTRIBUTES, base_type_name};
+use crate::model::*;
+use crate::model::{ImplType, TypeRef};
+use crate::typelib::*;
+
+/// DISPID of a method without an `id` attribute, until one is assigned.
+const AUTO_ID: i32 = i32::MIN;
+
+const STDOLE2: Guid = Guid::from_u128(0x00020430_0000_0000_c000_000000000046);
+
+/// Attributes whose arguments are values; the arguments of the others
+/// (`size_is(*pcb)` and the like) are skipped.
+const VALUED_ATTRIBUTES: [&str; 11] = [
+    "id",
+    "helpstring",
+    "helpcontext",
+    "helpstringcontext",
+    "helpfile",
+    "helpstringdll",
+    "version",
+    "lcid",
+    "defaultvalue",
+    "entry",
+    "dllname",
+];
+
+/// The reserved DISPIDs of oaidl.idl, which ODL sources use by name.
+const DISPIDS: [(&str, i32); 8] = [
+    ("DISPID_VALUE", 0),
+    ("DISPID_UNKNOWN", -1),
+    ("DISPID_PROPERTYPUT", -3),
+    ("DISPID_NEWENUM", -4),
+    ("DISPID_EVALUATE", -5),
+    ("DISPID_CONSTRUCTOR", -6),
+    ("DISPID_DESTRUCTOR", -7),
+    ("DISPID_COLLECT", -8),
+];
+
+/// MIDL spellings of base types the printer spells differently. Typedefs
+/// of imported IDL files that are not `[public]` are written as the type
+/// they stand for, so these come out the same way.
+const SYNONYMS: [(&str, u16); 31] = [
+    ("BOOL", VT_I4),
+    ("LONG", VT_I4),
+    ("INT", VT_INT),
+    ("UINT", VT_UINT),
+    ("DWORD", VT_UI4),
+    ("ULONG", VT_UI4),
+    ("SHORT", VT_I2),
+    ("USHORT", VT_UI2),
+    ("WORD", VT_UI2),
+    ("CHAR", VT_I1),
+    ("small", VT_I1),
+    ("BYTE", VT_UI1),
+    ("UCHAR", VT_UI1),
+    ("byte", VT_UI1),
+    ("boolean", VT_UI1),
+    ("wchar_t", VT_UI2),
+    ("WCHAR", VT_UI2),
+    ("OLECHAR", VT_UI2),
+    ("hyper", VT_I8),
+    ("__int64", VT_I8),
+    ("LONGLONG", VT_I8),
+    ("ULONGLONG", VT_UI8),
+    ("CY", VT_CY),
+    ("LPOLESTR", VT_LPWSTR),
+    ("LPCOLESTR", VT_LPWSTR),
+    ("LPCWSTR", VT_LPWSTR),
+    ("LPCSTR", VT_LPSTR),
+    ("VARIANTARG", VT_VARIANT),
+    ("unsigned small", VT_UI1),
+    ("unsigned hyper", VT_UI8),
+    ("unsigned __int64", VT_UI8),
+];
+
+const PUNCTUATION: [&str; 26] = [
+    "<<", ">>", "{", "}", "[", "]", "(", ")", ";", ",", ":", "*", "=", "-", "+", "~", "!", "|",
+    "&", "^", "%", "/", "<", ">", ".", "?",
+];
+
+/// Parses IDL source with a `library` block. Of the types it uses from
+/// other files, only those of stdole2 resolve.
+pub fn parse(source: &str) -> Result<Library, Error> {
+    parse_in(source, None)
+}
+
+/// Reads an .idl or .odl file with a `library` block. The IDL files it
+/// `import`s and the type libraries it `importlib`s are looked up next to
+/// it for the types it uses from them; those that are not there, such as
+/// the system IDL files, are skipped.
+pub fn load(path: &Path) -> Result<Library, Error> {
+    let source = read_source(path)?;
+    parse_in(&source, Some(path.parent().unwrap_or(Path::new(""))))
+}
+
+/// IDL sources are ANSI text as often as UTF-8.
+fn read_source(path: &Path) -> Result<String, Error> {
+    let bytes = fs::read(path).map_err(|e| Error::io(path, e))?;
+    Ok(decode_ansi(&bytes))
+}
+
+/// Parses IDL source as if it were a file in `dir`, for the types it uses
+/// from the files there; with no `dir` it is [`parse`].
+pub fn parse_in(source: &str, dir: Option<&Path>) -> Result<Library, Error> {
+    let mut parser = Parser::new(source);
+    let mut lib = parser
+        .file()?
+        .ok_or_else(|| parser.error("no library block"))?;
+    if let Some(dir) = dir {
+        parser.import_files(dir)?;
+    }
+    let (types, packing) = parser.library_types();
+    lib.types = types;
+    let imported = match dir {
+        Some(dir) => imported_libraries(&mut lib, dir),
+        None => Vec::new(),
+    };
+    resolve(&mut lib, &imported);
+    lay_out_interfaces(&mut lib, &imported);
+    lay_out_records(&mut lib, &packing);
+    Ok(lib)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    /// A numeric literal as written.
+    Number(String),
+    Str(String),
+    Punct(&'static str),
+    /// A preprocessor line, without the `#`.
+    Directive(String),
+    Eof,
+}
+
+impl Token {
+    /// The token as an error message names it.
+    fn describe(&self) -> String {
+        match self {
+            Token::Ident(s) | Token::Number(s) => format!("`{}`", s),
+            Token::Str(s) => format!("string {:?}", s),
+            Token::Punct(p) => format!("`{}`", p),
+            Token::Directive(s) => format!("`#{}`", s),
+            Token::Eof => "end of file".to_string(),
+        }
+    }
+}
+
+struct Lexer<'a> {
+    src: &'a [u8],
+    pos: usize,
+    line: usize,
+}
+
+impl<'a> Lexer<'a> {
+    fn new(source: &'a str) -> Self {
+        Lexer {
+            src: source.as_bytes(),
+            pos: 0,
+            line: 1,
+        }
+    }
+
+    fn byte(&self, ahead: usize) -> Option<u8> {
+        self.src.get(self.pos + ahead).copied()
+    }
+
+    fn error(&self, message: impl Into<String>) -> Error {
+        Error::InvalidIdl {
+            line: self.line,
+            message: message.into(),
+        }
+    }
+
+    /// Skips white space and comments.
+    fn skip_blank(&mut self) {
+        while let Some(c) = self.byte(0) {
+            if c == b'\n' {
+                self.line += 1;
+                self.pos += 1;
+            } else if c.is_ascii_whitespace() {
+                self.pos += 1;
+            } else if c == b'/' && self.byte(1) == Some(b'/') {
+                while self.byte(0).is_some_and(|c| c != b'\n') {
+                    self.pos += 1;
+                }
+            } else if c == b'/' && self.byte(1) == Some(b'*') {
+                self.pos += 2;
+                while self.byte(0).is_some()
+                    && !(self.byte(0) == Some(b'*') && self.byte(1) == Some(b'/'))
+                {
+                    if self.byte(0) == Some(b'\n') {
+                        self.line += 1;
+                    }
+                    self.pos += 1;
+                }
+                self.pos = (self.pos + 2).min(self.src.len());
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn next(&mut self) -> Result<Token, Error> {
+        self.skip_blank();
+        let Some(c) = self.byte(0) else {
+            return Ok(Token::Eof);
+        };
+        let start = self.pos;
+        if c == b'#' {
+            // Up to the end of the line, continuation lines included.
+            while let Some(c) = self.byte(0) {
+                if c == b'\n' {
+                    break;
+                }
+                if c == b'\\' && self.byte(1) == Some(b'\n') {
+                    self.line += 1;
+                    self.pos += 1;
+                }
+                self.pos += 1;
+            }
+            let text = text(&self.src[start + 1..self.pos]).replace("\\\n", " ");
+            return Ok(Token::Directive(text.trim().to_string()));
+        }
+        if c.is_ascii_alphabetic() || c == b'_' {
+            while self
+                .byte(0)
+                .is_some_and(|c| c.is_ascii_alphanumeric() || c == b'_')
+            {
+                self.pos += 1;
+            }
+            let ident = text(&self.src[start..self.pos]);
+            // Wide strings read like narrow ones.
+            if ident == "L" && self.byte(0) == Some(b'"') {
+                self.pos += 1;
+                return Ok(Token::Str(decode_ansi(&self.quoted(b'"')?)));
+            }
+            return Ok(Token::Ident(ident));
+        }
+        if c.is_ascii_digit() || (c == b'.' && self.byte(1).is_some_and(|c| c.is_ascii_digit())) {
+            let hex = c == b'0' && matches!(self.byte(1), Some(b'x' | b'X'));
+            while let Some(c) = self.byte(0) {
+                let exponent_sign = !hex
+                    && matches!(c, b'+' | b'-')
+                    && matches!(self.src[self.pos - 1], b'e' | b'E');
+                if c.is_ascii_alphanumeric() || c == b'.' || exponent_sign {
+                    self.pos += 1;
+                } else {
+                    break;
+                }
+            }
+            return Ok(Token::Number(text(&self.src[start..self.pos])));
+        }
+        if c == b'"' {
+            self.pos += 1;
+            return Ok(Token::Str(decode_ansi(&self.quoted(b'"')?)));
+        }
+        if c == b'\'' {
+            self.pos += 1;
+            let bytes = self.quoted(b'\'')?;
+            return Ok(Token::Number(
+                bytes.first().copied().unwrap_or(0).to_string(),
+            ));
+        }
+        let rest = &self.src[self.pos..];
+        match PUNCTUATION.iter().find(|p| rest.starts_with(p.as_bytes())) {
+            Some(p) => {
+                self.pos += p.len();
+                Ok(Token::Punct(p))
+            }
+            None => {
+                let c = text(rest).chars().next().unwrap_or('?');
+                Err(self.error(format!("unexpected character `{}`", c)))
+            }
+        }
+    }
+
+    /// The bytes of a C literal up to the closing `quote`, escapes decoded.
+    fn quoted(&mut self, quote: u8) -> Result<Vec<u8>, Error> {
+        let mut bytes = Vec::new();
+        loop {
+            let c = match self.byte(0) {
+                Some(b'\n') | None => return Err(self.error("unterminated literal")),
+                Some(c) => c,
+            };
+            self.pos += 1;
+            if c == quote {
+                return Ok(bytes);
+            }
+            if c != b'\\' {
+                bytes.push(c);
+                continue;
+            }
+            let Some(e) = self.byte(0) else {
+                continue;
+            };
+            self.pos += 1;
+            match e {
+                b'n' => bytes.push(b'\n'),
+                b'r' => bytes.push(b'\r'),
+                b't' => bytes.push(b'\t'),
+                b'a' => bytes.push(7),
+                b'b' => bytes.push(8),
+                b'v' => bytes.push(11),
+                b'f' => bytes.push(12),
+                b'0'..=b'7' => {
+                    // At most three digits.
+                    let mut value = (e - b'0') as u32;
+                    for _ in 0..2 {
+                        match self.byte(0) {
+                            Some(d @ b'0'..=b'7') => {
+                                value = value * 8 + (d - b'0') as u32;
+                                self.pos += 1;
+                            }
+                            _ => break,
+                        }
+                    }
+                    bytes.push(value as u8);
+                }
+                b'x' => {
+                    let mut value = 0u32;
+                    while let Some(d) = self.byte(0).and_then(|d| (d as char).to_digit(16)) {
+                        value = value.wrapping_mul(16) + d;
+                        self.pos += 1;
+                    }
+                    bytes.push(value as u8);
+                }
+                b'\n' => self.line += 1,
+                e => bytes.push(e),
+            }
+        }
+    }
+
+    /// The GUID of `uuid` and `custom`, which does not tokenize: the text
+    /// up to the next `)` or `,`, without quotes or braces.
+    fn guid(&mut self) -> Result<Guid, Error> {
+        self.skip_blank();
+        let start = self.pos;
+        while self
+            .byte(0)
+            .is_some_and(|c| !matches!(c, b')' | b',' | b'\n'))
+        {
+            self.pos += 1;
+        }
+        let raw = text(&self.src[start..self.pos]);
+        let raw = raw.trim();
+        Guid::parse(raw.trim_matches(['"', '{', '}']))
+            .ok_or_else(|| self.error(format!("invalid GUID `{}`", raw)))
+    }
+}
+
+fn text(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).into_owned()
+}
+
+/// A literal, or the value of a constant expression.
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Int(i128),
+    /// A floating-point literal and its text, which scaled types read
+    /// exactly.
+    Float(f64, String),
+    Str(String),
+    Bool(bool),
+    Guid(Guid),
+}
+
+struct Attribute {
+    name: String,
+    args: Vec<Value>,
+}
+
+/// The attributes in front of a declaration.
+#[derive(Default)]
+struct Attributes(Vec<Attribute>);
+
+impl Attributes {
+    fn has(&self, name: &str) -> bool {
+        self.0.iter().any(|a| a.name == name)
+    }
+
+    fn value(&self, name: &str) -> Option<&Value> {
+        self.0.iter().find(|a| a.name == name)?.args.first()
+    }
+
+    fn int(&self, name: &str) -> Option<i128> {
+        match self.value(name)? {
+            Value::Int(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    fn string(&self, name: &str) -> Option<String> {
+        match self.value(name)? {
+            Value::Str(s) => Some(s.clone()),
+            _ => None,
+        }
+    }
+
+    fn guid(&self) -> Guid {
+        match self.value("uuid") {
+            Some(Value::Guid(guid)) => *guid,
+            _ => Guid::default(),
+        }
+    }
+
+    /// `version(1.2)`; the literal is read as text, so `1.10` is 1.10.
+    fn version(&self) -> (u16, u16) {
+        match self.value("version") {
+            Some(Value::Int(major)) => (*major as u16, 0),
+            Some(Value::Float(_, text)) => {
+                let (major, minor) = text.split_once('.').unwrap_or((text, "0"));
+                (major.parse().unwrap_or(0), minor.parse().unwrap_or(0))
+            }
+            _ => (0, 0),
+        }
+    }
+
+    fn doc(&self) -> Doc {
+        Doc {
+            help_string: self.string("helpstring").unwrap_or_default(),
+            help_context: self.int("helpcontext").unwrap_or(0) as u32,
+            help_string_context: self.int("helpstringcontext").unwrap_or(0) as u32,
+        }
+    }
+
+    fn custom_data(&self) -> Vec<CustData> {
+        self.0
+            .iter()
+            .filter(|a| a.name == "custom")
+            .filter_map(|a| match a.args.as_slice() {
+                [Value::Guid(guid), value] => Some(CustData {
+                    guid: *guid,
+                    value: variant(value, &Type::Base(VT_VARIANT)),
+                }),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// `TYPEFLAG_*` bits; `cancreate` and `dispatchable` are derived.
+    fn type_flags(&self) -> u16 {
+        TYPE_FLAG_NAMES
+            .iter()
+            .filter(|&&(flag, name)| {
+                flag != TYPEFLAG_FCANCREATE && flag != TYPEFLAG_FDISPATCHABLE && self.has(name)
+            })
+            .fold(0, |flags, (flag, _)| flags | flag)
+    }
+
+    fn func_flags(&self) -> u16 {
+        FUNC_FLAG_ATTRIBUTES
+            .iter()
+            .filter(|(_, name)| self.has(name))
+            .fold(0, |flags, (flag, _)| flags | flag)
+    }
+
+    /// `VARFLAG_*` bits, which share the `FUNCFLAG_*` ones but for
+    /// `readonly` and `restricted`.
+    fn var_flags(&self) -> u16 {
+        let mut flags = self.func_flags() & !(FUNCFLAG_FRESTRICTED | FUNCFLAG_FUSESGETLASTERROR);
+        if self.has("restricted") {
+            flags |= VARFLAG_FRESTRICTED;
+        }
+        if self.has("readonly") {
+            flags |= VARFLAG_FREADONLY;
+        }
+        flags
+    }
+
+    fn param_flags(&self) -> u16 {
+        [
+            (PARAMFLAG_FIN, "in"),
+            (PARAMFLAG_FOUT, "out"),
+            (PARAMFLAG_FLCID, "lcid"),
+            (PARAMFLAG_FRETVAL, "retval"),
+            (PARAMFLAG_FOPT, "optional"),
+            (PARAMFLAG_FHASDEFAULT, "defaultvalue"),
+        ]
+        .iter()
+        .filter(|(_, name)| self.has(name))
+        .fold(0, |flags, (flag, _)| flags | flag)
+    }
+
+    fn impl_flags(&self) -> i32 {
+        [
+            (IMPLTYPEFLAG_FDEFAULT, "default"),
+            (IMPLTYPEFLAG_FSOURCE, "source"),
+            (IMPLTYPEFLAG_FRESTRICTED, "restricted"),
+            (IMPLTYPEFLAG_FDEFAULTVTABLE, "defaultvtable"),
+        ]
+        .iter()
+        .filter(|(_, name)| self.has(name))
+        .fold(0, |flags, (flag, _)| flags | flag)
+    }
+
+    fn entry(&self) -> Option<DllEntry> {
+        match self.value("entry")? {
+            Value::Str(name) => Some(DllEntry::Name(name.clone())),
+            Value::Int(ordinal) => Some(DllEntry::Ordinal(*ordinal as u16)),
+            _ => None,
+        }
+    }
+}
+
+struct Parser<'a> {
+    lexer: Lexer<'a>,
+    peeked: Option<Token>,
+    /// The `#pragma pack` stack; the last entry is in effect.
+    pack: Vec<u32>,
+    /// Packing in effect for each type, by index.
+    packing: Vec<u32>,
+    /// Values of the enum members and integer constants defined so far.
+    constants: HashMap<String, i128>,
+    /// Every type declared, in order, and whether it is declared in the
+    /// library block.
+    types: Vec<TypeDef>,
+    inside: Vec<bool>,
+    in_library: bool,
+    /// Types declared outside the library block and named by a forward
+    /// declaration inside it.
+    mentioned: Vec<String>,
+    /// Files of `import` directives.
+    imports: Vec<String>,
+}
+
+impl<'a> Parser<'a> {
+    fn new(source: &'a str) -> Self {
+        Parser {
+            lexer: Lexer::new(source),
+            peeked: None,
+            pack: vec![8],
+            packing: Vec::new(),
+            constants: DISPIDS
+                .iter()
+                .map(|&(name, dispid)| (name.to_string(), dispid as i128))
+                .collect(),
+            types: Vec::new(),
+            inside: Vec::new(),
+            in_library: false,
+            mentioned: Vec::new(),
+            imports: Vec::new(),
+        }
+    }
+
+    fn error(&self, message: impl Into<String>) -> Error {
+        self.lexer.error(message)
+    }
+
+    /// The next token, with preprocessor lines handled.
+    fn read(&mut self) -> Result<Token, Error> {
+        loop {
+            match self.lexer.next()? {
+                Token::Directive(text) => self.directive(&text),
+                token => return Ok(token),
+            }
+        }
+    }
+
+    fn peek(&mut self) -> Result<&Token, Error> {
+        let token = match self.peeked.take() {
+            Some(token) => token,
+            None => self.read()?,
+        };
+        Ok(self.peeked.insert(token))
+    }
+
+    fn next(&mut self) -> Result<Token, Error> {
+        match self.peeked.take() {
+            Some(token) => Ok(token),
+            None => self.read(),
+        }
+    }
+
+    fn is(&mut self, punct: &str) -> Result<bool, Error> {
+        Ok(matches!(self.peek()?, Token::Punct(p) if *p == punct))
+    }
+
+    fn eat(&mut self, punct: &str) -> Result<bool, Error> {
+        let found = self.is(punct)?;
+        if found {
+            self.next()?;
+        }
+        Ok(found)
+    }
+
+    fn expect(&mut self, punct: &str) -> Result<(), Error> {
+        if self.eat(punct)? {
+            return Ok(());
+        }
+        let found = self.peek()?.describe();
+        Err(self.error(format!("expected `{}`, found {}", punct, found)))
+    }
+
+    fn is_keyword(&mut self, keyword: &str) -> Result<bool, Error> {
+        Ok(matches!(self.peek()?, Token::Ident(s) if s == keyword))
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> Result<bool, Error> {
+        let found = self.is_keyword(keyword)?;
+        if found {
+            self.next()?;
+        }
+        Ok(found)
+    }
+
+    fn ident(&mut self) -> Result<String, Error> {
+        match self.next()? {
+            Token::Ident(name) => Ok(name),
+            token => Err(self.error(format!("expected a name, found {}", token.describe()))),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, Error> {
+        match self.next()? {
+            Token::Str(s) => Ok(s),
+            token => Err(self.error(format!("expected a string, found {}", token.describe()))),
+        }
+    }
+
+    /// Handles `#pragma pack`; other preprocessor lines are ignored.
+    fn directive(&mut self, text: &str) {
+        let Some(args) = text
+            .strip_prefix("pragma")
+            .and_then(|rest| rest.trim_start().strip_prefix("pack"))
+        else {
+            return;
+        };
+        let args: Vec<&str> = args
+            .trim()
+            .trim_start_matches('(')
+            .trim_end_matches(')')
+            .split(',')
+            .map(str::trim)
+            .filter(|arg| !arg.is_empty())
+            .collect();
+        let current = self.pack.last().copied().unwrap_or(8);
+        match args.as_slice() {
+            [] => self.set_pack(8),
+            ["push"] => self.pack.push(current),
+            ["push", n] => self.pack.push(n.parse().unwrap_or(current)),
+            ["pop", ..] if self.pack.len() > 1 => {
+                self.pack.pop();
+            }
+            [n] => {
+                if let Ok(n) = n.parse() {
+                    self.set_pack(n);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn set_pack(&mut self, pack: u32) {
+        if let Some(current) = self.pack.last_mut() {
+            *current = pack;
+        }
+    }
+
+    /// The whole source. Declarations outside the library block are kept
+    /// for the types it refers to; a file without a library block, such as
+    /// an imported one, only declares.
+    fn file(&mut self) -> Result<Option<Library>, Error> {
+        let mut lib = None;
+        while *self.peek()? != Token::Eof {
+            if self.eat(";")? {
+                continue;
+            }
+            let attrs = self.attributes()?;
+            let keyword = self.ident()?;
+            match keyword.as_str() {
+                "library" if lib.is_some() => {
+                    return Err(self.error("more than one library block"));
+                }
+                "library" => lib = Some(self.library(&attrs)?),
+                "import" => {
+                    loop {
+                        let file_name = self.string()?;
+                        self.imports.push(file_name);
+                        if !self.eat(",")? {
+                            break;
+                        }
+                    }
+                    self.expect(";")?;
+                }
+                _ => self.declaration(&keyword, attrs)?,
+            }
+        }
+        Ok(lib)
+    }
+
+    fn library(&mut self, attrs: &Attributes) -> Result<Library, Error> {
+        let mut lib = Library {
+            name: self.ident()?,
+            guid: attrs.guid(),
+            version: attrs.version(),
+            lcid: attrs.int("lcid").unwrap_or(0) as u32,
+            syskind: SYS_WIN32,
+            help_file: attrs.string("helpfile").unwrap_or_default(),
+            doc: attrs.doc(),
+            custom_data: attrs.custom_data(),
+            ..Library::default()
+        };
+        self.expect("{")?;
+        self.in_library = true;
+        while !self.eat("}")? {
+            if self.eat(";")? {
+                continue;
+            }
+            let attrs = self.attributes()?;
+            let keyword = self.ident()?;
+            if keyword == "importlib" {
+                self.expect("(")?;
+                let file_name = self.string()?;
+                self.expect(")")?;
+                self.expect(";")?;
+                lib.imports.push(import(&file_name));
+            } else {
+                self.declaration(&keyword, attrs)?;
+            }
+        }
+        self.in_library = false;
+        self.eat(";")?;
+        Ok(lib)
+    }
+
+    /// A declaration after its attributes and first keyword, in the library
+    /// block or outside it.
+    fn declaration(&mut self, keyword: &str, attrs: Attributes) -> Result<(), Error> {
+        match keyword {
+            "interface" | "dispinterface" | "coclass" => {
+                let name = self.ident()?;
+                // A forward declaration, which brings a type declared
+                // outside into the library.
+                if self.eat(";")? {
+                    if self.in_library {
+                        self.mentioned.push(name);
+                    }
+                    return Ok(());
+                }
+                let ty = match keyword {
+                    "interface" => self.interface(&name, &attrs)?,
+                    "dispinterface" => self.dispinterface(&name, &attrs)?,
+                    _ => self.coclass(&name, &attrs)?,
+                };
+                self.add(ty)
+            }
+            "module" => {
+                let name = self.ident()?;
+                let ty = self.module(&name, &attrs)?;
+                self.add(ty)
+            }
+            "enum" | "struct" | "union" => {
+                let name = self.ident()?;
+                let ty = self.definition(keyword, &name, &attrs)?;
+                self.expect(";")?;
+                self.add(ty)
+            }
+            "typedef" => self.typedef(attrs),
+            "const" => self.constant().map(drop),
+            // Text for the C header, and MIDL warnings.
+            "cpp_quote" | "midl_pragma" => {
+                // `midl_pragma warning (disable: 2111)`
+                if keyword == "midl_pragma" {
+                    self.eat_keyword("warning")?;
+                }
+                self.expect("(")?;
+                self.skip_group()?;
+                self.eat(";")?;
+                Ok(())
+            }
+            _ => Err(self.error(format!("unexpected `{}`", keyword))),
+        }
+    }
+
+    /// `type name = value;` after `const`; integer values become known to
+    /// later expressions.
+    fn constant(&mut self) -> Result<(String, Type, Value), Error> {
+        let ty = self.type_spec()?;
+        let name = self.ident()?;
+        self.expect("=")?;
+        let value = self.expr()?;
+        self.expect(";")?;
+        if let Value::Int(v) = value {
+            self.constants.insert(name.clone(), v);
+        }
+        Ok((name, ty, value))
+    }
+
+    /// Adds a type, recording the packing in effect and whether it is
+    /// declared in the library block.
+    fn add(&mut self, ty: TypeDef) -> Result<(), Error> {
+        if self.types.iter().any(|t| t.name == ty.name) {
+            return Err(self.error(format!("{} is defined twice", ty.name)));
+        }
+        self.types.push(ty);
+        self.packing.push(self.pack.last().copied().unwrap_or(8));
+        self.inside.push(self.in_library);
+        Ok(())
+    }
+
+    /// Reads the declarations of the files `import`ed from `dir`, and of
+    /// those they import in turn. Files that are not there are skipped.
+    fn import_files(&mut self, dir: &Path) -> Result<(), Error> {
+        let mut done = HashSet::new();
+        while let Some(file_name) = self.imports.pop() {
+            let path = dir.join(&file_name);
+            if !done.insert(file_name.clone()) || !path.is_file() {
+                continue;
+            }
+            let source = read_source(&path)?;
+            let mut imported = Parser::new(&source);
+            imported.constants = std::mem::take(&mut self.constants);
+            imported.file().map_err(|e| match e {
+                Error::InvalidIdl { line, message } => Error::InvalidIdl {
+                    line,
+                    message: format!("{}: {}", file_name, message),
+                },
+                e => e,
+            })?;
+            self.constants = imported.constants;
+            for (ty, pack) in imported.types.into_iter().zip(imported.packing) {
+                if !self.types.iter().any(|t| t.name == ty.name) {
+                    self.types.push(ty);
+                    self.packing.push(pack);
+                    self.inside.push(false);
+                }
+            }
+            self.imports.extend(imported.imports);
+        }
+        Ok(())
+    }
+
+    /// The types of the library block and, as in MIDL, those declared
+    /// outside it that it refers to, in the order they are declared; with
+    /// the packing of each.
+    fn library_types(&mut self) -> (Vec<TypeDef>, Vec<u32>) {
+        let mut keep = self.inside.clone();
+        let mut wanted = std::mem::take(&mut self.mentioned);
+        for (ty, _) in self.types.iter().zip(&keep).filter(|(_, inside)| **inside) {
+            wanted.extend(referenced(ty));
+        }
+        while let Some(name) = wanted.pop() {
+            if let Some(index) = self.types.iter().position(|t| t.name == name)
+                && !keep[index]
+            {
+                keep[index] = true;
+                wanted.extend(referenced(&self.types[index]));
+            }
+        }
+        let types = std::mem::take(&mut self.types);
+        let packing = std::mem::take(&mut self.packing);
+        types
+            .into_iter()
+            .zip(packing)
+            .zip(keep)
+            .filter(|(_, keep)| *keep)
+            .map(|(declared, _)| declared)
+            .unzip()
+    }
+
+    /// Attribute lists in front of a declaration; consecutive lists add up.
+    fn attributes(&mut self) -> Result<Attributes, Error> {
+        let mut attributes = Attributes::default();
+        while self.eat("[")? {
+            if self.eat("]")? {
+                continue;
+            }
+            loop {
+                let name = self.ident()?;
+                let mut args = Vec::new();
+                if self.eat("(")? {
+                    match name.as_str() {
+                        "uuid" => args.push(Value::Guid(self.lexer.guid()?)),
+                        "custom" => {
+                            args.push(Value::Guid(self.lexer.guid()?));
+                            self.expect(",")?;
+                            args.push(self.expr()?);
+                        }
+                        name if VALUED_ATTRIBUTES.contains(&name) => loop {
+                            args.push(self.expr()?);
+                            if !self.eat(",")? {
+                                break;
+                            }
+                        },
+                        _ => {
+                            self.skip_group()?;
+                            attributes.0.push(Attribute { name, args });
+                            if !self.eat(",")? || self.is("]")? {
+                                break;
+                            }
+                            continue;
+                        }
+                    }
+                    self.expect(")")?;
+                }
+                attributes.0.push(Attribute { name, args });
+                // MIDL takes a comma before the `]`.
+                if !self.eat(",")? || self.is("]")? {
+                    break;
+                }
+            }
+            self.expect("]")?;
+        }
+        Ok(attributes)
+    }
+
+    /// Skips up to the `)` closing a `(` already read.
+    fn skip_group(&mut self) -> Result<(), Error> {
+        let mut depth = 1;
+        while depth > 0 {
+            match self.next()? {
+                Token::Punct("(") => depth += 1,
+                Token::Punct(")") => depth -= 1,
+                Token::Eof => return Err(self.error("unterminated `(`")),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// A constant expression: enum values, `const` initializers and
+    /// attribute arguments.
+    fn expr(&mut self) -> Result<Value, Error> {
+        self.binary(0)
+    }
+
+    fn binary(&mut self, level: usize) -> Result<Value, Error> {
+        const LEVELS: [&[&str]; 6] = [
+            &["|"],
+            &["^"],
+            &["&"],
+            &["<<", ">>"],
+            &["+", "-"],
+            &["*", "/", "%"],
+        ];
+        if level == LEVELS.len() {
+            return self.unary();
+        }
+        let mut left = self.binary(level + 1)?;
+        loop {
+            let op = match self.peek()? {
+                Token::Punct(p) if LEVELS[level].contains(p) => *p,
+                _ => return Ok(left),
+            };
+            self.next()?;
+            let right = self.binary(level + 1)?;
+            let (Value::Int(l), Value::Int(r)) = (&left, &right) else {
+                return Err(self.error(format!("`{}` needs integer operands", op)));
+            };
+            let (l, r) = (*l, *r);
+            left = Value::Int(match op {
+                "|" => l | r,
+                "^" => l ^ r,
+                "&" => l & r,
+                "<<" => l.checked_shl(r as u32).unwrap_or(0),
+                ">>" => l.checked_shr(r as u32).unwrap_or(0),
+                "+" => l.wrapping_add(r),
+                "-" => l.wrapping_sub(r),
+                "*" => l.wrapping_mul(r),
+                _ if r == 0 => return Err(self.error("division by zero")),
+                "/" => l / r,
+                _ => l % r,
+            });
+        }
+    }
+
+    fn unary(&mut self) -> Result<Value, Error> {
+        if self.eat("-")? {
+            return match self.unary()? {
+                Value::Int(v) => Ok(Value::Int(-v)),
+                Value::Float(v, text) => Ok(Value::Float(-v, format!("-{}", text))),
+                _ => Err(self.error("`-` needs a number")),
+            };
+        }
+        if self.eat("+")? {
+            return self.unary();
+        }
+        if self.eat("~")? {
+            return match self.unary()? {
+                Value::Int(v) => Ok(Value::Int(!v)),
+                _ => Err(self.error("`~` needs an integer")),
+            };
+        }
+        if self.eat("!")? {
+            return match self.unary()? {
+                Value::Int(v) => Ok(Value::Int((v == 0) as i128)),
+                Value::Bool(b) => Ok(Value::Bool(!b)),
+                _ => Err(self.error("`!` needs an integer")),
+            };
+        }
+        if self.eat("(")? {
+            let value = self.expr()?;
+            self.expect(")")?;
+            return Ok(value);
+        }
+        match self.next()? {
+            Token::Number(text) => {
+                number(&text).ok_or_else(|| self.error(format!("invalid number `{}`", text)))
+            }
+            Token::Str(s) => Ok(Value::Str(s)),
+            Token::Ident(name) => match name.as_str() {
+                "TRUE" | "true" => Ok(Value::Bool(true)),
+                "FALSE" | "false" => Ok(Value::Bool(false)),
+                "NULL" => Ok(Value::Int(0)),
+                _ => match self.constants.get(&name) {
+                    Some(value) => Ok(Value::Int(*value)),
+                    None => Err(self.error(format!("unknown constant {}", name))),
+                },
+            },
+            token => Err(self.error(format!("expected a value, found {}", token.describe()))),
+        }
+    }
+
+    /// A type up to its declarator: a base or named type and its pointers.
+    fn type_spec(&mut self) -> Result<Type, Error> {
+        let mut ty = self.base_type()?;
+        while self.eat("*")? {
+            ty = Type::Ptr(Box::new(ty));
+        }
+        Ok(ty)
+    }
+
+    fn base_type(&mut self) -> Result<Type, Error> {
+        let mut word = self.ident()?;
+        while word == "const" {
+            word = self.ident()?;
+        }
+        match word.as_str() {
+            "struct" | "union" | "enum" => {
+                let mut ty = named(self.ident()?);
+                if let Type::Named(r) = &mut ty {
+                    r.kind = Some(defined_kind(&word));
+                }
+                return Ok(ty);
+            }
+            "SAFEARRAY" => {
+                self.expect("(")?;
+                let elem = self.type_spec()?;
+                self.expect(")")?;
+                return Ok(Type::SafeArray(Box::new(elem)));
+            }
+            "unsigned" => {
+                word = "unsigned int".to_string();
+                for size in ["char", "short", "long", "int", "small", "hyper", "__int64"] {
+                    if self.eat_keyword(size)? {
+                        word = format!("unsigned {}", size);
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+        // `short int`, `long int`.
+        if matches!(
+            word.as_str(),
+            "short" | "long" | "unsigned short" | "unsigned long"
+        ) {
+            self.eat_keyword("int")?;
+        }
+        // VARTYPEs the printer has no name for.
+        if let Some(vt) = word.strip_prefix("TYPE_").and_then(|vt| vt.parse().ok()) {
+            return Ok(Type::Base(vt));
+        }
+        // `IDispatch*` and the like name a VARTYPE of their own.
+        if self.is("*")?
+            && let Some(vt) = base_type(&format!("{}*", word))
+        {
+            self.next()?;
+            return Ok(Type::Base(vt));
+        }
+        let synonym = || {
+            SYNONYMS
+                .iter()
+                .find(|(name, _)| *name == word)
+                .map(|&(_, vt)| vt)
+        };
+        Ok(match base_type(&word).or_else(synonym) {
+            Some(vt) => Type::Base(vt),
+            None => named(word),
+        })
+    }
+
+    /// The name of a declaration, with the pointers before it and the
+    /// dimensions after it that turn `ty` into a fixed-size array. The name
+    /// is optional, as for parameters.
+    fn declarator(&mut self, mut ty: Type) -> Result<(Option<String>, Type), Error> {
+        while self.eat("*")? {
+            ty = Type::Ptr(Box::new(ty));
+        }
+        let name = match self.peek()? {
+            Token::Ident(_) => Some(self.ident()?),
+            _ => None,
+        };
+        let mut bounds = Vec::new();
+        while self.eat("[")? {
+            // A conformant array is passed as a pointer.
+            if self.eat("]")? {
+                ty = Type::Ptr(Box::new(ty));
+                continue;
+            }
+            match self.expr()? {
+                Value::Int(count) => bounds.push((count as u32, 0)),
+                _ => return Err(self.error("array size is not an integer")),
+            }
+            self.expect("]")?;
+        }
+        if !bounds.is_empty() {
+            ty = Type::CArray {
+                elem: Box::new(ty),
+                bounds,
+            };
+        }
+        Ok((name, ty))
+    }
+
+    fn call_conv(&mut self) -> Result<Option<CallConv>, Error> {
+        let call_conv = match self.peek()? {
+            Token::Ident(word) => match word.trim_start_matches('_') {
+                "stdcall" | "STDMETHODCALLTYPE" | "WINAPI" => CallConv::StdCall,
+                "cdecl" => CallConv::CDecl,
+                "pascal" => CallConv::Pascal,
+                "fastcall" => CallConv::FastCall,
+                _ => return Ok(None),
+            },
+            _ => return Ok(None),
+        };
+        self.next()?;
+        Ok(Some(call_conv))
+    }
+
+    /// A method or module function after its attributes.
+    fn function(&mut self, attrs: &Attributes, func_kind: FuncKind) -> Result<Function, Error> {
+        let ret = self.type_spec()?;
+        let call_conv = self.call_conv()?.unwrap_or(CallConv::StdCall);
+        let name = self.ident()?;
+        let params = self.params()?;
+        self.expect(";")?;
+        let invoke_kind = if attrs.has("propget") {
+            InvokeKind::PropertyGet
+        } else if attrs.has("propput") {
+            InvokeKind::PropertyPut
+        } else if attrs.has("propputref") {
+            InvokeKind::PropertyPutRef
+        } else {
+            InvokeKind::Func
+        };
+        // Trailing optional parameters without a default.
+        let optional_params = if attrs.has("vararg") {
+            -1
+        } else {
+            params
+                .iter()
+                .rev()
+                .take_while(|p| {
+                    p.flags & (PARAMFLAG_FOPT | PARAMFLAG_FHASDEFAULT) == PARAMFLAG_FOPT
+                })
+                .count() as i16
+        };
+        Ok(Function {
+            name,
+            memid: attrs.int("id").map_or(AUTO_ID, |id| id as i32),
+            invoke_kind,
+            func_kind,
+            call_conv,
+            flags: attrs.func_flags(),
+            vtable_offset: 0,
+            params,
+            optional_params,
+            ret,
+            entry: attrs.entry(),
+            doc: attrs.doc(),
+            custom_data: attrs.custom_data(),
+        })
+    }
+
+    fn params(&mut self) -> Result<Vec<Param>, Error> {
+        self.expect("(")?;
+        let mut params = Vec::new();
+        if self.eat(")")? {
+            return Ok(params);
+        }
+        loop {
+            let attrs = self.attributes()?;
+            let ty = self.type_spec()?;
+            if params.is_empty() && ty == Type::Base(VT_VOID) && self.eat(")")? {
+                return Ok(params);
+            }
+            let (name, ty) = self.declarator(ty)?;
+            params.push(Param {
+                name,
+                flags: attrs.param_flags(),
+                default_value: attrs.value("defaultvalue").map(|v| variant(v, &ty)),
+                custom_data: attrs.custom_data(),
+                ty,
+            });
+            if !self.eat(",")? {
+                break;
+            }
+        }
+        self.expect(")")?;
+        Ok(params)
+    }
+
+    fn interface(&mut self, name: &str, attrs: &Attributes) -> Result<TypeDef, Error> {
+        let base = if self.eat(":")? {
+            Some(self.ident()?)
+        } else {
+            None
+        };
+        self.expect("{")?;
+        let mut funcs = Vec::new();
+        while !self.eat("}")? {
+            if self.eat(";")? {
+                continue;
+            }
+            let attrs = self.attributes()?;
+            funcs.push(self.function(&attrs, FuncKind::PureVirtual)?);
+        }
+        self.eat(";")?;
+        // The library keeps the dispatch half of a dual interface.
+        let dual = attrs.has("dual");
+        let kind = if dual {
+            TypeKind::Dispatch
+        } else {
+            TypeKind::Interface
+        };
+        let mut ty = type_def(name, kind, attrs);
+        if dual {
+            ty.flags |= TYPEFLAG_FOLEAUTOMATION;
+        }
+        ty.funcs = funcs;
+        ty.impl_types = base.into_iter().map(implemented).collect();
+        Ok(ty)
+    }
+
+    fn coclass(&mut self, name: &str, attrs: &Attributes) -> Result<TypeDef, Error> {
+        let mut ty = type_def(name, TypeKind::CoClass, attrs);
+        if !attrs.has("noncreatable") {
+            ty.flags |= TYPEFLAG_FCANCREATE;
+        }
+        self.expect("{")?;
+        while !self.eat("}")? {
+            let attrs = self.attributes()?;
+            if !self.eat_keyword("interface")? && !self.eat_keyword("dispinterface")? {
+                let found = self.peek()?.describe();
+                return Err(self.error(format!("expected an interface, found {}", found)));
+            }
+            let mut implemented = implemented(self.ident()?);
+            implemented.flags = attrs.impl_flags();
+            implemented.custom_data = attrs.custom_data();
+            ty.impl_types.push(implemented);
+            self.expect(";")?;
+        }
+        self.eat(";")?;
+        Ok(ty)
+    }
+
+    /// `properties:` and `methods:` sections, or `interface I;` for the
+    /// methods of an interface declared before.
+    fn dispinterface(&mut self, name: &str, attrs: &Attributes) -> Result<TypeDef, Error> {
+        let mut ty = type_def(name, TypeKind::Dispatch, attrs);
+        ty.impl_types.push(implemented("IDispatch".to_string()));
+        self.expect("{")?;
+        let mut properties = false;
+        while !self.eat("}")? {
+            for (section, is_properties) in [("properties", true), ("methods", false)] {
+                if self.is_keyword(section)? {
+                    self.next()?;
+                    self.expect(":")?;
+                    properties = is_properties;
+                }
+            }
+            if self.is("}")? {
+                continue;
+            }
+            let attrs = self.attributes()?;
+            if self.eat_keyword("interface")? {
+                let interface = self.ident()?;
+                self.expect(";")?;
+                let Some(def) = self.types.iter().find(|t| t.name == interface) else {
+                    return Err(self.error(format!(
+                        "interface {} is not declared before {}",
+                        interface, name
+                    )));
+                };
+                ty.funcs.extend(def.funcs.iter().map(|func| Function {
+                    func_kind: FuncKind::Dispatch,
+                    ..func.clone()
+                }));
+            } else if properties {
+                let var_ty = self.type_spec()?;
+                let (var_name, var_ty) = self.declarator(var_ty)?;
+                let var_name = var_name.ok_or_else(|| self.error("property without a name"))?;
+                self.expect(";")?;
+                ty.vars.push(Variable {
+                    name: var_name,
+                    memid: attrs.int("id").map_or(AUTO_ID, |id| id as i32),
+                    kind: VarKind::Dispatch,
+                    flags: attrs.var_flags(),
+                    ty: var_ty,
+                    value: None,
+                    offset: 0,
+                    doc: attrs.doc(),
+                    custom_data: attrs.custom_data(),
+                });
+            } else {
+                ty.funcs.push(self.function(&attrs, FuncKind::Dispatch)?);
+            }
+        }
+        self.eat(";")?;
+        Ok(ty)
+    }
+
+    fn module(&mut self, name: &str, attrs: &Attributes) -> Result<TypeDef, Error> {
+        let mut ty = type_def(name, TypeKind::Module, attrs);
+        ty.dll_name = attrs.string("dllname");
+        self.expect("{")?;
+        while !self.eat("}")? {
+            let attrs = self.attributes()?;
+            if self.eat_keyword("const")? {
+                let (name, const_ty, value) = self.constant()?;
+                ty.vars.push(Variable {
+                    name,
+                    memid: 0x4000_0000 + ty.vars.len() as i32,
+                    kind: VarKind::Const,
+                    flags: attrs.var_flags(),
+                    value: Some(variant(&value, &const_ty)),
+                    ty: const_ty,
+                    offset: 0,
+                    doc: attrs.doc(),
+                    custom_data: attrs.custom_data(),
+                });
+            } else {
+                let mut func = self.function(&attrs, FuncKind::Static)?;
+                let index = ty.funcs.len() as i32;
+                if func.memid == AUTO_ID {
+                    func.memid = 0x6000_0000 + index;
+                }
+                func.vtable_offset = (index * 4) as i16;
+                ty.funcs.push(func);
+            }
+        }
+        self.eat(";")?;
+        Ok(ty)
+    }
+
+    /// The body of an `enum`, `struct` or `union` named `name`.
+    fn definition(
+        &mut self,
+        keyword: &str,
+        name: &str,
+        attrs: &Attributes,
+    ) -> Result<TypeDef, Error> {
+        let kind = defined_kind(keyword);
+        let mut ty = type_def(name, kind, attrs);
+        ty.vars = if kind == TypeKind::Enum {
+            self.enum_values()?
+        } else {
+            self.fields()?
+        };
+        Ok(ty)
+    }
+
+    fn enum_values(&mut self) -> Result<Vec<Variable>, Error> {
+        self.expect("{")?;
+        let mut vars = Vec::new();
+        let mut next = 0;
+        while !self.eat("}")? {
+            let attrs = self.attributes()?;
+            let name = self.ident()?;
+            if self.eat("=")? {
+                next = match self.expr()? {
+                    Value::Int(v) => v,
+                    _ => return Err(self.error(format!("value of {} is not an integer", name))),
+                };
+            }
+            self.constants.insert(name.clone(), next);
+            vars.push(Variable {
+                name,
+                memid: 0x4000_0000 + vars.len() as i32,
+                kind: VarKind::Const,
+                flags: attrs.var_flags(),
+                ty: Type::Base(VT_I4),
+                value: Some(Variant::I4(next as i32)),
+                offset: 0,
+                doc: attrs.doc(),
+                custom_data: attrs.custom_data(),
+            });
+            next += 1;
+            if !self.eat(",")? {
+                self.expect("}")?;
+                break;
+            }
+        }
+        Ok(vars)
+    }
+
+    fn fields(&mut self) -> Result<Vec<Variable>, Error> {
+        self.expect("{")?;
+        let mut vars = Vec::new();
+        while !self.eat("}")? {
+            let attrs = self.attributes()?;
+            let ty = self.type_spec()?;
+            loop {
+                let (name, ty) = self.declarator(ty.clone())?;
+                let name = name.ok_or_else(|| self.error("field without a name"))?;
+                vars.push(Variable {
+                    name,
+                    memid: 0x4000_0000 + vars.len() as i32,
+                    kind: VarKind::PerInstance,
+                    flags: attrs.var_flags(),
+                    ty,
+                    value: None,
+                    offset: 0,
+                    doc: attrs.doc(),
+                    custom_data: attrs.custom_data(),
+                });
+                if !self.eat(",")? {
+                    break;
+                }
+            }
+            self.expect(";")?;
+        }
+        Ok(vars)
+    }
+
+    /// `typedef [attributes] type name, *pointer_name;`, where the type may
+    /// define an enum, struct or union named after the first name.
+    fn typedef(&mut self, mut attrs: Attributes) -> Result<(), Error> {
+        attrs.0.extend(self.attributes()?.0);
+        let keyword = match self.peek()? {
+            Token::Ident(word) if matches!(word.as_str(), "enum" | "struct" | "union") => {
+                word.clone()
+            }
+            _ => String::new(),
+        };
+        let mut defined = None;
+        let mut base = if keyword.is_empty() {
+            self.type_spec()?
+        } else {
+            self.next()?;
+            let tag = match self.peek()? {
+                Token::Ident(_) => Some(self.ident()?),
+                _ => None,
+            };
+            if self.is("{")? {
+                // Named after the typedef rather than the tag, as in MIDL.
+                defined = Some(self.definition(&keyword, "", &attrs)?);
+                named(String::new())
+            } else {
+                let mut ty = named(tag.ok_or_else(|| self.error("expected a name"))?);
+                if let Type::Named(r) = &mut ty {
+                    r.kind = Some(defined_kind(&keyword));
+                }
+                ty
+            }
+        };
+        loop {
+            let (name, ty) = self.declarator(base.clone())?;
+            let name = name.ok_or_else(|| self.error("typedef without a name"))?;
+            match defined.take() {
+                // The definition takes the first name, and the names after
+                // it refer to the definition.
+                Some(mut def) if ty == base => {
+                    def.name = name.clone();
+                    self.add(def)?;
+                    base = named(name);
+                }
+                Some(_) => return Err(self.error(format!("{} is not a plain name", name))),
+                None => {
+                    let mut alias = type_def(&name, TypeKind::Alias, &attrs);
+                    alias.alias_of = Some(ty);
+                    self.add(alias)?;
+                }
+            }
+            if !self.eat(",")? {
+                break;
+            }
+        }
+        self.expect(";")
+    }
+}
+
+/// The kind of type `enum`, `struct` or `union` defines.
+fn defined_kind(keyword: &str) -> TypeKind {
+    match keyword {
+        "enum" => TypeKind::Enum,
+        "union" => TypeKind::Union,
+        _ => TypeKind::Record,
+    }
+}
+
+/// The VARTYPE the printer spells `spelling`.
+fn base_type(spelling: &str) -> Option<u16> {
+    (0..=VT_CLSID).find(|&vt| base_type_name(vt) == Some(spelling))
+}
+
+fn named(name: String) -> Type {
+    Type::Named(TypeRef {
+        name,
+        guid: None,
+        kind: None,
+        location: TypeLocation::Unresolved,
+        index: None,
+    })
+}
+
+fn implemented(name: String) -> ImplType {
+    let Type::Named(target) = named(name) else {
+        unreachable!()
+    };
+    ImplType {
+        target,
+        flags: 0,
+        custom_data: Vec::new(),
+    }
+}
+
+fn type_def(name: &str, kind: TypeKind, attrs: &Attributes) -> TypeDef {
+    let mut ty = TypeDef::new(name, kind, attrs.guid());
+    ty.flags = attrs.type_flags();
+    ty.version = attrs.version();
+    ty.doc = attrs.doc();
+    ty.custom_data = attrs.custom_data();
+    ty
+}
+
+fn is_stdole(file_name: &str) -> bool {
+    file_name.eq_ignore_ascii_case("stdole2.tlb") || file_name.eq_ignore_ascii_case("stdole32.tlb")
+}
+
+/// An `importlib`; only stdole2.tlb is known without opening it.
+fn import(file_name: &str) -> Import {
+    let (guid, version) = if is_stdole(file_name) {
+        (STDOLE2, (2, 0))
+    } else {
+        (Guid::default(), (0, 0))
+    };
+    Import {
+        guid,
+        version,
+        lcid: 0,
+        file_name: file_name.to_string(),
+    }
+}
+
+fn number(text: &str) -> Option<Value> {
+    let lower = text.to_ascii_lowercase();
+    if let Some(hex) = lower.strip_prefix("0x") {
+        return i128::from_str_radix(hex.trim_end_matches(['u', 'l']), 16)
+            .ok()
+            .map(Value::Int);
+    }
+    if lower.contains(['.', 'e']) {
+        let digits = lower.trim_end_matches(['f', 'l']);
+        return digits
+            .parse()
+            .ok()
+            .map(|v| Value::Float(v, digits.to_string()));
+    }
+    let digits = lower.trim_end_matches(['u', 'l']);
+    match digits.strip_prefix('0') {
+        Some(octal) if !octal.is_empty() => i128::from_str_radix(octal, 8).ok().map(Value::Int),
+        _ => digits.parse().ok().map(Value::Int),
+    }
+}
+
+/// `text` as a scaled integer with `scale` decimal places, truncated.
+fn scaled(text: &str, scale: u32) -> Option<i128> {
+    if text.contains(['e', 'E']) {
+        return None;
+    }
+    let (int, frac) = text.split_once('.').unwrap_or((text, ""));
+    let negative = int.starts_with('-');
+    let frac: String = frac
+        .chars()
+        .chain(std::iter::repeat('0'))
+        .take(scale as usize)
+        .collect();
+    let magnitude: i128 = format!("{}{}", int.trim_start_matches('-'), frac)
+        .parse()
+        .ok()?;
+    Some(if negative { -magnitude } else { magnitude })
+}
+
+/// The integer as the narrowest of `VT_I4`, `VT_I8` and `VT_UI8`.
+fn int_variant(v: i128) -> Variant {
+    if let Ok(v) = i32::try_from(v) {
+        Variant::I4(v)
+    } else if let Ok(v) = i64::try_from(v) {
+        Variant::I8(v)
+    } else {
+        Variant::UI8(v as u64)
+    }
+}
+
+/// A literal as stored for a constant or default value of type `ty`;
+/// for types that say nothing about it, the literal keeps its own type.
+fn variant(value: &Value, ty: &Type) -> Variant {
+    let vt = match ty {
+        Type::Base(vt) => *vt,
+        Type::Named(r) if r.kind == Some(TypeKind::Enum) => VT_I4,
+        _ => VT_VARIANT,
+    };
+    match value {
+        Value::Int(v) => match vt {
+            VT_BOOL => Variant::Bool(*v != 0),
+            VT_CY => Variant::Cy((*v * 10_000) as i64),
+            VT_DECIMAL => Variant::Decimal(*v, 0),
+            VT_DATE => Variant::Date(*v as f64),
+            _ => {
+                let natural = int_variant(*v);
+                natural.convert(vt).unwrap_or(natural)
+            }
+        },
+        Value::Float(v, text) => match vt {
+            VT_R4 => Variant::R4(*v as f32),
+            VT_DATE => Variant::Date(*v),
+            VT_CY => Variant::Cy(scaled(text, 4).unwrap_or((v * 10_000.0) as i128) as i64),
+            VT_DECIMAL => {
+                let scale = text
+                    .split_once('.')
+                    .map_or(0, |(_, frac)| frac.len() as u32);
+                match scaled(text, scale) {
+                    Some(mantissa) => Variant::Decimal(mantissa, scale as u8),
+                    None => Variant::R8(*v),
+                }
+            }
+            _ => Variant::R8(*v),
+        },
+        Value::Str(s) => Variant::Bstr(s.clone()),
+        Value::Bool(b) => Variant::Bool(*b),
+        Value::Guid(guid) => Variant::Bstr(guid.to_string()),
+    }
+}
+
+/// Names of the types `ty` refers to.
+fn referenced(ty: &TypeDef) -> Vec<String> {
+    let mut lib = Library {
+        types: vec![ty.clone()],
+        ..Library::default()
+    };
+    let mut names = Vec::new();
+    lib.for_each_type_ref(|r| names.push(r.name.clone()));
+    names
+}
+
+/// The type libraries `importlib`ed from `dir`, by import, whose GUID and
+/// version complete the import. stdole2 is known without opening it.
+fn imported_libraries(lib: &mut Library, dir: &Path) -> Vec<Option<Library>> {
+    lib.imports
+        .iter_mut()
+        .map(|import| {
+            if is_stdole(&import.file_name) {
+                return None;
+            }
+            let found = Library::load(&dir.join(&import.file_name), None).ok()?;
+            import.guid = found.guid;
+            import.version = found.version;
+            import.lcid = found.lcid;
+            Some(found)
+        })
+        .collect()
+}
+
+/// Points every named type at its definition: a type of the library, else
+/// of an imported library, else the well-known stdole2 type of that name
+/// when stdole2.tlb is imported.
+fn resolve(lib: &mut Library, imported: &[Option<Library>]) {
+    let local: HashMap<String, (usize, TypeKind, Guid)> = lib
+        .types
+        .iter()
+        .enumerate()
+        .map(|(index, ty)| (ty.name.clone(), (index, ty.kind, ty.guid)))
+        .collect();
+    let stdole = lib.imports.iter().position(|i| is_stdole(&i.file_name));
+    lib.for_each_type_ref(|r| {
+        let found = imported
+            .iter()
+            .enumerate()
+            .find_map(|(index, other)| Some((index, other.as_ref()?.find(&r.name)?)));
+        if let Some(&(index, kind, guid)) = local.get(&r.name) {
+            r.location = TypeLocation::Local(index);
+            r.kind = Some(kind);
+            r.guid = Some(guid);
+        } else if let Some((index, def)) = found {
+            r.location = TypeLocation::Imported(index);
+            r.kind = Some(def.kind);
+            r.guid = Some(def.guid);
+        } else if let Some(import) = stdole
+            && let Some((guid, tkind)) = well_known_type_by_name(&r.name)
+        {
+            r.location = TypeLocation::Imported(import);
+            r.kind = TypeKind::from_tkind(tkind);
+            r.guid = Some(guid);
+        }
+    });
+}
+
+/// Vtable slots and ancestors of the interface at `index`, and whether it
+/// derives from IDispatch. Bases from imported libraries count when the
+/// library was found.
+fn ancestry(lib: &Library, imported: &[Option<Library>], index: usize) -> (usize, usize, bool) {
+    let (mut slots, mut ancestors) = (0, 0);
+    let mut owner = lib;
+    let mut current = &lib.types[index];
+    while let Some(base) = current.base() {
+        // MIDL rejects cycles; this only keeps the walk finite.
+        if ancestors > 64 {
+            break;
+        }
+        let def = match base.location {
+            TypeLocation::Local(index) => owner.types.get(index),
+            // Only the imports of the library itself are loaded.
+            TypeLocation::Imported(index) if std::ptr::eq(owner, lib) => imported
+                .get(index)
+                .and_then(Option::as_ref)
+                .and_then(|other| {
+                    owner = other;
+                    other.find(&base.name)
+                }),
+            _ => None,
+        };
+        match def {
+            Some(def) if def.guid == IID_IDISPATCH => return (slots + 7, ancestors + 2, true),
+            Some(def) if def.guid == IID_IUNKNOWN => break,
+            Some(def) => {
+                ancestors += 1;
+                slots += def
+                    .funcs
+                    .iter()
+                    .filter(|f| !f.is_dispatch_plumbing())
+                    .count();
+                current = def;
+            }
+            None if base.name == "IDispatch" => return (slots + 7, ancestors + 2, true),
+            // IUnknown, or a base the source does not define.
+            None => return (slots + 3, ancestors + 1, false),
+        }
+    }
+    (slots + 3, ancestors + 1, false)
+}
+
+/// What MIDL computes for interfaces: the DISPIDs of members without an
+/// `id` (numbered after the depth of the interface), vtable offsets after
+/// those of the base interfaces, and `dispatchable`.
+fn lay_out_interfaces(lib: &mut Library, imported: &[Option<Library>]) {
+    let pointer = lib.pointer_size() as usize;
+    for index in 0..lib.types.len() {
+        if !matches!(
+            lib.types[index].kind,
+            TypeKind::Interface | TypeKind::Dispatch
+        ) {
+            continue;
+        }
+        let (slots, ancestors, dispatchable) = ancestry(lib, imported, index);
+        let ty = &mut lib.types[index];
+        if dispatchable {
+            ty.flags |= TYPEFLAG_FDISPATCHABLE;
+        }
+        let first_id = 0x6000_0000 + ((ancestors as i32) << 16);
+        let memids = ty
+            .vars
+            .iter_mut()
+            .map(|v| &mut v.memid)
+            .chain(ty.funcs.iter_mut().map(|f| &mut f.memid));
+        for (i, memid) in memids.enumerate() {
+            if *memid == AUTO_ID {
+                *memid = first_id + i as i32;
+            }
+        }
+        for (i, func) in ty.funcs.iter_mut().enumerate() {
+            func.vtable_offset = ((slots + i) * pointer) as i16;
+        }
+        ty.vtable_size = ((slots + ty.funcs.len()) * pointer) as u16;
+        ty.size = pointer as u32;
+        ty.alignment = pointer as u16;
+    }
+}
+
+/// Member offsets, size and alignment of records and unions, as a C
+/// compiler lays them out at their packing. Those with a member of unknown
+/// size are left without a layout.
+fn lay_out_records(lib: &mut Library, packing: &[u32]) {
+    for ty in &mut lib.types {
+        if ty.kind == TypeKind::Enum {
+            (ty.size, ty.alignment) = (4, 4);
+        }
+    }
+    // Records containing other records wait for those to be laid out.
+    let mut progress = true;
+    while progress {
+        progress = false;
+        for index in 0..lib.types.len() {
+            let ty = &lib.types[index];
+            if !matches!(ty.kind, TypeKind::Record | TypeKind::Union) || ty.alignment != 0 {
+                continue;
+            }
+            let Some(members) = ty
+                .vars
+                .iter()
+                .map(|v| lib.size_and_alignment(&v.ty))
+                .collect::<Option<Vec<_>>>()
+            else {
+                continue;
+            };
+            let pack = packing.get(index).copied().unwrap_or(8);
+            let union = ty.kind == TypeKind::Union;
+            let (mut end, mut max_align) = (0u32, 1u32);
+            let mut offsets = Vec::new();
+            for (size, align) in members {
+                let align = align.min(pack);
+                let offset = if union {
+                    0
+                } else {
+                    end.next_multiple_of(align)
+                };
+                offsets.push(offset);
+                end = end.max(offset + size);
+                max_align = max_align.max(align);
+            }
+            let ty = &mut lib.types[index];
+            for (var, offset) in ty.vars.iter_mut().zip(offsets) {
+                var.offset = offset;
+            }
+            ty.size = end.next_multiple_of(max_align);
+            ty.alignment = max_align as u16;
+            progress = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = r#"
+        [uuid(5A1E0A00-0100-4B1D-9C2E-0123456789AB), version(2.10), lcid(0x409)]
+        library Sample
+        {
+            importlib("stdole2.tlb");
+
+            typedef enum { Read = 1 << 2, Write, Both = Read | Write } Access;
+
+            #pragma pack(push, 2)
+            typedef struct tagRecord {
+                char c;
+                double d;
+                short s[3];
+            } Record;
+            #pragma pack(pop)
+
+            [uuid(5A1E0A00-0101-4B1D-9C2E-0123456789AB), dual]
+            interface IThing : IDispatch {
+                HRESULT Open([in] long mode, [in, optional] VARIANT extra);
+                [id(7), propget, helpstring("The \"owner\"")]
+                HRESULT Owner([out, retval] IDispatch** value);
+            };
+
+            [uuid(5A1E0A00-0102-4B1D-9C2E-0123456789AB)]
+            coclass Thing {
+                [default] interface IThing;
+            };
+        };
+    "#;
+
+    #[test]
+    fn reads_what_midl_would_compute() {
+        let lib = parse(SOURCE).unwrap();
+        assert_eq!(lib.name, "Sample");
+        assert_eq!(
+            lib.guid,
+            Guid::parse("5A1E0A00-0100-4B1D-9C2E-0123456789AB").unwrap()
+        );
+        assert_eq!((lib.version, lib.lcid), ((2, 10), 0x409));
+
+        let access = lib.find("Access").unwrap();
+        let values: Vec<_> = access.vars.iter().map(|v| v.value.clone()).collect();
+        assert_eq!(
+            values,
+            [
+                Some(Variant::I4(4)),
+                Some(Variant::I4(5)),
+                Some(Variant::I4(5))
+            ]
+        );
+
+        let record = lib.find("Record").unwrap();
+        let offsets: Vec<_> = record.vars.iter().map(|v| v.offset).collect();
+        assert_eq!(offsets, [0, 2, 10]);
+        assert_eq!((record.size, record.alignment), (16, 2));
+
+        let thing = lib.find("IThing").unwrap();
+        assert_eq!(thing.kind, TypeKind::Dispatch);
+        assert!(thing.has_flag(TYPEFLAG_FDUAL | TYPEFLAG_FOLEAUTOMATION | TYPEFLAG_FDISPATCHABLE));
+        assert_eq!(
+            thing.base().map(|b| &b.location),
+            Some(&TypeLocation::Imported(0))
+        );
+        let open = &thing.funcs[0];
+        assert_eq!((open.memid, open.vtable_offset), (0x6002_0000, 28));
+        assert_eq!(open.optional_params, 1);
+        let owner = &thing.funcs[1];
+        assert_eq!((owner.memid, owner.vtable_offset), (7, 32));
+        assert_eq!(owner.invoke_kind, InvokeKind::PropertyGet);
+        assert_eq!(owner.doc.help_string, "The \"owner\"");
+        assert_eq!(
+            owner.params[0].ty,
+            Type::Ptr(Box::new(Type::Base(VT_DISPATCH)))
+        );
+
+        let coclass = lib.find("Thing").unwrap();
+        assert!(coclass.has_flag(TYPEFLAG_FCANCREATE));
+        assert_eq!(coclass.impl_types[0].flags, IMPLTYPEFLAG_FDEFAULT);
+        assert_eq!(
+            coclass.impl_types[0].target.location,
+            TypeLocation::Local(2)
+        );
+    }
+
+    #[test]
+    fn reads_dispinterfaces_and_outside_declarations() {
+        let lib = parse(
+            r##"
+            import "oaidl.idl";
+            cpp_quote("#define SAMPLE 1")
+            const DWORD Limit = 0x10;
+
+            typedef [v1_enum] enum { Low, High = Limit } Level;
+
+            [uuid(5A1E0A00-0200-4B1D-9C2E-0123456789AB)]
+            library Outside
+            {
+                [uuid(5A1E0A00-0201-4B1D-9C2E-0123456789AB)]
+                dispinterface _Events {
+                    properties:
+                        [id(DISPID_VALUE)] BOOL Enabled;
+                        Level Current;
+                    methods:
+                        [id(1)] void Changed([in] DWORD count);
+                        void Reset();
+                };
+            };
+            "##,
+        )
+        .unwrap();
+        let names: Vec<_> = lib.types.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(names, ["Level", "_Events"]);
+        assert_eq!(
+            lib.find("Level").unwrap().vars[1].value,
+            Some(Variant::I4(16))
+        );
+
+        let events = lib.find("_Events").unwrap();
+        assert_eq!(events.kind, TypeKind::Dispatch);
+        let vars: Vec<_> = events
+            .vars
+            .iter()
+            .map(|v| (v.memid, v.kind, v.ty.vt()))
+            .collect();
+        assert_eq!(
+            vars,
+            [
+                (0, VarKind::Dispatch, VT_I4),
+                (0x6002_0001, VarKind::Dispatch, VT_USERDEFINED),
+            ]
+        );
+        let funcs: Vec<_> = events
+            .funcs
+            .iter()
+            .map(|f| (f.memid, f.func_kind, f.params.first().map(|p| p.ty.vt())))
+            .collect();
+        assert_eq!(
+            funcs,
+            [
+                (1, FuncKind::Dispatch, Some(VT_UI4)),
+                (0x6002_0003, FuncKind::Dispatch, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn accepts_midl_pragmas_and_trailing_commas() {
+        let lib = parse(
+            r#"
+            midl_pragma warning (disable: 2111)
+            [uuid(5A1E0A00-0300-4B1D-9C2E-0123456789AB), version(1.0),]
+            library Tolerant
+            {
+                midl_pragma warning(default: 2111);
+                [uuid(5A1E0A00-0301-4B1D-9C2E-0123456789AB), object, oleautomation,]
+                interface IThing : IUnknown {
+                    HRESULT Size([out, retval,] long* size);
+                };
+            };
+            "#,
+        )
+        .unwrap();
+        assert_eq!(lib.version, (1, 0));
+        let thing = lib.find("IThing").unwrap();
+        assert!(thing.has_flag(TYPEFLAG_FOLEAUTOMATION));
+        assert!(thing.funcs[0].params[0].is_retval());
+    }
+
+    #[test]
+    fn reports_the_line_of_an_error() {
+        let error =
+            parse("library Broken\n{\n    interface IBroken {\n        HRESULT F(;\n    };\n};")
+                .unwrap_err();
+        assert!(
+            matches!(error, Error::InvalidIdl { line: 4, .. }),
+            "{:?}",
+            error
+        );
+        let error =
+            parse("library Twice { struct S { long a; }; struct S { long b; }; };").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Invalid IDL at line 1: S is defined twice"
+        );
+    }
+}
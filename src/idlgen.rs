@@ -1,349 +1,308 @@
+//! IDL decompilation of a loaded library, and the summaries of its types
+//! the TUI browses.
+
 use super::error::Error;
-use windows::{
-    Win32::System::{
-        Com::{
-            CoInitialize, FUNCDESC, IDispatch, IMPLTYPEFLAGS, INVOKE_PROPERTYGET,
-            INVOKE_PROPERTYPUT, INVOKE_PROPERTYPUTREF, INVOKEKIND, ITypeInfo, ITypeInfo2, ITypeLib,
-            ITypeLib2, TKIND_ALIAS, TKIND_COCLASS, TKIND_DISPATCH, TKIND_ENUM, TKIND_INTERFACE,
-            TKIND_MODULE, TKIND_RECORD, TKIND_UNION, TLIBATTR, TYPEATTR, TYPEDESC, VARDESC,
-        },
-        Ole::{
-            LoadTypeLib, TYPEFLAG_FDISPATCHABLE, TYPEFLAG_FDUAL, TYPEFLAG_FHIDDEN,
-            TYPEFLAG_FNONEXTENSIBLE, TYPEFLAG_FOLEAUTOMATION, TYPEFLAG_FRESTRICTED,
-        },
-        Variant::{
-            VARIANT, VT_BOOL, VT_BSTR, VT_CY, VT_DATE, VT_DECIMAL, VT_DISPATCH, VT_EMPTY, VT_ERROR,
-            VT_HRESULT, VT_I1, VT_I2, VT_I4, VT_I8, VT_INT, VT_LPSTR, VT_LPWSTR, VT_NULL, VT_PTR,
-            VT_R4, VT_R8, VT_SAFEARRAY, VT_UI1, VT_UI2, VT_UI4, VT_UI8, VT_UINT, VT_UNKNOWN,
-            VT_USERDEFINED, VT_VARIANT, VT_VOID,
-        },
-    },
-    core::{Interface, PCWSTR},
-};
-use windows_core::{BSTR, HSTRING, IUnknown};
+use crate::config::Config;
+use crate::diagnostics::Diagnostics;
+use crate::model::TypeRef;
+use crate::model::*;
+use crate::typelib::*;
+
+/// `output` of the diagnostics of the IDL.
+const IDL: &str = "idl";
 
+/// The library the TUI shows, once loaded.
+#[derive(Default)]
 pub struct TypeLibInfo {
-    tlib: Option<ITypeLib>,
+    lib: Option<Library>,
 }
 
 impl TypeLibInfo {
+    /// Nothing loaded yet.
     pub fn new() -> Self {
-        TypeLibInfo { tlib: None }
+        TypeLibInfo { lib: None }
     }
 
-    pub fn load_type_lib(&mut self, path: &std::path::Path) -> Result<(), Error> {
-        unsafe {
-            let _ = CoInitialize(None);
-        }
-        let path_str = path.to_str().ok_or(Error::TypeLibNotLoaded)?;
-        let path_hstring = HSTRING::from(path_str);
-        let path_pcwstr = PCWSTR::from_raw(path_hstring.as_ptr());
-
-        let type_lib = unsafe { LoadTypeLib(path_pcwstr) }?;
-        self.tlib = Some(type_lib);
+    /// Loads a .tlb file, or the TYPELIB resource `resource` of a PE image
+    /// (the first one when `None`), and applies `config` to it. `foo.dll\2`
+    /// selects resource 2 as well.
+    pub fn load_type_lib(
+        &mut self,
+        path: &std::path::Path,
+        resource: Option<u16>,
+        config: &Config,
+    ) -> Result<(), Error> {
+        self.lib = Some(config.load_library(path, resource)?);
         Ok(())
     }
 
-    fn get_lib_attr(&self) -> Result<*mut TLIBATTR, Error> {
-        if let Some(tlib) = &self.tlib {
-            unsafe { Ok(tlib.GetLibAttr()?) }
-        } else {
-            Err(Error::TypeLibNotLoaded)
-        }
-    }
-
-    fn get_documentation(&self, index: i32) -> Result<(BSTR, BSTR), Error> {
-        if let Some(tlib) = &self.tlib {
-            let mut name = BSTR::new();
-            let mut doc_string = BSTR::new();
-            let mut help_context = 0;
-            unsafe {
-                tlib.GetDocumentation(
-                    index,
-                    Some(&mut name),
-                    Some(&mut doc_string),
-                    &mut help_context,
-                    None,
-                )?
-            };
-            Ok((name, doc_string))
-        } else {
-            Err(Error::TypeLibNotLoaded)
-        }
+    /// The loaded library.
+    pub fn lib(&self) -> Result<&Library, Error> {
+        self.lib.as_ref().ok_or(Error::TypeLibNotLoaded)
     }
 
+    /// Number of types, 0 before a library is loaded.
     pub fn get_type_info_count(&self) -> u32 {
-        if let Some(tlib) = &self.tlib {
-            unsafe { tlib.GetTypeInfoCount() }
+        if let Some(lib) = &self.lib {
+            lib.types.len() as u32
         } else {
             0
         }
     }
 
-    fn get_type_info(&self, index: u32) -> Result<ITypeInfo, Error> {
-        if let Some(tlib) = &self.tlib {
-            unsafe { Ok(tlib.GetTypeInfo(index)?) }
-        } else {
-            Err(Error::TypeLibNotLoaded)
-        }
+    fn get_type_info(&self, index: u32) -> Result<&TypeDef, Error> {
+        self.lib()?
+            .types
+            .get(index as usize)
+            .ok_or_else(|| Error::InvalidTypeLib(format!("no typeinfo at index {}", index)))
     }
 
+    /// Name of the type at `index`, and the name of its kind.
     pub fn get_type_name_and_kind(&self, index: u32) -> Result<(String, String), Error> {
         let type_info = self.get_type_info(index)?;
-        unsafe {
-            let type_attr = type_info.GetTypeAttr()?;
-            let kind = match (*type_attr).typekind {
-                TKIND_ENUM => "Enum",
-                TKIND_RECORD => "Record",
-                TKIND_MODULE => "Module",
-                TKIND_INTERFACE => "Interface",
-                TKIND_DISPATCH => "Dispatch",
-                TKIND_COCLASS => "CoClass",
-                TKIND_ALIAS => "Alias",
-                TKIND_UNION => "Union",
-                _ => "Unknown",
-            }
-            .to_string();
-            let (name, _) = get_type_documentation(&type_info, -1);
-            type_info.ReleaseTypeAttr(type_attr);
-            Ok((name, kind))
-        }
+        Ok((type_info.name.clone(), type_info.kind.name().to_string()))
+    }
+
+    /// Help context of the type at `index`.
+    pub fn get_type_help_context(&self, index: u32) -> Result<u32, Error> {
+        Ok(self.get_type_info(index)?.doc.help_context)
     }
 
+    /// IDL of the type at `index`.
     pub fn get_type_idl(&self, index: u32) -> Result<String, Error> {
         let type_info = self.get_type_info(index)?;
         let mut out = Vec::new();
-        print_type_info(&type_info, &mut out)?;
+        print_type_info(self.lib()?, type_info, &mut out, &mut Diagnostics::new())
+            .map_err(Error::IoError)?;
         Ok(String::from_utf8_lossy(&out).to_string())
     }
 
+    /// Methods of the type at `index`, with the accessors of the
+    /// properties of a dispinterface first.
     pub fn get_type_methods(&self, index: u32) -> Result<Vec<MethodInfo>, Error> {
         let type_info = self.get_type_info(index)?;
         let mut methods = Vec::new();
-        unsafe {
-            let type_attr = type_info.GetTypeAttr()?;
-            for i in 0..(*type_attr).cFuncs {
-                if let Ok(func_desc) = type_info.GetFuncDesc(i as u32) {
-                    if let Ok(info) = get_function_info(&type_info, &*func_desc) {
-                        methods.push(info);
-                    }
-                    type_info.ReleaseFuncDesc(func_desc);
-                }
+        for func in dispatch_properties(type_info)
+            .iter()
+            .chain(&type_info.funcs)
+        {
+            if !func.is_dispatch_plumbing() {
+                methods.push(get_function_info(func));
             }
-            type_info.ReleaseTypeAttr(type_attr);
         }
         Ok(methods)
     }
 
+    /// Values of the type at `index` when it is an enum; none otherwise.
     pub fn get_type_enums(&self, index: u32) -> Result<Vec<EnumItemInfo>, Error> {
         let type_info = self.get_type_info(index)?;
         let mut enums = Vec::new();
-        unsafe {
-            let type_attr = type_info.GetTypeAttr()?;
-            if (*type_attr).typekind == TKIND_ENUM {
-                for i in 0..(*type_attr).cVars {
-                    if let Ok(var_desc) = type_info.GetVarDesc(i as u32) {
-                        if let Ok(info) = get_enum_info(&type_info, &*var_desc) {
-                            enums.push(info);
-                        }
-                        type_info.ReleaseVarDesc(var_desc);
-                    }
+        if type_info.kind == TypeKind::Enum {
+            for var in &type_info.vars {
+                if let Ok(info) = get_enum_info(var) {
+                    enums.push(info);
                 }
             }
-            type_info.ReleaseTypeAttr(type_attr);
         }
         Ok(enums)
     }
 }
 
+/// An enum value, as the TUI lists it.
 #[derive(Debug, Clone)]
 pub struct EnumItemInfo {
+    /// Name of the value.
     pub name: String,
+    /// The value as IDL spells it; empty when it has none.
     pub value: String,
+    /// Help context of the value.
+    pub help_context: u32,
 }
 
+/// A parameter, as the TUI lists it.
 #[derive(Debug, Clone)]
 pub struct ParamInfo {
+    /// Name of the parameter.
     pub name: String,
+    /// Its type, as IDL spells it.
     pub type_name: String,
+    /// `PARAMFLAG_*` attributes, as spelled in IDL.
     pub flags: Vec<String>,
+    /// The `defaultvalue`, as IDL spells it.
     pub default_value: Option<String>,
 }
 
+/// A method, as the TUI lists it.
 #[derive(Debug, Clone)]
 pub struct MethodInfo {
+    /// Name of the method.
     pub name: String,
+    /// The type of the `retval` parameter for a method returning `HRESULT`,
+    /// and its return type otherwise.
     pub ret_type: String,
+    /// The parameters, without the `retval` one.
     pub params: Vec<ParamInfo>,
-    pub _invoke_kind: String,
+    /// `propget`, `propput` or `propputref` for property accessors, `func`
+    /// for other methods.
+    pub invoke_kind: String,
+    /// `FUNCFLAG_*` attributes and `vararg`, as spelled in IDL.
+    pub flags: Vec<String>,
+    /// Help context of the method.
+    pub help_context: u32,
 }
 
-unsafe fn get_enum_info(type_info: &ITypeInfo, var_desc: &VARDESC) -> Result<EnumItemInfo, Error> {
-    let memid = var_desc.memid;
-    let (name, _) = unsafe { get_type_documentation(type_info, memid) };
+/// Display strings of an enum value.
+pub fn get_enum_info(var: &Variable) -> Result<EnumItemInfo, Error> {
+    let name = var.name.clone();
 
-    let value = if let Some(val) = unsafe { var_desc.Anonymous.lpvarValue.as_ref() } {
-        unsafe { val.Anonymous.Anonymous.Anonymous.lVal.to_string() }
-    } else {
-        String::new()
+    let value = match var.typed_value() {
+        Some(val) => variant_to_string(&val),
+        None => String::new(),
     };
 
-    Ok(EnumItemInfo { name, value })
+    Ok(EnumItemInfo {
+        name,
+        value,
+        help_context: var.doc.help_context,
+    })
 }
 
-unsafe fn get_function_info(
-    type_info: &ITypeInfo,
-    func_desc: &FUNCDESC,
-) -> Result<MethodInfo, Error> {
-    let memid = func_desc.memid;
-    if memid >= 0x60000000 && memid < 0x60020000 {
-        return Err(Error::IoError(std::io::Error::new(
-            std::io::ErrorKind::Other,
-            "Hidden method",
-        )));
-    }
-
-    let (name, _) = unsafe { get_type_documentation(type_info, memid) };
-    let _invoke_kind = match func_desc.invkind {
-        INVOKE_PROPERTYGET => "propget",
-        INVOKE_PROPERTYPUT => "propput",
-        INVOKE_PROPERTYPUTREF => "propputref",
-        _ => "func",
-    }
-    .to_string();
-
-    let mut ret_type = unsafe { type_desc_to_string(type_info, &func_desc.elemdescFunc.tdesc) };
-
-    let mut names: Vec<BSTR> = vec![BSTR::new(); (func_desc.cParams + 1) as usize];
-    let mut c_names = 0;
-    unsafe {
-        type_info
-            .GetNames(memid, names.as_mut_slice(), &mut c_names)
-            .ok()
+/// Display strings of a parameter: `arg<index>` stands in for a missing
+/// name and the flags are spelled as IDL attributes.
+pub fn get_param_info(param: &Param, index: usize) -> ParamInfo {
+    let param_type = type_to_string(&param.ty);
+    let param_name = match &param.name {
+        Some(name) => name.clone(),
+        None => format!("arg{}", index),
     };
 
-    let mut params = Vec::new();
-    for i in 0..func_desc.cParams {
-        let elem_desc = unsafe { *func_desc.lprgelemdescParam.offset(i as isize) };
-        let param_type = unsafe { type_desc_to_string(type_info, &elem_desc.tdesc) };
-        let param_name = if (i + 1) < c_names as i16 {
-            names[(i + 1) as usize].to_string()
-        } else {
-            format!("arg{}", i)
+    let param_flags = param.flags;
+    let mut flags = Vec::new();
+    if (param_flags & PARAMFLAG_FIN) != 0 {
+        flags.push("in".to_string());
+    }
+    if (param_flags & PARAMFLAG_FOUT) != 0 {
+        flags.push("out".to_string());
+    }
+    if (param_flags & PARAMFLAG_FLCID) != 0 {
+        flags.push("lcid".to_string());
+    }
+    if (param_flags & PARAMFLAG_FRETVAL) != 0 {
+        flags.push("retval".to_string());
+    }
+    if (param_flags & PARAMFLAG_FOPT) != 0 {
+        flags.push("optional".to_string());
+    }
+    let mut default_value = None;
+    if (param_flags & PARAMFLAG_FHASDEFAULT) != 0 {
+        flags.push("defaultvalue".to_string());
+        let val = match &param.default_value {
+            Some(variant) => variant_to_string(variant),
+            None => String::new(),
         };
-
-        let param_flags = unsafe { elem_desc.Anonymous.paramdesc.wParamFlags };
-        let mut flags = Vec::new();
-        if (param_flags.0 & 1) != 0 {
-            flags.push("in".to_string());
-        }
-        if (param_flags.0 & 2) != 0 {
-            flags.push("out".to_string());
-        }
-        if (param_flags.0 & 4) != 0 {
-            flags.push("lcid".to_string());
-        }
-        if (param_flags.0 & 8) != 0 {
-            flags.push("retval".to_string());
-        }
-        if (param_flags.0 & 16) != 0 {
-            flags.push("optional".to_string());
-        }
-        let mut default_value = None;
-        if (param_flags.0 & 32) != 0 {
-            flags.push("defaultvalue".to_string());
-            let val = unsafe {
-                let param_desc_ex = elem_desc.Anonymous.paramdesc.pparamdescex;
-                if !param_desc_ex.is_null() {
-                    let variant = &(*param_desc_ex).varDefaultValue;
-                    variant_to_string(variant)
-                } else {
-                    String::new()
-                }
-            };
-            if !val.is_empty() {
-                default_value = Some(val);
-            }
+        if !val.is_empty() {
+            default_value = Some(val);
         }
+    }
 
-        params.push(ParamInfo {
-            name: param_name,
-            type_name: param_type,
-            flags,
-            default_value,
-        });
+    ParamInfo {
+        name: param_name,
+        type_name: param_type,
+        flags,
+        default_value,
     }
+}
+
+/// Accessors for the `properties:` of a dispinterface.
+pub(crate) fn dispatch_properties(type_info: &TypeDef) -> Vec<Function> {
+    type_info
+        .vars
+        .iter()
+        .filter(|var| var.kind == VarKind::Dispatch)
+        .flat_map(Variable::dispatch_accessors)
+        .collect()
+}
+
+fn get_function_info(func: &Function) -> MethodInfo {
+    let name = func.name.clone();
+    let invoke_kind = func
+        .invoke_kind
+        .idl_attribute()
+        .unwrap_or("func")
+        .to_string();
+
+    let mut ret_type = return_type_to_string(func);
+
+    let mut params: Vec<ParamInfo> = func
+        .params
+        .iter()
+        .enumerate()
+        .map(|(i, param)| get_param_info(param, i))
+        .collect();
 
     // Handle return value transformation for HRESULT methods
-    if func_desc.invkind == INVOKE_PROPERTYGET || ret_type != "void" {
-        let mut real_ret_type = ret_type.clone();
-        // Check if there is a retval param
-        if let Some(pos) = params
-            .iter()
-            .position(|p| p.flags.contains(&"retval".to_string()))
-        {
-            // It's a COM method returning HRESULT with a retval param
-            // The "real" return type is the type of the retval param (pointer stripped)
-            let retval_param = &params[pos];
-            real_ret_type = retval_param.type_name.trim_end_matches('*').to_string();
-            // Remove the retval param from the list as it's now the return value
-            params.remove(pos);
-        } else {
-            // If no retval, it returns HRESULT (or whatever raw type), but usually we want to show HRESULT if it is one.
-            // But wait, type_desc_to_string returns "HRESULT" for VT_HRESULT.
-            // If it is HRESULT and no retval, it's just void in high-level languages usually, or HRESULT.
-            // Let's keep it as is for now.
-        }
-        ret_type = real_ret_type;
+    if let Some(pos) = func.retval_index() {
+        // It's a COM method returning HRESULT with a retval param
+        // The "real" return type is the type of the retval param (pointer stripped)
+        let retval_param = &params[pos];
+        ret_type = retval_param.type_name.trim_end_matches('*').to_string();
+        // Remove the retval param from the list as it's now the return value
+        params.remove(pos);
     }
 
-    Ok(MethodInfo {
+    MethodInfo {
         name,
         ret_type,
         params,
-        _invoke_kind,
-    })
-}
-
-pub fn get_library_name(tlb_path: &std::path::Path) -> Result<String, Error> {
-    let mut type_lib_info = TypeLibInfo::new();
-    type_lib_info.load_type_lib(tlb_path)?;
-    let (name, _) = type_lib_info.get_documentation(-1)?;
-    Ok(name.to_string())
+        invoke_kind,
+        flags: function_flags(func),
+        help_context: func.doc.help_context,
+    }
 }
 
-pub fn build_tlb<W>(
+/// Prints the IDL for `lib` after a comment naming `tlb_path`, the file it
+/// was read from.
+pub fn print_decompiled<W>(
+    lib: &Library,
     tlb_path: &std::path::Path,
     mut out: W,
-    import_stdole: bool,
-) -> Result<(), Error>
+    diagnostics: &mut Diagnostics,
+) -> std::io::Result<()>
 where
     W: std::io::Write,
 {
-    let mut type_lib_info = TypeLibInfo::new();
-    type_lib_info.load_type_lib(tlb_path)?;
-
-    let lib_attr = unsafe { &*type_lib_info.get_lib_attr()? };
-    let (name, doc_string) = type_lib_info.get_documentation(-1)?;
-
     writeln!(out, "// Decompilated from {}", tlb_path.display())?;
+    print_library(lib, out, diagnostics)
+}
 
+/// Prints the IDL for a whole library, recording in `diagnostics` what it
+/// leaves out or cannot spell.
+pub fn print_library<W>(
+    lib: &Library,
+    mut out: W,
+    diagnostics: &mut Diagnostics,
+) -> std::io::Result<()>
+where
+    W: std::io::Write,
+{
+    diagnostics.set_library(&lib.name);
     let mut lib_attributes = Vec::new();
-    lib_attributes.push(format!("uuid({:?})", lib_attr.guid));
+    lib_attributes.push(format!("uuid({:?})", lib.guid));
+    lib_attributes.push(format!("version({}.{})", lib.version.0, lib.version.1));
+    if lib.lcid != 0 {
+        lib_attributes.push(format!("lcid(0x{:x})", lib.lcid));
+    }
     lib_attributes.push(format!(
-        "version({}.{})",
-        lib_attr.wMajorVerNum, lib_attr.wMinorVerNum
+        "helpstring({})",
+        string_literal(&lib.doc.help_string)
     ));
-    lib_attributes.push(format!("helpstring(\"{}\")", doc_string));
-
-    if let Some(tlib) = &type_lib_info.tlib {
-        if let Ok(tlib2) = tlib.cast::<ITypeLib2>() {
-            unsafe {
-                let custom_attrs = get_lib_custom_data(&tlib2)?;
-                lib_attributes.extend(custom_attrs);
-            }
-        }
+    if !lib.help_file.is_empty() {
+        lib_attributes.push(format!("helpfile({})", string_literal(&lib.help_file)));
+    }
+    if lib.doc.help_context != 0 {
+        lib_attributes.push(format!("helpcontext(0x{:08x})", lib.doc.help_context));
     }
+    lib_attributes.extend(get_custom_data(&lib.custom_data));
 
     writeln!(out, "[")?;
     for (i, attr) in lib_attributes.iter().enumerate() {
@@ -355,67 +314,49 @@ where
         writeln!(out, "  {}{}", attr, suffix)?;
     }
     writeln!(out, "]")?;
-    writeln!(out, "library {}", name)?;
+    writeln!(out, "library {}", lib.name)?;
     writeln!(out, "{{")?;
 
-    // Standard imports often found in IDLs
-    if import_stdole {
-        writeln!(out, "    importlib(\"stdole2.tlb\");")?;
+    // Every library the types reference, as recorded in the import table.
+    let mut imported = Vec::new();
+    for import in &lib.imports {
+        if !imported.contains(&&import.file_name) {
+            imported.push(&import.file_name);
+            writeln!(out, "    importlib({});", string_literal(&import.file_name))?;
+        }
     }
-    writeln!(out, "")?;
-
-    let count = type_lib_info.get_type_info_count();
+    writeln!(out)?;
 
     // Forward declarations
-    for i in 0..count {
-        if let Ok(type_info) = type_lib_info.get_type_info(i) {
-            unsafe {
-                let type_attr: *mut TYPEATTR = type_info.GetTypeAttr()?;
-                let type_kind = (*type_attr).typekind;
-                let (name, _) = get_type_documentation(&type_info, -1);
-
-                match type_kind {
-                    TKIND_INTERFACE => {
-                        writeln!(out, "    interface {};", name)?;
-                    }
-                    TKIND_DISPATCH => {
-                        writeln!(out, "    interface {};", name)?;
-                    }
-                    TKIND_COCLASS => {
-                        writeln!(out, "    coclass {};", name)?;
-                    }
-                    _ => {}
-                }
-                type_info.ReleaseTypeAttr(type_attr);
+    for type_info in &lib.types {
+        match type_info.kind {
+            TypeKind::Interface => {
+                writeln!(out, "    interface {};", type_info.name)?;
+            }
+            TypeKind::Dispatch if type_info.has_flag(TYPEFLAG_FDUAL) => {
+                writeln!(out, "    interface {};", type_info.name)?;
             }
+            TypeKind::Dispatch => {
+                writeln!(out, "    dispinterface {};", type_info.name)?;
+            }
+            TypeKind::CoClass => {
+                writeln!(out, "    coclass {};", type_info.name)?;
+            }
+            _ => {}
         }
     }
-    writeln!(out, "")?;
+    writeln!(out)?;
 
-    let count = type_lib_info.get_type_info_count();
-    for i in 0..count {
-        if let Ok(type_info) = type_lib_info.get_type_info(i) {
-            unsafe {
-                let type_attr = type_info.GetTypeAttr()?;
-                if (*type_attr).typekind == TKIND_ENUM {
-                    print_type_info(&type_info, &mut out)?;
-                }
-                type_info.ReleaseTypeAttr(type_attr);
-            }
+    for type_info in &lib.types {
+        if type_info.kind == TypeKind::Enum {
+            print_type_info(lib, type_info, &mut out, diagnostics)?;
         }
     }
-    writeln!(out, "")?;
+    writeln!(out)?;
 
-    let count = type_lib_info.get_type_info_count();
-    for i in 0..count {
-        if let Ok(type_info) = type_lib_info.get_type_info(i) {
-            unsafe {
-                let type_attr = type_info.GetTypeAttr()?;
-                if (*type_attr).typekind != TKIND_ENUM {
-                    print_type_info(&type_info, &mut out)?;
-                }
-                type_info.ReleaseTypeAttr(type_attr);
-            }
+    for type_info in &lib.types {
+        if type_info.kind != TypeKind::Enum {
+            print_type_info(lib, type_info, &mut out, diagnostics)?;
         }
     }
 
@@ -423,124 +364,148 @@ where
     Ok(())
 }
 
-fn print_interface_header<W>(type_info: &ITypeInfo, out: &mut W) -> Result<(), Error>
+fn print_interface_header<W>(type_info: &TypeDef, out: &mut W) -> std::io::Result<()>
 where
     W: std::io::Write,
 {
-    unsafe {
-        let type_attr: *mut TYPEATTR = type_info.GetTypeAttr()?;
-        let type_kind = (*type_attr).typekind;
-        let guid = (*type_attr).guid;
-        let (_, doc_string) = get_type_documentation(type_info, -1);
-        let type_flags = (*type_attr).wTypeFlags;
-
-        if type_kind == TKIND_INTERFACE
-            || type_kind == TKIND_DISPATCH
-            || type_kind == TKIND_COCLASS
-            || type_kind == TKIND_ENUM
-        {
-            let mut attributes = Vec::new();
-            attributes.push(format!("uuid({:?})", guid));
-
-            if !doc_string.is_empty() {
-                attributes.push(format!("helpstring(\"{}\")", doc_string));
-            }
-
-            let flags_map = [
-                (TYPEFLAG_FHIDDEN.0 as u16, "hidden"),
-                (TYPEFLAG_FDUAL.0 as u16, "dual"),
-                (TYPEFLAG_FRESTRICTED.0 as u16, "restricted"),
-                (TYPEFLAG_FNONEXTENSIBLE.0 as u16, "nonextensible"),
-                (TYPEFLAG_FOLEAUTOMATION.0 as u16, "oleautomation"),
-            ];
-
-            for (flag, attr) in flags_map {
-                if (type_flags & flag) != 0 {
-                    attributes.push(attr.to_string());
-                }
-            }
+    if matches!(
+        type_info.kind,
+        TypeKind::Interface | TypeKind::Dispatch | TypeKind::CoClass | TypeKind::Enum
+    ) {
+        let mut attributes = vec![format!("uuid({:?})", type_info.guid)];
+        attributes.extend(type_attributes(type_info));
+
+        writeln!(out, "    [")?;
+        for (i, attr) in attributes.iter().enumerate() {
+            let suffix = if i == attributes.len() - 1 { "" } else { "," };
+            writeln!(out, "      {}{}", attr, suffix)?;
+        }
+        writeln!(out, "    ]")?;
+    }
 
-            if type_flags & (TYPEFLAG_FDISPATCHABLE.0 as u16 | TYPEFLAG_FDUAL.0 as u16) != 0 {
-                attributes.push("oleautomation".to_string());
-            }
+    Ok(())
+}
 
-            // Custom attributes
-            if let Ok(type_info2) = type_info.cast::<ITypeInfo2>() {
-                let custom_attrs = get_custom_data(&type_info2)?;
-                attributes.extend(custom_attrs);
-            }
+/// The `helpstring`, flags and custom data of a type.
+fn type_attributes(type_info: &TypeDef) -> Vec<String> {
+    let mut attributes = Vec::new();
+    let doc_string = &type_info.doc.help_string;
+    if !doc_string.is_empty() {
+        attributes.push(format!("helpstring({})", string_literal(doc_string)));
+    }
 
-            writeln!(out, "    [")?;
-            for (i, attr) in attributes.iter().enumerate() {
-                let suffix = if i == attributes.len() - 1 { "" } else { "," };
-                writeln!(out, "      {}{}", attr, suffix)?;
-            }
-            writeln!(out, "    ]")?;
+    let flags_map = [
+        (TYPEFLAG_FHIDDEN, "hidden"),
+        (TYPEFLAG_FDUAL, "dual"),
+        (TYPEFLAG_FRESTRICTED, "restricted"),
+        (TYPEFLAG_FNONEXTENSIBLE, "nonextensible"),
+        (TYPEFLAG_FOLEAUTOMATION, "oleautomation"),
+    ];
+
+    for (flag, attr) in flags_map {
+        if (type_info.flags & flag) != 0 {
+            attributes.push(attr.to_string());
         }
+    }
 
-        Ok(())
+    // Custom attributes
+    attributes.extend(get_custom_data(&type_info.custom_data));
+    attributes
+}
+
+/// The attributes between `typedef` and the type of a record, union or
+/// alias, which only carry a GUID when they were given one.
+fn typedef_attributes(type_info: &TypeDef) -> String {
+    let mut attributes = Vec::new();
+    if type_info.kind == TypeKind::Alias {
+        attributes.push("public".to_string());
+    }
+    if !type_info.guid.is_nil() {
+        attributes.push(format!("uuid({:?})", type_info.guid));
+    }
+    attributes.extend(type_attributes(type_info));
+    if attributes.is_empty() {
+        String::new()
+    } else {
+        format!("[{}] ", attributes.join(", "))
     }
 }
 
-fn print_type_info<W>(type_info: &ITypeInfo, out: &mut W) -> Result<(), Error>
+fn print_type_info<W>(
+    lib: &Library,
+    type_info: &TypeDef,
+    out: &mut W,
+    diagnostics: &mut Diagnostics,
+) -> std::io::Result<()>
 where
     W: std::io::Write,
 {
-    unsafe {
-        let type_attr: *mut TYPEATTR = type_info.GetTypeAttr()?;
+    let guid = type_info.guid;
 
-        let guid = (*type_attr).guid;
+    if guid == IID_IUNKNOWN || guid == IID_IDISPATCH {
+        return Ok(());
+    }
 
-        if guid == IUnknown::IID || guid == IDispatch::IID {
-            type_info.ReleaseTypeAttr(type_attr);
-            return Ok(());
+    let type_kind = type_info.kind;
+    let type_flags = type_info.flags;
+
+    // Special handling for pure dispinterfaces: extract the inherited interface
+    if type_kind == TypeKind::Dispatch
+        && (type_flags & TYPEFLAG_FDUAL) == 0
+        && let Some(base) = type_info.base()
+        && let Some(ref_type_info) = lib.local(base)
+    {
+        let ref_kind = ref_type_info.kind;
+        let is_dual = ref_type_info.has_flag(TYPEFLAG_FDUAL);
+        let ref_guid = ref_type_info.guid;
+
+        if (ref_kind == TypeKind::Interface || (ref_kind == TypeKind::Dispatch && is_dual))
+            && ref_guid != IID_IUNKNOWN
+            && ref_guid != IID_IDISPATCH
+        {
+            return print_type_info(lib, ref_type_info, out, diagnostics);
         }
+    }
 
-        let type_kind = (*type_attr).typekind;
-        let type_flags = (*type_attr).wTypeFlags;
-
-        // Special handling for pure dispinterfaces: extract the inherited interface
-        if type_kind == TKIND_DISPATCH && (type_flags & TYPEFLAG_FDUAL.0 as u16) == 0 {
-            if (*type_attr).cImplTypes > 0 {
-                if let Ok(href) = type_info.GetRefTypeOfImplType(0) {
-                    if let Ok(ref_type_info) = type_info.GetRefTypeInfo(href) {
-                        if let Ok(ref_attr) = ref_type_info.GetTypeAttr() {
-                            let ref_kind = (*ref_attr).typekind;
-                            let ref_flags = (*ref_attr).wTypeFlags;
-                            let is_dual = (ref_flags & TYPEFLAG_FDUAL.0 as u16) != 0;
-                            let ref_guid = (*ref_attr).guid;
-                            ref_type_info.ReleaseTypeAttr(ref_attr);
-
-                            if (ref_kind == TKIND_INTERFACE
-                                || (ref_kind == TKIND_DISPATCH && is_dual))
-                                && ref_guid != IUnknown::IID
-                                && ref_guid != IDispatch::IID
-                            {
-                                type_info.ReleaseTypeAttr(type_attr);
-                                return print_type_info(&ref_type_info, out);
-                            }
-                        }
-                    }
-                }
-            }
-        }
+    let name = &type_info.name;
+    let doc_string = &type_info.doc.help_string;
+
+    print_interface_header(type_info, out)?;
+    if let Some(base) = type_info.base()
+        && !base.is_resolved()
+    {
+        diagnostics.warn(
+            IDL,
+            name.clone(),
+            format!("base interface {} does not resolve", describe_ref(base)),
+        );
+    }
 
-        let guid = (*type_attr).guid;
-        let (name, doc_string) = get_type_documentation(type_info, -1);
+    match type_kind {
+        TypeKind::Interface => {
+            // Find base interface
+            let base_name = type_info.base().map(|b| b.name.as_str()).unwrap_or("");
 
-        print_interface_header(type_info, out)?;
+            if !base_name.is_empty() {
+                writeln!(out, "    interface {} : {} {{", name, base_name)?;
+            } else {
+                writeln!(out, "    interface {} {{", name)?;
+            }
 
-        match type_kind {
-            TKIND_INTERFACE => {
-                // Find base interface
-                let mut base_name = String::new();
-                if (*type_attr).cImplTypes > 0 {
-                    if let Ok(href) = type_info.GetRefTypeOfImplType(0) {
-                        if let Ok(base_info) = type_info.GetRefTypeInfo(href) {
-                            base_name = get_name(&base_info);
-                        }
-                    }
-                }
+            // Print properties and methods
+            for func in &type_info.funcs {
+                print_function(func, name, out, diagnostics)?;
+            }
+
+            writeln!(out, "    }};")?;
+        }
+        TypeKind::Dispatch => {
+            let is_dual = (type_flags & TYPEFLAG_FDUAL) != 0;
+            if is_dual {
+                // Dual interface: the stored members are those of the partner
+                // (TKIND_INTERFACE) half, and the first implemented type is
+                // the partner's base interface.
+                let base_name = type_info.base().map(|b| b.name.as_str()).unwrap_or("");
 
                 if !base_name.is_empty() {
                     writeln!(out, "    interface {} : {} {{", name, base_name)?;
@@ -548,381 +513,467 @@ where
                     writeln!(out, "    interface {} {{", name)?;
                 }
 
-                // Print properties and methods
-                for i in 0..(*type_attr).cFuncs {
-                    if let Ok(func_desc) = type_info.GetFuncDesc(i as u32) {
-                        print_function(type_info, &*func_desc, out)?;
-                        type_info.ReleaseFuncDesc(func_desc);
-                    }
+                // Print methods
+                for func in &type_info.funcs {
+                    print_function(func, name, out, diagnostics)?;
                 }
 
                 writeln!(out, "    }};")?;
-            }
-            TKIND_DISPATCH => {
-                let is_dual = (type_flags & TYPEFLAG_FDUAL.0 as u16) != 0;
-                if is_dual {
-                    // Dual interface: get the partner interface (TKIND_INTERFACE)
-                    // The partner interface is usually at impl type -1 (0xFFFFFFFF)
-                    let mut partner_type_info = None;
-                    if let Ok(href) = type_info.GetRefTypeOfImplType(u32::MAX) {
-                        if let Ok(ref_type_info) = type_info.GetRefTypeInfo(href) {
-                            partner_type_info = Some(ref_type_info);
-                        }
-                    }
-
-                    if let Some(partner_info) = partner_type_info {
-                        // Use the partner interface for everything
-                        let partner_attr = partner_info.GetTypeAttr()?;
-
-                        // Find base interface of the partner
-                        let mut base_name = String::new();
-
-                        if (*partner_attr).cImplTypes > 0 {
-                            if let Ok(href) = partner_info.GetRefTypeOfImplType(0) {
-                                if let Ok(base_info) = partner_info.GetRefTypeInfo(href) {
-                                    base_name = get_name(&base_info);
-                                }
-                            }
-                        }
-
-                        if !base_name.is_empty() {
-                            writeln!(out, "    interface {} : {} {{", name, base_name)?;
-                        } else {
-                            writeln!(out, "    interface {} {{", name)?;
-                        }
-
-                        // Print methods
-                        for i in 0..(*partner_attr).cFuncs {
-                            if let Ok(func_desc) = partner_info.GetFuncDesc(i as u32) {
-                                print_function(&partner_info, &*func_desc, out)?;
-                                partner_info.ReleaseFuncDesc(func_desc);
-                            }
-                        }
-
-                        partner_info.ReleaseTypeAttr(partner_attr);
-                        writeln!(out, "    }};")?;
-                    } else {
-                        // Fallback if partner not found (shouldn't happen for valid duals)
-                        writeln!(out, "    interface {} : IDispatch {{", name)?;
-                        for i in 7..(*type_attr).cFuncs {
-                            // Skip IDispatch methods
-                            if let Ok(func_desc) = type_info.GetFuncDesc(i as u32) {
-                                print_function(type_info, &*func_desc, out)?;
-                                type_info.ReleaseFuncDesc(func_desc);
-                            }
-                        }
-                        writeln!(out, "    }};")?;
-                    }
-                } else {
-                    // Non-dual dispinterface treated as interface : IDispatch
-                    writeln!(out, "    interface {} : IDispatch {{", name)?;
-                    for i in 0..(*type_attr).cFuncs {
-                        if let Ok(func_desc) = type_info.GetFuncDesc(i as u32) {
-                            print_function(type_info, &*func_desc, out)?;
-                            type_info.ReleaseFuncDesc(func_desc);
-                        }
-                    }
-                    writeln!(out, "    }};")?;
+            } else {
+                writeln!(out, "    dispinterface {} {{", name)?;
+                writeln!(out, "        properties:")?;
+                for var in &type_info.vars {
+                    print_dispatch_property(var, name, out, diagnostics)?;
                 }
-            }
-            TKIND_ENUM => {
-                writeln!(out, "    enum {} {{", name)?;
-                for i in 0..(*type_attr).cVars {
-                    if let Ok(var_desc) = type_info.GetVarDesc(i as u32) {
-                        print_var(type_info, &*var_desc, out)?;
-                        type_info.ReleaseVarDesc(var_desc);
-                    }
+                writeln!(out, "        methods:")?;
+                for func in &type_info.funcs {
+                    print_dispatch_method(func, name, out, diagnostics)?;
                 }
                 writeln!(out, "    }};")?;
             }
-            TKIND_COCLASS => {
-                writeln!(out, "    coclass {} {{", name)?;
-                for i in 0..(*type_attr).cImplTypes {
-                    if let Ok(href) = type_info.GetRefTypeOfImplType(i as u32) {
-                        if let Ok(ref_type_info) = type_info.GetRefTypeInfo(href) {
-                            let ref_name = get_name(&ref_type_info);
-                            let impl_flags = type_info
-                                .GetImplTypeFlags(i as u32)
-                                .unwrap_or(IMPLTYPEFLAGS::default());
-                            // Check for [default]
-                            let default_str = if (impl_flags.0 & 1) != 0 {
-                                "[default] "
-                            } else {
-                                ""
-                            };
-                            // Check for [source] (2)
-                            let source_str = if (impl_flags.0 & 2) != 0 {
-                                "[source] "
-                            } else {
-                                ""
-                            };
-
-                            writeln!(
-                                out,
-                                "        {}{}{} {};",
-                                default_str, source_str, "interface", ref_name
-                            )?;
-                        }
-                    }
-                }
-                writeln!(out, "    }};")?;
+        }
+        TypeKind::Enum => {
+            writeln!(out, "    enum {} {{", name)?;
+            let hex = type_info.is_bit_flags();
+            for var in &type_info.vars {
+                print_var(var, hex, out)?;
             }
-            TKIND_ALIAS => {
-                let alias_type_name = type_desc_to_string(type_info, &(*type_attr).tdescAlias);
-                let mut attributes = Vec::new();
-
-                if (type_flags & TYPEFLAG_FHIDDEN.0 as u16) == 0 {
-                    attributes.push("public");
+            writeln!(out, "    }};")?;
+        }
+        TypeKind::CoClass => {
+            writeln!(out, "    coclass {} {{", name)?;
+            for impl_type in &type_info.impl_types {
+                if !impl_type.target.is_resolved() {
+                    diagnostics.warn(
+                        IDL,
+                        name.clone(),
+                        format!(
+                            "implemented interface {} does not resolve and is left out",
+                            describe_ref(&impl_type.target)
+                        ),
+                    );
+                    continue;
                 }
-
-                let attr_str = if !attributes.is_empty() {
-                    format!("[{}] ", attributes.join(", "))
+                let impl_flags = impl_type.flags;
+                // Check for [default]
+                let default_str = if (impl_flags & IMPLTYPEFLAG_FDEFAULT) != 0 {
+                    "[default] "
+                } else {
+                    ""
+                };
+                // Check for [source]
+                let source_str = if (impl_flags & IMPLTYPEFLAG_FSOURCE) != 0 {
+                    "[source] "
                 } else {
-                    String::new()
+                    ""
                 };
 
-                writeln!(out, "    typedef {}{} {};", attr_str, alias_type_name, name)?;
-            }
-            TKIND_RECORD => {
-                writeln!(out, "    typedef struct tag{} {{", name)?;
-                for i in 0..(*type_attr).cVars {
-                    if let Ok(var_desc) = type_info.GetVarDesc(i as u32) {
-                        print_record_member(type_info, &*var_desc, out)?;
-                        type_info.ReleaseVarDesc(var_desc);
+                // MIDL names a pure dispinterface with its own keyword.
+                let keyword = match lib.local(&impl_type.target) {
+                    Some(t) if t.kind == TypeKind::Dispatch && !t.has_flag(TYPEFLAG_FDUAL) => {
+                        "dispinterface"
                     }
-                }
-                writeln!(out, "    }} {};", name)?;
+                    _ => "interface",
+                };
+                writeln!(
+                    out,
+                    "        {}{}{} {};",
+                    default_str, source_str, keyword, impl_type.target.name
+                )?;
             }
-            TKIND_MODULE => {
-                let mut dll_name = String::new();
-                if (*type_attr).cFuncs > 0 {
-                    if let Ok(func_desc) = type_info.GetFuncDesc(0) {
-                        if let Ok(dll) =
-                            get_dll_entry(type_info, (*func_desc).memid, (*func_desc).invkind)
-                        {
-                            dll_name = dll;
-                        }
-                        type_info.ReleaseFuncDesc(func_desc);
-                    }
-                }
-
-                let mut attributes = Vec::new();
-                if !dll_name.is_empty() {
-                    attributes.push(format!("dllname(\"{}\")", dll_name));
+            writeln!(out, "    }};")?;
+        }
+        TypeKind::Alias => {
+            let aliased = match &type_info.alias_of {
+                Some(ty) => {
+                    check_type(ty, name, diagnostics);
+                    declaration(ty, name)
                 }
-                attributes.push(format!("uuid({:?})", guid));
-                if !doc_string.is_empty() {
-                    attributes.push(format!("helpstring(\"{}\")", doc_string));
+                None => {
+                    diagnostics.warn(
+                        IDL,
+                        name.clone(),
+                        "alias without a target, written as void".to_string(),
+                    );
+                    format!("void {}", name)
                 }
+            };
+            writeln!(
+                out,
+                "    typedef {}{};",
+                typedef_attributes(type_info),
+                aliased
+            )?;
+        }
+        TypeKind::Record | TypeKind::Union => {
+            let keyword = if type_kind == TypeKind::Union {
+                "union"
+            } else {
+                "struct"
+            };
+            let layout = lib.layout(type_info);
+            if let Layout::Packed(pack) = layout {
+                writeln!(out, "    #pragma pack(push, {})", pack)?;
+            }
+            writeln!(
+                out,
+                "    typedef {}{} tag{} {{",
+                typedef_attributes(type_info),
+                keyword,
+                name
+            )?;
+            if layout == Layout::Explicit {
+                diagnostics.warn(
+                    IDL,
+                    name.clone(),
+                    "member offsets no packing explains are only kept in comments".to_string(),
+                );
+            }
+            for var in &type_info.vars {
+                check_type(&var.ty, &format!("{}.{}", name, var.name), diagnostics);
+                print_record_member(var, layout == Layout::Explicit, out)?;
+            }
+            writeln!(out, "    }} {};", name)?;
+            if let Layout::Packed(_) = layout {
+                writeln!(out, "    #pragma pack(pop)")?;
+            }
+        }
+        TypeKind::Module => {
+            let dll_name = get_dll_entry(type_info);
 
-                writeln!(out, "    [")?;
-                for (i, attr) in attributes.iter().enumerate() {
-                    let suffix = if i == attributes.len() - 1 { "" } else { "," };
-                    writeln!(out, "      {}{}", attr, suffix)?;
-                }
-                writeln!(out, "    ]")?;
-                writeln!(out, "    module {} {{", name)?;
+            let mut attributes = Vec::new();
+            if !dll_name.is_empty() {
+                attributes.push(format!("dllname({})", string_literal(&dll_name)));
+            }
+            attributes.push(format!("uuid({:?})", guid));
+            if !doc_string.is_empty() {
+                attributes.push(format!("helpstring({})", string_literal(doc_string)));
+            }
 
-                for i in 0..(*type_attr).cVars {
-                    if let Ok(var_desc) = type_info.GetVarDesc(i as u32) {
-                        print_module_const(type_info, &*var_desc, out)?;
-                        type_info.ReleaseVarDesc(var_desc);
-                    }
-                }
+            writeln!(out, "    [")?;
+            for (i, attr) in attributes.iter().enumerate() {
+                let suffix = if i == attributes.len() - 1 { "" } else { "," };
+                writeln!(out, "      {}{}", attr, suffix)?;
+            }
+            writeln!(out, "    ]")?;
+            writeln!(out, "    module {} {{", name)?;
 
-                for i in 0..(*type_attr).cFuncs {
-                    if let Ok(func_desc) = type_info.GetFuncDesc(i as u32) {
-                        type_info.ReleaseFuncDesc(func_desc);
-                    }
-                }
-                writeln!(out, "    }};")?;
+            for var in &type_info.vars {
+                print_module_const(var, name, out, diagnostics)?;
             }
-            _ => {
-                writeln!(out, "    // Unsupported type kind: {:?}", type_kind)?;
+            for func in &type_info.funcs {
+                print_module_function(func, name, out, diagnostics)?;
             }
-        }
-        writeln!(out, "")?;
 
-        type_info.ReleaseTypeAttr(type_attr);
+            writeln!(out, "    }};")?;
+        }
     }
+    writeln!(out)?;
+
+    Ok(())
+}
+
+fn print_module_const<W>(
+    var: &Variable,
+    type_name: &str,
+    out: &mut W,
+    diagnostics: &mut Diagnostics,
+) -> std::io::Result<()>
+where
+    W: std::io::Write,
+{
+    let name = &var.name;
+    let path = format!("{}.{}", type_name, name);
+
+    let Some(val) = var.typed_value() else {
+        diagnostics.warn(
+            IDL,
+            path,
+            "constant without a value is left out".to_string(),
+        );
+        return Ok(());
+    };
+    check_value(&val, &path, "value", diagnostics);
+    // A VARIANT-typed constant is declared with the type it holds.
+    let ty = match &var.ty {
+        Type::Base(VT_VARIANT | VT_EMPTY) => Type::Base(val.vt()),
+        ty => ty.clone(),
+    };
+    check_type(&ty, &path, diagnostics);
+    writeln!(
+        out,
+        "        const {} {} = {};",
+        type_to_string(&ty),
+        name,
+        variant_to_string(&val)
+    )?;
     Ok(())
 }
 
-unsafe fn print_module_const<W>(
-    type_info: &ITypeInfo,
-    var_desc: &VARDESC,
+/// A DLL export declared in a module: `[entry(...)] <ret> <callconv> Name(...)`.
+/// Unlike interface methods the return type is printed as stored.
+fn print_module_function<W>(
+    func: &Function,
+    type_name: &str,
     out: &mut W,
-) -> Result<(), Error>
+    diagnostics: &mut Diagnostics,
+) -> std::io::Result<()>
 where
     W: std::io::Write,
 {
-    let memid = var_desc.memid;
-    let (name, _) = unsafe { get_type_documentation(type_info, memid) };
+    let path = format!("{}.{}", type_name, func.name);
+    let mut attributes = Vec::new();
+    match &func.entry {
+        Some(DllEntry::Name(name)) => attributes.push(format!("entry({})", string_literal(name))),
+        Some(DllEntry::Ordinal(ordinal)) => attributes.push(format!("entry({})", ordinal)),
+        None => {}
+    }
+    attributes.extend(function_attributes(func));
+    if !attributes.is_empty() {
+        writeln!(out, "        [{}]", attributes.join(", "))?;
+    }
 
-    if let Some(val) = unsafe { var_desc.Anonymous.lpvarValue.as_ref() } {
-        // Assuming int/long for now as per Olewoo example
-        let val_int = unsafe { val.Anonymous.Anonymous.Anonymous.lVal };
-        // Handle negative hex printing if needed, but simple print for now
-        writeln!(out, "        const int {} = {};", name, val_int)?;
+    check_type(&func.ret, &path, diagnostics);
+    write!(out, "        {} ", type_to_string(&func.ret))?;
+    if let Some(keyword) = func.call_conv.idl_keyword() {
+        write!(out, "{} ", keyword)?;
     }
+    write!(out, "{}(", func.name)?;
+    write_params(&func.params, &path, out, diagnostics)?;
+    writeln!(out, ");")?;
     Ok(())
 }
 
-unsafe fn get_dll_entry(
-    type_info: &ITypeInfo,
-    memid: i32,
-    invkind: INVOKEKIND,
-) -> Result<String, Error> {
-    let mut dll_name = BSTR::new();
-    let mut name = BSTR::new();
-    let mut ordinal = 0u16;
-    unsafe {
-        type_info.GetDllEntry(
-            memid,
-            invkind,
-            Some(&mut dll_name as *mut BSTR),
-            Some(&mut name as *mut BSTR),
-            &mut ordinal,
-        )?;
-    }
-    Ok(dll_name.to_string())
+/// IDL spellings of the `FUNCFLAG_*` bits.
+pub(crate) const FUNC_FLAG_ATTRIBUTES: [(u16, &str); 13] = [
+    (FUNCFLAG_FRESTRICTED, "restricted"),
+    (FUNCFLAG_FSOURCE, "source"),
+    (FUNCFLAG_FBINDABLE, "bindable"),
+    (FUNCFLAG_FREQUESTEDIT, "requestedit"),
+    (FUNCFLAG_FDISPLAYBIND, "displaybind"),
+    (FUNCFLAG_FDEFAULTBIND, "defaultbind"),
+    (FUNCFLAG_FHIDDEN, "hidden"),
+    (FUNCFLAG_FUSESGETLASTERROR, "usesgetlasterror"),
+    (FUNCFLAG_FDEFAULTCOLLELEM, "defaultcollelem"),
+    (FUNCFLAG_FUIDEFAULT, "uidefault"),
+    (FUNCFLAG_FNONBROWSABLE, "nonbrowsable"),
+    (FUNCFLAG_FREPLACEABLE, "replaceable"),
+    (FUNCFLAG_FIMMEDIATEBIND, "immediatebind"),
+];
+
+/// The flag attributes of a function: its `FUNCFLAG_*` bits and `vararg`.
+pub(crate) fn function_flags(func: &Function) -> Vec<String> {
+    let mut flags: Vec<String> = FUNC_FLAG_ATTRIBUTES
+        .iter()
+        .filter(|&&(flag, _)| func.flags & flag != 0)
+        .map(|&(_, attr)| attr.to_string())
+        .collect();
+    if func.is_vararg() {
+        flags.push("vararg".to_string());
+    }
+    flags
 }
 
-unsafe fn get_lib_custom_data(type_lib2: &ITypeLib2) -> Result<Vec<String>, Error> {
-    let mut attrs = Vec::new();
-    let cust_data = unsafe { type_lib2.GetAllCustData()? };
-
-    for i in 0..cust_data.cCustData {
-        let item = unsafe { &*cust_data.prgCustData.offset(i as isize) };
-        let guid = item.guid;
-        let val = &item.varValue;
-
-        let vt = unsafe { val.Anonymous.Anonymous.vt };
-        if vt == VT_BSTR {
-            let bstr_val = unsafe { &val.Anonymous.Anonymous.Anonymous.bstrVal };
-            let s = bstr_val.to_string();
-            attrs.push(format!("custom({:?}, \"{}\")", guid, s));
-        }
+/// Attributes common to methods and module functions, after their `id` or
+/// `entry`: flags, help and custom data.
+fn function_attributes(func: &Function) -> Vec<String> {
+    let mut attributes = function_flags(func);
+    if !func.doc.help_string.is_empty() {
+        attributes.push(format!(
+            "helpstring({})",
+            string_literal(&func.doc.help_string)
+        ));
+    }
+    if func.doc.help_context != 0 {
+        attributes.push(format!("helpcontext(0x{:08x})", func.doc.help_context));
     }
-    Ok(attrs)
+    attributes.extend(get_custom_data(&func.custom_data));
+    attributes
 }
 
-unsafe fn get_custom_data(type_info2: &ITypeInfo2) -> Result<Vec<String>, Error> {
+fn get_dll_entry(type_info: &TypeDef) -> String {
+    type_info.dll_name.clone().unwrap_or_default()
+}
+
+fn get_custom_data(cust_data: &[CustData]) -> Vec<String> {
     let mut attrs = Vec::new();
-    let cust_data = unsafe { type_info2.GetAllCustData()? };
-
-    for i in 0..cust_data.cCustData {
-        let item = unsafe { &*cust_data.prgCustData.offset(i as isize) };
-        let guid = item.guid;
-        let val = &item.varValue;
-
-        let vt = unsafe { val.Anonymous.Anonymous.vt };
-        if vt == VT_BSTR {
-            let bstr_val = unsafe { &val.Anonymous.Anonymous.Anonymous.bstrVal };
-            // bstr_val is ManuallyDrop<BSTR>
-            let s = bstr_val.to_string();
-            attrs.push(format!("custom({:?}, \"{}\")", guid, s));
+
+    for item in cust_data {
+        if let Variant::Bstr(s) = &item.value {
+            attrs.push(format!("custom({:?}, {})", item.guid, string_literal(s)));
         }
     }
-    Ok(attrs)
+    attrs
 }
 
-unsafe fn print_function<W>(
-    type_info: &ITypeInfo,
-    func_desc: &FUNCDESC,
+fn print_function<W>(
+    func: &Function,
+    type_name: &str,
     out: &mut W,
-) -> Result<(), Error>
+    diagnostics: &mut Diagnostics,
+) -> std::io::Result<()>
 where
     W: std::io::Write,
 {
-    let memid = func_desc.memid;
+    let memid = func.memid;
 
-    // Filter IUnknown and IDispatch methods
-    // Olewoo filters 0x60000000 to 0x60020000
-    if memid >= 0x60000000 && memid < 0x60020000 {
+    // Filter the inherited IUnknown and IDispatch methods
+    if func.is_dispatch_plumbing() {
         return Ok(());
     }
 
-    let (name, doc_string) = unsafe { get_type_documentation(type_info, memid) };
-
-    let invoke_kind = func_desc.invkind;
-    let prop_attr = match invoke_kind {
-        INVOKE_PROPERTYGET => "propget",
-        INVOKE_PROPERTYPUT => "propput",
-        INVOKE_PROPERTYPUTREF => "propputref",
-        _ => "",
-    };
+    let name = &func.name;
+    let path = format!("{}.{}", type_name, name);
 
-    let ret_type = unsafe { type_desc_to_string(type_info, &func_desc.elemdescFunc.tdesc) };
+    check_type(&func.ret, &path, diagnostics);
+    let ret_type = return_type_to_string(func);
 
-    write!(out, "        [id(0x{:08x})", memid)?;
-    if !prop_attr.is_empty() {
-        write!(out, ", {}", prop_attr)?;
+    let mut attributes = vec![format!("id(0x{:08x})", memid)];
+    if let Some(prop_attr) = func.invoke_kind.idl_attribute() {
+        attributes.push(prop_attr.to_string());
     }
-    if !doc_string.is_empty() {
-        write!(out, ", helpstring(\"{}\")", doc_string)?;
-    }
-    write!(out, "]\n")?;
+    attributes.extend(function_attributes(func));
+    writeln!(out, "        [{}]", attributes.join(", "))?;
+
+    write!(out, "        HRESULT {} (", name)?;
 
-    write!(out, "        {}HRESULT {} (", "", name)?;
+    let has_retval = func.params.iter().any(Param::is_retval);
+    write_params(&func.params, &path, out, diagnostics)?;
+
+    if (func.invoke_kind == InvokeKind::PropertyGet || ret_type != "void") && !has_retval {
+        if !func.params.is_empty() {
+            write!(out, ", ")?;
+        }
+
+        let mut ret_name = "val";
+        if func.params.iter().any(|p| {
+            p.name
+                .as_deref()
+                .is_some_and(|n| n.eq_ignore_ascii_case("val"))
+        }) {
+            ret_name = "retVal";
+        }
 
-    // Get parameter names
-    let mut names: Vec<BSTR> = vec![BSTR::new(); (func_desc.cParams + 1) as usize];
-    let mut c_names = 0;
-    unsafe {
-        type_info
-            .GetNames(memid, names.as_mut_slice(), &mut c_names)
-            .ok();
+        write!(out, "[out, retval] {}* {}", ret_type, ret_name)?;
     }
 
-    let mut has_retval = false;
+    writeln!(out, ");")?;
+    Ok(())
+}
 
-    for i in 0..func_desc.cParams {
-        let elem_desc = unsafe { *func_desc.lprgelemdescParam.offset(i as isize) };
-        let param_type = unsafe { type_desc_to_string(type_info, &elem_desc.tdesc) };
+/// A member of the `properties:` section of a dispinterface.
+fn print_dispatch_property<W>(
+    var: &Variable,
+    type_name: &str,
+    out: &mut W,
+    diagnostics: &mut Diagnostics,
+) -> std::io::Result<()>
+where
+    W: std::io::Write,
+{
+    let path = format!("{}.{}", type_name, var.name);
+    check_type(&var.ty, &path, diagnostics);
+    let mut attributes = vec![format!("id(0x{:08x})", var.memid)];
+    if var.flags & VARFLAG_FREADONLY != 0 {
+        attributes.push("readonly".to_string());
+    }
+    // The getter carries the flags, help and custom data of the property.
+    let accessors = var.dispatch_accessors();
+    attributes.extend(function_attributes(&accessors[0]));
+    writeln!(
+        out,
+        "            [{}] {};",
+        attributes.join(", "),
+        declaration(&var.ty, &var.name)
+    )?;
+    Ok(())
+}
 
+/// A member of the `methods:` section of a dispinterface, with the return
+/// type callers get through `IDispatch::Invoke`.
+fn print_dispatch_method<W>(
+    func: &Function,
+    type_name: &str,
+    out: &mut W,
+    diagnostics: &mut Diagnostics,
+) -> std::io::Result<()>
+where
+    W: std::io::Write,
+{
+    if func.is_dispatch_plumbing() {
+        return Ok(());
+    }
+    let path = format!("{}.{}", type_name, func.name);
+    check_type(&func.ret, &path, diagnostics);
+    let mut attributes = vec![format!("id(0x{:08x})", func.memid)];
+    if let Some(prop_attr) = func.invoke_kind.idl_attribute() {
+        attributes.push(prop_attr.to_string());
+    }
+    attributes.extend(function_attributes(func));
+    writeln!(out, "            [{}]", attributes.join(", "))?;
+    write!(
+        out,
+        "            {} {} (",
+        type_to_string(&func.ret),
+        func.name
+    )?;
+    write_params(&func.params, &path, out, diagnostics)?;
+    writeln!(out, ");")?;
+    Ok(())
+}
+
+/// Writes a comma-separated parameter list with its IDL attributes.
+/// `path` names the function.
+fn write_params<W>(
+    params: &[Param],
+    path: &str,
+    out: &mut W,
+    diagnostics: &mut Diagnostics,
+) -> std::io::Result<()>
+where
+    W: std::io::Write,
+{
+    for (i, param) in params.iter().enumerate() {
         // Get param name
-        let param_name = if (i + 1) < c_names as i16 {
-            names[(i + 1) as usize].to_string()
-        } else {
-            format!("arg{}", i)
+        let param_name = match &param.name {
+            Some(name) => name.clone(),
+            None => format!("arg{}", i),
         };
+        let param_path = format!("{}({})", path, param_name);
+        check_type(&param.ty, &param_path, diagnostics);
 
         // Param attributes
-        let param_flags = unsafe { elem_desc.Anonymous.paramdesc.wParamFlags };
+        let param_flags = param.flags;
         let mut attrs: Vec<String> = Vec::new();
-        if (param_flags.0 & 1) != 0 {
+        if (param_flags & PARAMFLAG_FIN) != 0 {
             attrs.push("in".to_string());
-        } // PARAMFLAG_FIN
-        if (param_flags.0 & 2) != 0 {
+        }
+        if (param_flags & PARAMFLAG_FOUT) != 0 {
             attrs.push("out".to_string());
-        } // PARAMFLAG_FOUT
-        if (param_flags.0 & 4) != 0 {
+        }
+        if (param_flags & PARAMFLAG_FLCID) != 0 {
             attrs.push("lcid".to_string());
-        } // PARAMFLAG_FLCID
-        if (param_flags.0 & 8) != 0 {
+        }
+        if (param_flags & PARAMFLAG_FRETVAL) != 0 {
             attrs.push("retval".to_string());
-            has_retval = true;
-        } // PARAMFLAG_FRETVAL
-        if (param_flags.0 & 16) != 0 {
+        }
+        if (param_flags & PARAMFLAG_FOPT) != 0 {
             attrs.push("optional".to_string());
-        } // PARAMFLAG_FOPT
-        if (param_flags.0 & 32) != 0 {
-            let default_val = unsafe {
-                let param_desc_ex = elem_desc.Anonymous.paramdesc.pparamdescex;
-                if !param_desc_ex.is_null() {
-                    let variant = &(*param_desc_ex).varDefaultValue;
+        }
+        if (param_flags & PARAMFLAG_FHASDEFAULT) != 0 {
+            let default_val = match &param.default_value {
+                Some(variant) => {
+                    check_value(variant, &param_path, "default value", diagnostics);
                     variant_to_string(variant)
-                } else {
-                    String::new()
                 }
+                None => String::new(),
             };
             if !default_val.is_empty() {
                 attrs.push(format!("defaultvalue({})", default_val));
             } else {
                 attrs.push("defaultvalue".to_string());
             }
-        } // PARAMFLAG_FHASDEFAULT
+        }
 
         // Ensure [in] is present if optional is set and no direction is specified
         if attrs.iter().any(|a| a == "optional") && !attrs.iter().any(|a| a == "in" || a == "out") {
@@ -938,163 +989,841 @@ where
         if i > 0 {
             write!(out, ", ")?;
         }
-        write!(out, "{}{} {}", attr_str, param_type, param_name)?;
-    }
-
-    if (invoke_kind == INVOKE_PROPERTYGET || ret_type != "void") && !has_retval {
-        if func_desc.cParams > 0 {
-            write!(out, ", ")?;
-        }
-
-        let mut ret_name = "val";
-        for i in 1..c_names {
-            if names[i as usize].to_string().eq_ignore_ascii_case("val") {
-                ret_name = "retVal";
-                break;
-            }
-        }
-
-        write!(out, "[out, retval] {}* {}", ret_type, ret_name)?;
+        write!(out, "{}{}", attr_str, declaration(&param.ty, &param_name))?;
     }
-
-    writeln!(out, ");")?;
     Ok(())
 }
 
-unsafe fn print_var<W>(type_info: &ITypeInfo, var_desc: &VARDESC, out: &mut W) -> Result<(), Error>
+fn print_var<W>(var: &Variable, hex: bool, out: &mut W) -> std::io::Result<()>
 where
     W: std::io::Write,
 {
     // For enums
-    let memid = var_desc.memid;
-    let (name, _) = unsafe { get_type_documentation(type_info, memid) };
+    let name = &var.name;
 
-    if let Some(val) = unsafe { var_desc.Anonymous.lpvarValue.as_ref() } {
-        // This is tricky without full Variant support, assuming I4 for enums usually
-        let val_int = unsafe { val.Anonymous.Anonymous.Anonymous.lVal };
-        writeln!(out, "        {} = {},", name, val_int)?;
-    } else {
-        writeln!(out, "        {},", name)?;
+    match var.typed_value() {
+        Some(val) if hex => writeln!(out, "        {} = 0x{:08x},", name, val.as_i32())?,
+        Some(val) => writeln!(out, "        {} = {},", name, variant_to_string(&val))?,
+        None => writeln!(out, "        {},", name)?,
     }
     Ok(())
 }
 
-unsafe fn print_record_member<W>(
-    type_info: &ITypeInfo,
-    var_desc: &VARDESC,
-    out: &mut W,
-) -> Result<(), Error>
+/// IDL has no syntax for member offsets, so an explicit layout is recorded
+/// in comments.
+fn print_record_member<W>(var: &Variable, explicit: bool, out: &mut W) -> std::io::Result<()>
 where
     W: std::io::Write,
 {
-    let memid = var_desc.memid;
-    let (name, _) = unsafe { get_type_documentation(type_info, memid) };
-    let type_name = unsafe { type_desc_to_string(type_info, &var_desc.elemdescVar.tdesc) };
-    writeln!(out, "        {} {};", type_name, name)?;
+    let declaration = declaration(&var.ty, &var.name);
+    if explicit {
+        writeln!(out, "        {}; // offset {}", declaration, var.offset)?;
+    } else {
+        writeln!(out, "        {};", declaration)?;
+    }
     Ok(())
 }
 
-unsafe fn get_type_documentation(type_info: &ITypeInfo, index: i32) -> (String, String) {
-    let mut name = BSTR::new();
-    let mut doc_string = BSTR::new();
-    unsafe {
-        let _ =
-            type_info.GetDocumentation(index, Some(&mut name), Some(&mut doc_string), &mut 0, None);
+/// Return type as seen through IDispatch: vtable methods store `HRESULT`, which
+/// the printed signature already spells out, so it is reported as `void`.
+fn return_type_to_string(func: &Function) -> String {
+    match func.ret {
+        Type::Base(VT_HRESULT) => "void".to_string(),
+        ref ret => type_to_string(ret),
     }
-    (name.to_string(), doc_string.to_string())
 }
 
-unsafe fn get_name(type_info: &ITypeInfo) -> String {
-    let (name, _) = unsafe { get_type_documentation(type_info, -1) };
-    name
-}
-
-unsafe fn type_desc_to_string(type_info: &ITypeInfo, tdesc: &TYPEDESC) -> String {
-    match tdesc.vt {
-        VT_I2 => "short".to_string(),
-        VT_I4 => "long".to_string(),
-        VT_R4 => "float".to_string(),
-        VT_R8 => "double".to_string(),
-        VT_CY => "CURRENCY".to_string(),
-        VT_DATE => "DATE".to_string(),
-        VT_BSTR => "BSTR".to_string(),
-        VT_DISPATCH => "IDispatch*".to_string(),
-        VT_ERROR => "SCODE".to_string(),
-        VT_BOOL => "VARIANT_BOOL".to_string(),
-        VT_VARIANT => "VARIANT".to_string(),
-        VT_UNKNOWN => "IUnknown*".to_string(),
-        VT_DECIMAL => "DECIMAL".to_string(),
-        VT_I1 => "char".to_string(),
-        VT_UI1 => "unsigned char".to_string(),
-        VT_UI2 => "unsigned short".to_string(),
-        VT_UI4 => "unsigned long".to_string(),
-        VT_I8 => "int64".to_string(),
-        VT_UI8 => "uint64".to_string(),
-        VT_INT => "int".to_string(),
-        VT_UINT => "unsigned int".to_string(),
-        VT_VOID => "void".to_string(),
-        VT_HRESULT => "HRESULT".to_string(),
-        VT_PTR => {
-            let pointed_type = unsafe { type_desc_to_string(type_info, &*tdesc.Anonymous.lptdesc) };
+/// A type, as IDL spells it.
+pub fn type_to_string(ty: &Type) -> String {
+    match ty {
+        Type::Ptr(pointed) => {
+            let pointed_type = type_to_string(pointed);
             format!("{}*", pointed_type)
         }
-        VT_SAFEARRAY => {
-            let element_type = unsafe { type_desc_to_string(type_info, &*tdesc.Anonymous.lptdesc) };
+        Type::SafeArray(element) => {
+            let element_type = type_to_string(element);
             format!("SAFEARRAY({})", element_type)
         }
-        VT_USERDEFINED => {
-            if let Ok(ref_type_info) = unsafe { type_info.GetRefTypeInfo(tdesc.Anonymous.hreftype) }
-            {
-                let name = unsafe { get_name(&ref_type_info) };
-                unsafe {
-                    if let Ok(type_attr) = ref_type_info.GetTypeAttr() {
-                        let kind = (*type_attr).typekind;
-                        ref_type_info.ReleaseTypeAttr(type_attr);
-                        if kind == TKIND_ENUM {
-                            format!("enum {}", name)
-                        } else {
-                            name
-                        }
-                    } else {
-                        name
-                    }
-                }
-            } else {
+        Type::Named(r) => {
+            if !r.is_resolved() {
                 "UnknownUserDefined".to_string()
+            } else if r.kind == Some(TypeKind::Enum) {
+                format!("enum {}", r.name)
+            } else {
+                r.name.clone()
             }
         }
-        VT_LPSTR => "LPSTR".to_string(),
-        VT_LPWSTR => "LPWSTR".to_string(),
-        _ => format!("TYPE_{}", tdesc.vt.0),
+        Type::CArray { elem, bounds } => format!("{}{}", type_to_string(elem), dimensions(bounds)),
+        Type::Base(vt) => {
+            base_type_name(*vt).map_or_else(|| format!("TYPE_{}", vt), str::to_string)
+        }
     }
 }
 
-unsafe fn variant_to_string(variant: &VARIANT) -> String {
-    let variant = &*variant;
-    unsafe {
-        match variant.Anonymous.Anonymous.vt {
-            VT_I2 => variant.Anonymous.Anonymous.Anonymous.iVal.to_string(),
-            VT_I4 => variant.Anonymous.Anonymous.Anonymous.lVal.to_string(),
-            VT_R4 => variant.Anonymous.Anonymous.Anonymous.fltVal.to_string(),
-            VT_R8 => variant.Anonymous.Anonymous.Anonymous.dblVal.to_string(),
-            VT_BOOL => {
-                if variant.Anonymous.Anonymous.Anonymous.boolVal.as_bool() {
-                    "TRUE".to_string()
-                } else {
-                    "FALSE".to_string()
-                }
+/// IDL spelling of a base VARTYPE, if it has one.
+pub(crate) fn base_type_name(vt: u16) -> Option<&'static str> {
+    Some(match vt {
+        VT_I2 => "short",
+        VT_I4 => "long",
+        VT_R4 => "float",
+        VT_R8 => "double",
+        VT_CY => "CURRENCY",
+        VT_DATE => "DATE",
+        VT_BSTR => "BSTR",
+        VT_DISPATCH => "IDispatch*",
+        VT_ERROR => "SCODE",
+        VT_BOOL => "VARIANT_BOOL",
+        VT_VARIANT => "VARIANT",
+        VT_UNKNOWN => "IUnknown*",
+        VT_DECIMAL => "DECIMAL",
+        VT_I1 => "char",
+        VT_UI1 => "unsigned char",
+        VT_UI2 => "unsigned short",
+        VT_UI4 => "unsigned long",
+        VT_I8 => "int64",
+        VT_UI8 => "uint64",
+        VT_INT => "int",
+        VT_UINT => "unsigned int",
+        VT_VOID => "void",
+        VT_HRESULT => "HRESULT",
+        VT_LPSTR => "LPSTR",
+        VT_LPWSTR => "LPWSTR",
+        VT_INT_PTR => "INT_PTR",
+        VT_UINT_PTR => "UINT_PTR",
+        VT_FILETIME => "FILETIME",
+        VT_BLOB | VT_BLOB_OBJECT => "BLOB",
+        VT_STREAM | VT_STREAMED_OBJECT => "IStream*",
+        VT_STORAGE | VT_STORED_OBJECT => "IStorage*",
+        VT_CF => "CLIPDATA*",
+        VT_CLSID => "CLSID",
+        // A record whose type is only known at run time (IRecordInfo).
+        VT_RECORD => "void* /* VT_RECORD */",
+        _ => return None,
+    })
+}
+
+/// Records what [`type_to_string`] cannot spell faithfully in `ty`.
+fn check_type(ty: &Type, path: &str, diagnostics: &mut Diagnostics) {
+    match ty {
+        Type::Ptr(inner) | Type::SafeArray(inner) => check_type(inner, path, diagnostics),
+        Type::CArray { elem, bounds } => {
+            for &(_, lower) in bounds.iter().filter(|(_, lower)| *lower != 0) {
+                diagnostics.warn(
+                    IDL,
+                    path.to_string(),
+                    format!("array lower bound {} is left out", lower),
+                );
             }
-            VT_BSTR => {
-                let bstr = &variant.Anonymous.Anonymous.Anonymous.bstrVal;
-                if !bstr.is_empty() {
-                    format!("\"{}\"", bstr.to_string())
-                } else {
-                    "\"\"".to_string()
+            check_type(elem, path, diagnostics);
+        }
+        Type::Named(r) if !r.is_resolved() => diagnostics.warn(
+            IDL,
+            path.to_string(),
+            format!(
+                "type {} does not resolve, written as UnknownUserDefined",
+                describe_ref(r)
+            ),
+        ),
+        Type::Named(_) => {}
+        Type::Base(VT_RECORD) => diagnostics.warn(
+            IDL,
+            path.to_string(),
+            "VT_RECORD without its record type, written as void*".to_string(),
+        ),
+        Type::Base(vt) if base_type_name(*vt).is_none() => diagnostics.warn(
+            IDL,
+            path.to_string(),
+            format!("VARTYPE {} has no IDL spelling, written as TYPE_{}", vt, vt),
+        ),
+        Type::Base(_) => {}
+    }
+}
+
+/// Records a constant `what` that IDL cannot spell.
+fn check_value(value: &Variant, path: &str, what: &str, diagnostics: &mut Diagnostics) {
+    if let Variant::Unsupported(vt) = value {
+        diagnostics.warn(
+            IDL,
+            path.to_string(),
+            format!("{} of VARTYPE {} written as a comment", what, vt),
+        );
+    }
+}
+
+/// A referenced type by name, for diagnostics.
+fn describe_ref(r: &TypeRef) -> String {
+    if r.name.is_empty() {
+        "of unknown name".to_string()
+    } else {
+        r.name.clone()
+    }
+}
+
+/// A declaration of `name` with type `ty`. Fixed-size arrays put their
+/// dimensions after the name, as in `long cells[4][8]`.
+pub fn declaration(ty: &Type, name: &str) -> String {
+    match ty {
+        Type::CArray { elem, bounds } => {
+            format!("{} {}{}", type_to_string(elem), name, dimensions(bounds))
+        }
+        ty => format!("{} {}", type_to_string(ty), name),
+    }
+}
+
+/// `[4][8]`. IDL has no syntax for a lower bound, so it is dropped.
+fn dimensions(bounds: &[(u32, i32)]) -> String {
+    bounds
+        .iter()
+        .map(|(count, _)| format!("[{}]", count))
+        .collect()
+}
+
+/// A VARIANT constant as an IDL literal.
+pub fn variant_to_string(variant: &Variant) -> String {
+    match variant {
+        Variant::I1(v) => v.to_string(),
+        Variant::I2(v) => v.to_string(),
+        Variant::I4(v) | Variant::Int(v) => v.to_string(),
+        Variant::I8(v) => v.to_string(),
+        Variant::UI1(v) => v.to_string(),
+        Variant::UI2(v) => v.to_string(),
+        Variant::UI4(v) | Variant::UInt(v) => v.to_string(),
+        Variant::UI8(v) => v.to_string(),
+        Variant::R4(v) => format!("{:?}", v),
+        Variant::R8(v) | Variant::Date(v) => format!("{:?}", v),
+        Variant::Cy(v) => trim_fraction(decimal_string(*v as i128, 4)),
+        Variant::Decimal(v, scale) => trim_fraction(decimal_string(*v, *scale)),
+        Variant::Error(v) => format!("0x{:08x}", v),
+        Variant::Bool(v) => {
+            if *v {
+                "TRUE".to_string()
+            } else {
+                "FALSE".to_string()
+            }
+        }
+        Variant::Bstr(s) => string_literal(s),
+        Variant::Empty => "".to_string(),
+        Variant::Null => "null".to_string(),
+        Variant::Unsupported(vt) => format!("/* vt: {} */", vt),
+    }
+}
+
+/// `value / 10^scale` in plain decimal notation.
+pub fn decimal_string(value: i128, scale: u8) -> String {
+    let digits = value.unsigned_abs().to_string();
+    let scale = scale as usize;
+    let sign = if value < 0 { "-" } else { "" };
+    if scale == 0 {
+        return format!("{}{}", sign, digits);
+    }
+    let digits = format!("{:0>width$}", digits, width = scale + 1);
+    let (int, frac) = digits.split_at(digits.len() - scale);
+    format!("{}{}.{}", sign, int, frac)
+}
+
+fn trim_fraction(s: String) -> String {
+    if s.contains('.') {
+        s.trim_end_matches('0').trim_end_matches('.').to_string()
+    } else {
+        s
+    }
+}
+
+/// A string as an IDL (C) string literal. This is the only place strings
+/// are quoted, so everything the printer emits tokenizes back to the text
+/// it came from.
+///
+/// Quotes and backslashes are escaped, and so is every byte outside
+/// printable ASCII: MIDL reads its input in the ANSI code page and rejects
+/// raw control characters. Non-ASCII text is written as the octal escapes
+/// of its UTF-8 bytes, which the readers decode back.
+/// Octal is used because, unlike `\x`, an escape ends after three digits.
+pub fn string_literal(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for &b in s.as_bytes() {
+        match b {
+            b'"' => out.push_str("\\\""),
+            b'\\' => out.push_str("\\\\"),
+            b'\n' => out.push_str("\\n"),
+            b'\r' => out.push_str("\\r"),
+            b'\t' => out.push_str("\\t"),
+            0x20..=0x7e => out.push(b as char),
+            _ => out.push_str(&format!("\\{:03o}", b)),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{ImplType, TypeRef, fixture};
+
+    fn print(lib: &Library) -> String {
+        let mut out = Vec::new();
+        print_library(lib, &mut out, &mut Diagnostics::new()).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    fn guid(n: u128) -> Guid {
+        Guid::from_u128(0x11111111_2222_3333_4444_000000000000 | n)
+    }
+
+    #[test]
+    fn prints_hand_built_library() {
+        let mut colors = TypeDef::new("Colors", TypeKind::Enum, guid(1));
+        colors.vars.push(Variable {
+            name: "Red".to_string(),
+            memid: 0,
+            kind: VarKind::Const,
+            flags: 0,
+            ty: Type::Base(VT_I4),
+            value: Some(Variant::I4(7)),
+            offset: 0,
+            doc: Doc::default(),
+            custom_data: Vec::new(),
+        });
+
+        let mut painter = TypeDef::new("IPainter", TypeKind::Interface, guid(2));
+        painter.funcs.push(Function {
+            name: "Paint".to_string(),
+            memid: 1,
+            invoke_kind: InvokeKind::Func,
+            func_kind: FuncKind::PureVirtual,
+            call_conv: CallConv::StdCall,
+            flags: 0,
+            vtable_offset: 24,
+            params: vec![Param {
+                name: Some("color".to_string()),
+                ty: Type::Named(TypeRef::local("Colors", TypeKind::Enum, 0)),
+                flags: PARAMFLAG_FIN,
+                default_value: None,
+                custom_data: Vec::new(),
+            }],
+            optional_params: 0,
+            ret: Type::Base(VT_HRESULT),
+            entry: None,
+            doc: Doc::default(),
+            custom_data: Vec::new(),
+        });
+
+        let mut coclass = TypeDef::new("Painter", TypeKind::CoClass, guid(3));
+        coclass.impl_types.push(ImplType {
+            target: TypeRef::local("IPainter", TypeKind::Interface, 1),
+            flags: IMPLTYPEFLAG_FDEFAULT,
+            custom_data: Vec::new(),
+        });
+
+        let lib = Library {
+            name: "PaintLib".to_string(),
+            guid: guid(0),
+            version: (1, 0),
+            types: vec![colors, painter, coclass],
+            ..Default::default()
+        };
+
+        let idl = print(&lib);
+        assert!(idl.contains("library PaintLib"), "{}", idl);
+        assert!(idl.contains("        Red = 7,"), "{}", idl);
+        assert!(
+            idl.contains("HRESULT Paint ([in] enum Colors color);"),
+            "{}",
+            idl
+        );
+        assert!(
+            idl.contains("        [default] interface IPainter;"),
+            "{}",
+            idl
+        );
+    }
+
+    #[test]
+    fn skips_only_the_inherited_dispatch_members() {
+        let mut lib = fixture("simple.tlb");
+        let idl = print(&lib);
+        assert!(
+            idl.contains(
+                "    interface IRenderer : IUnknown {\n        [id(0x60010000)]\n        \
+                 HRESULT Fill ([in] long color);\n        [id(0x60010001)]\n        \
+                 HRESULT Bounds ([out, retval] Point* pt);\n    };\n"
+            ),
+            "{}",
+            idl
+        );
+
+        // IUnknown::QueryInterface, as OLE lists it in the dispatch half of
+        // a dual interface.
+        let shape = lib.types.iter_mut().find(|t| t.name == "IShape").unwrap();
+        let mut query = shape.funcs[0].clone();
+        query.name = "QueryInterface".to_string();
+        query.memid = 0x6000_0000;
+        query.func_kind = FuncKind::Dispatch;
+        shape.funcs.insert(0, query);
+        let idl = print(&lib);
+        assert!(!idl.contains("QueryInterface"), "{}", idl);
+        assert!(
+            idl.contains("HRESULT Name ([out, retval] BSTR* val);"),
+            "{}",
+            idl
+        );
+    }
+
+    #[test]
+    fn imports_every_referenced_library_once() {
+        let idl = print(&fixture("simple.tlb"));
+        assert!(
+            idl.contains("{\n    importlib(\"stdole2.tlb\");\n\n"),
+            "{}",
+            idl
+        );
+
+        let import = |file_name: &str| Import {
+            guid: guid(9),
+            version: (1, 0),
+            lcid: 0,
+            file_name: file_name.to_string(),
+        };
+        let lib = Library {
+            name: "ImportLib".to_string(),
+            imports: vec![
+                import("stdole2.tlb"),
+                import("C:\\Libs\\Shapes.tlb"),
+                import("stdole2.tlb"),
+            ],
+            ..Default::default()
+        };
+        let idl = print(&lib);
+        assert!(
+            idl.contains(
+                "    importlib(\"stdole2.tlb\");\n    importlib(\"C:\\\\Libs\\\\Shapes.tlb\");\n\n"
+            ),
+            "{}",
+            idl
+        );
+    }
+
+    #[test]
+    fn prints_unresolved_references_as_placeholders() {
+        let mut alias = TypeDef::new("Handle", TypeKind::Alias, guid(1));
+        alias.alias_of = Some(Type::Ptr(Box::new(Type::Named(TypeRef {
+            name: String::new(),
+            guid: None,
+            kind: None,
+            location: TypeLocation::Unresolved,
+            index: None,
+        }))));
+        let lib = Library {
+            name: "L".to_string(),
+            types: vec![alias],
+            ..Default::default()
+        };
+        let expected = format!(
+            "typedef [public, uuid({:?})] UnknownUserDefined* Handle;",
+            guid(1)
+        );
+        assert!(print(&lib).contains(&expected));
+    }
+
+    #[test]
+    fn formats_variant_literals() {
+        assert_eq!(
+            variant_to_string(&Variant::UI4(4_000_000_000)),
+            "4000000000"
+        );
+        assert_eq!(variant_to_string(&Variant::I8(-5)), "-5");
+        assert_eq!(variant_to_string(&Variant::R8(2.0)), "2.0");
+        assert_eq!(variant_to_string(&Variant::Cy(-15000)), "-1.5");
+        assert_eq!(variant_to_string(&Variant::Cy(30000)), "3");
+        assert_eq!(variant_to_string(&Variant::Decimal(12345, 3)), "12.345");
+        assert_eq!(
+            variant_to_string(&Variant::Error(-2147024809)),
+            "0x80070057"
+        );
+        assert_eq!(
+            variant_to_string(&Variant::Bstr("a\\b\t\"c\"\u{1}7".to_string())),
+            "\"a\\\\b\\t\\\"c\\\"\\0017\""
+        );
+    }
+
+    #[test]
+    fn prints_flag_enums_in_hex() {
+        let member = |name: &str, value: i32| Variable {
+            name: name.to_string(),
+            memid: 0,
+            kind: VarKind::Const,
+            flags: 0,
+            ty: Type::Base(VT_I4),
+            value: Some(Variant::Int(value)),
+            offset: 0,
+            doc: Doc::default(),
+            custom_data: Vec::new(),
+        };
+        let mut access = TypeDef::new("Access", TypeKind::Enum, guid(1));
+        access.vars = vec![
+            member("None", 0),
+            member("Read", 1),
+            member("Write", 2),
+            member("Delete", 4),
+            member("All", 7),
+        ];
+        let mut colors = TypeDef::new("Colors", TypeKind::Enum, guid(2));
+        colors.vars = vec![member("Red", 1), member("Green", 2), member("Both", 3)];
+        let lib = Library {
+            name: "L".to_string(),
+            types: vec![access, colors],
+            ..Default::default()
+        };
+        let idl = print(&lib);
+        assert!(idl.contains("        Delete = 0x00000004,\n        All = 0x00000007,"));
+        assert!(idl.contains("        Both = 3,"), "{}", idl);
+    }
+
+    /// Strings MIDL or a naive printer would get wrong.
+    const ADVERSARIAL: &[&str] = &[
+        "say \"hi\"",
+        "C:\\path\\",
+        "ends with a backslash \\",
+        "line one\nline two\r\n",
+        "tab\there",
+        "\\x41 is not an escape, \\101 neither",
+        "*/ /* // not a comment",
+        "nul\0byte and \u{7f} and \u{1b}[0m",
+        "Grüße – ünïcödé ✓ 日本",
+        "\u{1}7 octal must stop after three digits",
+        "",
+    ];
+
+    /// The text of every string literal in `idl`, decoded the way a C
+    /// tokenizer reads them. Comments are skipped; a raw control character
+    /// inside a literal fails the test.
+    fn string_literals(idl: &str) -> Vec<String> {
+        let b = idl.as_bytes();
+        let mut literals = Vec::new();
+        let mut i = 0;
+        while i < b.len() {
+            if b[i..].starts_with(b"//") {
+                i += b[i..]
+                    .iter()
+                    .position(|&c| c == b'\n')
+                    .unwrap_or(b.len() - i);
+                continue;
+            }
+            if b[i] != b'"' {
+                i += 1;
+                continue;
+            }
+            i += 1;
+            let mut text = Vec::new();
+            loop {
+                let c = b[i];
+                i += 1;
+                match c {
+                    b'"' => break,
+                    b'\\' => {
+                        let e = b[i];
+                        i += 1;
+                        match e {
+                            b'n' => text.push(b'\n'),
+                            b'r' => text.push(b'\r'),
+                            b't' => text.push(b'\t'),
+                            b'\\' | b'"' | b'\'' | b'?' => text.push(e),
+                            b'0'..=b'7' => {
+                                let mut v = (e - b'0') as u32;
+                                for _ in 0..2 {
+                                    if matches!(b[i], b'0'..=b'7') {
+                                        v = v * 8 + (b[i] - b'0') as u32;
+                                        i += 1;
+                                    }
+                                }
+                                text.push(v as u8);
+                            }
+                            other => panic!("unknown escape \\{}", other as char),
+                        }
+                    }
+                    c if c < 0x20 || c == 0x7f => panic!("raw control byte {:#x} in a literal", c),
+                    c => text.push(c),
                 }
             }
-            VT_EMPTY => "".to_string(),
-            VT_NULL => "null".to_string(),
-            _ => format!("/* vt: {} */", variant.Anonymous.Anonymous.vt.0),
+            literals.push(decode_ansi(&text));
+        }
+        literals
+    }
+
+    #[test]
+    fn string_literals_round_trip() {
+        for s in ADVERSARIAL {
+            let literal = string_literal(s);
+            assert!(literal.is_ascii(), "{}", literal);
+            assert_eq!(string_literals(&literal), [s.to_string()], "{}", literal);
         }
     }
+
+    #[test]
+    fn every_printed_string_round_trips() {
+        let custom = |s: &str| CustData {
+            guid: guid(9),
+            value: Variant::Bstr(s.to_string()),
+        };
+        let doc = |s: &str| Doc {
+            help_string: s.to_string(),
+            ..Doc::default()
+        };
+        let mut lib = Library {
+            name: "L".to_string(),
+            guid: guid(0),
+            ..Default::default()
+        };
+        for (i, s) in ADVERSARIAL.iter().enumerate() {
+            lib.doc.help_string.push_str(s);
+            lib.custom_data.push(custom(s));
+
+            let mut iface =
+                TypeDef::new(&format!("I{}", i), TypeKind::Interface, guid(i as u128 + 1));
+            iface.doc = doc(s);
+            iface.custom_data.push(custom(s));
+            iface.funcs.push(Function {
+                name: "F".to_string(),
+                memid: 1,
+                invoke_kind: InvokeKind::Func,
+                func_kind: FuncKind::PureVirtual,
+                call_conv: CallConv::StdCall,
+                flags: 0,
+                vtable_offset: 24,
+                params: vec![Param {
+                    name: Some("p".to_string()),
+                    ty: Type::Base(VT_BSTR),
+                    flags: PARAMFLAG_FIN | PARAMFLAG_FOPT | PARAMFLAG_FHASDEFAULT,
+                    default_value: Some(Variant::Bstr(s.to_string())),
+                    custom_data: Vec::new(),
+                }],
+                optional_params: 0,
+                ret: Type::Base(VT_HRESULT),
+                entry: None,
+                doc: doc(s),
+                custom_data: Vec::new(),
+            });
+            lib.types.push(iface);
+
+            let mut module =
+                TypeDef::new(&format!("M{}", i), TypeKind::Module, guid(i as u128 + 100));
+            module.dll_name = Some(s.to_string());
+            module.doc = doc(s);
+            module.vars.push(Variable {
+                name: "S".to_string(),
+                memid: 0,
+                kind: VarKind::Const,
+                flags: 0,
+                ty: Type::Base(VT_BSTR),
+                value: Some(Variant::Bstr(s.to_string())),
+                offset: 0,
+                doc: Doc::default(),
+                custom_data: Vec::new(),
+            });
+            module.funcs.push(Function {
+                name: "E".to_string(),
+                func_kind: FuncKind::Static,
+                vtable_offset: 0,
+                params: Vec::new(),
+                ret: Type::Base(VT_VOID),
+                entry: Some(DllEntry::Name(s.to_string())),
+                ..lib.types[lib.types.len() - 1].funcs[0].clone()
+            });
+            lib.types.push(module);
+        }
+
+        let idl = print(&lib);
+        assert!(idl.is_ascii());
+        let literals = string_literals(&idl);
+        let count = |s: &str| literals.iter().filter(|l| l.as_str() == s).count();
+        assert_eq!(count(&ADVERSARIAL.concat()), 1, "{}", idl);
+        for s in &ADVERSARIAL[..ADVERSARIAL.len() - 1] {
+            // Library and interface custom(), the interface, method and
+            // module helpstrings, the defaultvalue, dllname, the constant,
+            // and the entry point with its helpstring.
+            assert_eq!(count(s), 10, "{:?} in\n{}", s, idl);
+        }
+    }
+
+    #[test]
+    fn prints_array_dimensions_after_the_name() {
+        let idl = print(&fixture("simple.tlb"));
+        assert!(
+            idl.contains(
+                "    typedef [uuid(5A1E0A00-0008-4B1D-9C2E-0123456789AB), \
+                 helpstring(\"A grid of cells\")] struct tagMatrix {\n\
+                 \x20       long cells[4][8];\n\
+                 \x20       FILETIME stamp;\n\
+                 \x20       CLSID id;\n\
+                 \x20       BLOB extra;\n\
+                 \x20       long* count;\n\
+                 \x20   } Matrix;\n"
+            ),
+            "{}",
+            idl
+        );
+        let idl = print(&fixture("legacy_sltg.tlb"));
+        assert!(
+            idl.contains("HRESULT Fill ([in] long cells[4]);"),
+            "{}",
+            idl
+        );
+    }
+
+    #[test]
+    fn prints_unions_and_record_packing() {
+        let idl = print(&fixture("simple.tlb"));
+        assert!(
+            idl.contains(
+                "    typedef [uuid(5A1E0A00-0009-4B1D-9C2E-0123456789AB), \
+                 helpstring(\"One of three values\")] union tagValue {\n        long l;\n"
+            ),
+            "{}",
+            idl
+        );
+        assert!(idl.contains(
+            "    #pragma pack(push, 1)\n    typedef struct tagPacket {\n\
+             \x20       unsigned char tag;\n\
+             \x20       long value;\n\
+             \x20       short extra;\n\
+             \x20   } Packet;\n    #pragma pack(pop)\n"
+        ));
+        assert!(!idl.contains("#pragma pack(push, 1)\n    typedef struct tagPoint"));
+
+        let mut gap = TypeDef::new("Gap", TypeKind::Record, guid(1));
+        gap.size = 12;
+        for (name, offset) in [("a", 0), ("b", 8)] {
+            gap.vars.push(Variable {
+                name: name.to_string(),
+                memid: 0,
+                kind: VarKind::PerInstance,
+                flags: 0,
+                ty: Type::Base(VT_I4),
+                value: None,
+                offset,
+                doc: Doc::default(),
+                custom_data: Vec::new(),
+            });
+        }
+        let lib = Library {
+            name: "GapLib".to_string(),
+            types: vec![gap],
+            ..Default::default()
+        };
+        let idl = print(&lib);
+        assert!(
+            idl.contains("        long a; // offset 0\n        long b; // offset 8\n"),
+            "{}",
+            idl
+        );
+    }
+
+    /// The simple fixture, loaded for the TUI, and the index of `name` in it.
+    fn simple_type(name: &str) -> (TypeLibInfo, u32) {
+        let path =
+            std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/simple.tlb");
+        let mut info = TypeLibInfo::new();
+        info.load_type_lib(&path, None, &Config::default()).unwrap();
+        let index = info
+            .lib()
+            .unwrap()
+            .types
+            .iter()
+            .position(|t| t.name == name);
+        (info, index.unwrap() as u32)
+    }
+
+    #[test]
+    fn prints_pure_dispinterfaces_with_their_sections() {
+        let (info, index) = simple_type("_ShapeEvents");
+
+        let idl = info.get_type_idl(index).unwrap();
+        assert!(
+            idl.contains(
+                "    dispinterface _ShapeEvents {\n\
+                 \x20       properties:\n\
+                 \x20           [id(0x00000002), helpstring(\"Whether the shape is drawn\")] \
+                 VARIANT_BOOL Visible;\n\
+                 \x20           [id(0x00000003), readonly] long Id;\n\
+                 \x20       methods:\n\
+                 \x20           [id(0x00000001)]\n\
+                 \x20           void Moved ([in] long dx, [in] long dy);\n\
+                 \x20   };\n"
+            ),
+            "{}",
+            idl
+        );
+
+        // The accessors are still listed as methods.
+        let methods: Vec<_> = info
+            .get_type_methods(index)
+            .unwrap()
+            .into_iter()
+            .map(|m| (m.name, m.ret_type, m.invoke_kind))
+            .collect();
+        let method = |name: &str, ret: &str, kind: &str| {
+            (name.to_string(), ret.to_string(), kind.to_string())
+        };
+        assert_eq!(
+            methods,
+            vec![
+                method("Visible", "VARIANT_BOOL", "propget"),
+                method("Visible", "void", "propput"),
+                method("Id", "long", "propget"),
+                method("Moved", "void", "func"),
+            ]
+        );
+    }
+
+    #[test]
+    fn prints_function_flags_help_and_custom_data() {
+        let (info, index) = simple_type("IShape");
+
+        let idl = info.get_type_idl(index).unwrap();
+        assert!(
+            idl.contains(
+                "        [id(0x00000004), propget, bindable, displaybind, nonbrowsable, \
+                 helpcontext(0x00000020)]\n"
+            ),
+            "{}",
+            idl
+        );
+        assert!(idl.contains(
+            "        [id(0x00000005), restricted, hidden, \
+             custom(5A1E0A00-00FF-4B1D-9C2E-0123456789AB, \"center\")]\n"
+        ));
+
+        let methods = info.get_type_methods(index).unwrap();
+        let flags: Vec<_> = methods.iter().map(|m| m.flags.join(" ")).collect();
+        assert_eq!(
+            flags,
+            [
+                "",
+                "",
+                "",
+                "bindable displaybind nonbrowsable",
+                "restricted hidden"
+            ]
+        );
+        assert_eq!(methods[3].help_context, 0x20);
+    }
+
+    #[test]
+    fn prints_module_functions() {
+        let idl = print(&fixture("simple.tlb"));
+        assert!(idl.contains("      dllname(\"simple.dll\"),\n"), "{}", idl);
+        assert!(idl.contains(
+            "        [entry(\"SimpleBeep\"), helpstring(\"Sounds a tone\")]\n\
+             \x20       long __stdcall Beep([in] long freq, [in] long duration);\n"
+        ));
+        assert!(idl.contains(
+            "        [entry(\"SimpleLog\"), vararg]\n\
+             \x20       void __cdecl Log([in] LPSTR format, [in] SAFEARRAY(VARIANT) args);\n"
+        ));
+        assert!(idl.contains(
+            "        [entry(7), usesgetlasterror]\n\
+             \x20       HRESULT __stdcall Version([out, retval] long* v);\n"
+        ));
+    }
 }
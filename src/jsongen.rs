@@ -0,0 +1,406 @@
+//! JSON export of a whole type library, for scripts and review tooling.
+//!
+//! The document is versioned through its top-level `schema_version`; fields
+//! are only ever added within a version. Output is deterministic: types and
+//! members keep their library order, object keys a fixed order, and every
+//! key is present even when empty (`null`, `""` or `[]`).
+//!
+//! Parameter and enum entries use the same spellings as the TUI
+//! ([`get_param_info`], [`get_enum_info`]); types are written in IDL syntax.
+
+use std::io::Write;
+
+use crate::idlgen::{decimal_string, get_enum_info, get_param_info, type_to_string};
+use crate::model::*;
+use crate::typelib::*;
+
+/// Version of the document layout; bumped on incompatible changes.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// A JSON value whose objects keep insertion order.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    /// `null`.
+    Null,
+    /// `true` or `false`.
+    Bool(bool),
+    /// A number without a fraction.
+    Int(i128),
+    /// Any other number.
+    Float(f64),
+    /// A string, escaped when written.
+    String(String),
+    /// An array.
+    Array(Vec<Json>),
+    /// An object, with its members in the order they are written.
+    Object(Vec<(&'static str, Json)>),
+}
+
+impl From<&str> for Json {
+    fn from(s: &str) -> Self {
+        Json::String(s.to_string())
+    }
+}
+
+impl From<String> for Json {
+    fn from(s: String) -> Self {
+        Json::String(s)
+    }
+}
+
+impl From<bool> for Json {
+    fn from(b: bool) -> Self {
+        Json::Bool(b)
+    }
+}
+
+macro_rules! json_int {
+    ($($t:ty),*) => {
+        $(impl From<$t> for Json {
+            fn from(v: $t) -> Self {
+                Json::Int(v as i128)
+            }
+        })*
+    };
+}
+
+json_int!(i8, i16, i32, i64, u8, u16, u32, u64, usize);
+
+impl<T: Into<Json>> From<Option<T>> for Json {
+    fn from(v: Option<T>) -> Self {
+        v.map_or(Json::Null, Into::into)
+    }
+}
+
+impl Json {
+    /// An array of `f` applied to each of `items`.
+    pub fn array<T, F>(items: &[T], f: F) -> Json
+    where
+        F: Fn(&T) -> Json,
+    {
+        Json::Array(items.iter().map(f).collect())
+    }
+
+    /// The pretty-printed document, terminated by a newline.
+    pub fn render(&self) -> String {
+        let mut out = Vec::new();
+        self.write(&mut out, 0)
+            .expect("writing to a Vec cannot fail");
+        out.push(b'\n');
+        String::from_utf8(out).expect("JSON output is UTF-8")
+    }
+
+    fn write<W: Write>(&self, out: &mut W, indent: usize) -> std::io::Result<()> {
+        match self {
+            Json::Null => write!(out, "null"),
+            Json::Bool(b) => write!(out, "{}", b),
+            Json::Int(v) => write!(out, "{}", v),
+            Json::Float(v) if v.is_finite() => write!(out, "{:?}", v),
+            Json::Float(_) => write!(out, "null"),
+            Json::String(s) => write_string(s, out),
+            Json::Array(items) if items.is_empty() => write!(out, "[]"),
+            Json::Array(items) => {
+                writeln!(out, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    write!(out, "{:width$}", "", width = indent + 2)?;
+                    item.write(out, indent + 2)?;
+                    writeln!(out, "{}", if i + 1 < items.len() { "," } else { "" })?;
+                }
+                write!(out, "{:width$}]", "", width = indent)
+            }
+            Json::Object(fields) if fields.is_empty() => write!(out, "{{}}"),
+            Json::Object(fields) => {
+                writeln!(out, "{{")?;
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    write!(out, "{:width$}", "", width = indent + 2)?;
+                    write_string(key, out)?;
+                    write!(out, ": ")?;
+                    value.write(out, indent + 2)?;
+                    writeln!(out, "{}", if i + 1 < fields.len() { "," } else { "" })?;
+                }
+                write!(out, "{:width$}}}", "", width = indent)
+            }
+        }
+    }
+}
+
+fn write_string<W: Write>(s: &str, out: &mut W) -> std::io::Result<()> {
+    write!(out, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(out, "\\\"")?,
+            '\\' => write!(out, "\\\\")?,
+            '\n' => write!(out, "\\n")?,
+            '\r' => write!(out, "\\r")?,
+            '\t' => write!(out, "\\t")?,
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32)?,
+            c => write!(out, "{}", c)?,
+        }
+    }
+    write!(out, "\"")
+}
+
+/// The JSON document for `lib`, terminated by a newline.
+pub fn to_json(lib: &Library) -> String {
+    library(lib).render()
+}
+
+fn library(lib: &Library) -> Json {
+    Json::Object(vec![
+        ("schema_version", SCHEMA_VERSION.into()),
+        (
+            "library",
+            Json::Object(vec![
+                ("name", lib.name.as_str().into()),
+                ("guid", lib.guid.to_string().into()),
+                ("version", version(lib.version)),
+                ("lcid", lib.lcid.into()),
+                ("syskind", lib.syskind.into()),
+                ("flags", lib.flags.into()),
+                ("help_file", lib.help_file.as_str().into()),
+                ("doc", doc(&lib.doc)),
+                ("custom_data", custom_data(&lib.custom_data)),
+                (
+                    "imports",
+                    Json::array(&lib.imports, |i| {
+                        Json::Object(vec![
+                            ("file_name", i.file_name.as_str().into()),
+                            ("guid", i.guid.to_string().into()),
+                            ("version", version(i.version)),
+                            ("lcid", i.lcid.into()),
+                        ])
+                    }),
+                ),
+            ]),
+        ),
+        ("types", Json::array(&lib.types, type_def)),
+    ])
+}
+
+fn type_def(ty: &TypeDef) -> Json {
+    let enum_values = if ty.kind == TypeKind::Enum {
+        ty.vars
+            .iter()
+            .filter_map(|v| get_enum_info(v).ok())
+            .map(|info| {
+                Json::Object(vec![
+                    ("name", info.name.into()),
+                    ("value", info.value.parse::<i32>().ok().into()),
+                ])
+            })
+            .collect()
+    } else {
+        Vec::new()
+    };
+    let fields = if ty.kind == TypeKind::Enum {
+        Vec::new()
+    } else {
+        ty.vars.iter().map(variable).collect()
+    };
+    Json::Object(vec![
+        ("name", ty.name.as_str().into()),
+        ("kind", ty.kind.name().into()),
+        (
+            "guid",
+            (!ty.guid.is_nil()).then(|| ty.guid.to_string()).into(),
+        ),
+        ("version", version(ty.version)),
+        ("flags", ty.flags.into()),
+        ("flag_names", flag_names(ty.flags)),
+        ("size", ty.size.into()),
+        ("alignment", ty.alignment.into()),
+        ("doc", doc(&ty.doc)),
+        ("custom_data", custom_data(&ty.custom_data)),
+        ("alias_of", ty.alias_of.as_ref().map(type_to_string).into()),
+        ("dll_name", ty.dll_name.as_deref().into()),
+        (
+            "impl_types",
+            Json::array(&ty.impl_types, |i| {
+                Json::Object(vec![
+                    ("name", i.target.name.as_str().into()),
+                    ("flags", i.flags.into()),
+                    ("custom_data", custom_data(&i.custom_data)),
+                ])
+            }),
+        ),
+        ("methods", Json::array(&ty.funcs, function)),
+        ("enum_values", Json::Array(enum_values)),
+        ("fields", Json::Array(fields)),
+    ])
+}
+
+fn function(func: &Function) -> Json {
+    Json::Object(vec![
+        ("name", func.name.as_str().into()),
+        ("memid", func.memid.into()),
+        (
+            "invoke_kind",
+            func.invoke_kind.idl_attribute().unwrap_or("func").into(),
+        ),
+        ("func_kind", format!("{:?}", func.func_kind).into()),
+        ("call_conv", format!("{:?}", func.call_conv).into()),
+        ("flags", func.flags.into()),
+        ("vtable_offset", func.vtable_offset.into()),
+        ("optional_params", func.optional_params.into()),
+        (
+            "entry",
+            func.entry
+                .as_ref()
+                .map(|entry| match entry {
+                    DllEntry::Name(name) => Json::from(name.as_str()),
+                    DllEntry::Ordinal(ordinal) => Json::from(*ordinal),
+                })
+                .into(),
+        ),
+        ("return_type", type_to_string(&func.ret).into()),
+        (
+            "params",
+            Json::Array(
+                func.params
+                    .iter()
+                    .enumerate()
+                    .map(|(i, p)| {
+                        let info = get_param_info(p, i);
+                        Json::Object(vec![
+                            ("name", info.name.into()),
+                            ("type", info.type_name.into()),
+                            ("flags", Json::array(&info.flags, |f| f.as_str().into())),
+                            (
+                                "default_value",
+                                p.default_value.as_ref().map(variant).into(),
+                            ),
+                            ("custom_data", custom_data(&p.custom_data)),
+                        ])
+                    })
+                    .collect(),
+            ),
+        ),
+        ("doc", doc(&func.doc)),
+        ("custom_data", custom_data(&func.custom_data)),
+    ])
+}
+
+fn variable(var: &Variable) -> Json {
+    Json::Object(vec![
+        ("name", var.name.as_str().into()),
+        ("memid", var.memid.into()),
+        ("var_kind", format!("{:?}", var.kind).into()),
+        ("flags", var.flags.into()),
+        ("type", type_to_string(&var.ty).into()),
+        ("offset", var.offset.into()),
+        ("value", var.value.as_ref().map(variant).into()),
+        ("doc", doc(&var.doc)),
+        ("custom_data", custom_data(&var.custom_data)),
+    ])
+}
+
+fn version((major, minor): (u16, u16)) -> Json {
+    format!("{}.{}", major, minor).into()
+}
+
+fn doc(doc: &Doc) -> Json {
+    Json::Object(vec![
+        ("help_string", doc.help_string.as_str().into()),
+        ("help_context", doc.help_context.into()),
+        ("help_string_context", doc.help_string_context.into()),
+    ])
+}
+
+fn custom_data(items: &[CustData]) -> Json {
+    Json::array(items, |c| {
+        Json::Object(vec![
+            ("guid", c.guid.to_string().into()),
+            ("value", variant(&c.value)),
+        ])
+    })
+}
+
+/// A VARIANT as `{"vt": <VARTYPE>, "value": ...}`. Currency and decimal
+/// values are strings so no precision is lost.
+fn variant(v: &Variant) -> Json {
+    let value = match v {
+        Variant::Empty | Variant::Null | Variant::Unsupported(_) => Json::Null,
+        Variant::I1(v) => (*v).into(),
+        Variant::I2(v) => (*v).into(),
+        Variant::I4(v) | Variant::Int(v) | Variant::Error(v) => (*v).into(),
+        Variant::I8(v) => (*v).into(),
+        Variant::UI1(v) => (*v).into(),
+        Variant::UI2(v) => (*v).into(),
+        Variant::UI4(v) | Variant::UInt(v) => (*v).into(),
+        Variant::UI8(v) => (*v).into(),
+        Variant::R4(v) => Json::Float(*v as f64),
+        Variant::R8(v) | Variant::Date(v) => Json::Float(*v),
+        Variant::Cy(v) => decimal_string(*v as i128, 4).into(),
+        Variant::Decimal(v, scale) => decimal_string(*v, *scale).into(),
+        Variant::Bool(v) => (*v).into(),
+        Variant::Bstr(s) => s.as_str().into(),
+    };
+    Json::Object(vec![("vt", v.vt().into()), ("value", value)])
+}
+
+fn flag_names(flags: u16) -> Json {
+    Json::Array(
+        TYPE_FLAG_NAMES
+            .iter()
+            .filter(|(flag, _)| flags & flag != 0)
+            .map(|(_, name)| (*name).into())
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::fixture;
+
+    fn render(value: &Json) -> String {
+        value.render().trim_end().to_string()
+    }
+
+    #[test]
+    fn escapes_strings() {
+        let value = Json::from("a \"quoted\" \\ path\n\u{1}é");
+        assert_eq!(render(&value), r#""a \"quoted\" \\ path\n\u0001é""#);
+    }
+
+    #[test]
+    fn formats_scaled_decimals() {
+        assert_eq!(decimal_string(12345, 4), "1.2345");
+        assert_eq!(decimal_string(-5, 4), "-0.0005");
+        assert_eq!(decimal_string(42, 0), "42");
+        assert_eq!(
+            render(&variant(&Variant::Cy(15000))),
+            "{\n  \"vt\": 6,\n  \"value\": \"1.5000\"\n}"
+        );
+    }
+
+    #[test]
+    fn output_is_deterministic() {
+        let first = to_json(&fixture("simple.tlb"));
+        let second = to_json(&fixture("simple.tlb"));
+        assert_eq!(first, second);
+        assert!(first.starts_with("{\n  \"schema_version\": 1,\n  \"library\": {\n"));
+        assert!(first.ends_with("}\n"));
+    }
+
+    #[test]
+    fn exports_members() {
+        let json = to_json(&fixture("simple.tlb"));
+        for expected in [
+            "\"name\": \"Unset\",\n          \"value\": -1",
+            "\"alias_of\": \"long\"",
+            "\"flag_names\": [\n        \"dual\",",
+            "\"invoke_kind\": \"propget\"",
+            "\"flags\": [\n                \"out\",\n                \"retval\"\n              ]",
+            "\"default_value\": {\n                \"vt\": 3,\n                \"value\": 5\n              }",
+            "\"name\": \"Answer\"",
+        ] {
+            assert!(
+                json.contains(expected),
+                "missing {:?} in\n{}",
+                expected,
+                json
+            );
+        }
+    }
+}
@@ -0,0 +1,77 @@
+//! Windows metadata (.winmd), IDL and Rust bindings from COM type libraries.
+//!
+//! [`Library::load`] reads a type library — a .tlb file in the MSFT or SLTG
+//! format, a `TYPELIB` resource of a DLL, OCX or EXE, or IDL/ODL source —
+//! into the owned [`model`], and every generator works from that model:
+//!
+//! - [`idlgen`] decompiles it to IDL;
+//! - [`winmd`] writes a .winmd for windows-bindgen, one or several libraries
+//!   at a time, and [`dotnet`] drives the WinmdGenerator SDK instead;
+//! - [`rustgen`] and [`jsongen`] dump Rust bindings and JSON;
+//! - [`diff`] compares two versions of a library;
+//! - [`idl`] reads IDL back into the model, and [`verify`] compares a
+//!   library with what its IDL reads back as.
+//!
+//! A [`Config`] (`tlb_winmd_gen.toml`) filters and renames the types of a
+//! library right after it is loaded. Every failure is an [`Error`], but
+//! for the writers to a [`std::io::Write`], which return its
+//! [`std::io::Error`]; what the IDL and the .winmd leave out or degrade is
+//! collected as [`diagnostics`].
+//!
+//! ```no_run
+//! use std::path::Path;
+//! use tlb_winmd_gen::{Config, diagnostics::Diagnostics, idlgen, reference::References, winmd};
+//!
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let config = Config::load(Some(Path::new("tlb_winmd_gen.toml")))?;
+//! let lib = config.load_library(Path::new("shapes.dll"), None)?;
+//!
+//! let mut idl = Vec::new();
+//! let mut diagnostics = Diagnostics::new();
+//! idlgen::print_library(&lib, &mut idl, &mut diagnostics)?;
+//!
+//! let partition = winmd::Partition {
+//!     lib: &lib,
+//!     namespace: "Contoso.Shapes".to_string(),
+//! };
+//! let image = winmd::write_partitions(
+//!     vec![partition],
+//!     "Contoso.Shapes",
+//!     None,
+//!     &References::default(),
+//!     &mut diagnostics,
+//! )?;
+//! std::fs::write("Contoso.Shapes.winmd", image)?;
+//! for d in diagnostics.entries() {
+//!     println!("cargo:warning={}: {}", d.path, d.message);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+#![warn(missing_docs)]
+
+pub mod chm;
+pub mod config;
+pub mod diagnostics;
+pub mod diff;
+pub mod dotnet;
+pub mod error;
+pub mod idl;
+pub mod idlgen;
+pub mod jsongen;
+pub mod model;
+pub mod reference;
+pub mod rustgen;
+pub mod verify;
+pub mod winmd;
+
+mod lzx;
+mod msft;
+mod pe;
+mod sltg;
+mod typelib;
+
+pub use config::Config;
+pub use error::Error;
+pub use model::Library;
@@ -0,0 +1,631 @@
+//! LZX decompression, as used by the `MSCompressed` section of CHM files.
+//!
+//! The decoder follows the LZX bitstream of the Microsoft Cabinet format
+//! (verbatim, aligned offset and uncompressed blocks, Intel E8 call
+//! translation) with the framing CHM adds on top: output is produced in
+//! 32 KiB frames, the bitstream is realigned to 16 bits after each frame and
+//! the decoder state is reset every `reset_interval` frames. Decoding always
+//! starts at such a reset point, so the window never has to be seeded with
+//! earlier output.
+
+use crate::error::Error;
+
+/// Uncompressed size of a frame; the CHM reset table has one entry per frame.
+pub const FRAME_SIZE: usize = 0x8000;
+
+const MIN_MATCH: usize = 2;
+const NUM_CHARS: usize = 256;
+const NUM_PRIMARY_LENGTHS: usize = 7;
+const NUM_SECONDARY_LENGTHS: usize = 249;
+const PRETREE_SYMBOLS: usize = 20;
+const ALIGNED_SYMBOLS: usize = 8;
+const MAX_CODE_LEN: u32 = 16;
+/// Slack after each length table: run codes may write past the range being
+/// read, and those lengths become the delta base of the next range.
+const LENTABLE_SAFETY: usize = 64;
+
+const BLOCKTYPE_VERBATIM: u32 = 1;
+const BLOCKTYPE_ALIGNED: u32 = 2;
+const BLOCKTYPE_UNCOMPRESSED: u32 = 3;
+
+fn corrupt(what: &str) -> Error {
+    Error::InvalidChm(format!("LZX: {}", what))
+}
+
+/// Number of position slots for a window of `1 << window_bits` bytes.
+fn position_slots(window_bits: u32) -> Result<usize, Error> {
+    match window_bits {
+        15 => Ok(30),
+        16 => Ok(32),
+        17 => Ok(34),
+        18 => Ok(36),
+        19 => Ok(38),
+        20 => Ok(42),
+        21 => Ok(50),
+        _ => Err(corrupt(&format!(
+            "unsupported window size 2^{}",
+            window_bits
+        ))),
+    }
+}
+
+/// Extra offset bits and base offset of each position slot.
+fn slot_tables() -> ([u8; 51], [u32; 51]) {
+    let mut extra_bits = [0u8; 51];
+    let mut position_base = [0u32; 51];
+    let mut j = 0;
+    for i in (0..50).step_by(2) {
+        extra_bits[i] = j;
+        extra_bits[i + 1] = j;
+        if i != 0 && j < 17 {
+            j += 1;
+        }
+    }
+    extra_bits[50] = 17;
+    let mut base = 0u32;
+    for (i, bits) in extra_bits.iter().enumerate() {
+        position_base[i] = base;
+        base += 1 << bits;
+    }
+    (extra_bits, position_base)
+}
+
+/// Reads 16-bit little-endian words and hands out their bits MSB first.
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    buf: u64,
+    left: u32,
+    overrun: usize,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        BitReader {
+            data,
+            pos: 0,
+            buf: 0,
+            left: 0,
+            overrun: 0,
+        }
+    }
+
+    /// Makes at least `n` bits available. Past the end of the input the
+    /// stream is padded with zeros, as the last frame may be decoded from a
+    /// peek that reaches beyond the final word.
+    fn ensure(&mut self, n: u32) -> Result<(), Error> {
+        while self.left < n {
+            let word = match self.data.get(self.pos..self.pos + 2) {
+                Some(b) => u16::from_le_bytes([b[0], b[1]]),
+                None => {
+                    self.overrun += 2;
+                    if self.overrun > 16 {
+                        return Err(corrupt("unexpected end of compressed data"));
+                    }
+                    0
+                }
+            };
+            self.pos += 2;
+            self.buf = (self.buf << 16) | word as u64;
+            self.left += 16;
+        }
+        Ok(())
+    }
+
+    fn peek(&self, n: u32) -> u32 {
+        ((self.buf >> (self.left - n)) & ((1 << n) - 1)) as u32
+    }
+
+    fn consume(&mut self, n: u32) {
+        self.left -= n;
+        self.buf &= (1 << self.left) - 1;
+    }
+
+    fn read(&mut self, n: u32) -> Result<u32, Error> {
+        if n == 0 {
+            return Ok(0);
+        }
+        self.ensure(n)?;
+        let value = self.peek(n);
+        self.consume(n);
+        Ok(value)
+    }
+
+    /// Drops the bits left in the current word; whole words already
+    /// buffered by a Huffman peek are kept.
+    fn align(&mut self) {
+        self.consume(self.left % 16);
+    }
+
+    /// Reads a byte of an uncompressed block; the bit buffer is empty there.
+    fn byte(&mut self) -> Result<u8, Error> {
+        let byte = self
+            .data
+            .get(self.pos)
+            .copied()
+            .ok_or_else(|| corrupt("uncompressed block runs past the end of the input"))?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    fn u32_le(&mut self) -> Result<u32, Error> {
+        let mut b = [0u8; 4];
+        for byte in &mut b {
+            *byte = self.byte()?;
+        }
+        Ok(u32::from_le_bytes(b))
+    }
+}
+
+/// A canonical Huffman code decoded through a single lookup table indexed by
+/// the next `bits` bits of input.
+struct Tree {
+    bits: u32,
+    /// `(symbol, code length)`, or `None` for prefixes no code starts with.
+    table: Vec<Option<(u16, u8)>>,
+}
+
+impl Tree {
+    fn empty() -> Tree {
+        Tree {
+            bits: 0,
+            table: Vec::new(),
+        }
+    }
+
+    fn build(lens: &[u8]) -> Result<Tree, Error> {
+        let bits = lens.iter().copied().max().unwrap_or(0) as u32;
+        if bits > MAX_CODE_LEN {
+            return Err(corrupt("Huffman code longer than 16 bits"));
+        }
+        let mut table = vec![None; 1 << bits];
+        let mut code = 0usize;
+        for len in 1..=bits {
+            for (symbol, _) in lens.iter().enumerate().filter(|(_, l)| **l as u32 == len) {
+                let span = 1 << (bits - len);
+                let start = code << (bits - len);
+                let slots = table
+                    .get_mut(start..start + span)
+                    .ok_or_else(|| corrupt("over-subscribed Huffman table"))?;
+                slots.fill(Some((symbol as u16, len as u8)));
+                code += 1;
+            }
+            code <<= 1;
+        }
+        Ok(Tree { bits, table })
+    }
+
+    fn decode(&self, bits: &mut BitReader) -> Result<usize, Error> {
+        if self.bits == 0 {
+            return Err(corrupt("symbol read from an empty Huffman table"));
+        }
+        bits.ensure(self.bits)?;
+        match self.table[bits.peek(self.bits) as usize] {
+            Some((symbol, len)) => {
+                bits.consume(len as u32);
+                Ok(symbol as usize)
+            }
+            None => Err(corrupt("invalid Huffman code")),
+        }
+    }
+}
+
+/// Reads the code lengths `first..last` of `lens` as deltas against their
+/// previous values, encoded with a pretree.
+fn read_lengths(
+    bits: &mut BitReader,
+    lens: &mut [u8],
+    first: usize,
+    last: usize,
+) -> Result<(), Error> {
+    let mut pretree_lens = [0u8; PRETREE_SYMBOLS];
+    for len in &mut pretree_lens {
+        *len = bits.read(4)? as u8;
+    }
+    let pretree = Tree::build(&pretree_lens)?;
+    let delta = |old: u8, z: usize| ((old as usize + 17 - z) % 17) as u8;
+
+    let mut x = first;
+    while x < last {
+        let (run, value) = match pretree.decode(bits)? {
+            17 => (bits.read(4)? as usize + 4, 0),
+            18 => (bits.read(5)? as usize + 20, 0),
+            19 => {
+                let run = bits.read(1)? as usize + 4;
+                let z = pretree.decode(bits)?;
+                if z > 16 {
+                    return Err(corrupt("invalid pretree run"));
+                }
+                (run, delta(lens[x], z))
+            }
+            z => (1, delta(lens[x], z)),
+        };
+        lens.get_mut(x..x + run)
+            .ok_or_else(|| corrupt("code length run overflows the table"))?
+            .fill(value);
+        x += run;
+    }
+    Ok(())
+}
+
+struct Decoder<'a> {
+    bits: BitReader<'a>,
+    out: Vec<u8>,
+    window_size: usize,
+    main_symbols: usize,
+    reset_interval: usize,
+    extra_bits: [u8; 51],
+    position_base: [u32; 51],
+
+    header_read: bool,
+    intel_filesize: i32,
+    intel_started: bool,
+    block_type: u32,
+    block_length: usize,
+    block_remaining: usize,
+    r: [usize; 3],
+    main_lens: Vec<u8>,
+    length_lens: Vec<u8>,
+    main_tree: Tree,
+    length_tree: Tree,
+    aligned_tree: Tree,
+}
+
+impl<'a> Decoder<'a> {
+    fn reset(&mut self) {
+        self.header_read = false;
+        self.intel_started = false;
+        self.block_type = 0;
+        self.block_length = 0;
+        self.block_remaining = 0;
+        self.r = [1, 1, 1];
+        self.main_lens.fill(0);
+        self.length_lens.fill(0);
+    }
+
+    fn read_block_header(&mut self) -> Result<(), Error> {
+        if self.block_type == BLOCKTYPE_UNCOMPRESSED && self.block_length % 2 == 1 {
+            self.bits.byte()?;
+        }
+        self.block_type = self.bits.read(3)?;
+        let hi = self.bits.read(16)? as usize;
+        let lo = self.bits.read(8)? as usize;
+        self.block_length = (hi << 8) | lo;
+        self.block_remaining = self.block_length;
+
+        match self.block_type {
+            BLOCKTYPE_ALIGNED | BLOCKTYPE_VERBATIM => {
+                if self.block_type == BLOCKTYPE_ALIGNED {
+                    let mut lens = [0u8; ALIGNED_SYMBOLS];
+                    for len in &mut lens {
+                        *len = self.bits.read(3)? as u8;
+                    }
+                    self.aligned_tree = Tree::build(&lens)?;
+                }
+                read_lengths(&mut self.bits, &mut self.main_lens, 0, NUM_CHARS)?;
+                read_lengths(
+                    &mut self.bits,
+                    &mut self.main_lens,
+                    NUM_CHARS,
+                    self.main_symbols,
+                )?;
+                self.main_tree = Tree::build(&self.main_lens[..self.main_symbols])?;
+                if self.main_lens[0xe8] != 0 {
+                    self.intel_started = true;
+                }
+                read_lengths(
+                    &mut self.bits,
+                    &mut self.length_lens,
+                    0,
+                    NUM_SECONDARY_LENGTHS,
+                )?;
+                self.length_tree = Tree::build(&self.length_lens[..NUM_SECONDARY_LENGTHS])?;
+            }
+            BLOCKTYPE_UNCOMPRESSED => {
+                self.intel_started = true;
+                // 1 to 16 bits of padding up to the next word. The header was
+                // just read with plain reads, so no whole word is buffered.
+                if self.bits.left == 0 {
+                    self.bits.ensure(16)?;
+                }
+                self.bits.left = 0;
+                self.bits.buf = 0;
+                for r in &mut self.r {
+                    *r = self.bits.u32_le()? as usize;
+                }
+            }
+            other => return Err(corrupt(&format!("invalid block type {}", other))),
+        }
+        Ok(())
+    }
+
+    fn read_match_offset(&mut self, slot: usize) -> Result<usize, Error> {
+        let offset = match slot {
+            0 => self.r[0],
+            1 => {
+                self.r.swap(0, 1);
+                self.r[0]
+            }
+            2 => {
+                self.r.swap(0, 2);
+                self.r[0]
+            }
+            _ => {
+                let extra = self.extra_bits[slot] as u32;
+                let base = self.position_base[slot] as usize - 2;
+                let offset = if self.block_type == BLOCKTYPE_ALIGNED && extra >= 3 {
+                    let verbatim = self.bits.read(extra - 3)? as usize;
+                    let aligned = self.aligned_tree.decode(&mut self.bits)?;
+                    base + (verbatim << 3) + aligned
+                } else {
+                    base + self.bits.read(extra)? as usize
+                };
+                self.r = [offset, self.r[0], self.r[1]];
+                offset
+            }
+        };
+        Ok(offset)
+    }
+
+    /// Decodes compressed symbols until `run` bytes are produced; the last
+    /// match may produce more, which is returned as a negative remainder.
+    fn decode_run(&mut self, frame_end: usize, run: usize) -> Result<isize, Error> {
+        let mut remaining = run as isize;
+        while remaining > 0 {
+            let main = self.main_tree.decode(&mut self.bits)?;
+            if main < NUM_CHARS {
+                self.out.push(main as u8);
+                remaining -= 1;
+                continue;
+            }
+            let main = main - NUM_CHARS;
+            let mut length = main & NUM_PRIMARY_LENGTHS;
+            if length == NUM_PRIMARY_LENGTHS {
+                length += self.length_tree.decode(&mut self.bits)?;
+            }
+            let length = length + MIN_MATCH;
+            let offset = self.read_match_offset(main >> 3)?;
+
+            let pos = self.out.len();
+            if offset == 0 || offset > pos || offset > self.window_size {
+                return Err(corrupt(&format!(
+                    "match offset {} before the start of the stream",
+                    offset
+                )));
+            }
+            if pos + length > frame_end {
+                return Err(corrupt("match crosses a frame boundary"));
+            }
+            for i in pos..pos + length {
+                let byte = self.out[i - offset];
+                self.out.push(byte);
+            }
+            remaining -= length as isize;
+        }
+        Ok(remaining)
+    }
+
+    fn decode_frame(&mut self, frame: usize, frame_size: usize) -> Result<(), Error> {
+        if self.reset_interval != 0 && frame.is_multiple_of(self.reset_interval) {
+            if self.block_remaining != 0 {
+                return Err(corrupt("block continues past a reset point"));
+            }
+            self.reset();
+        }
+        if !self.header_read {
+            self.intel_filesize = if self.bits.read(1)? == 1 {
+                let hi = self.bits.read(16)?;
+                let lo = self.bits.read(16)?;
+                ((hi << 16) | lo) as i32
+            } else {
+                0
+            };
+            self.header_read = true;
+        }
+
+        let frame_start = self.out.len();
+        let frame_end = frame_start + frame_size;
+        while self.out.len() < frame_end {
+            if self.block_remaining == 0 {
+                self.read_block_header()?;
+            }
+            let run = self.block_remaining.min(frame_end - self.out.len());
+            self.block_remaining -= run;
+            if self.block_type == BLOCKTYPE_UNCOMPRESSED {
+                for _ in 0..run {
+                    let byte = self.bits.byte()?;
+                    self.out.push(byte);
+                }
+                continue;
+            }
+            let remaining = self.decode_run(frame_end, run)?;
+            let overrun = remaining.unsigned_abs();
+            if overrun > self.block_remaining {
+                return Err(corrupt("match runs past the end of the block"));
+            }
+            self.block_remaining -= overrun;
+        }
+        self.bits.align();
+
+        if self.intel_started && self.intel_filesize != 0 && frame < 32768 && frame_size > 10 {
+            self.translate_e8(frame_start, frame_end);
+        }
+        Ok(())
+    }
+
+    /// Undoes the encoder's translation of relative CALL targets to
+    /// absolute ones.
+    fn translate_e8(&mut self, start: usize, end: usize) {
+        let filesize = self.intel_filesize;
+        let mut i = start;
+        let mut curpos = start as i32;
+        while i < end - 10 {
+            if self.out[i] != 0xe8 {
+                i += 1;
+                curpos += 1;
+                continue;
+            }
+            let bytes = [
+                self.out[i + 1],
+                self.out[i + 2],
+                self.out[i + 3],
+                self.out[i + 4],
+            ];
+            let abs = i32::from_le_bytes(bytes);
+            if abs >= -curpos && abs < filesize {
+                let rel = if abs >= 0 {
+                    abs - curpos
+                } else {
+                    abs + filesize
+                };
+                self.out[i + 1..i + 5].copy_from_slice(&rel.to_le_bytes());
+            }
+            i += 5;
+            curpos += 5;
+        }
+    }
+}
+
+/// Decompresses `out_len` bytes from an LZX stream starting at a reset
+/// point. `reset_interval` is the number of frames between resets (0 for a
+/// single, never reset stream).
+pub fn decompress(
+    input: &[u8],
+    window_bits: u32,
+    reset_interval: usize,
+    out_len: usize,
+) -> Result<Vec<u8>, Error> {
+    let main_symbols = NUM_CHARS + (position_slots(window_bits)? << 3);
+    let (extra_bits, position_base) = slot_tables();
+    let mut decoder = Decoder {
+        bits: BitReader::new(input),
+        out: Vec::with_capacity(out_len),
+        window_size: 1 << window_bits,
+        main_symbols,
+        reset_interval,
+        extra_bits,
+        position_base,
+        header_read: false,
+        intel_filesize: 0,
+        intel_started: false,
+        block_type: 0,
+        block_length: 0,
+        block_remaining: 0,
+        r: [1, 1, 1],
+        main_lens: vec![0; main_symbols + LENTABLE_SAFETY],
+        length_lens: vec![0; NUM_SECONDARY_LENGTHS + LENTABLE_SAFETY],
+        main_tree: Tree::empty(),
+        length_tree: Tree::empty(),
+        aligned_tree: Tree::empty(),
+    };
+
+    let mut frame = 0;
+    while decoder.out.len() < out_len {
+        let frame_size = FRAME_SIZE.min(out_len - decoder.out.len());
+        decoder.decode_frame(frame, frame_size)?;
+        frame += 1;
+    }
+    Ok(decoder.out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Packs `(value, bit count)` pairs into LZX's 16-bit little-endian words.
+    fn pack(fields: &[(u32, u32)]) -> Vec<u8> {
+        let mut words = Vec::new();
+        let (mut acc, mut n) = (0u32, 0u32);
+        for &(value, bits) in fields {
+            for i in (0..bits).rev() {
+                acc = (acc << 1) | ((value >> i) & 1);
+                n += 1;
+                if n == 16 {
+                    words.extend_from_slice(&(acc as u16).to_le_bytes());
+                    acc = 0;
+                    n = 0;
+                }
+            }
+        }
+        if n > 0 {
+            words.extend_from_slice(&((acc << (16 - n)) as u16).to_le_bytes());
+        }
+        words
+    }
+
+    /// Pretree code 18 runs (with the pretree above, code `1`) of `n` zeros.
+    fn zeros(mut n: u32) -> Vec<(u32, u32)> {
+        let mut fields = Vec::new();
+        while n > 0 {
+            let mut take = n.min(51);
+            if (1..20).contains(&(n - take)) {
+                take = n - 20;
+            }
+            fields.extend([(1, 1), (take - 20, 5)]);
+            n -= take;
+        }
+        fields
+    }
+
+    #[test]
+    fn decodes_uncompressed_block() {
+        let text = b"uncompressed LZX";
+        // No E8 header, block type 3, 24-bit length, then padding to the word.
+        let mut input = pack(&[(0, 1), (3, 3), (0, 16), (text.len() as u32, 8)]);
+        input.extend_from_slice(&[1, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0]);
+        input.extend_from_slice(text);
+        assert_eq!(decompress(&input, 16, 0, text.len()).unwrap(), text);
+    }
+
+    #[test]
+    fn decodes_verbatim_block_with_match() {
+        // Main tree: 'a' and 'b' plus one match symbol (slot 3, i.e. offset 1,
+        // length header 2 -> 4 bytes), all with 2-bit codes; lengths are sent
+        // as pretree deltas (17 - 2 = 15) with a 1-bit pretree {15, 18}.
+        let mut pretree = [(0, 4); PRETREE_SYMBOLS];
+        pretree[15] = (1, 4);
+        pretree[18] = (1, 4);
+        let sym15 = (0, 1);
+
+        let mut fields = vec![(0, 1), (1, 3), (0, 16), (7, 8)];
+        // Literals 0..256: zeros up to 'a' (97), 'a', 'b', zeros to 256.
+        fields.extend(pretree.iter().copied());
+        fields.extend(zeros(97));
+        fields.extend([sym15, sym15]);
+        fields.extend(zeros(157));
+        // Match symbols 256..512: slot 3 with length header 2 is symbol 282.
+        fields.extend(pretree.iter().copied());
+        fields.extend(zeros(26));
+        fields.push(sym15);
+        fields.extend(zeros(229));
+        // Empty length tree.
+        fields.extend(pretree.iter().copied());
+        fields.extend(zeros(249));
+        // Codes: 'a' = 00, 'b' = 01, match = 10.
+        fields.extend([(0, 2), (1, 2), (2, 2), (0, 2)]);
+        let input = pack(&fields);
+        assert_eq!(decompress(&input, 16, 0, 7).unwrap(), b"abbbbba");
+    }
+
+    #[test]
+    fn rejects_match_before_start() {
+        let mut pretree = [(0, 4); PRETREE_SYMBOLS];
+        pretree[15] = (1, 4);
+        pretree[18] = (1, 4);
+        let mut fields = vec![(0, 1), (1, 3), (0, 16), (4, 8)];
+        fields.extend(pretree.iter().copied());
+        fields.extend(zeros(97));
+        fields.extend([(0, 1), (0, 1)]);
+        fields.extend(zeros(157));
+        fields.extend(pretree.iter().copied());
+        fields.extend(zeros(26));
+        fields.push((0, 1));
+        fields.extend(zeros(229));
+        fields.extend(pretree.iter().copied());
+        fields.extend(zeros(249));
+        // The match comes first, with nothing to copy from.
+        fields.extend([(2, 2)]);
+        let err = decompress(&pack(&fields), 16, 0, 4).unwrap_err();
+        assert!(err.to_string().contains("before the start"), "{}", err);
+    }
+}
@@ -1,146 +1,794 @@
-mod error;
-mod idlgen;
 mod ui;
 use std::fs::{self, File};
 use std::io::{BufWriter, Write};
 use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::process::ExitCode;
 
-use clap::Parser;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+
+use tlb_winmd_gen::config::Config;
+use tlb_winmd_gen::diagnostics::Diagnostics;
+use tlb_winmd_gen::model::{self, Library};
+use tlb_winmd_gen::{diff, dotnet, error, idl, idlgen, jsongen, reference, rustgen, verify, winmd};
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
-struct Args {
-    /// Path to the input TLB file
-    tlb_path: PathBuf,
+struct Cli {
+    #[command(subcommand)]
+    command: Commands,
 
-    /// Output directory for intermediate files (IDL, proj, cpp)
-    #[arg(long, default_value = "proj")]
-    out_dir: PathBuf,
+    /// Configuration file with the namespace, renames, filters and other
+    /// settings (default: tlb_winmd_gen.toml in the current directory, if
+    /// there is one)
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+}
 
-    /// Output directory for the final .winmd file
-    #[arg(long, default_value = "out")]
-    winmd_dir: PathBuf,
+/// The type library to load.
+#[derive(Args, Debug)]
+struct Input {
+    /// Path to the input TLB file, a DLL/OCX/EXE with embedded TYPELIB
+    /// resources (`foo.dll\2` selects resource 2), or an IDL/ODL source
+    tlb_path: PathBuf,
 
-    /// Run in TUI mode to inspect the TypeLib
+    /// TYPELIB resource ID to load from a PE image (default: the first one)
     #[arg(long)]
-    ui: bool,
+    resource: Option<u16>,
+}
 
-    /// Import stdole2.tlb in the generated IDL
+/// What to do with the content generation leaves out or degrades.
+#[derive(Args, Debug)]
+struct DiagnosticsArgs {
+    /// Fail when anything was left out or degraded
     #[arg(long)]
-    import_stdole: bool,
+    deny_warnings: bool,
+
+    /// File to write the diagnostics to as JSON
+    #[arg(long, value_name = "FILE")]
+    diagnostics_json: Option<PathBuf>,
+}
+
+impl DiagnosticsArgs {
+    /// Prints the summary to standard error and writes the JSON file.
+    fn finish(&self, diagnostics: &Diagnostics) -> Result<(), error::Error> {
+        diagnostics
+            .write_summary(std::io::stderr().lock())
+            .map_err(error::Error::IoError)?;
+        if let Some(path) = &self.diagnostics_json {
+            fs::write(path, diagnostics.to_json()).map_err(|e| error::Error::io(path, e))?;
+        }
+        if self.deny_warnings && !diagnostics.is_empty() {
+            return Err(error::Error::WarningsDenied(diagnostics.len()));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum Commands {
+    /// Decompile the type library to IDL
+    Idl {
+        #[command(flatten)]
+        input: Input,
+
+        /// File to write the IDL to (default: standard output)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        #[command(flatten)]
+        diagnostics: DiagnosticsArgs,
+    },
+
+    /// Generate a .winmd file for windows-bindgen from one or more type
+    /// libraries
+    Winmd {
+        /// Type libraries to write, each in its own namespace: TLB files, or
+        /// DLL/OCX/EXE files with embedded TYPELIB resources (`foo.dll\2`
+        /// selects resource 2)
+        #[arg(required_unless_present = "manifest")]
+        tlb_paths: Vec<PathBuf>,
+
+        /// TYPELIB resource ID to load from PE images (default: the first
+        /// one)
+        #[arg(long)]
+        resource: Option<u16>,
+
+        /// File listing more type libraries, one per line as `PATH` or
+        /// `PATH = NAMESPACE`; relative paths are relative to the file
+        #[arg(long)]
+        manifest: Option<PathBuf>,
+
+        /// Namespace of the types of a library, by library name (default:
+        /// the configured namespace, or else the library name); repeatable
+        #[arg(
+            long = "namespace",
+            value_name = "LIBRARY=NAMESPACE",
+            value_parser = parse_assignment
+        )]
+        namespaces: Vec<(String, String)>,
+
+        /// Name of the .winmd file and assembly (default: the namespace of
+        /// the first library)
+        #[arg(long)]
+        name: Option<String>,
+
+        /// Output directory for the final .winmd file
+        #[arg(long, default_value = "out")]
+        winmd_dir: PathBuf,
+
+        /// Resolve the types of an imported library (named as in its
+        /// `importlib`, e.g. `stdole2.tlb`) to an existing .winmd file;
+        /// repeatable
+        #[arg(
+            long = "reference",
+            value_name = "IMPORT=WINMD",
+            value_parser = parse_assignment,
+            conflicts_with = "dotnet"
+        )]
+        references: Vec<(String, String)>,
+
+        /// Build the .winmd with `dotnet build` and the WinmdGenerator SDK
+        /// instead of the built-in writer
+        #[arg(long)]
+        dotnet: bool,
+
+        /// Output directory for the intermediate IDL, proj and cpp files of
+        /// the --dotnet build
+        #[arg(long, default_value = "proj", requires = "dotnet")]
+        out_dir: PathBuf,
+
+        /// Only write the intermediate files of the --dotnet build; skip
+        /// running `dotnet build`
+        #[arg(long, requires = "dotnet")]
+        no_build: bool,
+
+        #[command(flatten)]
+        diagnostics: DiagnosticsArgs,
+    },
+
+    /// Browse the type library in an interactive terminal UI
+    Ui {
+        #[command(flatten)]
+        input: Input,
+
+        /// Compiled HTML Help file whose topics are shown for the help
+        /// contexts of types and members
+        #[arg(long)]
+        chm: Option<PathBuf>,
+    },
+
+    /// Print a summary of the library attributes and its types
+    Info {
+        #[command(flatten)]
+        input: Input,
+    },
+
+    /// Compare two versions of a type library and classify every change as
+    /// binary-breaking, source-breaking or additive
+    Diff {
+        /// The old type library (TLB, or DLL/OCX/EXE with a TYPELIB resource)
+        old: PathBuf,
+
+        /// The new type library
+        new: PathBuf,
+
+        /// Output format
+        #[arg(long, value_enum, default_value_t = DiffFormat::Text)]
+        format: DiffFormat,
+    },
+
+    /// Dump the complete library contents as JSON, or as Rust bindings
+    Dump {
+        #[command(flatten)]
+        input: Input,
+
+        /// Output format
+        #[arg(long, value_enum, default_value_t = DumpFormat::Json)]
+        format: DumpFormat,
+
+        /// File to write to (default: standard output)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+
+    /// Decompile the type library to IDL, parse the IDL back and report
+    /// everything that differs from the library
+    Verify {
+        #[command(flatten)]
+        input: Input,
+
+        /// Output format
+        #[arg(long, value_enum, default_value_t = DiffFormat::Text)]
+        format: DiffFormat,
+
+        /// File to write the IDL that was checked to
+        #[arg(long, value_name = "FILE")]
+        idl: Option<PathBuf>,
+    },
 }
 
-fn main() -> Result<(), error::Error> {
-    let args = Args::parse();
-    let tlb_path = std::path::Path::new(&args.tlb_path);
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum DiffFormat {
+    Text,
+    Json,
+}
 
-    if args.ui {
-        if let Err(e) = ui::run(tlb_path.to_path_buf()) {
-            eprintln!("Error running TUI: {}", e);
-            std::process::exit(1);
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum DumpFormat {
+    /// Every attribute, type and member, in a versioned schema
+    Json,
+    /// Bindings in the style of `windows-core`, without a .winmd
+    Rust,
+}
+
+fn main() -> ExitCode {
+    match run(Cli::parse()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            ExitCode::from(exit_code(&e))
         }
-        return Ok(());
     }
+}
+
+/// Exit status of a failed command, by kind of failure. clap exits with 2
+/// on bad usage.
+fn exit_code(error: &error::Error) -> u8 {
+    use error::Error::*;
+    match error {
+        LoadFailed { .. } | InvalidTypeLib(_) | UnsupportedTypeKind { .. } | InvalidIdl { .. } => 3,
+        UnresolvedReference { .. } => 4,
+        ToolFailed { .. } => 5,
+        InvalidConfig(_) => 6,
+        InvalidChm(_) | InvalidWinmd(_) => 7,
+        Io { .. } | IoError(_) => 8,
+        WarningsDenied(_) => 9,
+        VerificationFailed(_) => 10,
+        TypeLibNotLoaded => 1,
+    }
+}
+
+fn run(cli: Cli) -> Result<(), error::Error> {
+    let config = Config::load(cli.config.as_deref())?;
+    match cli.command {
+        Commands::Idl {
+            input,
+            output,
+            diagnostics,
+        } => {
+            let mut found = Diagnostics::new();
+            run_idl(&input, output.as_deref(), &config, &mut found)?;
+            diagnostics.finish(&found)
+        }
+        Commands::Winmd {
+            tlb_paths,
+            resource,
+            manifest,
+            namespaces,
+            name,
+            winmd_dir,
+            references,
+            dotnet,
+            out_dir,
+            no_build,
+            diagnostics,
+        } => {
+            let mut inputs: Vec<(PathBuf, Option<String>)> =
+                tlb_paths.into_iter().map(|path| (path, None)).collect();
+            if let Some(manifest) = manifest {
+                inputs.extend(read_manifest(&manifest)?);
+            }
+            let options = WinmdOptions {
+                namespaces,
+                name,
+                winmd_dir,
+                references,
+            };
+            let mut found = Diagnostics::new();
+            run_winmd(
+                &inputs,
+                resource,
+                &options,
+                dotnet.then_some(DotnetOptions {
+                    out_dir,
+                    build: !no_build,
+                }),
+                &config,
+                &mut found,
+            )?;
+            diagnostics.finish(&found)
+        }
+        Commands::Ui { input, chm } => {
+            if let Err(e) = ui::run(input.tlb_path, input.resource, chm, &config) {
+                eprintln!("Error running TUI: {}", e);
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+        Commands::Info { input } => run_info(&input, &config),
+        Commands::Diff { old, new, format } => run_diff(&old, &new, format, &config),
+        Commands::Dump {
+            input,
+            format,
+            output,
+        } => run_dump(&input, format, output.as_deref(), &config),
+        Commands::Verify { input, format, idl } => {
+            run_verify(&input, format, idl.as_deref(), &config)
+        }
+    }
+}
+
+/// Opens `path` for writing, or standard output when there is none.
+fn output_writer(path: Option<&Path>) -> Result<Box<dyn Write>, error::Error> {
+    Ok(match path {
+        Some(path) => Box::new(BufWriter::new(
+            File::create(path).map_err(|e| error::Error::io(path, e))?,
+        )),
+        None => Box::new(BufWriter::new(std::io::stdout().lock())),
+    })
+}
+
+/// Names the file of a failure to write the output, which has no name when
+/// it goes to standard output.
+fn output_error(path: Option<&Path>) -> impl Fn(std::io::Error) -> error::Error + '_ {
+    move |e| match path {
+        Some(path) => error::Error::io(path, e),
+        None => error::Error::IoError(e),
+    }
+}
+
+/// `KEY=VALUE` of `--reference` and `--namespace`.
+fn parse_assignment(arg: &str) -> Result<(String, String), String> {
+    match arg.split_once('=') {
+        Some((key, value)) if !key.is_empty() && !value.is_empty() => {
+            Ok((key.to_string(), value.to_string()))
+        }
+        _ => Err(format!("expected KEY=VALUE, got `{}`", arg)),
+    }
+}
 
-    let out_dir = &args.out_dir;
-    let winmd_dir = &args.winmd_dir;
+/// The libraries of a `--manifest` file and their namespaces. Blank lines
+/// and lines starting with `#` are skipped.
+fn read_manifest(path: &Path) -> Result<Vec<(PathBuf, Option<String>)>, error::Error> {
+    let dir = path.parent().unwrap_or(Path::new(""));
+    let text = fs::read_to_string(path).map_err(|e| error::Error::io(path, e))?;
+    Ok(parse_manifest(&text)
+        .into_iter()
+        .map(|(tlb, namespace)| (dir.join(tlb), namespace))
+        .collect())
+}
 
-    // Ensure output directories exist
-    fs::create_dir_all(out_dir)?;
-    fs::create_dir_all(winmd_dir)?;
+fn parse_manifest(text: &str) -> Vec<(PathBuf, Option<String>)> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| match line.rsplit_once('=') {
+            Some((tlb, namespace)) => (
+                PathBuf::from(tlb.trim()),
+                Some(namespace.trim().to_string()),
+            ),
+            None => (PathBuf::from(line), None),
+        })
+        .collect()
+}
 
-    // Get library name from TLB
-    let lib_name = idlgen::get_library_name(tlb_path)?;
-    println!("Library Name: {}", lib_name);
+fn run_idl(
+    input: &Input,
+    output: Option<&Path>,
+    config: &Config,
+    diagnostics: &mut Diagnostics,
+) -> Result<(), error::Error> {
+    let lib = config.load_library(&input.tlb_path, input.resource)?;
+    let mut out = output_writer(output)?;
+    idlgen::print_decompiled(&lib, &input.tlb_path, &mut out, diagnostics)
+        .and_then(|()| out.flush())
+        .map_err(output_error(output))
+}
+
+/// Settings of `winmd` common to both paths.
+struct WinmdOptions {
+    namespaces: Vec<(String, String)>,
+    name: Option<String>,
+    winmd_dir: PathBuf,
+    references: Vec<(String, String)>,
+}
+
+/// Settings of the `dotnet build` path of `winmd`.
+struct DotnetOptions {
+    out_dir: PathBuf,
+    build: bool,
+}
+
+fn run_winmd(
+    inputs: &[(PathBuf, Option<String>)],
+    resource: Option<u16>,
+    options: &WinmdOptions,
+    dotnet: Option<DotnetOptions>,
+    config: &Config,
+    diagnostics: &mut Diagnostics,
+) -> Result<(), error::Error> {
+    if inputs.is_empty() {
+        return Err(error::Error::InvalidConfig("no type libraries given".to_string()));
+    }
+    let winmd_dir = options.winmd_dir.as_path();
+    fs::create_dir_all(winmd_dir).map_err(|e| error::Error::io(winmd_dir, e))?;
+
+    let mut libs = Vec::new();
+    for (tlb_path, namespace) in inputs {
+        let lib = config.load_library(tlb_path, resource)?;
+        println!("Library Name: {}", lib.name);
+        let namespace = namespace
+            .clone()
+            .or_else(|| {
+                options
+                    .namespaces
+                    .iter()
+                    .find(|(lib_name, _)| *lib_name == lib.name)
+                    .map(|(_, namespace)| namespace.clone())
+            })
+            .or_else(|| config.namespace.clone())
+            .unwrap_or_else(|| lib.name.clone());
+        libs.push((tlb_path.as_path(), lib, namespace));
+    }
+    let winmd_name = match &options.name {
+        Some(name) => name.clone(),
+        None => libs[0].2.clone(),
+    };
+    let version = config.winmd_version.map(|v| v.0);
+
+    let Some(dotnet) = dotnet else {
+        let mut refs = reference::References::default();
+        for (import, path) in &options.references {
+            let imported = libs
+                .iter()
+                .flat_map(|(_, lib, _)| &lib.imports)
+                .any(|i| reference::is_import_of(i, import));
+            if !imported {
+                return Err(error::Error::UnresolvedReference {
+                    name: import.clone(),
+                    reason: format!("no library imports it (--reference {}={})", import, path),
+                });
+            }
+            refs.insert(import, reference::Metadata::load(Path::new(path))?);
+        }
+        let partitions = libs
+            .iter()
+            .map(|(_, lib, namespace)| winmd::Partition {
+                lib,
+                namespace: namespace.clone(),
+            })
+            .collect();
+        let winmd_path = winmd_dir.join(format!("{}.winmd", winmd_name));
+        println!("Writing WinMD: {}", winmd_path.display());
+        let image =
+            winmd::write_partitions(partitions, &winmd_name, version, &refs, diagnostics)?;
+        fs::write(&winmd_path, image).map_err(|e| error::Error::io(&winmd_path, e))?;
+        println!("WinMD generation complete.");
+        return Ok(());
+    };
+
+    let out_dir = dotnet.out_dir.as_path();
+    fs::create_dir_all(out_dir).map_err(|e| error::Error::io(out_dir, e))?;
 
     // Generate IDL
-    let idl_path = out_dir.join(format!("{}.idl", lib_name));
-    println!("Generating IDL: {}", idl_path.display());
-    {
-        let file = File::create(&idl_path)?;
+    for (tlb_path, lib, _) in &libs {
+        let idl_path = out_dir.join(format!("{}.idl", lib.name));
+        println!("Generating IDL: {}", idl_path.display());
+        let file = File::create(&idl_path).map_err(|e| error::Error::io(&idl_path, e))?;
         let mut writer = BufWriter::new(file);
-        idlgen::build_tlb(tlb_path, &mut writer, args.import_stdole)?;
+        idlgen::print_decompiled(lib, tlb_path, &mut writer, diagnostics)
+            .and_then(|()| writer.flush())
+            .map_err(|e| error::Error::io(&idl_path, e))?;
     }
 
+    let partitions: Vec<(&str, &str)> = libs
+        .iter()
+        .map(|(_, lib, namespace)| (lib.name.as_str(), namespace.as_str()))
+        .collect();
     let proj_path = out_dir.join("generate.proj");
     println!("Generating Project File: {}", proj_path.display());
-    generate_proj(&proj_path, &lib_name, winmd_dir)?;
+    let version = config
+        .winmd_version
+        .map_or("255.255.255.255".to_string(), |v| v.to_string());
+    dotnet::generate_proj(&proj_path, &partitions, &winmd_name, &version, winmd_dir)?;
 
     let main_cpp_path = out_dir.join("main.cpp");
     println!("Generating main.cpp: {}", main_cpp_path.display());
-    generate_main_cpp(&main_cpp_path, &lib_name)?;
+    dotnet::generate_main_cpp(&main_cpp_path, &partitions)?;
 
-    // Check for dotnet
-    if !check_dotnet() {
-        eprintln!("Error: 'dotnet' command not found. Please install .NET SDK.");
+    if !dotnet.build {
+        println!("Skipping dotnet build.");
         return Ok(());
     }
 
+    // Check for dotnet
+    if !dotnet::check_dotnet() {
+        return Err(error::Error::ToolFailed {
+            tool: "dotnet".to_string(),
+            code: None,
+            output: "command not found; install the .NET SDK".to_string(),
+        });
+    }
+
     println!("Running dotnet build...");
-    run_dotnet_build(out_dir)?;
+    dotnet::run_dotnet_build(out_dir)?;
 
     println!("WinMD generation complete.");
     Ok(())
 }
 
-fn generate_proj(path: &Path, lib_name: &str, winmd_dir: &Path) -> Result<(), error::Error> {
-    let winmd_abs_path = std::fs::canonicalize(winmd_dir).unwrap_or(winmd_dir.to_path_buf());
-    let winmd_file_path = winmd_abs_path.join(format!("{}.winmd", lib_name));
-
-    let content = format!(
-        r#"<?xml version="1.0" encoding="utf-8"?>
-<Project Sdk="Microsoft.Windows.WinmdGenerator/0.65.8-preview">
-  <PropertyGroup Label="Globals">
-    <OutputWinmd>{}</OutputWinmd>
-    <WinmdVersion>255.255.255.255</WinmdVersion>
-    <IdlsRoot>$(MSBuildThisFileDirectory)</IdlsRoot>
-    <AdditionalIncludes>$(CompiledHeadersDir)</AdditionalIncludes>
-  </PropertyGroup>
-  <ItemGroup>
-    <Idls Include="$(IdlsRoot)\{}.idl"/>
-    <Headers Include="$(CompiledHeadersDir)\{}.h"/>
-    <Partition Include="main.cpp">
-      <TraverseFiles>@(Headers)</TraverseFiles>
-      <Namespace>{}</Namespace>
-    </Partition>
-  </ItemGroup>
-</Project>"#,
-        winmd_file_path.display(),
-        lib_name,
-        lib_name,
-        lib_name
-    );
-
-    let mut file = File::create(path)?;
-    file.write_all(content.as_bytes())?;
+fn run_info(input: &Input, config: &Config) -> Result<(), error::Error> {
+    let lib = config.load_library(&input.tlb_path, input.resource)?;
+    let embedded = Library::embedded(&input.tlb_path)?;
+    print_info(&lib, &embedded, std::io::stdout().lock()).map_err(error::Error::IoError)
+}
+
+fn print_info<W: Write>(
+    lib: &Library,
+    embedded: &[model::EmbeddedLibrary],
+    mut out: W,
+) -> std::io::Result<()> {
+    writeln!(
+        out,
+        "Library:   {} {}.{}",
+        lib.name, lib.version.0, lib.version.1
+    )?;
+    writeln!(out, "GUID:      {}", lib.guid)?;
+    writeln!(out, "LCID:      {:#x}", lib.lcid)?;
+    let syskind = match lib.syskind {
+        0 => "win16",
+        1 => "win32",
+        2 => "mac",
+        3 => "win64",
+        _ => "unknown",
+    };
+    writeln!(out, "SYSKIND:   {}", syskind)?;
+    if !lib.doc.help_string.is_empty() {
+        writeln!(out, "Help:      {}", lib.doc.help_string)?;
+    }
+    if !lib.help_file.is_empty() {
+        writeln!(out, "Help file: {}", lib.help_file)?;
+    }
+    for import in &lib.imports {
+        writeln!(
+            out,
+            "Imports:   {} ({}, {}.{})",
+            import.file_name, import.guid, import.version.0, import.version.1
+        )?;
+    }
+    // Every type library of a DLL, OCX or EXE, not only the one shown.
+    for e in embedded {
+        writeln!(
+            out,
+            "TYPELIB:   {} {} ({}, {}.{})",
+            e.resource, e.name, e.guid, e.version.0, e.version.1
+        )?;
+    }
+    writeln!(out, "Types:     {}", lib.types.len())?;
+    writeln!(out)?;
+    let width = lib.types.iter().map(|t| t.name.len()).max().unwrap_or(0);
+    for ty in &lib.types {
+        let guid = if ty.guid.is_nil() {
+            String::new()
+        } else {
+            ty.guid.to_string()
+        };
+        let line = format!("  {:<9}  {:<width$}  {}", ty.kind.name(), ty.name, guid);
+        writeln!(out, "{}", line.trim_end())?;
+    }
     Ok(())
 }
 
-fn generate_main_cpp(path: &Path, lib_name: &str) -> Result<(), error::Error> {
-    let content = format!(r#"#include "{}.h""#, lib_name);
-    let mut file = File::create(path)?;
-    file.write_all(content.as_bytes())?;
+fn run_dump(
+    input: &Input,
+    format: DumpFormat,
+    output: Option<&Path>,
+    config: &Config,
+) -> Result<(), error::Error> {
+    let lib = config.load_library(&input.tlb_path, input.resource)?;
+    let mut out = output_writer(output)?;
+    match format {
+        DumpFormat::Json => out.write_all(jsongen::to_json(&lib).as_bytes()),
+        DumpFormat::Rust => rustgen::write_rust(&lib, &mut out),
+    }
+    .and_then(|()| out.flush())
+    .map_err(output_error(output))
+}
+
+fn run_diff(
+    old: &Path,
+    new: &Path,
+    format: DiffFormat,
+    config: &Config,
+) -> Result<(), error::Error> {
+    let old = config.load_library(old, None)?;
+    let new = config.load_library(new, None)?;
+    let changes = diff::diff_libraries(&old, &new);
+    match format {
+        DiffFormat::Text => diff::write_text(&old, &new, &changes, std::io::stdout().lock())
+            .map_err(error::Error::IoError)?,
+        DiffFormat::Json => print!("{}", diff::to_json(&old, &new, &changes)),
+    }
     Ok(())
 }
 
-fn check_dotnet() -> bool {
-    Command::new("dotnet").arg("--version").output().is_ok()
+fn run_verify(
+    input: &Input,
+    format: DiffFormat,
+    idl_path: Option<&Path>,
+    config: &Config,
+) -> Result<(), error::Error> {
+    let lib = config.load_library(&input.tlb_path, input.resource)?;
+    let mut text = Vec::new();
+    idlgen::print_library(&lib, &mut text, &mut Diagnostics::new())
+        .map_err(error::Error::IoError)?;
+    if let Some(path) = idl_path {
+        fs::write(path, &text).map_err(|e| error::Error::io(path, e))?;
+    }
+    // The imported libraries are looked up next to the input, as they were
+    // when it was loaded.
+    let dir = input.tlb_path.parent();
+    let parsed = idl::parse_in(&String::from_utf8_lossy(&text), dir)?;
+    let discrepancies = verify::compare(&lib, &parsed);
+    match format {
+        DiffFormat::Text => verify::write_text(&lib, &discrepancies, std::io::stdout().lock())
+            .map_err(error::Error::IoError)?,
+        DiffFormat::Json => print!("{}", verify::to_json(&lib, &discrepancies)),
+    }
+    if discrepancies.is_empty() {
+        Ok(())
+    } else {
+        Err(error::Error::VerificationFailed(discrepancies.len()))
+    }
 }
 
-fn run_dotnet_build(proj_dir: &Path) -> Result<(), error::Error> {
-    let status = Command::new("dotnet")
-        .arg("build")
-        .arg("generate.proj")
-        .current_dir(proj_dir)
-        .status()?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
 
-    if !status.success() {
-        // Return a generic IO error for build failure, as we don't have a specific error variant for it yet
-        return Err(error::Error::IoError(std::io::Error::new(
-            std::io::ErrorKind::Other,
-            "dotnet build failed",
-        )));
+    #[test]
+    fn cli_is_well_formed() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn failures_have_distinct_exit_codes() {
+        let code = |args: &[&str]| {
+            let cli = Cli::try_parse_from([&["tlb_winmd_gen"], args].concat()).unwrap();
+            exit_code(&run(cli).unwrap_err())
+        };
+        let tlb = format!("{}/tests/fixtures/simple.tlb", env!("CARGO_MANIFEST_DIR"));
+        let out = std::env::temp_dir();
+        assert_eq!(code(&["info", "missing.tlb"]), 3);
+        assert_eq!(
+            code(&[
+                "winmd",
+                &tlb,
+                "--winmd-dir",
+                out.to_str().unwrap(),
+                "--reference",
+                "shapes.tlb=Shapes.winmd",
+            ]),
+            4
+        );
+        assert_eq!(code(&["--config", "missing.toml", "info", &tlb]), 6);
+        assert_eq!(code(&["winmd", &tlb, "--manifest", "missing.txt"]), 8);
+        let empty = out.join(format!("empty-{}.txt", std::process::id()));
+        fs::write(&empty, "# Nothing yet\n").unwrap();
+        assert_eq!(code(&["winmd", "--manifest", empty.to_str().unwrap()]), 6);
+        fs::remove_file(&empty).unwrap();
+        // The fixtures round-trip, so no run of verify fails.
+        assert_eq!(exit_code(&error::Error::VerificationFailed(1)), 10);
+    }
+
+    #[test]
+    fn verifies_against_the_imported_libraries_next_to_the_input() {
+        let tlb = format!("{}/tests/fixtures/consumer.tlb", env!("CARGO_MANIFEST_DIR"));
+        let cli = Cli::try_parse_from(["tlb_winmd_gen", "verify", &tlb]).unwrap();
+        assert!(run(cli).is_ok());
+    }
+
+    #[test]
+    fn denies_warnings() {
+        let tlb = format!("{}/tests/fixtures/simple.tlb", env!("CARGO_MANIFEST_DIR"));
+        let out = std::env::temp_dir().join("tlb_winmd_gen_denies_warnings");
+        let json = out.join("diagnostics.json");
+        let cli = Cli::try_parse_from([
+            "tlb_winmd_gen",
+            "winmd",
+            &tlb,
+            "--winmd-dir",
+            out.to_str().unwrap(),
+            "--diagnostics-json",
+            json.to_str().unwrap(),
+            "--deny-warnings",
+        ])
+        .unwrap();
+        // The CURRENCY constant has no metadata equivalent.
+        let error = run(cli).unwrap_err();
+        assert_eq!(exit_code(&error), 9);
+        let json = fs::read_to_string(json).unwrap();
+        assert!(json.contains("\"path\": \"Constants.Price\""), "{}", json);
+    }
+
+    #[test]
+    fn dotnet_options_require_dotnet() {
+        assert!(Cli::try_parse_from(["tlb_winmd_gen", "winmd", "a.tlb", "--no-build"]).is_err());
+        let cli =
+            Cli::try_parse_from(["tlb_winmd_gen", "winmd", "a.tlb", "--dotnet", "--no-build"])
+                .unwrap();
+        assert!(matches!(
+            cli.command,
+            Commands::Winmd {
+                dotnet: true,
+                no_build: true,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn winmd_takes_several_libraries() {
+        assert!(Cli::try_parse_from(["tlb_winmd_gen", "winmd"]).is_err());
+        let cli = Cli::try_parse_from([
+            "tlb_winmd_gen",
+            "winmd",
+            "a.tlb",
+            "b.dll\\2",
+            "--namespace",
+            "ALib=Contoso.A",
+            "--name",
+            "Contoso",
+        ])
+        .unwrap();
+        let Commands::Winmd {
+            tlb_paths,
+            namespaces,
+            name,
+            ..
+        } = cli.command
+        else {
+            panic!("not winmd");
+        };
+        assert_eq!(tlb_paths.len(), 2);
+        assert_eq!(namespaces, [("ALib".to_string(), "Contoso.A".to_string())]);
+        assert_eq!(name.as_deref(), Some("Contoso"));
+        assert!(Cli::try_parse_from(["tlb_winmd_gen", "winmd", "--manifest", "libs.txt"]).is_ok());
+    }
+
+    #[test]
+    fn parses_manifests() {
+        let manifest = "# Suite\n\nshapes.tlb\n  canvas.dll\\2 = Contoso.Canvas  \n";
+        assert_eq!(
+            parse_manifest(manifest),
+            [
+                (PathBuf::from("shapes.tlb"), None),
+                (
+                    PathBuf::from("canvas.dll\\2"),
+                    Some("Contoso.Canvas".to_string())
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn config_is_a_global_option() {
+        let cli = Cli::try_parse_from(["tlb_winmd_gen", "info", "a.tlb", "--config", "suite.toml"])
+            .unwrap();
+        assert_eq!(cli.config, Some(PathBuf::from("suite.toml")));
+        let cli = Cli::try_parse_from(["tlb_winmd_gen", "idl", "a.tlb"]).unwrap();
+        assert_eq!(cli.config, None);
+    }
+
+    #[test]
+    fn idl_defaults_to_stdout() {
+        let cli =
+            Cli::try_parse_from(["tlb_winmd_gen", "idl", "foo.dll", "--resource", "2"]).unwrap();
+        let Commands::Idl { input, output, .. } = cli.command else {
+            panic!("not idl");
+        };
+        assert_eq!(input.resource, Some(2));
+        assert_eq!(output, None);
     }
-    Ok(())
 }
@@ -0,0 +1,1453 @@
+//! Owned, platform-neutral model of a type library.
+//!
+//! The decoded form the readers produce mirrors the OLE descriptors: types
+//! refer to each other through `HRefType` handles that only mean something
+//! together with the library's reference table. This model resolves all of
+//! that once, when a library is loaded, so the IDL printer, the TUI and the
+//! emitters can walk plain owned data. It can also be built by hand, which is
+//! how the printers are tested.
+
+use std::path::Path;
+
+use crate::error::Error;
+use crate::msft;
+use crate::pe;
+use crate::sltg;
+use crate::typelib::{self, *};
+
+pub use crate::typelib::{CustData, DllEntry, Guid, Variant};
+// The codes and flags the fields of the model hold, as OLE defines them.
+pub use crate::typelib::{
+    FUNCFLAG_FBINDABLE, FUNCFLAG_FDEFAULTBIND, FUNCFLAG_FDEFAULTCOLLELEM, FUNCFLAG_FDISPLAYBIND,
+    FUNCFLAG_FHIDDEN, FUNCFLAG_FIMMEDIATEBIND, FUNCFLAG_FNONBROWSABLE, FUNCFLAG_FREPLACEABLE,
+    FUNCFLAG_FREQUESTEDIT, FUNCFLAG_FRESTRICTED, FUNCFLAG_FSOURCE, FUNCFLAG_FUIDEFAULT,
+    FUNCFLAG_FUSESGETLASTERROR, IMPLTYPEFLAG_FDEFAULT, IMPLTYPEFLAG_FDEFAULTVTABLE,
+    IMPLTYPEFLAG_FRESTRICTED, IMPLTYPEFLAG_FSOURCE, PARAMFLAG_FHASDEFAULT, PARAMFLAG_FIN,
+    PARAMFLAG_FLCID, PARAMFLAG_FOPT, PARAMFLAG_FOUT, PARAMFLAG_FRETVAL, SYS_MAC, SYS_WIN16,
+    SYS_WIN32, SYS_WIN64, TYPEFLAG_FAGGREGATABLE, TYPEFLAG_FAPPOBJECT, TYPEFLAG_FCANCREATE,
+    TYPEFLAG_FCONTROL, TYPEFLAG_FDISPATCHABLE, TYPEFLAG_FDUAL, TYPEFLAG_FHIDDEN,
+    TYPEFLAG_FLICENSED, TYPEFLAG_FNONEXTENSIBLE, TYPEFLAG_FOLEAUTOMATION, TYPEFLAG_FPREDECLID,
+    TYPEFLAG_FPROXY, TYPEFLAG_FREPLACEABLE, TYPEFLAG_FRESTRICTED, TYPEFLAG_FREVERSEBIND,
+    VARFLAG_FBINDABLE, VARFLAG_FDEFAULTBIND, VARFLAG_FDEFAULTCOLLELEM, VARFLAG_FDISPLAYBIND,
+    VARFLAG_FHIDDEN, VARFLAG_FIMMEDIATEBIND, VARFLAG_FNONBROWSABLE, VARFLAG_FREADONLY,
+    VARFLAG_FREPLACEABLE, VARFLAG_FREQUESTEDIT, VARFLAG_FRESTRICTED, VARFLAG_FSOURCE,
+    VARFLAG_FUIDEFAULT, VT_ARRAY, VT_BLOB, VT_BLOB_OBJECT, VT_BOOL, VT_BSTR, VT_BYREF, VT_CARRAY,
+    VT_CF, VT_CLSID, VT_CY, VT_DATE, VT_DECIMAL, VT_DISPATCH, VT_EMPTY, VT_ERROR, VT_FILETIME,
+    VT_HRESULT, VT_I1, VT_I2, VT_I4, VT_I8, VT_INT, VT_INT_PTR, VT_LPSTR, VT_LPWSTR, VT_NULL,
+    VT_PTR, VT_R4, VT_R8, VT_RECORD, VT_RESERVED, VT_SAFEARRAY, VT_STORAGE, VT_STORED_OBJECT,
+    VT_STREAM, VT_STREAMED_OBJECT, VT_TYPEMASK, VT_UI1, VT_UI2, VT_UI4, VT_UI8, VT_UINT,
+    VT_UINT_PTR, VT_UNKNOWN, VT_USERDEFINED, VT_VARIANT, VT_VECTOR, VT_VOID,
+};
+
+/// What a type is, after OLE's `TYPEKIND`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TypeKind {
+    /// An `enum`.
+    Enum,
+    /// A `struct`.
+    Record,
+    /// A `module` of constants and DLL entry points.
+    Module,
+    /// An `interface` called through its vtable.
+    Interface,
+    /// A `dispinterface`, called through `IDispatch::Invoke`.
+    Dispatch,
+    /// A `coclass`.
+    CoClass,
+    /// A `typedef` of another type.
+    Alias,
+    /// A `union`.
+    Union,
+}
+
+impl TypeKind {
+    pub(crate) fn from_tkind(tkind: u32) -> Option<Self> {
+        Some(match tkind {
+            TKIND_ENUM => TypeKind::Enum,
+            TKIND_RECORD => TypeKind::Record,
+            TKIND_MODULE => TypeKind::Module,
+            TKIND_INTERFACE => TypeKind::Interface,
+            TKIND_DISPATCH => TypeKind::Dispatch,
+            TKIND_COCLASS => TypeKind::CoClass,
+            TKIND_ALIAS => TypeKind::Alias,
+            TKIND_UNION => TypeKind::Union,
+            _ => return None,
+        })
+    }
+
+    /// Display name used by the TUI.
+    pub fn name(self) -> &'static str {
+        match self {
+            TypeKind::Enum => "Enum",
+            TypeKind::Record => "Record",
+            TypeKind::Module => "Module",
+            TypeKind::Interface => "Interface",
+            TypeKind::Dispatch => "Dispatch",
+            TypeKind::CoClass => "CoClass",
+            TypeKind::Alias => "Alias",
+            TypeKind::Union => "Union",
+        }
+    }
+}
+
+/// Whether a function is a method or a property accessor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum InvokeKind {
+    /// A method.
+    Func,
+    /// `propget`.
+    PropertyGet,
+    /// `propput`.
+    PropertyPut,
+    /// `propputref`.
+    PropertyPutRef,
+}
+
+impl InvokeKind {
+    fn from_invkind(invkind: u32) -> Self {
+        match invkind {
+            INVOKE_PROPERTYGET => InvokeKind::PropertyGet,
+            INVOKE_PROPERTYPUT => InvokeKind::PropertyPut,
+            INVOKE_PROPERTYPUTREF => InvokeKind::PropertyPutRef,
+            _ => InvokeKind::Func,
+        }
+    }
+
+    /// The IDL attribute for property accessors.
+    pub fn idl_attribute(self) -> Option<&'static str> {
+        match self {
+            InvokeKind::Func => None,
+            InvokeKind::PropertyGet => Some("propget"),
+            InvokeKind::PropertyPut => Some("propput"),
+            InvokeKind::PropertyPutRef => Some("propputref"),
+        }
+    }
+}
+
+/// How a function is called, after OLE's `FUNCKIND`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FuncKind {
+    /// Through the vtable, with an implementation.
+    Virtual,
+    /// Through the vtable; what interfaces have.
+    PureVirtual,
+    /// At a fixed address.
+    NonVirtual,
+    /// A DLL entry point of a module.
+    Static,
+    /// Through `IDispatch::Invoke`.
+    Dispatch,
+}
+
+impl FuncKind {
+    fn from_funckind(funckind: u32) -> Self {
+        match funckind {
+            FUNC_VIRTUAL => FuncKind::Virtual,
+            FUNC_NONVIRTUAL => FuncKind::NonVirtual,
+            FUNC_STATIC => FuncKind::Static,
+            FUNC_DISPATCH => FuncKind::Dispatch,
+            _ => FuncKind::PureVirtual,
+        }
+    }
+}
+
+/// Calling convention of a function, after OLE's `CALLCONV`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CallConv {
+    /// `CC_FASTCALL`.
+    FastCall,
+    /// `CC_CDECL`.
+    CDecl,
+    /// `CC_PASCAL`.
+    Pascal,
+    /// `CC_MACPASCAL`.
+    MacPascal,
+    /// `CC_STDCALL`, what vtable methods use.
+    StdCall,
+    /// `CC_FPFASTCALL`.
+    FpFastCall,
+    /// `CC_SYSCALL`.
+    SysCall,
+    /// `CC_MPWCDECL`.
+    MpwCDecl,
+    /// `CC_MPWPASCAL`.
+    MpwPascal,
+    /// A value OLE does not define.
+    Other(u32),
+}
+
+impl CallConv {
+    fn from_callconv(callconv: u32) -> Self {
+        match callconv {
+            0 => CallConv::FastCall,
+            1 => CallConv::CDecl,
+            2 => CallConv::Pascal,
+            3 => CallConv::MacPascal,
+            4 => CallConv::StdCall,
+            5 => CallConv::FpFastCall,
+            6 => CallConv::SysCall,
+            7 => CallConv::MpwCDecl,
+            8 => CallConv::MpwPascal,
+            other => CallConv::Other(other),
+        }
+    }
+
+    /// The MIDL keyword spelling the convention of a module function.
+    pub fn idl_keyword(self) -> Option<&'static str> {
+        match self {
+            CallConv::FastCall => Some("__fastcall"),
+            CallConv::CDecl | CallConv::MpwCDecl => Some("__cdecl"),
+            CallConv::Pascal | CallConv::MacPascal | CallConv::MpwPascal => Some("__pascal"),
+            CallConv::StdCall => Some("__stdcall"),
+            CallConv::FpFastCall | CallConv::SysCall | CallConv::Other(_) => None,
+        }
+    }
+}
+
+/// What a variable is, after OLE's `VARKIND`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum VarKind {
+    /// A field of a record or union.
+    PerInstance,
+    /// A static member.
+    Static,
+    /// A constant: an enum value or a constant of a module.
+    Const,
+    /// A property of a dispinterface.
+    Dispatch,
+}
+
+impl VarKind {
+    fn from_varkind(varkind: u32) -> Self {
+        match varkind {
+            VAR_STATIC => VarKind::Static,
+            VAR_CONST => VarKind::Const,
+            VAR_DISPATCH => VarKind::Dispatch,
+            _ => VarKind::PerInstance,
+        }
+    }
+}
+
+/// Help attributes shared by libraries, types and members.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Doc {
+    /// `helpstring`.
+    pub help_string: String,
+    /// `helpcontext`, the topic of the item in the help file.
+    pub help_context: u32,
+    /// `helpstringcontext`, for a help string DLL.
+    pub help_string_context: u32,
+}
+
+/// Where a referenced type is defined.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TypeLocation {
+    /// Index into [`Library::types`].
+    Local(usize),
+    /// Index into [`Library::imports`].
+    Imported(usize),
+    /// The reference table had no usable entry.
+    Unresolved,
+}
+
+/// A reference to a named type, resolved at load time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeRef {
+    /// Empty when an imported library that could not be found would give it.
+    pub name: String,
+    /// GUID of the type, when the reference table gives it.
+    pub guid: Option<Guid>,
+    /// Kind of the type, when known.
+    pub kind: Option<TypeKind>,
+    /// Where the type is defined.
+    pub location: TypeLocation,
+    /// Position of an imported type in its library, when the reference
+    /// table gives it.
+    pub index: Option<usize>,
+}
+
+impl TypeRef {
+    /// A reference to a type defined by this library.
+    pub fn local(name: &str, kind: TypeKind, index: usize) -> Self {
+        TypeRef {
+            name: name.to_string(),
+            guid: None,
+            kind: Some(kind),
+            location: TypeLocation::Local(index),
+            index: None,
+        }
+    }
+
+    /// Whether the type is known by name: not when the reference table had
+    /// no usable entry, nor when its imported library was not found.
+    pub fn is_resolved(&self) -> bool {
+        self.location != TypeLocation::Unresolved && !self.name.is_empty()
+    }
+}
+
+/// A structured type description.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Type {
+    /// A fundamental type, identified by its `VT_*` code.
+    Base(u16),
+    /// A pointer to a type.
+    Ptr(Box<Type>),
+    /// A `SAFEARRAY` of a type.
+    SafeArray(Box<Type>),
+    /// A fixed-size C array.
+    CArray {
+        /// Type of the elements.
+        elem: Box<Type>,
+        /// `(cElements, lLbound)` per dimension.
+        bounds: Vec<(u32, i32)>,
+    },
+    /// A type of this library or of an imported one.
+    Named(TypeRef),
+}
+
+impl Type {
+    /// The `VT_*` code OLE gives the type.
+    pub fn vt(&self) -> u16 {
+        match self {
+            Type::Base(vt) => *vt,
+            Type::Ptr(_) => VT_PTR,
+            Type::SafeArray(_) => VT_SAFEARRAY,
+            Type::CArray { .. } => VT_CARRAY,
+            Type::Named(_) => VT_USERDEFINED,
+        }
+    }
+}
+
+/// A parameter of a function.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Param {
+    /// Name of the parameter; libraries need not store one.
+    pub name: Option<String>,
+    /// Its type.
+    pub ty: Type,
+    /// `PARAMFLAG_*` bits.
+    pub flags: u16,
+    /// The `defaultvalue`.
+    pub default_value: Option<Variant>,
+    /// The `custom` attributes of the parameter.
+    pub custom_data: Vec<CustData>,
+}
+
+impl Param {
+    /// Whether the parameter is the `retval` of its function.
+    pub fn is_retval(&self) -> bool {
+        self.flags & PARAMFLAG_FRETVAL != 0
+    }
+}
+
+/// A method, property accessor or DLL entry point.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    /// Name of the function.
+    pub name: String,
+    /// Its member ID, the DISPID of a dispatch function.
+    pub memid: i32,
+    /// Whether it is a method or a property accessor.
+    pub invoke_kind: InvokeKind,
+    /// How it is called.
+    pub func_kind: FuncKind,
+    /// Its calling convention.
+    pub call_conv: CallConv,
+    /// `FUNCFLAG_*` bits.
+    pub flags: u16,
+    /// Vtable offset in bytes.
+    pub vtable_offset: i16,
+    /// The parameters.
+    pub params: Vec<Param>,
+    /// Number of trailing optional parameters; -1 for a `vararg` function.
+    pub optional_params: i16,
+    /// The return type, `HRESULT` for most vtable methods.
+    pub ret: Type,
+    /// The `entry` of a module function.
+    pub entry: Option<DllEntry>,
+    /// Help attributes.
+    pub doc: Doc,
+    /// The `custom` attributes of the function.
+    pub custom_data: Vec<CustData>,
+}
+
+/// The members of IUnknown and IDispatch, in vtable order.
+const PLUMBING: [&str; 7] = [
+    "QueryInterface",
+    "AddRef",
+    "Release",
+    "GetTypeInfoCount",
+    "GetTypeInfo",
+    "GetIDsOfNames",
+    "Invoke",
+];
+
+impl Function {
+    /// IUnknown/IDispatch members that the dispatch half of an interface
+    /// inherits, which printers skip. They are dispatch functions named
+    /// after those methods with the memids OLE gives them; MIDL hands the
+    /// same memid range to the own methods of interfaces without `[id]`.
+    pub fn is_dispatch_plumbing(&self) -> bool {
+        self.func_kind == FuncKind::Dispatch
+            && (0x6000_0000..0x6002_0000).contains(&self.memid)
+            && PLUMBING.contains(&self.name.as_str())
+    }
+
+    /// Whether the last parameter is a `SAFEARRAY(VARIANT)` of extra arguments.
+    pub fn is_vararg(&self) -> bool {
+        self.optional_params == -1
+    }
+
+    /// Position of the `[out, retval]` parameter, which becomes the return
+    /// value when the HRESULT is lowered into an error.
+    pub fn retval_index(&self) -> Option<usize> {
+        self.params.iter().position(Param::is_retval)
+    }
+
+    /// Name of the vtable slot: properties get a `get_`/`put_`/`putref_`
+    /// prefix, as in MIDL-generated headers.
+    pub fn vtable_name(&self) -> String {
+        match self.invoke_kind {
+            InvokeKind::Func => self.name.clone(),
+            InvokeKind::PropertyGet => format!("get_{}", self.name),
+            InvokeKind::PropertyPut => format!("put_{}", self.name),
+            InvokeKind::PropertyPutRef => format!("putref_{}", self.name),
+        }
+    }
+}
+
+/// A field, enum value, constant or dispatch property.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Variable {
+    /// Name of the variable.
+    pub name: String,
+    /// Its member ID, the DISPID of a dispatch property.
+    pub memid: i32,
+    /// What it is.
+    pub kind: VarKind,
+    /// `VARFLAG_*` bits.
+    pub flags: u16,
+    /// Its type.
+    pub ty: Type,
+    /// Set for [`VarKind::Const`] members.
+    pub value: Option<Variant>,
+    /// Byte offset for [`VarKind::PerInstance`] members.
+    pub offset: u32,
+    /// Help attributes.
+    pub doc: Doc,
+    /// The `custom` attributes of the variable.
+    pub custom_data: Vec<CustData>,
+}
+
+impl Variable {
+    /// The constant value in the member's declared type. Readers do not
+    /// always store it that way (SLTG keeps every integer as `VT_INT`), so
+    /// integral values are converted; anything else is returned as stored.
+    pub fn typed_value(&self) -> Option<Variant> {
+        let value = self.value.as_ref()?;
+        Some(match self.ty {
+            Type::Base(vt) => value.convert(vt).unwrap_or_else(|| value.clone()),
+            _ => value.clone(),
+        })
+    }
+
+    /// The `propget`/`propput` pair a dispinterface property (a
+    /// [`VarKind::Dispatch`] member) stands for, in the `FUNC_DISPATCH`
+    /// shape: the getter returns the value, the setter takes it. Read-only
+    /// properties have no setter.
+    pub fn dispatch_accessors(&self) -> Vec<Function> {
+        // VARFLAGS and FUNCFLAGS share their bits, except for these two.
+        let mut flags = self.flags & !(VARFLAG_FREADONLY | VARFLAG_FRESTRICTED);
+        if self.flags & VARFLAG_FRESTRICTED != 0 {
+            flags |= FUNCFLAG_FRESTRICTED;
+        }
+        let accessor = |invoke_kind, ret, params| Function {
+            name: self.name.clone(),
+            memid: self.memid,
+            invoke_kind,
+            func_kind: FuncKind::Dispatch,
+            call_conv: CallConv::StdCall,
+            flags,
+            vtable_offset: 0,
+            params,
+            optional_params: 0,
+            ret,
+            entry: None,
+            doc: self.doc.clone(),
+            custom_data: self.custom_data.clone(),
+        };
+        let mut accessors = vec![accessor(
+            InvokeKind::PropertyGet,
+            self.ty.clone(),
+            Vec::new(),
+        )];
+        if self.flags & VARFLAG_FREADONLY == 0 {
+            let value = Param {
+                name: Some("val".to_string()),
+                ty: self.ty.clone(),
+                flags: PARAMFLAG_FIN,
+                default_value: None,
+                custom_data: Vec::new(),
+            };
+            accessors.push(accessor(
+                InvokeKind::PropertyPut,
+                Type::Base(VT_VOID),
+                vec![value],
+            ));
+        }
+        accessors
+    }
+}
+
+/// The base interface of an interface, or an interface a coclass
+/// implements.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImplType {
+    /// The interface.
+    pub target: TypeRef,
+    /// `IMPLTYPEFLAG_*` bits.
+    pub flags: i32,
+    /// The `custom` attributes of the implementation.
+    pub custom_data: Vec<CustData>,
+}
+
+/// A type defined by a library.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeDef {
+    /// Name of the type.
+    pub name: String,
+    /// What it is.
+    pub kind: TypeKind,
+    /// Its `uuid`; nil when it has none.
+    pub guid: Guid,
+    /// `TYPEFLAG_*` bits.
+    pub flags: u16,
+    /// Its `version`, major and minor.
+    pub version: (u16, u16),
+    /// Size of an instance in bytes; the pointer size for interfaces.
+    pub size: u32,
+    /// Alignment of an instance in bytes.
+    pub alignment: u16,
+    /// Size of the vtable in bytes, inherited methods included.
+    pub vtable_size: u16,
+    /// The aliased type of a [`TypeKind::Alias`].
+    pub alias_of: Option<Type>,
+    /// Methods and DLL entry points, in declaration order.
+    pub funcs: Vec<Function>,
+    /// Fields, enum values, constants and dispatch properties.
+    pub vars: Vec<Variable>,
+    /// The base interface, or the interfaces of a coclass.
+    pub impl_types: Vec<ImplType>,
+    /// `dllname` of a [`TypeKind::Module`].
+    pub dll_name: Option<String>,
+    /// Whether an enum is a set of bit flags, when configured; a type
+    /// library has no way to say. `None` guesses from the values.
+    pub bit_flags: Option<bool>,
+    /// Help attributes.
+    pub doc: Doc,
+    /// The `custom` attributes of the type.
+    pub custom_data: Vec<CustData>,
+}
+
+impl TypeDef {
+    /// An empty type, to be filled in by hand.
+    pub fn new(name: &str, kind: TypeKind, guid: Guid) -> Self {
+        TypeDef {
+            name: name.to_string(),
+            kind,
+            guid,
+            flags: 0,
+            version: (0, 0),
+            size: 0,
+            alignment: 0,
+            vtable_size: 0,
+            alias_of: None,
+            funcs: Vec::new(),
+            vars: Vec::new(),
+            impl_types: Vec::new(),
+            dll_name: None,
+            bit_flags: None,
+            doc: Doc::default(),
+            custom_data: Vec::new(),
+        }
+    }
+
+    /// Whether the `TYPEFLAG_*` bit `flag` is set.
+    pub fn has_flag(&self, flag: u16) -> bool {
+        self.flags & flag != 0
+    }
+
+    /// Whether an enum reads as a set of bit flags: as configured, or else
+    /// when it has at least three distinct single bits and every other value
+    /// is a combination of them.
+    pub fn is_bit_flags(&self) -> bool {
+        if self.kind != TypeKind::Enum {
+            return false;
+        }
+        if let Some(bit_flags) = self.bit_flags {
+            return bit_flags;
+        }
+        let values: Vec<u32> = self
+            .vars
+            .iter()
+            .filter_map(|v| v.value.as_ref().map(|v| v.as_i32() as u32))
+            .collect();
+        let bits = values
+            .iter()
+            .filter(|v| v.is_power_of_two())
+            .fold(0u32, |acc, v| acc | v);
+        bits.count_ones() >= 3 && values.iter().all(|v| v & !bits == 0)
+    }
+
+    /// The first implemented type: the base interface of an interface.
+    pub fn base(&self) -> Option<&TypeRef> {
+        self.impl_types.first().map(|i| &i.target)
+    }
+}
+
+/// How the members of a record or union are placed, compared with the
+/// layout a C compiler would pick at the default `/Zp8` packing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layout {
+    /// The C layout at the default packing.
+    Natural,
+    /// The stored offsets and size are those of `#pragma pack(n)`.
+    Packed(u32),
+    /// No packing reproduces the stored offsets; each member has to be
+    /// placed explicitly.
+    Explicit,
+}
+
+/// A type library referenced through `importlib`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Import {
+    /// GUID of the imported library.
+    pub guid: Guid,
+    /// Its version, major and minor.
+    pub version: (u16, u16),
+    /// Its locale.
+    pub lcid: u32,
+    /// The file it was imported from, as the library stores it.
+    pub file_name: String,
+}
+
+/// A `TYPELIB` resource of a PE image and the library it holds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EmbeddedLibrary {
+    /// The resource ID, or the name of a named resource.
+    pub resource: String,
+    /// Name of the library.
+    pub name: String,
+    /// Its GUID.
+    pub guid: Guid,
+    /// Its version, major and minor.
+    pub version: (u16, u16),
+}
+
+/// A type library.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Library {
+    /// Name of the library.
+    pub name: String,
+    /// Its GUID.
+    pub guid: Guid,
+    /// Its version, major and minor.
+    pub version: (u16, u16),
+    /// Its locale.
+    pub lcid: u32,
+    /// The platform it was built for, one of the `SYS_*` constants.
+    pub syskind: u32,
+    /// `LIBFLAG_*` bits.
+    pub flags: u16,
+    /// The `helpfile`.
+    pub help_file: String,
+    /// Help attributes.
+    pub doc: Doc,
+    /// The `custom` attributes of the library.
+    pub custom_data: Vec<CustData>,
+    /// The libraries it imports.
+    pub imports: Vec<Import>,
+    /// The types it defines.
+    pub types: Vec<TypeDef>,
+}
+
+impl Library {
+    /// Loads a .tlb file, or the TYPELIB resource `resource` of a PE image
+    /// (the first one when `None`). `foo.dll\2` selects resource 2 as well,
+    /// and a different `resource` is an [`Error::InvalidConfig`].
+    /// .idl and .odl files are parsed as source (see [`idl::load`]).
+    /// Failures to read the file are [`Error::LoadFailed`], naming it.
+    ///
+    /// [`idl::load`]: crate::idl::load
+    pub fn load(path: &Path, resource: Option<u16>) -> Result<Self, Error> {
+        let is_source = path.extension().is_some_and(|extension| {
+            extension.eq_ignore_ascii_case("idl") || extension.eq_ignore_ascii_case("odl")
+        });
+        if is_source {
+            return crate::idl::load(path).map_err(|e| Error::load_failed(path, e));
+        }
+        let (path, suffix) = pe::split_resource_suffix(path);
+        let resource = match (resource, suffix) {
+            (Some(id), Some(named)) if id != named => {
+                return Err(Error::InvalidConfig(format!(
+                    "resource {} conflicts with {}\\{}",
+                    id,
+                    path.display(),
+                    named
+                )));
+            }
+            _ => resource.or(suffix),
+        };
+        let tlib = read_type_lib(&path, resource).map_err(|e| Error::load_failed(&path, e))?;
+        let mut lib = Library::from_type_lib(&tlib);
+        if let Some(dir) = path.parent() {
+            lib.name_imported_types(dir);
+        }
+        Ok(lib)
+    }
+
+    /// The `TYPELIB` resources of the PE image at `path` (a `foo.dll\2`
+    /// suffix is ignored) and the library in each, in resource directory
+    /// order; empty when the file is not a PE image.
+    pub fn embedded(path: &Path) -> Result<Vec<EmbeddedLibrary>, Error> {
+        let (path, _) = pe::split_resource_suffix(path);
+        let read = || {
+            let file = std::fs::read(&path).map_err(|e| Error::io(&path, e))?;
+            if !pe::is_pe(&file) {
+                return Ok(Vec::new());
+            }
+            pe::type_lib_resources(&file)?
+                .into_iter()
+                .map(|r| {
+                    let lib = Library::from_type_lib(&parse_type_lib(r.data)?);
+                    Ok(EmbeddedLibrary {
+                        resource: r.id.to_string(),
+                        name: lib.name,
+                        guid: lib.guid,
+                        version: lib.version,
+                    })
+                })
+                .collect()
+        };
+        read().map_err(|e| Error::load_failed(&path, e))
+    }
+
+    /// Names the imported types the reference tables give only by GUID or
+    /// position, from the imported libraries found in `dir`. Their own
+    /// imports are left alone.
+    fn name_imported_types(&mut self, dir: &Path) {
+        let imported: Vec<Option<Library>> = self
+            .imports
+            .iter()
+            .map(|import| {
+                let file_name = import.file_name.rsplit(['\\', '/']).next()?;
+                let tlib = read_type_lib(&dir.join(file_name), None).ok()?;
+                Some(Library::from_type_lib(&tlib))
+            })
+            .collect();
+        self.for_each_type_ref(|r| {
+            let TypeLocation::Imported(import) = r.location else {
+                return;
+            };
+            let Some(Some(other)) = imported.get(import) else {
+                return;
+            };
+            let index = match (r.guid, r.index) {
+                (Some(guid), _) => other.types.iter().position(|ty| ty.guid == guid),
+                (None, index) => index,
+            };
+            let Some((index, ty)) = index.and_then(|index| Some((index, other.types.get(index)?)))
+            else {
+                return;
+            };
+            r.name = ty.name.clone();
+            r.kind = Some(ty.kind);
+            r.index = Some(index);
+            if !ty.guid.is_nil() {
+                r.guid = Some(ty.guid);
+            }
+        });
+    }
+
+    /// Builds the model from a decoded library, resolving every reference.
+    pub(crate) fn from_type_lib(tlib: &TypeLib) -> Self {
+        let types = tlib
+            .type_infos
+            .iter()
+            .map(|ti| convert_type_info(tlib, ti))
+            .collect();
+        Library {
+            name: tlib.name.clone(),
+            guid: tlib.attr.guid,
+            version: (tlib.attr.major_ver, tlib.attr.minor_ver),
+            lcid: tlib.attr.lcid,
+            syskind: tlib.attr.syskind,
+            flags: tlib.attr.flags,
+            help_file: tlib.help_file.clone(),
+            doc: Doc {
+                help_string: tlib.doc_string.clone(),
+                help_context: tlib.help_context,
+                help_string_context: 0,
+            },
+            custom_data: tlib.custom_data.clone(),
+            imports: tlib
+                .imports
+                .iter()
+                .map(|i| Import {
+                    guid: i.guid,
+                    version: (i.major_ver, i.minor_ver),
+                    lcid: i.lcid,
+                    file_name: i.file_name.clone(),
+                })
+                .collect(),
+            types,
+        }
+    }
+
+    /// The definition behind a reference, if it is local to this library.
+    pub fn local(&self, r: &TypeRef) -> Option<&TypeDef> {
+        match r.location {
+            TypeLocation::Local(index) => self.types.get(index),
+            _ => None,
+        }
+    }
+
+    /// The type named `name`.
+    pub fn find(&self, name: &str) -> Option<&TypeDef> {
+        self.types.iter().find(|t| t.name == name)
+    }
+
+    /// Size of a pointer on the platform of the library.
+    pub fn pointer_size(&self) -> u32 {
+        if self.syskind == SYS_WIN64 { 8 } else { 4 }
+    }
+
+    /// Size and alignment of a value of `ty` in bytes, or `None` when it
+    /// cannot be known (imported records, unresolved references, `void`,
+    /// sizes beyond `u32`).
+    pub fn size_and_alignment(&self, ty: &Type) -> Option<(u32, u32)> {
+        self.size_and_alignment_at(ty, 0)
+    }
+
+    /// `depth` counts the aliases followed, so that a cycle of them in a
+    /// corrupt library ends.
+    fn size_and_alignment_at(&self, ty: &Type, depth: usize) -> Option<(u32, u32)> {
+        let pointer = self.pointer_size();
+        match ty {
+            Type::Base(vt) => Some(match *vt {
+                VT_I1 | VT_UI1 => (1, 1),
+                VT_I2 | VT_UI2 | VT_BOOL => (2, 2),
+                VT_I4 | VT_UI4 | VT_INT | VT_UINT | VT_R4 | VT_ERROR | VT_HRESULT => (4, 4),
+                VT_I8 | VT_UI8 | VT_R8 | VT_CY | VT_DATE => (8, 8),
+                VT_FILETIME => (8, 4),
+                VT_CLSID => (16, 4),
+                VT_DECIMAL => (16, 8),
+                VT_VARIANT => (8 + 2 * pointer, 8),
+                VT_BLOB | VT_BLOB_OBJECT => (2 * pointer, pointer),
+                VT_BSTR | VT_LPSTR | VT_LPWSTR | VT_UNKNOWN | VT_DISPATCH | VT_INT_PTR
+                | VT_UINT_PTR | VT_PTR | VT_SAFEARRAY | VT_STREAM | VT_STORAGE
+                | VT_STREAMED_OBJECT | VT_STORED_OBJECT | VT_CF | VT_RECORD => (pointer, pointer),
+                _ => return None,
+            }),
+            Type::Ptr(_) | Type::SafeArray(_) => Some((pointer, pointer)),
+            Type::CArray { elem, bounds } => {
+                let (size, align) = self.size_and_alignment_at(elem, depth)?;
+                let size = bounds
+                    .iter()
+                    .try_fold(size, |size, (count, _)| size.checked_mul(*count))?;
+                Some((size, align))
+            }
+            Type::Named(r) => {
+                let def = self.local(r)?;
+                match def.kind {
+                    TypeKind::Enum => Some((4, 4)),
+                    TypeKind::Alias if depth < 64 => {
+                        self.size_and_alignment_at(def.alias_of.as_ref()?, depth + 1)
+                    }
+                    TypeKind::Record | TypeKind::Union if def.alignment > 0 => {
+                        Some((def.size, def.alignment as u32))
+                    }
+                    TypeKind::Interface | TypeKind::Dispatch => Some((pointer, pointer)),
+                    _ => None,
+                }
+            }
+        }
+    }
+
+    /// Calls `f` on every reference to a named type.
+    pub fn for_each_type_ref(&mut self, mut f: impl FnMut(&mut TypeRef)) {
+        fn visit(ty: &mut Type, f: &mut impl FnMut(&mut TypeRef)) {
+            match ty {
+                Type::Base(_) => {}
+                Type::Ptr(inner) | Type::SafeArray(inner) => visit(inner, f),
+                Type::CArray { elem, .. } => visit(elem, f),
+                Type::Named(r) => f(r),
+            }
+        }
+        for ty in &mut self.types {
+            if let Some(aliased) = &mut ty.alias_of {
+                visit(aliased, &mut f);
+            }
+            for impl_type in &mut ty.impl_types {
+                f(&mut impl_type.target);
+            }
+            for var in &mut ty.vars {
+                visit(&mut var.ty, &mut f);
+            }
+            for func in &mut ty.funcs {
+                visit(&mut func.ret, &mut f);
+                for param in &mut func.params {
+                    visit(&mut param.ty, &mut f);
+                }
+            }
+        }
+    }
+
+    /// Keeps the types `keep` accepts. References to the others become
+    /// unresolved; references to the rest follow them to their new index.
+    pub fn retain_types(&mut self, mut keep: impl FnMut(&TypeDef) -> bool) {
+        let mut next = 0;
+        let new_index: Vec<Option<usize>> = self
+            .types
+            .iter()
+            .map(|ty| {
+                keep(ty).then(|| {
+                    next += 1;
+                    next - 1
+                })
+            })
+            .collect();
+        let mut index = 0;
+        self.types.retain(|_| {
+            index += 1;
+            new_index[index - 1].is_some()
+        });
+        self.for_each_type_ref(|r| {
+            if let TypeLocation::Local(index) = r.location {
+                r.location = match new_index.get(index).copied().flatten() {
+                    Some(index) => TypeLocation::Local(index),
+                    None => TypeLocation::Unresolved,
+                };
+            }
+        });
+    }
+
+    /// Renames the type at `index` and every reference to it.
+    pub fn rename_type(&mut self, index: usize, name: &str) {
+        self.types[index].name = name.to_string();
+        self.for_each_type_ref(|r| {
+            if r.location == TypeLocation::Local(index) {
+                r.name = name.to_string();
+            }
+        });
+    }
+
+    /// Which packing, if any, explains the stored member offsets and size of
+    /// a record or union. Members of unknown size are trusted to be natural.
+    pub fn layout(&self, ty: &TypeDef) -> Layout {
+        let members: Option<Vec<_>> = ty
+            .vars
+            .iter()
+            .filter(|v| v.kind == VarKind::PerInstance)
+            .map(|v| Some((v.offset, self.size_and_alignment(&v.ty)?)))
+            .collect();
+        let Some(members) = members else {
+            return Layout::Natural;
+        };
+        let union = ty.kind == TypeKind::Union;
+        // `None` when the offsets overflow, which leaves the record natural.
+        let fits = |pack: u32| -> Option<bool> {
+            let (mut end, mut max_align) = (0u32, 1u32);
+            for &(offset, (size, align)) in &members {
+                let align = align.min(pack);
+                let expected = if union {
+                    0
+                } else {
+                    end.checked_next_multiple_of(align)?
+                };
+                if offset != expected {
+                    return Some(false);
+                }
+                end = end.max(offset.checked_add(size)?);
+                max_align = max_align.max(align);
+            }
+            // Hand-built types may not record a size.
+            Some(ty.size == 0 || ty.size == end.checked_next_multiple_of(max_align)?)
+        };
+        match [8, 4, 2, 1]
+            .into_iter()
+            .map(|pack| (pack, fits(pack)))
+            .find(|&(_, fits)| fits != Some(false))
+        {
+            Some((8, _) | (_, None)) => Layout::Natural,
+            Some((pack, _)) => Layout::Packed(pack),
+            None => Layout::Explicit,
+        }
+    }
+}
+
+fn read_type_lib(path: &Path, resource: Option<u16>) -> Result<TypeLib, Error> {
+    let file = std::fs::read(path).map_err(|e| Error::io(path, e))?;
+    let data = if pe::is_pe(&file) {
+        pe::select_type_lib(&file, resource)?
+    } else {
+        &file[..]
+    };
+    parse_type_lib(data)
+}
+
+fn parse_type_lib(data: &[u8]) -> Result<TypeLib, Error> {
+    if msft::is_msft(data) {
+        msft::parse(data)
+    } else if sltg::is_sltg(data) {
+        sltg::parse(data)
+    } else {
+        Err(Error::InvalidTypeLib(
+            "not an MSFT or SLTG type library".to_string(),
+        ))
+    }
+}
+
+fn convert_ref(tlib: &TypeLib, href: HRefType) -> TypeRef {
+    let (location, index) = match tlib.refs.get(&href) {
+        Some(typelib::TypeRef::Local(index)) => (TypeLocation::Local(*index), None),
+        Some(typelib::TypeRef::External { import, index, .. }) => (
+            TypeLocation::Imported(*import),
+            index.map(|index| index as usize),
+        ),
+        None => (TypeLocation::Unresolved, None),
+    };
+    match tlib.resolve_ref(href) {
+        Some(info) => TypeRef {
+            name: info.name,
+            guid: info.guid,
+            kind: info.typekind.and_then(TypeKind::from_tkind),
+            location,
+            index,
+        },
+        None => TypeRef {
+            name: String::new(),
+            guid: None,
+            kind: None,
+            location: TypeLocation::Unresolved,
+            index: None,
+        },
+    }
+}
+
+fn convert_type(tlib: &TypeLib, tdesc: &TypeDesc) -> Type {
+    match tdesc {
+        // VARIANT-style modifiers spell out what a TYPEDESC nests.
+        TypeDesc::Base(vt) if vt & VT_BYREF != 0 => Type::Ptr(Box::new(convert_type(
+            tlib,
+            &TypeDesc::Base(vt & !VT_BYREF),
+        ))),
+        TypeDesc::Base(vt) if vt & VT_ARRAY != 0 => Type::SafeArray(Box::new(convert_type(
+            tlib,
+            &TypeDesc::Base(vt & !VT_ARRAY),
+        ))),
+        TypeDesc::Base(vt) => Type::Base(vt & VT_TYPEMASK),
+        TypeDesc::Ptr(inner) => Type::Ptr(Box::new(convert_type(tlib, inner))),
+        TypeDesc::SafeArray(inner) => Type::SafeArray(Box::new(convert_type(tlib, inner))),
+        TypeDesc::CArray { elem, bounds } => Type::CArray {
+            elem: Box::new(convert_type(tlib, elem)),
+            bounds: bounds.clone(),
+        },
+        TypeDesc::UserDefined(href) => Type::Named(convert_ref(tlib, *href)),
+    }
+}
+
+fn convert_type_info(tlib: &TypeLib, ti: &TypeInfo) -> TypeDef {
+    let attr = &ti.attr;
+    TypeDef {
+        name: ti.name.clone(),
+        // Readers only produce the eight known kinds.
+        kind: TypeKind::from_tkind(attr.typekind).unwrap_or(TypeKind::Record),
+        guid: attr.guid,
+        flags: attr.flags,
+        version: (attr.major_ver, attr.minor_ver),
+        size: attr.cb_size_instance,
+        alignment: attr.cb_alignment,
+        vtable_size: attr.cb_size_vft,
+        alias_of: attr.tdesc_alias.as_ref().map(|t| convert_type(tlib, t)),
+        funcs: ti.funcs.iter().map(|f| convert_func(tlib, f)).collect(),
+        vars: ti.vars.iter().map(|v| convert_var(tlib, v)).collect(),
+        impl_types: ti
+            .impl_types
+            .iter()
+            .map(|i| ImplType {
+                target: convert_ref(tlib, i.href),
+                flags: i.flags,
+                custom_data: i.custom_data.clone(),
+            })
+            .collect(),
+        dll_name: ti.dll_name.clone(),
+        bit_flags: None,
+        doc: Doc {
+            help_string: ti.doc_string.clone(),
+            help_context: ti.help_context,
+            help_string_context: ti.help_string_context,
+        },
+        custom_data: ti.custom_data.clone(),
+    }
+}
+
+fn convert_func(tlib: &TypeLib, f: &FuncDesc) -> Function {
+    Function {
+        name: f.name.clone(),
+        memid: f.memid,
+        invoke_kind: InvokeKind::from_invkind(f.invkind),
+        func_kind: FuncKind::from_funckind(f.funckind),
+        call_conv: CallConv::from_callconv(f.callconv),
+        flags: f.func_flags,
+        vtable_offset: f.ovft,
+        params: f
+            .params
+            .iter()
+            .map(|p| Param {
+                name: p.name.clone(),
+                ty: convert_type(tlib, &p.tdesc),
+                flags: p.flags,
+                default_value: p.default_value.clone(),
+                custom_data: p.custom_data.clone(),
+            })
+            .collect(),
+        optional_params: f.c_params_opt,
+        ret: convert_type(tlib, &f.ret),
+        entry: f.entry.clone(),
+        doc: Doc {
+            help_string: f.doc_string.clone(),
+            help_context: f.help_context,
+            help_string_context: f.help_string_context,
+        },
+        custom_data: f.custom_data.clone(),
+    }
+}
+
+fn convert_var(tlib: &TypeLib, v: &VarDesc) -> Variable {
+    Variable {
+        name: v.name.clone(),
+        memid: v.memid,
+        kind: VarKind::from_varkind(v.varkind),
+        flags: v.var_flags,
+        ty: convert_type(tlib, &v.tdesc),
+        value: v.value.clone(),
+        offset: v.oinst,
+        doc: Doc {
+            help_string: v.doc_string.clone(),
+            help_context: v.help_context,
+            help_string_context: v.help_string_context,
+        },
+        custom_data: v.custom_data.clone(),
+    }
+}
+
+/// A library of tests/fixtures, for the tests of every module.
+#[cfg(test)]
+pub(crate) fn fixture(name: &str) -> Library {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name);
+    Library::load(&path, None).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_references_to_local_types() {
+        let lib = fixture("simple.tlb");
+        let shape = lib.find("IShape").unwrap();
+        let color = shape.funcs.iter().find(|f| f.name == "Color").unwrap();
+        let Type::Ptr(inner) = &color.params[0].ty else {
+            panic!("expected a pointer, got {:?}", color.params[0].ty);
+        };
+        let Type::Named(colors) = inner.as_ref() else {
+            panic!("expected a named type, got {:?}", inner);
+        };
+        assert_eq!(colors.name, "Colors");
+        assert_eq!(colors.kind, Some(TypeKind::Enum));
+        assert_eq!(lib.local(colors).unwrap().vars.len(), 4);
+
+        let coclass = lib.find("Shape").unwrap();
+        let targets: Vec<_> = coclass
+            .impl_types
+            .iter()
+            .map(|i| (i.target.name.as_str(), i.flags))
+            .collect();
+        assert_eq!(
+            targets,
+            vec![
+                ("IShape", IMPLTYPEFLAG_FDEFAULT),
+                ("_ShapeEvents", IMPLTYPEFLAG_FDEFAULT | IMPLTYPEFLAG_FSOURCE)
+            ]
+        );
+    }
+
+    #[test]
+    fn resolves_imported_base_interfaces() {
+        let lib = fixture("simple.tlb");
+        let shape = lib.find("IShape").unwrap();
+        let base = shape.base().unwrap();
+        assert_eq!(base.name, "IDispatch");
+        assert!(matches!(base.location, TypeLocation::Imported(_)));
+        assert_eq!(lib.imports[0].file_name, "stdole2.tlb");
+    }
+
+    /// The named type of each parameter of `ICanvas`, which uses types of
+    /// simple.tlb.
+    fn canvas_targets(lib: &Library) -> Vec<TypeRef> {
+        fn named(ty: &Type) -> &TypeRef {
+            match ty {
+                Type::Ptr(inner) => named(inner),
+                Type::Named(r) => r,
+                ty => panic!("expected a named type, got {:?}", ty),
+            }
+        }
+        let canvas = lib.find("ICanvas").unwrap();
+        canvas
+            .funcs
+            .iter()
+            .map(|f| named(&f.params[0].ty).clone())
+            .collect()
+    }
+
+    #[test]
+    fn lists_the_libraries_of_an_image() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+        let dll = dir.join("typelibs.dll");
+        let found: Vec<_> = Library::embedded(&dll)
+            .unwrap()
+            .into_iter()
+            .map(|e| (e.resource, e.name, e.version))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                ("1".to_string(), "SimpleLib".to_string(), (1, 2)),
+                ("2".to_string(), "LegacyLib".to_string(), (3, 1)),
+            ]
+        );
+        assert!(
+            Library::embedded(&dir.join("simple.tlb"))
+                .unwrap()
+                .is_empty()
+        );
+
+        let suffixed = Path::new(&format!("{}\\2", dll.display())).to_path_buf();
+        assert_eq!(Library::load(&suffixed, Some(2)).unwrap().name, "LegacyLib");
+        let error = Library::load(&suffixed, Some(1)).unwrap_err();
+        assert!(matches!(error, Error::InvalidConfig(_)), "{:?}", error);
+    }
+
+    #[test]
+    fn names_imported_types_from_the_library_next_to_it() {
+        let simple = fixture("simple.tlb");
+        for name in ["consumer.tlb", "consumer_sltg.tlb"] {
+            let lib = fixture(name);
+            let import = lib
+                .imports
+                .iter()
+                .position(|i| i.file_name == "simple.tlb")
+                .unwrap();
+            let targets: Vec<_> = canvas_targets(&lib)
+                .into_iter()
+                .map(|r| (r.name, r.kind, r.location, r.index))
+                .collect();
+            assert_eq!(
+                targets,
+                [
+                    (
+                        "IShape".to_string(),
+                        Some(simple.types[3].kind),
+                        TypeLocation::Imported(import),
+                        Some(3)
+                    ),
+                    (
+                        "Point".to_string(),
+                        Some(TypeKind::Record),
+                        TypeLocation::Imported(import),
+                        Some(1)
+                    ),
+                    (
+                        "Colors".to_string(),
+                        Some(TypeKind::Enum),
+                        TypeLocation::Imported(import),
+                        Some(0)
+                    ),
+                ],
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn keeps_imported_types_it_cannot_name() {
+        let dir = std::env::temp_dir().join(format!("consumer-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+        for name in ["consumer.tlb", "consumer_sltg.tlb"] {
+            std::fs::copy(fixtures.join(name), dir.join(name)).unwrap();
+        }
+        let msft = canvas_targets(&Library::load(&dir.join("consumer.tlb"), None).unwrap());
+        let sltg = canvas_targets(&Library::load(&dir.join("consumer_sltg.tlb"), None).unwrap());
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(msft.iter().chain(&sltg).all(|r| {
+            r.name.is_empty() && matches!(r.location, TypeLocation::Imported(_)) && !r.is_resolved()
+        }));
+        // MSFT imports the types with a GUID by it, the others by position.
+        assert_eq!(
+            msft[0].guid,
+            Some(Guid::from_u128(0x5a1e0a00_0003_4b1d_9c2e_0123456789ab))
+        );
+        assert_eq!(
+            (msft[0].index, msft[1].guid, msft[1].index),
+            (None, None, Some(1))
+        );
+        let indexes: Vec<_> = sltg.iter().map(|r| r.index).collect();
+        assert_eq!(indexes, [Some(3), Some(1), Some(0)]);
+    }
+
+    #[test]
+    fn finds_the_packing_behind_stored_offsets() {
+        let lib = fixture("simple.tlb");
+        let layout = |name| lib.layout(lib.find(name).unwrap());
+        assert_eq!(layout("Point"), Layout::Natural);
+        assert_eq!(layout("Matrix"), Layout::Natural);
+        assert_eq!(layout("Value"), Layout::Natural);
+        assert_eq!(layout("Packet"), Layout::Packed(1));
+        let matrix = Type::Named(TypeRef::local("Matrix", TypeKind::Record, 7));
+        assert_eq!(lib.size_and_alignment(&matrix), Some((164, 4)));
+
+        // A gap no packing produces.
+        let mut gap = TypeDef::new("Gap", TypeKind::Record, Guid::default());
+        gap.size = 12;
+        for (name, offset) in [("a", 0), ("b", 8)] {
+            gap.vars.push(Variable {
+                name: name.to_string(),
+                memid: 0,
+                kind: VarKind::PerInstance,
+                flags: 0,
+                ty: Type::Base(VT_I4),
+                value: None,
+                offset,
+                doc: Doc::default(),
+                custom_data: Vec::new(),
+            });
+        }
+        assert_eq!(lib.layout(&gap), Layout::Explicit);
+    }
+
+    #[test]
+    fn gives_up_on_sizes_beyond_u32() {
+        let mut lib = fixture("simple.tlb");
+        let huge = Type::CArray {
+            elem: Box::new(Type::Base(VT_R8)),
+            bounds: vec![(0x1_0000, 0), (0x1_0000, 0)],
+        };
+        assert_eq!(lib.size_and_alignment(&huge), None);
+
+        let bytes = Type::CArray {
+            elem: Box::new(Type::Base(VT_UI1)),
+            bounds: vec![(u32::MAX, 0)],
+        };
+        let mut record = TypeDef::new("Huge", TypeKind::Record, Guid::default());
+        for (name, ty) in [("a", bytes), ("b", Type::Base(VT_R8))] {
+            record.vars.push(Variable {
+                name: name.to_string(),
+                memid: 0,
+                kind: VarKind::PerInstance,
+                flags: 0,
+                ty,
+                value: None,
+                offset: 0,
+                doc: Doc::default(),
+                custom_data: Vec::new(),
+            });
+        }
+        assert_eq!(lib.layout(&record), Layout::Natural);
+
+        // Aliases of each other, as only a corrupt library has them.
+        let index = lib.types.len();
+        for (name, target) in [("A", "B"), ("B", "A")] {
+            let mut alias = TypeDef::new(name, TypeKind::Alias, Guid::default());
+            let other = if name == "A" { index + 1 } else { index };
+            alias.alias_of = Some(Type::Named(TypeRef::local(target, TypeKind::Alias, other)));
+            lib.types.push(alias);
+        }
+        let a = Type::Named(TypeRef::local("A", TypeKind::Alias, index));
+        assert_eq!(lib.size_and_alignment(&a), None);
+    }
+
+    #[test]
+    fn retains_and_renames_types_with_their_references() {
+        let mut lib = fixture("simple.tlb");
+        let colors = lib.types.iter().position(|t| t.name == "Colors").unwrap();
+        lib.rename_type(colors, "Colours");
+        lib.retain_types(|t| t.name != "Point" && t.name != "Colors");
+
+        let shape = lib.find("IShape").unwrap();
+        let color = shape.funcs.iter().find(|f| f.name == "Color").unwrap();
+        let Type::Ptr(inner) = &color.params[0].ty else {
+            panic!("expected a pointer, got {:?}", color.params[0].ty);
+        };
+        let Type::Named(colours) = inner.as_ref() else {
+            panic!("expected a named type, got {:?}", inner);
+        };
+        assert_eq!(colours.name, "Colours");
+        assert_eq!(lib.local(colours).unwrap().name, "Colours");
+
+        let center = shape.funcs.iter().find(|f| f.name == "Center").unwrap();
+        let Type::Ptr(inner) = &center.params[0].ty else {
+            panic!("expected a pointer, got {:?}", center.params[0].ty);
+        };
+        assert!(matches!(
+            inner.as_ref(),
+            Type::Named(TypeRef {
+                location: TypeLocation::Unresolved,
+                ..
+            })
+        ));
+        let coclass = lib.find("Shape").unwrap();
+        for impl_type in &coclass.impl_types {
+            assert_eq!(
+                lib.local(&impl_type.target).unwrap().name,
+                impl_type.target.name
+            );
+        }
+    }
+
+    #[test]
+    fn guesses_and_overrides_bit_flags() {
+        let lib = fixture("simple.tlb");
+        let mut colors = lib.find("Colors").unwrap().clone();
+        assert!(!colors.is_bit_flags());
+        colors.bit_flags = Some(true);
+        assert!(colors.is_bit_flags());
+        assert!(!lib.find("Point").unwrap().is_bit_flags());
+    }
+
+    #[test]
+    fn converts_member_kinds() {
+        let lib = fixture("simple.tlb");
+        let shape = lib.find("IShape").unwrap();
+        let kinds: Vec<_> = shape.funcs.iter().map(|f| f.invoke_kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                InvokeKind::PropertyGet,
+                InvokeKind::PropertyPut,
+                InvokeKind::Func,
+                InvokeKind::PropertyGet,
+                InvokeKind::Func
+            ]
+        );
+        assert!(shape.funcs.iter().all(|f| f.call_conv == CallConv::StdCall));
+        let constants = lib.find("Constants").unwrap();
+        assert_eq!(constants.vars[0].kind, VarKind::Const);
+        assert_eq!(constants.vars[0].value, Some(Variant::I4(42)));
+    }
+}
@@ -0,0 +1,890 @@
+//! Reader for the MSFT type library format (the format MIDL and
+//! `ICreateTypeLib2` produce), decoded without going through OLE.
+//!
+//! Layout reference: the file starts with [`Header`], optionally followed by the
+//! help-string DLL offset, one offset per typeinfo and then a directory of
+//! fifteen segments (typeinfo table, import tables, GUID/name/string tables,
+//! type descriptions, custom data...). Member records of each typeinfo live in
+//! a separate block pointed to by its `memoffset`.
+
+use std::collections::HashMap;
+
+use crate::error::Error;
+use crate::typelib::*;
+
+pub const MSFT_MAGIC: u32 = 0x5446_534d; // "MSFT"
+
+const HELPDLLFLAG: i32 = 0x0100;
+const HEADER_SIZE: usize = 0x54;
+const TYPEINFO_SIZE: usize = 0x64;
+const IMPINFO_OFFSET_IS_GUID: i32 = 0x0001_0000;
+
+/// Returns true if `data` starts with the MSFT signature.
+pub fn is_msft(data: &[u8]) -> bool {
+    data.len() >= 4 && u32::from_le_bytes([data[0], data[1], data[2], data[3]]) == MSFT_MAGIC
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Segment {
+    offset: i32,
+    length: i32,
+}
+
+#[derive(Debug, Default)]
+struct SegDir {
+    typeinfo_tab: Segment,
+    imp_info: Segment,
+    imp_files: Segment,
+    ref_tab: Segment,
+    guid_tab: Segment,
+    name_tab: Segment,
+    string_tab: Segment,
+    typedesc_tab: Segment,
+    array_descs: Segment,
+    cust_data: Segment,
+    cd_guids: Segment,
+}
+
+struct Header {
+    posguid: i32,
+    lcid: i32,
+    varflags: i32,
+    version: i32,
+    flags: i32,
+    nrtypeinfos: i32,
+    helpstring: i32,
+    helpcontext: i32,
+    name_offset: i32,
+    helpfile: i32,
+    custom_data_offset: i32,
+    dispatchpos: i32,
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    seg: SegDir,
+    typedescs: Vec<TypeDesc>,
+}
+
+fn corrupt(what: &str) -> Error {
+    Error::InvalidTypeLib(format!("MSFT: {}", what))
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&self, offset: usize, len: usize) -> Result<&'a [u8], Error> {
+        offset
+            .checked_add(len)
+            .and_then(|end| self.data.get(offset..end))
+            .ok_or_else(|| corrupt(&format!("read of {} bytes at {:#x} out of range", len, offset)))
+    }
+
+    fn i32_at(&self, offset: usize) -> Result<i32, Error> {
+        let b = self.bytes(offset, 4)?;
+        Ok(i32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn i16_at(&self, offset: usize) -> Result<i16, Error> {
+        let b = self.bytes(offset, 2)?;
+        Ok(i16::from_le_bytes([b[0], b[1]]))
+    }
+
+    fn u16_at(&self, offset: usize) -> Result<u16, Error> {
+        Ok(self.i16_at(offset)? as u16)
+    }
+
+    fn seg_pos(&self, seg: Segment, offset: i32) -> Result<usize, Error> {
+        if seg.offset < 0 || offset < 0 || offset >= seg.length {
+            return Err(corrupt(&format!("offset {:#x} outside segment", offset)));
+        }
+        Ok(seg.offset as usize + offset as usize)
+    }
+
+    fn guid(&self, offset: i32) -> Result<Guid, Error> {
+        if offset < 0 {
+            return Ok(Guid::default());
+        }
+        let pos = self.seg_pos(self.seg.guid_tab, offset)?;
+        let b = self.bytes(pos, 16)?;
+        let mut raw = [0u8; 16];
+        raw.copy_from_slice(b);
+        Ok(Guid::from_le_bytes(raw))
+    }
+
+    /// Name table entry: hreftype, next hash, length/flags/hash word, chars.
+    fn name(&self, offset: i32) -> Result<String, Error> {
+        if offset < 0 {
+            return Ok(String::new());
+        }
+        let pos = self.seg_pos(self.seg.name_tab, offset)?;
+        let len = (self.i32_at(pos + 8)? & 0xff) as usize;
+        Ok(decode_ansi(self.bytes(pos + 12, len)?))
+    }
+
+    /// String table entry: 16-bit length followed by the characters.
+    fn string(&self, offset: i32) -> Result<String, Error> {
+        if offset < 0 {
+            return Ok(String::new());
+        }
+        let pos = self.seg_pos(self.seg.string_tab, offset)?;
+        let len = self.i16_at(pos)?;
+        if len <= 0 {
+            return Ok(String::new());
+        }
+        Ok(decode_ansi(self.bytes(pos + 2, len as usize)?))
+    }
+
+    /// Values are either packed into the offset itself (high bit set, VT in
+    /// bits 26..31, 26-bit value) or stored in the custom data segment as a
+    /// VARTYPE followed by the payload.
+    fn value(&self, offset: i32) -> Result<Variant, Error> {
+        if offset < 0 {
+            let vt = ((offset as u32 & 0x7c00_0000) >> 26) as u16;
+            let v = offset & 0x03ff_ffff;
+            return Ok(variant_from_bits(vt, v as u64));
+        }
+        let pos = self.seg_pos(self.seg.cust_data, offset)?;
+        let vt = self.u16_at(pos)?;
+        let size = match vt {
+            VT_EMPTY | VT_NULL | VT_I2 | VT_I4 | VT_R4 | VT_ERROR | VT_BOOL | VT_I1 | VT_UI1
+            | VT_UI2 | VT_UI4 | VT_INT | VT_UINT | VT_VOID | VT_HRESULT => 4,
+            VT_R8 | VT_CY | VT_DATE | VT_I8 | VT_UI8 | VT_FILETIME => 8,
+            VT_DECIMAL => 16,
+            VT_BSTR => {
+                let len = self.i32_at(pos + 2)?;
+                if len < 0 {
+                    return Ok(Variant::Bstr(String::new()));
+                }
+                return Ok(Variant::Bstr(decode_ansi(
+                    self.bytes(pos + 6, len as usize)?,
+                )));
+            }
+            _ => return Ok(Variant::Unsupported(vt)),
+        };
+        if vt == VT_DECIMAL {
+            // The VARTYPE overlaps wReserved of the DECIMAL, exactly as in memory.
+            let b = self.bytes(pos, 16)?;
+            let scale = b[2];
+            let negative = b[3] & 0x80 != 0;
+            let hi = u32::from_le_bytes([b[4], b[5], b[6], b[7]]) as i128;
+            let lo = u64::from_le_bytes([b[8], b[9], b[10], b[11], b[12], b[13], b[14], b[15]])
+                as i128;
+            let mag = (hi << 64) | lo;
+            return Ok(Variant::Decimal(if negative { -mag } else { mag }, scale));
+        }
+        let b = self.bytes(pos + 2, size)?;
+        let mut raw = [0u8; 8];
+        raw[..size].copy_from_slice(b);
+        Ok(variant_from_bits(vt, u64::from_le_bytes(raw)))
+    }
+
+    /// Custom data is a linked list of (guid offset, value offset, next).
+    fn cust_data(&self, mut offset: i32) -> Result<Vec<CustData>, Error> {
+        let mut items = Vec::new();
+        while offset >= 0 {
+            let pos = self.seg_pos(self.seg.cd_guids, offset)?;
+            let guid = self.guid(self.i32_at(pos)?)?;
+            let value = self.value(self.i32_at(pos + 4)?)?;
+            items.push(CustData { guid, value });
+            let next = self.i32_at(pos + 8)?;
+            if next == offset || items.len() > 0x10000 {
+                return Err(corrupt("custom data list loops"));
+            }
+            offset = next;
+        }
+        Ok(items)
+    }
+
+    /// Negative encodings are simple VARTYPEs, possibly with `VT_BYREF` or
+    /// `VT_ARRAY`; anything else indexes the type description table in
+    /// 8-byte units.
+    fn tdesc(&self, encoded: i32) -> Result<TypeDesc, Error> {
+        if encoded < 0 {
+            return Ok(TypeDesc::Base(encoded as u16 & !VT_RESERVED));
+        }
+        self.typedescs
+            .get(encoded as usize / 8)
+            .cloned()
+            .ok_or_else(|| corrupt(&format!("type description {:#x} out of range", encoded)))
+    }
+
+    fn load_typedescs(&mut self) -> Result<(), Error> {
+        let seg = self.seg.typedesc_tab;
+        if seg.offset < 0 || seg.length <= 0 {
+            return Ok(());
+        }
+        let count = seg.length as usize / 8;
+        let mut raw = Vec::with_capacity(count);
+        for i in 0..count {
+            let pos = seg.offset as usize + i * 8;
+            raw.push((self.i32_at(pos)?, self.i32_at(pos + 4)?));
+        }
+        // Entries only ever point backwards in files written by MIDL, but
+        // resolve recursively so the order does not matter.
+        let mut done: Vec<Option<TypeDesc>> = vec![None; count];
+        for i in 0..count {
+            let td = self.resolve_typedesc(&raw, &mut done, i, 0)?;
+            done[i] = Some(td);
+        }
+        self.typedescs = done.into_iter().map(|t| t.unwrap()).collect();
+        Ok(())
+    }
+
+    fn resolve_typedesc(
+        &self,
+        raw: &[(i32, i32)],
+        done: &mut Vec<Option<TypeDesc>>,
+        index: usize,
+        depth: usize,
+    ) -> Result<TypeDesc, Error> {
+        if let Some(td) = &done[index] {
+            return Ok(td.clone());
+        }
+        if depth > 64 {
+            return Err(corrupt("type description nesting too deep"));
+        }
+        let (word0, word1) = raw[index];
+        let vt = (word0 & 0xffff) as u16 & VT_TYPEMASK;
+        let mut inner = |encoded: i32| -> Result<TypeDesc, Error> {
+            if encoded < 0 {
+                Ok(TypeDesc::Base(encoded as u16 & !VT_RESERVED))
+            } else {
+                let i = encoded as usize / 8;
+                if i >= raw.len() {
+                    return Err(corrupt("type description reference out of range"));
+                }
+                let td = self.resolve_typedesc(raw, done, i, depth + 1)?;
+                done[i] = Some(td.clone());
+                Ok(td)
+            }
+        };
+        Ok(match vt {
+            VT_PTR => TypeDesc::Ptr(Box::new(inner(word1)?)),
+            VT_SAFEARRAY => TypeDesc::SafeArray(Box::new(inner(word1)?)),
+            VT_USERDEFINED => TypeDesc::UserDefined(word1 as u32),
+            VT_CARRAY => {
+                let pos = self.seg_pos(self.seg.array_descs, word1)?;
+                let elem = inner(self.i32_at(pos)?)?;
+                let dims = (self.i32_at(pos + 4)? & 0xffff) as usize;
+                let mut bounds = Vec::with_capacity(dims);
+                for d in 0..dims {
+                    let p = pos + 8 + d * 8;
+                    bounds.push((self.i32_at(p)? as u32, self.i32_at(p + 4)?));
+                }
+                TypeDesc::CArray {
+                    elem: Box::new(elem),
+                    bounds,
+                }
+            }
+            _ => TypeDesc::Base(vt),
+        })
+    }
+}
+
+fn variant_from_bits(vt: u16, bits: u64) -> Variant {
+    match vt {
+        VT_EMPTY => Variant::Empty,
+        VT_NULL => Variant::Null,
+        VT_I1 => Variant::I1(bits as i8),
+        VT_I2 => Variant::I2(bits as i16),
+        VT_I4 => Variant::I4(bits as i32),
+        VT_I8 => Variant::I8(bits as i64),
+        VT_UI1 => Variant::UI1(bits as u8),
+        VT_UI2 => Variant::UI2(bits as u16),
+        VT_UI4 => Variant::UI4(bits as u32),
+        VT_UI8 => Variant::UI8(bits),
+        VT_INT => Variant::Int(bits as i32),
+        VT_UINT => Variant::UInt(bits as u32),
+        VT_R4 => Variant::R4(f32::from_bits(bits as u32)),
+        VT_R8 => Variant::R8(f64::from_bits(bits)),
+        VT_CY => Variant::Cy(bits as i64),
+        VT_DATE => Variant::Date(f64::from_bits(bits)),
+        VT_BOOL => Variant::Bool(bits as u16 != 0),
+        VT_ERROR | VT_HRESULT => Variant::Error(bits as i32),
+        other => Variant::Unsupported(other),
+    }
+}
+
+/// Parses an MSFT type library image.
+pub fn parse(data: &[u8]) -> Result<TypeLib, Error> {
+    if !is_msft(data) {
+        return Err(corrupt("missing MSFT signature"));
+    }
+    let mut reader = Reader {
+        data,
+        seg: SegDir::default(),
+        typedescs: Vec::new(),
+    };
+    let r = &reader;
+    let header = Header {
+        posguid: r.i32_at(0x08)?,
+        lcid: r.i32_at(0x0c)?,
+        varflags: r.i32_at(0x14)?,
+        version: r.i32_at(0x18)?,
+        flags: r.i32_at(0x1c)?,
+        nrtypeinfos: r.i32_at(0x20)?,
+        helpstring: r.i32_at(0x24)?,
+        helpcontext: r.i32_at(0x2c)?,
+        name_offset: r.i32_at(0x38)?,
+        helpfile: r.i32_at(0x3c)?,
+        custom_data_offset: r.i32_at(0x40)?,
+        dispatchpos: r.i32_at(0x4c)?,
+    };
+    if header.nrtypeinfos < 0 || header.nrtypeinfos > 0xffff {
+        return Err(corrupt("bad typeinfo count"));
+    }
+
+    let mut pos = HEADER_SIZE + header.nrtypeinfos as usize * 4;
+    if header.varflags & HELPDLLFLAG != 0 {
+        pos += 4;
+    }
+    let mut segs = [Segment::default(); 15];
+    for seg in segs.iter_mut() {
+        *seg = Segment {
+            offset: reader.i32_at(pos)?,
+            length: reader.i32_at(pos + 4)?,
+        };
+        pos += 16;
+    }
+    reader.seg = SegDir {
+        typeinfo_tab: segs[0],
+        imp_info: segs[1],
+        imp_files: segs[2],
+        ref_tab: segs[3],
+        guid_tab: segs[5],
+        name_tab: segs[7],
+        string_tab: segs[8],
+        typedesc_tab: segs[9],
+        array_descs: segs[10],
+        cust_data: segs[11],
+        cd_guids: segs[12],
+    };
+    reader.load_typedescs()?;
+    let r = &reader;
+
+    let mut lib = TypeLib {
+        attr: LibAttr {
+            guid: r.guid(header.posguid)?,
+            lcid: header.lcid as u32,
+            syskind: (header.varflags & 0x0f) as u32,
+            major_ver: (header.version & 0xffff) as u16,
+            minor_ver: (header.version >> 16) as u16,
+            flags: (header.flags & 0xffff) as u16,
+        },
+        name: r.name(header.name_offset)?,
+        doc_string: r.string(header.helpstring)?,
+        help_context: header.helpcontext as u32,
+        help_file: r.string(header.helpfile)?,
+        custom_data: if header.custom_data_offset >= 0 {
+            r.cust_data(header.custom_data_offset)?
+        } else {
+            Vec::new()
+        },
+        ..Default::default()
+    };
+
+    // Imported libraries, keyed by their offset in the import file segment.
+    let mut import_offsets = HashMap::new();
+    let files = r.seg.imp_files;
+    if files.offset >= 0 {
+        let mut off = 0usize;
+        while (off as i32) < files.length {
+            let p = files.offset as usize + off;
+            let guid = r.guid(r.i32_at(p)?)?;
+            let lcid = r.i32_at(p + 4)? as u32;
+            let major_ver = r.u16_at(p + 8)?;
+            let minor_ver = r.u16_at(p + 10)?;
+            let len = (r.u16_at(p + 12)? >> 2) as usize;
+            let file_name = decode_ansi(r.bytes(p + 14, len)?);
+            import_offsets.insert(off as i32, lib.imports.len());
+            lib.imports.push(ImportLib {
+                guid,
+                lcid,
+                major_ver,
+                minor_ver,
+                file_name,
+            });
+            off = (off + 14 + len + 3) & !3;
+        }
+    }
+
+    // Every hreftype: multiples of the typeinfo size are local typeinfos,
+    // offsets with the low bit set index the import info table.
+    for i in 0..header.nrtypeinfos as usize {
+        lib.refs
+            .insert((i * TYPEINFO_SIZE) as HRefType, TypeRef::Local(i));
+    }
+    let imps = r.seg.imp_info;
+    if imps.offset >= 0 {
+        let mut off = 0;
+        while off + 12 <= imps.length {
+            let p = imps.offset as usize + off as usize;
+            let flags = r.i32_at(p)?;
+            let file = r.i32_at(p + 4)?;
+            let oguid = r.i32_at(p + 8)?;
+            let import = *import_offsets
+                .get(&file)
+                .ok_or_else(|| corrupt("import info references unknown import file"))?;
+            let typekind = ((flags >> 24) & 0xf) as u32;
+            let (guid, index) = if flags & IMPINFO_OFFSET_IS_GUID != 0 {
+                (Some(r.guid(oguid)?), None)
+            } else {
+                (None, u32::try_from(oguid).ok())
+            };
+            let name = guid
+                .as_ref()
+                .and_then(well_known_type)
+                .map(|(name, _)| name.to_string());
+            lib.refs.insert(
+                (off | 1) as HRefType,
+                TypeRef::External {
+                    import,
+                    guid,
+                    index,
+                    name,
+                    typekind: Some(typekind),
+                },
+            );
+            off += 12;
+        }
+    }
+
+    for i in 0..header.nrtypeinfos as usize {
+        let ti = read_type_info(r, i, header.dispatchpos)?;
+        lib.type_infos.push(ti);
+    }
+    Ok(lib)
+}
+
+fn read_type_info(r: &Reader, index: usize, dispatchpos: i32) -> Result<TypeInfo, Error> {
+    let base = r.seg_pos(r.seg.typeinfo_tab, (index * TYPEINFO_SIZE) as i32)?;
+    let raw_kind = r.i32_at(base)?;
+    let memoffset = r.i32_at(base + 0x04)?;
+    let c_element = r.i32_at(base + 0x18)?;
+    let posguid = r.i32_at(base + 0x2c)?;
+    let flags = (r.i32_at(base + 0x30)? & 0xffff) as u16;
+    let name_offset = r.i32_at(base + 0x34)?;
+    let version = r.i32_at(base + 0x38)?;
+    let docstring = r.i32_at(base + 0x3c)?;
+    let help_string_context = r.i32_at(base + 0x40)? as u32;
+    let help_context = r.i32_at(base + 0x44)? as u32;
+    let o_cust_data = r.i32_at(base + 0x48)?;
+    let c_impl_types = r.i16_at(base + 0x4c)?;
+    let cb_size_vft = r.u16_at(base + 0x4e)?;
+    let size = r.i32_at(base + 0x50)?;
+    let datatype1 = r.i32_at(base + 0x54)?;
+
+    let mut typekind = (raw_kind & 0xf) as u32;
+    if typekind > TKIND_UNION {
+        return Err(Error::UnsupportedTypeKind {
+            name: r.name(name_offset)?,
+            kind: typekind,
+        });
+    }
+    // MIDL may store a dual interface as TKIND_INTERFACE; OLE always presents
+    // it as the dispinterface half of the pair.
+    if typekind == TKIND_INTERFACE && flags & TYPEFLAG_FDUAL != 0 {
+        typekind = TKIND_DISPATCH;
+    }
+    let c_funcs = (c_element & 0xffff) as usize;
+    let c_vars = ((c_element >> 16) & 0xffff) as usize;
+
+    let tdesc_alias = if typekind == TKIND_ALIAS {
+        Some(r.tdesc(datatype1)?)
+    } else {
+        None
+    };
+
+    let mut impl_types = Vec::new();
+    match typekind {
+        TKIND_COCLASS => {
+            let mut offset = datatype1;
+            for _ in 0..c_impl_types.max(0) {
+                if offset < 0 {
+                    break;
+                }
+                let p = r.seg_pos(r.seg.ref_tab, offset)?;
+                impl_types.push(ImplType {
+                    href: r.i32_at(p)? as HRefType,
+                    flags: r.i32_at(p + 4)?,
+                    custom_data: r.cust_data(r.i32_at(p + 8)?)?,
+                });
+                offset = r.i32_at(p + 12)?;
+            }
+        }
+        TKIND_DISPATCH => {
+            // Dispinterfaces always present IDispatch as their first
+            // implemented type; a different datatype1 names the wrapped or
+            // dual interface.
+            let href = if datatype1 != -1 { datatype1 } else { dispatchpos };
+            if href != -1 {
+                impl_types.push(ImplType {
+                    href: href as HRefType,
+                    flags: 0,
+                    custom_data: Vec::new(),
+                });
+            }
+        }
+        _ => {
+            if c_impl_types > 0 && datatype1 != -1 {
+                impl_types.push(ImplType {
+                    href: datatype1 as HRefType,
+                    flags: 0,
+                    custom_data: Vec::new(),
+                });
+            }
+        }
+    }
+
+    let dll_name = if typekind == TKIND_MODULE && datatype1 >= 0 {
+        Some(r.string(datatype1)?)
+    } else {
+        None
+    };
+
+    let (funcs, vars) = if c_funcs + c_vars > 0 {
+        read_members(r, memoffset, c_funcs, c_vars)?
+    } else {
+        (Vec::new(), Vec::new())
+    };
+
+    Ok(TypeInfo {
+        name: r.name(name_offset)?,
+        doc_string: r.string(docstring)?,
+        help_context,
+        help_string_context,
+        attr: TypeAttr {
+            guid: r.guid(posguid)?,
+            typekind,
+            flags,
+            major_ver: (version & 0xffff) as u16,
+            minor_ver: (version >> 16) as u16,
+            cb_size_instance: size as u32,
+            cb_size_vft,
+            cb_alignment: ((raw_kind >> 11) & 0x1f) as u16,
+            tdesc_alias,
+        },
+        funcs,
+        vars,
+        impl_types,
+        dll_name,
+        custom_data: r.cust_data(o_cust_data)?,
+    })
+}
+
+/// The member block: total record length, the records (functions first),
+/// then arrays of member ids, name offsets and record offsets.
+fn read_members(
+    r: &Reader,
+    offset: i32,
+    c_funcs: usize,
+    c_vars: usize,
+) -> Result<(Vec<FuncDesc>, Vec<VarDesc>), Error> {
+    if offset < 0 {
+        return Err(corrupt("typeinfo has members but no member block"));
+    }
+    let offset = offset as usize;
+    let infolen = r.i32_at(offset)?;
+    if infolen < 0 {
+        return Err(corrupt("negative member block length"));
+    }
+    let records = offset + 4;
+    let arrays = records + infolen as usize;
+    let total = c_funcs + c_vars;
+    let memid_at = |i: usize| r.i32_at(arrays + i * 4);
+    let name_at = |i: usize| r.i32_at(arrays + (total + i) * 4);
+    let record_at = |i: usize| -> Result<usize, Error> {
+        let offset = r.i32_at(arrays + (2 * total + i) * 4)?;
+        usize::try_from(offset)
+            .ok()
+            .and_then(|offset| records.checked_add(offset))
+            .ok_or_else(|| corrupt(&format!("member record offset {:#x} out of range", offset)))
+    };
+
+    let mut funcs: Vec<FuncDesc> = Vec::with_capacity(c_funcs);
+    for i in 0..c_funcs {
+        let rec = record_at(i)?;
+        let reclen = (r.i32_at(rec)? & 0xffff) as usize;
+        let datatype = r.i32_at(rec + 4)?;
+        let func_flags = (r.i32_at(rec + 8)? & 0xffff) as u16;
+        let vtable_offset = r.i16_at(rec + 12)?;
+        let fkccic = r.i32_at(rec + 16)?;
+        let nrargs = r.i16_at(rec + 20)?.max(0) as usize;
+        let nroargs = r.i16_at(rec + 22)?;
+
+        let has_defaults = fkccic & 0x1000 != 0;
+        let has_custdata = fkccic & 0x80 != 0;
+        // Parameters, and their default values when present, fill the end
+        // of the record.
+        let trailing = nrargs * if has_defaults { 16 } else { 12 };
+        let optional = reclen
+            .checked_sub(trailing)
+            .ok_or_else(|| corrupt("function record shorter than its parameters"))?;
+        let opt = |field: usize| -> Result<Option<i32>, Error> {
+            if optional > field {
+                Ok(Some(r.i32_at(rec + field)?))
+            } else {
+                Ok(None)
+            }
+        };
+        let help_context = opt(24)?.unwrap_or(0) as u32;
+        let doc_string = match opt(28)? {
+            Some(o) => r.string(o)?,
+            None => String::new(),
+        };
+        let entry = match opt(32)? {
+            Some(o) if fkccic & 0x2000 != 0 => Some(DllEntry::Ordinal(o as u16)),
+            Some(o) if o >= 0 => Some(DllEntry::Name(r.string(o)?)),
+            _ => None,
+        };
+        let help_string_context = opt(44)?.unwrap_or(0) as u32;
+        let custom_data = match opt(48)? {
+            Some(o) if has_custdata => r.cust_data(o)?,
+            _ => Vec::new(),
+        };
+
+        let invkind = ((fkccic >> 3) & 0xf) as u32;
+        let name_offset = name_at(i)?;
+        // The second half of a propget/propput pair may omit its name.
+        let name = if name_offset == -1 && i > 0 && is_prop(funcs[i - 1].invkind) && is_prop(invkind)
+        {
+            funcs[i - 1].name.clone()
+        } else {
+            r.name(name_offset)?
+        };
+
+        let defaults_at = rec + optional;
+        let params_at = rec + reclen - nrargs * 12;
+        let mut params = Vec::with_capacity(nrargs);
+        for j in 0..nrargs {
+            let p = params_at + j * 12;
+            let datatype = r.i32_at(p)?;
+            let oname = r.i32_at(p + 4)?;
+            let flags = (r.i32_at(p + 8)? & 0xffff) as u16;
+            let default_value = if flags & PARAMFLAG_FHASDEFAULT != 0 && has_defaults {
+                Some(r.value(r.i32_at(defaults_at + j * 4)?)?)
+            } else {
+                None
+            };
+            let custom_data = match opt(52 + j * 4)? {
+                Some(o) if has_custdata => r.cust_data(o)?,
+                _ => Vec::new(),
+            };
+            params.push(ParamDesc {
+                name: if oname == -1 {
+                    None
+                } else {
+                    Some(r.name(oname)?)
+                },
+                tdesc: r.tdesc(datatype)?,
+                flags,
+                default_value,
+                custom_data,
+            });
+        }
+
+        funcs.push(FuncDesc {
+            memid: memid_at(i)?,
+            name,
+            doc_string,
+            help_context,
+            help_string_context,
+            funckind: (fkccic & 0x7) as u32,
+            invkind,
+            callconv: ((fkccic >> 8) & 0xf) as u32,
+            func_flags,
+            ovft: vtable_offset & !1,
+            params,
+            c_params_opt: nroargs,
+            ret: r.tdesc(datatype)?,
+            entry,
+            custom_data,
+        });
+    }
+
+    let mut vars = Vec::with_capacity(c_vars);
+    for i in 0..c_vars {
+        let rec = record_at(c_funcs + i)?;
+        let reclen = (r.i32_at(rec)? & 0xff) as usize;
+        let datatype = r.i32_at(rec + 4)?;
+        let var_flags = (r.i32_at(rec + 8)? & 0xffff) as u16;
+        let varkind = r.i16_at(rec + 12)? as u32;
+        let offs_value = r.i32_at(rec + 16)?;
+        let opt = |field: usize| -> Result<Option<i32>, Error> {
+            if reclen > field {
+                Ok(Some(r.i32_at(rec + field)?))
+            } else {
+                Ok(None)
+            }
+        };
+        let help_context = opt(20)?.unwrap_or(0) as u32;
+        let doc_string = match opt(24)? {
+            Some(o) => r.string(o)?,
+            None => String::new(),
+        };
+        let custom_data = match opt(32)? {
+            Some(o) => r.cust_data(o)?,
+            None => Vec::new(),
+        };
+        let help_string_context = opt(36)?.unwrap_or(0) as u32;
+        let (value, oinst) = if varkind == VAR_CONST {
+            (Some(r.value(offs_value)?), 0)
+        } else {
+            (None, offs_value as u32)
+        };
+        vars.push(VarDesc {
+            memid: memid_at(c_funcs + i)?,
+            name: r.name(name_at(c_funcs + i)?)?,
+            doc_string,
+            help_context,
+            help_string_context,
+            varkind,
+            var_flags,
+            tdesc: r.tdesc(datatype)?,
+            value,
+            oinst,
+            custom_data,
+        });
+    }
+    Ok((funcs, vars))
+}
+
+fn is_prop(invkind: u32) -> bool {
+    matches!(
+        invkind,
+        INVOKE_PROPERTYGET | INVOKE_PROPERTYPUT | INVOKE_PROPERTYPUTREF
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str) -> TypeLib {
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures")
+            .join(name);
+        parse(&std::fs::read(path).unwrap()).unwrap()
+    }
+
+    fn type_info<'a>(lib: &'a TypeLib, name: &str) -> &'a TypeInfo {
+        lib.type_infos.iter().find(|t| t.name == name).unwrap()
+    }
+
+    #[test]
+    fn reads_library_attributes() {
+        let lib = fixture("simple.tlb");
+        assert_eq!(lib.name, "SimpleLib");
+        assert_eq!(lib.doc_string, "Simple test library");
+        assert_eq!(
+            lib.attr.guid,
+            Guid::from_u128(0x5a1e0a00_0001_4b1d_9c2e_0123456789ab)
+        );
+        assert_eq!((lib.attr.major_ver, lib.attr.minor_ver), (1, 2));
+        assert_eq!(lib.custom_data.len(), 1);
+        assert_eq!(
+            lib.custom_data[0].value,
+            Variant::Bstr("Simple fixture".to_string())
+        );
+        assert_eq!(lib.imports.len(), 1);
+        assert_eq!(lib.imports[0].file_name, "stdole2.tlb");
+    }
+
+    #[test]
+    fn reads_enum_values() {
+        let lib = fixture("simple.tlb");
+        let colors = type_info(&lib, "Colors");
+        assert_eq!(colors.attr.typekind, TKIND_ENUM);
+        let values: Vec<_> = colors
+            .vars
+            .iter()
+            .map(|v| (v.name.as_str(), v.value.clone().unwrap()))
+            .collect();
+        assert_eq!(
+            values,
+            vec![
+                ("Red", Variant::I4(0)),
+                ("Green", Variant::I4(1)),
+                ("Blue", Variant::I4(2)),
+                ("Unset", Variant::I4(-1)),
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_dual_interface_methods() {
+        let lib = fixture("simple.tlb");
+        let shape = type_info(&lib, "IShape");
+        assert_eq!(shape.attr.typekind, TKIND_DISPATCH);
+        assert!(shape.attr.flags & TYPEFLAG_FDUAL != 0);
+        let base = lib.resolve_ref(shape.impl_types[0].href).unwrap();
+        assert_eq!(base.name, "IDispatch");
+
+        let names: Vec<_> = shape.funcs.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, vec!["Name", "Name", "Move", "Color", "Center"]);
+        assert_eq!(shape.funcs[0].invkind, INVOKE_PROPERTYGET);
+        assert_eq!(shape.funcs[1].invkind, INVOKE_PROPERTYPUT);
+        assert_eq!(shape.funcs[0].doc_string, "The shape name");
+
+        let mv = &shape.funcs[2];
+        assert_eq!(mv.memid, 3);
+        assert_eq!(mv.params.len(), 2);
+        assert_eq!(mv.params[1].name.as_deref(), Some("dy"));
+        assert_eq!(
+            mv.params[1].flags,
+            PARAMFLAG_FIN | PARAMFLAG_FOPT | PARAMFLAG_FHASDEFAULT
+        );
+        assert_eq!(mv.params[1].default_value, Some(Variant::I4(5)));
+
+        let color = &shape.funcs[3];
+        match &color.params[0].tdesc {
+            TypeDesc::Ptr(inner) => match **inner {
+                TypeDesc::UserDefined(href) => {
+                    assert_eq!(lib.resolve_ref(href).unwrap().name, "Colors")
+                }
+                ref other => panic!("unexpected {:?}", other),
+            },
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn reads_records_aliases_and_coclasses() {
+        let lib = fixture("simple.tlb");
+        let point = type_info(&lib, "Point");
+        assert_eq!(point.attr.typekind, TKIND_RECORD);
+        assert_eq!(point.attr.cb_size_instance, 8);
+        assert_eq!(point.vars[1].name, "y");
+        assert_eq!(point.vars[1].oinst, 4);
+
+        let handle = type_info(&lib, "Handle");
+        assert_eq!(handle.attr.tdesc_alias, Some(TypeDesc::Base(VT_I4)));
+
+        let shape = type_info(&lib, "Shape");
+        assert_eq!(shape.attr.typekind, TKIND_COCLASS);
+        let impls: Vec<_> = shape
+            .impl_types
+            .iter()
+            .map(|i| (lib.resolve_ref(i.href).unwrap().name, i.flags))
+            .collect();
+        assert_eq!(
+            impls,
+            vec![
+                ("IShape".to_string(), IMPLTYPEFLAG_FDEFAULT),
+                (
+                    "_ShapeEvents".to_string(),
+                    IMPLTYPEFLAG_FDEFAULT | IMPLTYPEFLAG_FSOURCE
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_truncated_files() {
+        let data = std::fs::read(
+            std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/simple.tlb"),
+        )
+        .unwrap();
+        assert!(parse(&data[..0x60]).is_err());
+        assert!(parse(b"not a type library").is_err());
+    }
+}
@@ -0,0 +1,334 @@
+//! Extraction of `TYPELIB` resources from PE images (.dll, .ocx, .exe).
+//!
+//! Only what is needed to reach the resource data is decoded: the section
+//! table (to map RVAs to file offsets) and the three-level resource directory
+//! (type, name, language).
+
+use std::path::{Path, PathBuf};
+
+use crate::error::Error;
+
+const IMAGE_DIRECTORY_ENTRY_RESOURCE: usize = 2;
+const IMAGE_DIRECTORY_ENTRY_COM_DESCRIPTOR: usize = 14;
+const PE32_MAGIC: u16 = 0x10b;
+const PE32_PLUS_MAGIC: u16 = 0x20b;
+const SUBDIRECTORY_FLAG: u32 = 0x8000_0000;
+const NAMED_ENTRY_FLAG: u32 = 0x8000_0000;
+
+/// Identifier of a resource: either numeric or a string name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ResourceId {
+    Id(u16),
+    Name(String),
+}
+
+impl std::fmt::Display for ResourceId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ResourceId::Id(id) => write!(f, "{}", id),
+            ResourceId::Name(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+/// An embedded type library.
+#[derive(Debug, Clone)]
+pub struct TypeLibResource<'a> {
+    pub id: ResourceId,
+    pub data: &'a [u8],
+}
+
+/// Returns true if `data` looks like a PE image.
+pub fn is_pe(data: &[u8]) -> bool {
+    data.starts_with(b"MZ")
+}
+
+fn corrupt(what: &str) -> Error {
+    Error::InvalidTypeLib(format!("PE: {}", what))
+}
+
+struct Section {
+    virtual_address: u32,
+    virtual_size: u32,
+    raw_size: u32,
+    raw_offset: u32,
+}
+
+struct Image<'a> {
+    data: &'a [u8],
+    sections: Vec<Section>,
+    /// RVA of each data directory, 0 when absent.
+    directories: Vec<u32>,
+}
+
+impl<'a> Image<'a> {
+    /// Reads the headers and the section table.
+    fn parse(data: &'a [u8]) -> Result<Self, Error> {
+        let mut image = Image {
+            data,
+            sections: Vec::new(),
+            directories: Vec::new(),
+        };
+        if !is_pe(data) {
+            return Err(corrupt("missing MZ signature"));
+        }
+        let pe = image.u32_at(0x3c)? as usize;
+        if image.bytes(pe, 4)? != b"PE\0\0" {
+            return Err(corrupt("missing PE signature"));
+        }
+        let n_sections = image.u16_at(pe + 6)? as usize;
+        let opt_size = image.u16_at(pe + 20)? as usize;
+        let opt = pe + 24;
+        let (n_dirs_at, dirs_at) = match image.u16_at(opt)? {
+            PE32_MAGIC => (opt + 92, opt + 96),
+            PE32_PLUS_MAGIC => (opt + 108, opt + 112),
+            other => {
+                return Err(corrupt(&format!(
+                    "unknown optional header magic {:#x}",
+                    other
+                )));
+            }
+        };
+        for i in 0..n_sections {
+            let s = opt + opt_size + i * 40;
+            image.sections.push(Section {
+                virtual_size: image.u32_at(s + 8)?,
+                virtual_address: image.u32_at(s + 12)?,
+                raw_size: image.u32_at(s + 16)?,
+                raw_offset: image.u32_at(s + 20)?,
+            });
+        }
+        // The count is bounded by the optional header, whatever it claims.
+        let n_dirs = (image.u32_at(n_dirs_at)? as usize).min(16);
+        for i in 0..n_dirs {
+            let rva = image.u32_at(dirs_at + i * 8)?;
+            image.directories.push(rva);
+        }
+        Ok(image)
+    }
+
+    fn directory(&self, index: usize) -> Option<u32> {
+        self.directories.get(index).copied().filter(|&rva| rva != 0)
+    }
+
+    fn bytes(&self, offset: usize, len: usize) -> Result<&'a [u8], Error> {
+        offset
+            .checked_add(len)
+            .and_then(|end| self.data.get(offset..end))
+            .ok_or_else(|| {
+                corrupt(&format!(
+                    "read of {} bytes at {:#x} out of range",
+                    len, offset
+                ))
+            })
+    }
+
+    fn u16_at(&self, offset: usize) -> Result<u16, Error> {
+        let b = self.bytes(offset, 2)?;
+        Ok(u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    fn u32_at(&self, offset: usize) -> Result<u32, Error> {
+        let b = self.bytes(offset, 4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn rva_to_offset(&self, rva: u32) -> Result<usize, Error> {
+        let section = self
+            .sections
+            .iter()
+            .find(|s| {
+                let size = s.virtual_size.max(s.raw_size);
+                rva >= s.virtual_address && rva - s.virtual_address < size
+            })
+            .ok_or_else(|| corrupt(&format!("RVA {:#x} is not in any section", rva)))?;
+        (rva - section.virtual_address)
+            .checked_add(section.raw_offset)
+            .map(|offset| offset as usize)
+            .ok_or_else(|| corrupt(&format!("RVA {:#x} maps past the end of the file", rva)))
+    }
+}
+
+/// Lists the `TYPELIB` resources of a PE image, in resource directory order
+/// (named entries first, then IDs ascending). Images without a resource
+/// directory yield an empty list.
+pub fn type_lib_resources(data: &[u8]) -> Result<Vec<TypeLibResource<'_>>, Error> {
+    let image = Image::parse(data)?;
+    let Some(rsrc_rva) = image.directory(IMAGE_DIRECTORY_ENTRY_RESOURCE) else {
+        return Ok(Vec::new());
+    };
+    let root = image.rva_to_offset(rsrc_rva)?;
+
+    let mut resources = Vec::new();
+    for (ty, ty_entry) in read_directory(&image, root, root)? {
+        if ty != ResourceId::Name("TYPELIB".to_string()) {
+            continue;
+        }
+        let ResourceEntry::Directory(ty_dir) = ty_entry else {
+            return Err(corrupt("TYPELIB entry is not a directory"));
+        };
+        for (id, name_entry) in read_directory(&image, root, ty_dir)? {
+            let ResourceEntry::Directory(lang_dir) = name_entry else {
+                return Err(corrupt("TYPELIB name entry is not a directory"));
+            };
+            // Take the first language; type libraries are language neutral.
+            let Some((_, lang_entry)) = read_directory(&image, root, lang_dir)?.into_iter().next()
+            else {
+                continue;
+            };
+            let ResourceEntry::Data(entry) = lang_entry else {
+                return Err(corrupt("resource tree is deeper than three levels"));
+            };
+            let rva = image.u32_at(entry)?;
+            let size = image.u32_at(entry + 4)? as usize;
+            let data = image.bytes(image.rva_to_offset(rva)?, size)?;
+            resources.push(TypeLibResource { id, data });
+        }
+    }
+    Ok(resources)
+}
+
+/// The ECMA-335 metadata of a CLI image such as a .winmd file, located
+/// through the CLI header.
+pub fn cli_metadata(data: &[u8]) -> Result<&[u8], Error> {
+    let image = Image::parse(data)?;
+    let header = image
+        .directory(IMAGE_DIRECTORY_ENTRY_COM_DESCRIPTOR)
+        .ok_or_else(|| corrupt("no CLI header"))?;
+    let header = image.rva_to_offset(header)?;
+    let rva = image.u32_at(header + 8)?;
+    let size = image.u32_at(header + 12)? as usize;
+    image.bytes(image.rva_to_offset(rva)?, size)
+}
+
+enum ResourceEntry {
+    Directory(usize),
+    Data(usize),
+}
+
+/// Reads one level of the resource directory; offsets are made absolute.
+fn read_directory(
+    image: &Image,
+    root: usize,
+    dir: usize,
+) -> Result<Vec<(ResourceId, ResourceEntry)>, Error> {
+    let count = image.u16_at(dir + 12)? as usize + image.u16_at(dir + 14)? as usize;
+    let mut entries = Vec::with_capacity(count);
+    for i in 0..count {
+        let e = dir + 16 + i * 8;
+        let name = image.u32_at(e)?;
+        let target = image.u32_at(e + 4)?;
+        let id = if name & NAMED_ENTRY_FLAG != 0 {
+            let at = root + (name & !NAMED_ENTRY_FLAG) as usize;
+            let len = image.u16_at(at)? as usize;
+            let units: Vec<u16> = image
+                .bytes(at + 2, len * 2)?
+                .chunks_exact(2)
+                .map(|c| u16::from_le_bytes([c[0], c[1]]))
+                .collect();
+            ResourceId::Name(String::from_utf16_lossy(&units).to_ascii_uppercase())
+        } else {
+            ResourceId::Id(name as u16)
+        };
+        let offset = root + (target & !SUBDIRECTORY_FLAG) as usize;
+        let entry = if target & SUBDIRECTORY_FLAG != 0 {
+            ResourceEntry::Directory(offset)
+        } else {
+            ResourceEntry::Data(offset)
+        };
+        entries.push((id, entry));
+    }
+    Ok(entries)
+}
+
+/// Picks the type library to load from a PE image: the resource with ID
+/// `resource`, or the first one when no ID is given.
+pub fn select_type_lib(data: &[u8], resource: Option<u16>) -> Result<&[u8], Error> {
+    let resources = type_lib_resources(data)?;
+    if resources.is_empty() {
+        return Err(Error::InvalidTypeLib(
+            "image has no TYPELIB resource".to_string(),
+        ));
+    }
+    let found = match resource {
+        None => resources.first(),
+        Some(id) => resources.iter().find(|r| r.id == ResourceId::Id(id)),
+    };
+    match found {
+        Some(r) => Ok(r.data),
+        None => {
+            let available: Vec<String> = resources.iter().map(|r| r.id.to_string()).collect();
+            Err(Error::InvalidTypeLib(format!(
+                "no TYPELIB resource {} (available: {})",
+                resource.unwrap_or_default(),
+                available.join(", ")
+            )))
+        }
+    }
+}
+
+/// Splits the `foo.dll\2` syntax LoadTypeLib accepts into the image path and
+/// the resource ID. Paths that exist as given are never split.
+pub fn split_resource_suffix(path: &Path) -> (PathBuf, Option<u16>) {
+    if path.exists() {
+        return (path.to_path_buf(), None);
+    }
+    let text = path.to_string_lossy();
+    if let Some((file, suffix)) = text.rsplit_once('\\')
+        && let Ok(id) = suffix.parse::<u16>()
+    {
+        return (PathBuf::from(file), Some(id));
+    }
+    (path.to_path_buf(), None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str) -> Vec<u8> {
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures")
+            .join(name);
+        std::fs::read(path).unwrap()
+    }
+
+    #[test]
+    fn lists_type_lib_resources() {
+        let dll = fixture("typelibs.dll");
+        let resources = type_lib_resources(&dll).unwrap();
+        let ids: Vec<_> = resources.iter().map(|r| r.id.clone()).collect();
+        assert_eq!(ids, vec![ResourceId::Id(1), ResourceId::Id(2)]);
+        assert_eq!(resources[0].data, fixture("simple.tlb").as_slice());
+        assert_eq!(resources[1].data, fixture("legacy_sltg.tlb").as_slice());
+    }
+
+    #[test]
+    fn selects_by_resource_id() {
+        let dll = fixture("typelibs.dll");
+        assert_eq!(
+            select_type_lib(&dll, None).unwrap(),
+            fixture("simple.tlb").as_slice()
+        );
+        assert_eq!(
+            select_type_lib(&dll, Some(2)).unwrap(),
+            fixture("legacy_sltg.tlb").as_slice()
+        );
+        let err = select_type_lib(&dll, Some(3)).unwrap_err().to_string();
+        assert!(err.contains("available: 1, 2"), "{}", err);
+    }
+
+    #[test]
+    fn splits_resource_suffix() {
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+        let dll = dir.join("typelibs.dll");
+        assert_eq!(split_resource_suffix(&dll), (dll.clone(), None));
+        let with_suffix = PathBuf::from(format!("{}\\2", dll.display()));
+        assert_eq!(split_resource_suffix(&with_suffix), (dll, Some(2)));
+    }
+
+    #[test]
+    fn rejects_non_pe_data() {
+        assert!(type_lib_resources(&fixture("simple.tlb")).is_err());
+    }
+}
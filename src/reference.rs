@@ -0,0 +1,361 @@
+//! Existing .winmd files that imported type libraries map to.
+//!
+//! `winmd --reference stdole2.tlb=Stdole.winmd` makes types imported from
+//! `stdole2.tlb` resolve to the TypeDefs of `Stdole.winmd`: the generated
+//! TypeRefs carry their namespace and an AssemblyRef to that file, so
+//! windows-bindgen binds them to the referenced metadata.
+//!
+//! Only the `Module` and `TypeDef` tables are read (ECMA-335 II.22), which
+//! needs the row counts of the tables their indexes point into.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::error::Error;
+use crate::model::Import;
+use crate::pe;
+
+// Metadata table numbers.
+const MODULE: usize = 0x00;
+const TYPE_REF: usize = 0x01;
+const TYPE_DEF: usize = 0x02;
+const FIELD: usize = 0x04;
+const METHOD_DEF: usize = 0x06;
+const MODULE_REF: usize = 0x1a;
+const TYPE_SPEC: usize = 0x1b;
+const ASSEMBLY_REF: usize = 0x23;
+
+const METADATA_SIGNATURE: u32 = 0x424a_5342;
+/// `TypeAttributes` visibility mask; values above 1 are nested types.
+const TD_VISIBILITY_MASK: u32 = 0x7;
+
+fn corrupt(what: &str) -> Error {
+    Error::InvalidWinmd(what.to_string())
+}
+
+fn bytes(data: &[u8], offset: usize, len: usize) -> Result<&[u8], Error> {
+    offset
+        .checked_add(len)
+        .and_then(|end| data.get(offset..end))
+        .ok_or_else(|| {
+            corrupt(&format!(
+                "read of {} bytes at {:#x} out of range",
+                len, offset
+            ))
+        })
+}
+
+fn u16_at(data: &[u8], offset: usize) -> Result<u16, Error> {
+    let b = bytes(data, offset, 2)?;
+    Ok(u16::from_le_bytes([b[0], b[1]]))
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Result<u32, Error> {
+    let b = bytes(data, offset, 4)?;
+    Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+/// The top-level types of one .winmd file.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Metadata {
+    /// Assembly the types are referenced from: the module name without its
+    /// extension, as in the Win32 metadata.
+    pub assembly: String,
+    /// Namespace of each type, by name. The first definition wins.
+    pub namespaces: HashMap<String, String>,
+}
+
+impl Metadata {
+    /// Reads the .winmd at `path`.
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let data = std::fs::read(path).map_err(|e| Error::io(path, e))?;
+        Self::parse(&data).map_err(|e| match e {
+            Error::InvalidWinmd(what) => {
+                Error::InvalidWinmd(format!("{}: {}", path.display(), what))
+            }
+            e => e,
+        })
+    }
+
+    /// Reads a .winmd already in memory.
+    pub fn parse(image: &[u8]) -> Result<Self, Error> {
+        let root = pe::cli_metadata(image).map_err(|e| match e {
+            Error::InvalidTypeLib(what) => corrupt(&what),
+            e => e,
+        })?;
+        if u32_at(root, 0)? != METADATA_SIGNATURE {
+            return Err(corrupt("missing metadata signature"));
+        }
+        let version_len = u32_at(root, 12)? as usize;
+        let n_streams = u16_at(root, 16 + version_len + 2)? as usize;
+        let mut at = 16 + version_len + 4;
+        let (mut tables, mut strings) = (None, None);
+        for _ in 0..n_streams {
+            let offset = u32_at(root, at)? as usize;
+            let size = u32_at(root, at + 4)? as usize;
+            let name_len = bytes(root, at + 8, root.len().saturating_sub(at + 8))?
+                .iter()
+                .position(|&b| b == 0)
+                .ok_or_else(|| corrupt("unterminated stream name"))?;
+            let stream = bytes(root, offset, size)?;
+            match bytes(root, at + 8, name_len)? {
+                b"#~" | b"#-" => tables = Some(stream),
+                b"#Strings" => strings = Some(stream),
+                _ => {}
+            }
+            at += 8 + (name_len + 1).next_multiple_of(4);
+        }
+        let tables = tables.ok_or_else(|| corrupt("no #~ stream"))?;
+        let strings = strings.ok_or_else(|| corrupt("no #Strings stream"))?;
+        Tables::parse(tables, strings)?.metadata()
+    }
+}
+
+/// The parts of the `#~` stream needed to walk `Module` and `TypeDef`.
+struct Tables<'a> {
+    data: &'a [u8],
+    strings: &'a [u8],
+    rows: [u32; 64],
+    /// Offset of the first row of `Module`.
+    start: usize,
+    wide_strings: bool,
+    wide_guids: bool,
+}
+
+impl<'a> Tables<'a> {
+    fn parse(data: &'a [u8], strings: &'a [u8]) -> Result<Self, Error> {
+        let heap_sizes = bytes(data, 6, 1)?[0];
+        let valid = u64::from_le_bytes(bytes(data, 8, 8)?.try_into().unwrap());
+        let mut rows = [0; 64];
+        let mut at = 24;
+        for (i, count) in rows.iter_mut().enumerate() {
+            if valid & (1 << i) != 0 {
+                *count = u32_at(data, at)?;
+                at += 4;
+            }
+        }
+        Ok(Tables {
+            data,
+            strings,
+            rows,
+            start: at,
+            wide_strings: heap_sizes & 1 != 0,
+            wide_guids: heap_sizes & 2 != 0,
+        })
+    }
+
+    fn string_size(&self) -> usize {
+        if self.wide_strings { 4 } else { 2 }
+    }
+
+    fn guid_size(&self) -> usize {
+        if self.wide_guids { 4 } else { 2 }
+    }
+
+    fn index_size(&self, table: usize) -> usize {
+        if self.rows[table] < 0x1_0000 { 2 } else { 4 }
+    }
+
+    /// Size of a coded index over `tables`, II.24.2.6.
+    fn coded_size(&self, tables: &[usize]) -> usize {
+        let tag_bits = usize::BITS - (tables.len() - 1).leading_zeros();
+        let max = tables.iter().map(|&t| self.rows[t]).max().unwrap_or(0);
+        if max < 1 << (16 - tag_bits) { 2 } else { 4 }
+    }
+
+    fn index_at(&self, offset: usize, size: usize) -> Result<u32, Error> {
+        Ok(if size == 2 {
+            u16_at(self.data, offset)? as u32
+        } else {
+            u32_at(self.data, offset)?
+        })
+    }
+
+    fn string(&self, index: u32) -> Result<String, Error> {
+        let tail = self
+            .strings
+            .get(index as usize..)
+            .ok_or_else(|| corrupt(&format!("string index {:#x} out of range", index)))?;
+        let end = tail
+            .iter()
+            .position(|&b| b == 0)
+            .ok_or_else(|| corrupt("unterminated string"))?;
+        Ok(String::from_utf8_lossy(&tail[..end]).into_owned())
+    }
+
+    fn metadata(&self) -> Result<Metadata, Error> {
+        let (string, guid) = (self.string_size(), self.guid_size());
+        let module_row = 2 + string + 3 * guid;
+        // ResolutionScope: Module, ModuleRef, AssemblyRef, TypeRef.
+        let type_ref_row =
+            self.coded_size(&[MODULE, MODULE_REF, ASSEMBLY_REF, TYPE_REF]) + 2 * string;
+        let type_def_row = 4
+            + 2 * string
+            + self.coded_size(&[TYPE_DEF, TYPE_REF, TYPE_SPEC])
+            + self.index_size(FIELD)
+            + self.index_size(METHOD_DEF);
+
+        let module = self.string(self.index_at(self.start + 2, string)?)?;
+        let assembly = Path::new(&module)
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or(module);
+
+        let mut namespaces = HashMap::new();
+        let type_defs = self.start
+            + self.rows[MODULE] as usize * module_row
+            + self.rows[TYPE_REF] as usize * type_ref_row;
+        for i in 0..self.rows[TYPE_DEF] as usize {
+            let row = type_defs + i * type_def_row;
+            let flags = u32_at(self.data, row)?;
+            let name = self.string(self.index_at(row + 4, string)?)?;
+            let namespace = self.string(self.index_at(row + 4 + string, string)?)?;
+            if flags & TD_VISIBILITY_MASK > 1 || namespace.is_empty() {
+                continue;
+            }
+            namespaces.entry(name).or_insert(namespace);
+        }
+        Ok(Metadata {
+            assembly,
+            namespaces,
+        })
+    }
+}
+
+/// .winmd files by the file name of the type library they stand for.
+#[derive(Debug, Clone, Default)]
+pub struct References {
+    by_import: HashMap<String, Metadata>,
+}
+
+/// Imports name their library by path or by file name; both match on the
+/// file name, case-insensitively.
+fn import_key(file_name: &str) -> String {
+    let name = file_name.rsplit(['\\', '/']).next().unwrap_or(file_name);
+    name.to_lowercase()
+}
+
+/// Whether `import` is the library `file_name`.
+pub fn is_import_of(import: &Import, file_name: &str) -> bool {
+    import_key(&import.file_name) == import_key(file_name)
+}
+
+impl References {
+    /// Resolves the types of the imported library `import`, a file name such
+    /// as `stdole2.tlb`, with `metadata`.
+    pub fn insert(&mut self, import: &str, metadata: Metadata) {
+        self.by_import.insert(import_key(import), metadata);
+    }
+
+    /// Assembly and namespace of `name` imported through `import`, when a
+    /// reference covers it.
+    pub fn resolve(&self, import: &Import, name: &str) -> Option<(&str, &str)> {
+        let metadata = self.by_import.get(&import_key(&import.file_name))?;
+        let namespace = metadata.namespaces.get(name)?;
+        Some((&metadata.assembly, namespace))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostics::Diagnostics;
+    use crate::model::{Type, TypeLocation, fixture};
+    use crate::winmd;
+
+    #[test]
+    fn reads_the_types_of_a_written_winmd() {
+        let lib = fixture("legacy_sltg.tlb");
+        let partition = winmd::Partition {
+            lib: &lib,
+            namespace: "LegacyLib".to_string(),
+        };
+        let image = winmd::write_partitions(
+            vec![partition],
+            "LegacyLib",
+            None,
+            &References::default(),
+            &mut Diagnostics::new(),
+        )
+        .unwrap();
+        let metadata = Metadata::parse(&image).unwrap();
+        assert_eq!(metadata.assembly, "LegacyLib");
+        let mut names: Vec<_> = metadata.namespaces.keys().map(String::as_str).collect();
+        names.sort();
+        assert_eq!(
+            names,
+            [
+                "Apis",
+                "Handle",
+                "ICounter",
+                "Mode",
+                "ModePtr",
+                "Rect",
+                "_CounterEvents"
+            ]
+        );
+        assert!(metadata.namespaces.values().all(|ns| ns == "LegacyLib"));
+    }
+
+    #[test]
+    fn matches_imports_by_file_name() {
+        let mut references = References::default();
+        let mut metadata = Metadata {
+            assembly: "Stdole".to_string(),
+            ..Default::default()
+        };
+        metadata
+            .namespaces
+            .insert("Font".to_string(), "Contoso.Stdole".to_string());
+        references.insert("stdole2.tlb", metadata);
+        let import = Import {
+            guid: Default::default(),
+            version: (2, 0),
+            lcid: 0,
+            file_name: "C:\\Windows\\System32\\STDOLE2.TLB".to_string(),
+        };
+        assert_eq!(
+            references.resolve(&import, "Font"),
+            Some(("Stdole", "Contoso.Stdole"))
+        );
+        assert_eq!(references.resolve(&import, "Picture"), None);
+    }
+
+    #[test]
+    fn resolves_the_types_of_an_imported_library() {
+        let simple = fixture("simple.tlb");
+        let partition = winmd::Partition {
+            lib: &simple,
+            namespace: "Contoso.Simple".to_string(),
+        };
+        let image = winmd::write_partitions(
+            vec![partition],
+            "Simple",
+            None,
+            &References::default(),
+            &mut Diagnostics::new(),
+        )
+        .unwrap();
+        let mut references = References::default();
+        references.insert("simple.tlb", Metadata::parse(&image).unwrap());
+
+        let lib = fixture("consumer.tlb");
+        let canvas = lib.find("ICanvas").unwrap();
+        let Type::Named(color) = &canvas.funcs[2].params[0].ty else {
+            panic!("expected a named type");
+        };
+        let TypeLocation::Imported(import) = color.location else {
+            panic!("expected an imported type, got {:?}", color.location);
+        };
+        assert_eq!(
+            references.resolve(&lib.imports[import], &color.name),
+            Some(("Simple", "Contoso.Simple"))
+        );
+    }
+
+    #[test]
+    fn rejects_images_without_metadata() {
+        let error = Metadata::parse(b"MZ").unwrap_err();
+        assert!(matches!(error, Error::InvalidWinmd(_)), "{:?}", error);
+    }
+}
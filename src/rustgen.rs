@@ -0,0 +1,816 @@
+//! Rust bindings generated straight from a type library, without the .winmd
+//! round trip through windows-bindgen.
+//!
+//! The output is a single module in the style of `windows-core`:
+//! interfaces become `#[windows_core::interface]` traits (which generate the
+//! vtable and the calling wrappers), enums become `i32` newtypes with
+//! associated constants (and bit operators for bit flags), records and unions are `#[repr(C)]`, coclass
+//! CLSIDs and module constants are plain `const`s, and module functions are
+//! `raw-dylib` imports from the module's DLL.
+//!
+//! Methods keep their vtable signature, with an HRESULT return surfaced as
+//! `windows_core::Result<()>`. Methods with an `[out, retval]` parameter also
+//! get a snake_case wrapper that returns the value instead, following the
+//! same lowering as the IDL view of the TUI.
+//!
+//! Fundamental types come from `windows_core` (`HRESULT`, `BSTR`, `GUID`,
+//! `IUnknown`, ...) or from the `windows` crate at the path the Win32
+//! metadata gives them (`IDispatch`, `VARIANT`, `SAFEARRAY`, ...), so the
+//! consuming crate needs the matching `windows` features enabled.
+
+use std::collections::HashSet;
+use std::io::Write;
+
+use crate::model::TypeRef;
+use crate::model::*;
+use crate::typelib::*;
+use crate::winmd::win32_namespace;
+
+/// Where a type is used; decides how owned COM types are wrapped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Position {
+    /// A by-value `[in]` parameter.
+    Param,
+    /// A record or union field.
+    Field,
+    /// The target of a pointer.
+    Pointee,
+}
+
+/// Writes the bindings for `lib` as one Rust module.
+pub fn write_rust<W>(lib: &Library, mut out: W) -> std::io::Result<()>
+where
+    W: Write,
+{
+    writeln!(out, "// Rust bindings for the {} type library.", lib.name)?;
+    writeln!(
+        out,
+        "#![allow(non_snake_case, non_camel_case_types, non_upper_case_globals, dead_code, clippy::all)]"
+    )?;
+
+    for ty in &lib.types {
+        writeln!(out)?;
+        match ty.kind {
+            TypeKind::Enum => write_enum(ty, &mut out)?,
+            TypeKind::Record | TypeKind::Union => write_struct(lib, ty, &mut out)?,
+            TypeKind::Alias => write_alias(lib, ty, &mut out)?,
+            TypeKind::Interface | TypeKind::Dispatch => write_interface(lib, ty, &mut out)?,
+            TypeKind::CoClass => write_coclass(ty, &mut out)?,
+            TypeKind::Module => write_module(lib, ty, &mut out)?,
+        }
+    }
+    Ok(())
+}
+
+fn write_doc<W: Write>(doc: &Doc, indent: &str, out: &mut W) -> std::io::Result<()> {
+    for line in doc.help_string.lines() {
+        writeln!(out, "{}/// {}", indent, line)?;
+    }
+    Ok(())
+}
+
+fn write_enum<W: Write>(ty: &TypeDef, out: &mut W) -> std::io::Result<()> {
+    let name = ident(&ty.name);
+    write_doc(&ty.doc, "", out)?;
+    writeln!(out, "#[repr(transparent)]")?;
+    writeln!(
+        out,
+        "#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]"
+    )?;
+    writeln!(out, "pub struct {}(pub i32);", name)?;
+    writeln!(out, "impl {} {{", name)?;
+    for var in &ty.vars {
+        let value = var.typed_value().map_or(0, |v| v.as_i32());
+        write_doc(&var.doc, "    ", out)?;
+        writeln!(
+            out,
+            "    pub const {}: Self = Self({});",
+            ident(&var.name),
+            value
+        )?;
+    }
+    writeln!(out, "}}")?;
+    if ty.is_bit_flags() {
+        for (op, method, operator) in [("BitOr", "bitor", "|"), ("BitAnd", "bitand", "&")] {
+            writeln!(out, "impl core::ops::{} for {} {{", op, name)?;
+            writeln!(out, "    type Output = Self;")?;
+            writeln!(
+                out,
+                "    fn {}(self, other: Self) -> Self {{ Self(self.0 {} other.0) }}",
+                method, operator
+            )?;
+            writeln!(out, "}}")?;
+        }
+    }
+    Ok(())
+}
+
+fn write_struct<W: Write>(lib: &Library, ty: &TypeDef, out: &mut W) -> std::io::Result<()> {
+    let pod = ty.vars.iter().all(|v| is_pod(lib, &v.ty));
+    write_doc(&ty.doc, "", out)?;
+    match lib.layout(ty) {
+        Layout::Packed(pack) => writeln!(out, "#[repr(C, packed({}))]", pack)?,
+        _ => writeln!(out, "#[repr(C)]")?,
+    }
+    if pod {
+        writeln!(out, "#[derive(Clone, Copy)]")?;
+    }
+    let keyword = if ty.kind == TypeKind::Union {
+        "union"
+    } else {
+        "struct"
+    };
+    writeln!(out, "pub {} {} {{", keyword, ident(&ty.name))?;
+    for var in &ty.vars {
+        let mut field = rust_type(lib, &var.ty, Position::Field);
+        // Union fields must be Copy or explicitly not dropped.
+        if ty.kind == TypeKind::Union && !is_pod(lib, &var.ty) && !is_dropless(&field) {
+            field = format!("core::mem::ManuallyDrop<{}>", field);
+        }
+        write_doc(&var.doc, "    ", out)?;
+        writeln!(out, "    pub {}: {},", ident(&var.name), field)?;
+    }
+    writeln!(out, "}}")?;
+    Ok(())
+}
+
+fn write_alias<W: Write>(lib: &Library, ty: &TypeDef, out: &mut W) -> std::io::Result<()> {
+    let aliased = ty.alias_of.clone().unwrap_or(Type::Base(VT_VOID));
+    write_doc(&ty.doc, "", out)?;
+    writeln!(
+        out,
+        "pub type {} = {};",
+        ident(&ty.name),
+        rust_type(lib, &aliased, Position::Field)
+    )?;
+    Ok(())
+}
+
+fn write_interface<W: Write>(lib: &Library, ty: &TypeDef, out: &mut W) -> std::io::Result<()> {
+    // IUnknown and IDispatch themselves come from windows-core/windows.
+    if ty.guid == IID_IUNKNOWN || ty.guid == IID_IDISPATCH {
+        return Ok(());
+    }
+    let name = ident(&ty.name);
+    let is_dispinterface = ty.kind == TypeKind::Dispatch && !ty.has_flag(TYPEFLAG_FDUAL);
+    let base = if is_dispinterface {
+        dispatch_path()
+    } else {
+        match ty.base() {
+            Some(base) => named_path(lib, base),
+            None => "windows_core::IUnknown".to_string(),
+        }
+    };
+
+    // Members of a pure dispinterface are only reachable through
+    // IDispatch::Invoke, so the trait has no vtable slots of its own;
+    // neither have dispatch functions, such as the inherited IDispatch
+    // members, elsewhere.
+    let funcs: Vec<&Function> = if is_dispinterface {
+        Vec::new()
+    } else {
+        ty.funcs
+            .iter()
+            .filter(|f| f.func_kind != FuncKind::Dispatch)
+            .collect()
+    };
+
+    write_doc(&ty.doc, "", out)?;
+    writeln!(out, "#[windows_core::interface(\"{}\")]", ty.guid)?;
+    writeln!(out, "pub unsafe trait {}: {} {{", name, base)?;
+    for func in &funcs {
+        write_doc(&func.doc, "    ", out)?;
+        let params: Vec<String> = func
+            .params
+            .iter()
+            .enumerate()
+            .map(|(i, p)| format!("{}: {}", param_name(p, i), param_type(lib, &p.ty)))
+            .collect();
+        writeln!(
+            out,
+            "    pub fn {}(&self{}){};",
+            ident(&func.vtable_name()),
+            params
+                .iter()
+                .map(|p| format!(", {}", p))
+                .collect::<String>(),
+            return_suffix(lib, func)
+        )?;
+    }
+    writeln!(out, "}}")?;
+
+    let vtable_names: HashSet<String> = funcs.iter().map(|f| f.vtable_name()).collect();
+    let wrappers: Vec<&&Function> = funcs
+        .iter()
+        .filter(|f| {
+            f.ret == Type::Base(VT_HRESULT)
+                && f.retval_index().is_some()
+                && !vtable_names.contains(&snake_case(&f.name))
+        })
+        .collect();
+    if wrappers.is_empty() {
+        return Ok(());
+    }
+    writeln!(out, "impl {} {{", name)?;
+    for func in wrappers {
+        write_lowered(lib, func, out)?;
+    }
+    writeln!(out, "}}")?;
+    Ok(())
+}
+
+/// A wrapper that returns the `[out, retval]` value instead of writing it
+/// through a pointer.
+fn write_lowered<W: Write>(lib: &Library, func: &Function, out: &mut W) -> std::io::Result<()> {
+    let Some(pos) = func.retval_index() else {
+        return Ok(());
+    };
+    let value_type = match &func.params[pos].ty {
+        Type::Ptr(inner) => rust_type(lib, inner, Position::Pointee),
+        other => rust_type(lib, other, Position::Pointee),
+    };
+    let mut params = String::new();
+    let mut args = Vec::new();
+    for (i, param) in func.params.iter().enumerate() {
+        if i == pos {
+            args.push("&mut value".to_string());
+            continue;
+        }
+        let name = param_name(param, i);
+        let mut ty = param_type(lib, &param.ty);
+        // The generated vtable wrappers accept anything convertible to a
+        // `Ref`, so the lowered one does too.
+        if let Some(inner) = ty
+            .strip_prefix("windows_core::Ref<")
+            .and_then(|t| t.strip_suffix('>'))
+        {
+            ty = format!("impl windows_core::Param<{}>", inner);
+        }
+        params.push_str(&format!(", {}: {}", name, ty));
+        args.push(name);
+    }
+    writeln!(
+        out,
+        "    pub unsafe fn {}(&self{}) -> windows_core::Result<{}> {{",
+        ident(&snake_case(&func.name)),
+        params,
+        value_type
+    )?;
+    writeln!(out, "        unsafe {{")?;
+    writeln!(
+        out,
+        "            let mut value: {} = core::mem::zeroed();",
+        value_type
+    )?;
+    writeln!(
+        out,
+        "            self.{}({}).map(|()| value)",
+        ident(&func.vtable_name()),
+        args.join(", ")
+    )?;
+    writeln!(out, "        }}")?;
+    writeln!(out, "    }}")?;
+    Ok(())
+}
+
+fn return_suffix(lib: &Library, func: &Function) -> String {
+    match &func.ret {
+        Type::Base(VT_HRESULT) => " -> windows_core::Result<()>".to_string(),
+        Type::Base(VT_VOID) => String::new(),
+        ty => format!(" -> {}", rust_type(lib, ty, Position::Pointee)),
+    }
+}
+
+fn write_coclass<W: Write>(ty: &TypeDef, out: &mut W) -> std::io::Result<()> {
+    write_doc(&ty.doc, "", out)?;
+    writeln!(
+        out,
+        "pub const CLSID_{}: windows_core::GUID = windows_core::GUID::from_u128({});",
+        ty.name,
+        guid_literal(&ty.guid)
+    )?;
+    Ok(())
+}
+
+fn write_module<W: Write>(lib: &Library, ty: &TypeDef, out: &mut W) -> std::io::Result<()> {
+    writeln!(out, "// module {}", ty.name)?;
+    for var in &ty.vars {
+        let Some((ty, value)) = var.typed_value().as_ref().and_then(constant) else {
+            writeln!(out, "// {}: constant type not supported", var.name)?;
+            continue;
+        };
+        write_doc(&var.doc, "", out)?;
+        writeln!(out, "pub const {}: {} = {};", ident(&var.name), ty, value)?;
+    }
+    if ty.funcs.is_empty() {
+        return Ok(());
+    }
+    let Some(dll_name) = &ty.dll_name else {
+        writeln!(out, "// {}: functions without a dllname", ty.name)?;
+        return Ok(());
+    };
+
+    // One block per calling convention, in order of first use.
+    let mut abis: Vec<&str> = Vec::new();
+    for func in &ty.funcs {
+        if !abis.contains(&abi(func.call_conv)) {
+            abis.push(abi(func.call_conv));
+        }
+    }
+    for abi_name in abis {
+        writeln!(out, "#[link(name = {:?}, kind = \"raw-dylib\")]", dll_name)?;
+        writeln!(out, "unsafe extern {:?} {{", abi_name)?;
+        for func in ty.funcs.iter().filter(|f| abi(f.call_conv) == abi_name) {
+            write_doc(&func.doc, "    ", out)?;
+            match &func.entry {
+                Some(DllEntry::Name(name)) if *name != func.name => {
+                    writeln!(out, "    #[link_name = {:?}]", name)?;
+                }
+                Some(DllEntry::Ordinal(ordinal)) => {
+                    writeln!(out, "    #[link_ordinal({})]", ordinal)?;
+                }
+                _ => {}
+            }
+            let params: Vec<String> = func
+                .params
+                .iter()
+                .enumerate()
+                .map(|(i, p)| format!("{}: {}", param_name(p, i), param_type(lib, &p.ty)))
+                .collect();
+            let ret = match &func.ret {
+                Type::Base(VT_VOID) => String::new(),
+                ret => format!(" -> {}", rust_type(lib, ret, Position::Pointee)),
+            };
+            writeln!(
+                out,
+                "    pub fn {}({}){};",
+                ident(&func.name),
+                params.join(", "),
+                ret
+            )?;
+        }
+        writeln!(out, "}}")?;
+    }
+    Ok(())
+}
+
+/// The Rust ABI string of a module function's calling convention.
+fn abi(call_conv: CallConv) -> &'static str {
+    match call_conv {
+        CallConv::CDecl | CallConv::MpwCDecl => "C",
+        CallConv::FastCall => "fastcall",
+        _ => "system",
+    }
+}
+
+/// The Rust type and literal of a module constant.
+fn constant(value: &Variant) -> Option<(&'static str, String)> {
+    Some(match value {
+        Variant::I1(v) => ("i8", v.to_string()),
+        Variant::I2(v) => ("i16", v.to_string()),
+        Variant::I4(v) | Variant::Int(v) | Variant::Error(v) => ("i32", v.to_string()),
+        Variant::I8(v) => ("i64", v.to_string()),
+        Variant::UI1(v) => ("u8", v.to_string()),
+        Variant::UI2(v) => ("u16", v.to_string()),
+        Variant::UI4(v) | Variant::UInt(v) => ("u32", v.to_string()),
+        Variant::UI8(v) => ("u64", v.to_string()),
+        Variant::R4(v) if v.is_finite() => ("f32", format!("{:?}", v)),
+        Variant::R8(v) | Variant::Date(v) if v.is_finite() => ("f64", format!("{:?}", v)),
+        Variant::Bool(v) => ("bool", v.to_string()),
+        Variant::Bstr(s) => ("&str", format!("{:?}", s)),
+        _ => return None,
+    })
+}
+
+fn guid_literal(guid: &Guid) -> String {
+    let d = &guid.data4;
+    format!(
+        "0x{:08x}_{:04x}_{:04x}_{:02x}{:02x}_{:02x}{:02x}{:02x}{:02x}{:02x}{:02x}",
+        guid.data1, guid.data2, guid.data3, d[0], d[1], d[2], d[3], d[4], d[5], d[6], d[7]
+    )
+}
+
+fn param_name(param: &Param, index: usize) -> String {
+    match &param.name {
+        Some(name) => ident(name),
+        None => format!("arg{}", index),
+    }
+}
+
+fn param_type(lib: &Library, ty: &Type) -> String {
+    match ty {
+        // Array parameters decay to pointers, as in C.
+        Type::CArray { elem, .. } => format!("*mut {}", rust_type(lib, elem, Position::Pointee)),
+        ty => rust_type(lib, ty, Position::Param),
+    }
+}
+
+fn rust_type(lib: &Library, ty: &Type, position: Position) -> String {
+    match ty {
+        Type::Base(vt) => base_type(*vt, position),
+        Type::Ptr(inner) => {
+            if let Some(interface) = interface_path(lib, inner) {
+                interface_type(interface, position)
+            } else {
+                format!("*mut {}", rust_type(lib, inner, Position::Pointee))
+            }
+        }
+        Type::SafeArray(_) => "*mut windows::Win32::System::Com::SAFEARRAY".to_string(),
+        Type::CArray { elem, bounds } => {
+            let mut s = rust_type(lib, elem, position);
+            for (count, _) in bounds.iter().rev() {
+                s = format!("[{}; {}]", s, count);
+            }
+            s
+        }
+        Type::Named(r) => match interface_path(lib, ty) {
+            Some(interface) => interface_type(interface, position),
+            None if !r.is_resolved() => "*mut core::ffi::c_void".to_string(),
+            None => named_path(lib, r),
+        },
+    }
+}
+
+/// How an interface pointer is passed: borrowed in parameters, owned (and
+/// nullable) behind out pointers and in fields.
+fn interface_type(path: String, position: Position) -> String {
+    match position {
+        Position::Param => format!("windows_core::Ref<{}>", path),
+        Position::Field => format!("core::mem::ManuallyDrop<Option<{}>>", path),
+        Position::Pointee => format!("Option<{}>", path),
+    }
+}
+
+/// The path of `ty` if it names an interface. `VT_UNKNOWN` and
+/// `VT_DISPATCH` are not included: they already stand for the pointer.
+fn interface_path(lib: &Library, ty: &Type) -> Option<String> {
+    match ty {
+        Type::Named(r)
+            if matches!(
+                r.kind,
+                Some(TypeKind::Interface | TypeKind::Dispatch | TypeKind::CoClass)
+            ) =>
+        {
+            Some(named_path(lib, r))
+        }
+        _ => None,
+    }
+}
+
+fn dispatch_path() -> String {
+    "windows::Win32::System::Com::IDispatch".to_string()
+}
+
+fn base_type(vt: u16, position: Position) -> String {
+    let owned = |path: &str| match position {
+        Position::Pointee => path.to_string(),
+        _ => format!("core::mem::ManuallyDrop<{}>", path),
+    };
+    match vt {
+        VT_I1 => "i8".to_string(),
+        VT_UI1 => "u8".to_string(),
+        VT_I2 => "i16".to_string(),
+        VT_UI2 => "u16".to_string(),
+        VT_I4 | VT_INT | VT_ERROR => "i32".to_string(),
+        VT_UI4 | VT_UINT => "u32".to_string(),
+        VT_I8 => "i64".to_string(),
+        VT_UI8 => "u64".to_string(),
+        VT_R4 => "f32".to_string(),
+        VT_R8 | VT_DATE => "f64".to_string(),
+        VT_INT_PTR => "isize".to_string(),
+        VT_UINT_PTR => "usize".to_string(),
+        VT_VOID => "core::ffi::c_void".to_string(),
+        VT_HRESULT => "windows_core::HRESULT".to_string(),
+        VT_CLSID => "windows_core::GUID".to_string(),
+        VT_LPSTR => "windows_core::PSTR".to_string(),
+        VT_LPWSTR => "windows_core::PWSTR".to_string(),
+        VT_BSTR if position == Position::Param => {
+            "windows_core::Ref<windows_core::BSTR>".to_string()
+        }
+        VT_BSTR => owned("windows_core::BSTR"),
+        VT_VARIANT => owned("windows::Win32::System::Variant::VARIANT"),
+        VT_UNKNOWN => interface_type("windows_core::IUnknown".to_string(), position),
+        VT_DISPATCH => interface_type(dispatch_path(), position),
+        VT_BOOL => "windows::Win32::Foundation::VARIANT_BOOL".to_string(),
+        VT_CY => "windows::Win32::System::Com::CY".to_string(),
+        VT_DECIMAL => "windows::Win32::Foundation::DECIMAL".to_string(),
+        VT_FILETIME => "windows::Win32::Foundation::FILETIME".to_string(),
+        VT_BLOB | VT_BLOB_OBJECT => "windows::Win32::System::Com::BLOB".to_string(),
+        VT_CF => "*mut windows::Win32::System::Com::CLIPDATA".to_string(),
+        VT_STREAM | VT_STREAMED_OBJECT => {
+            interface_type("windows::Win32::System::Com::IStream".to_string(), position)
+        }
+        VT_STORAGE | VT_STORED_OBJECT => interface_type(
+            "windows::Win32::System::Com::StructuredStorage::IStorage".to_string(),
+            position,
+        ),
+        // No faithful equivalent: keep the ABI of an opaque pointer.
+        _ => "*mut core::ffi::c_void".to_string(),
+    }
+}
+
+/// The path of a named type: local types by name, well-known imports at
+/// their `windows`/`windows_core` location.
+fn named_path(lib: &Library, r: &TypeRef) -> String {
+    if r.guid == Some(IID_IUNKNOWN) {
+        return "windows_core::IUnknown".to_string();
+    }
+    if r.guid == Some(IID_IDISPATCH) {
+        return dispatch_path();
+    }
+    if let TypeLocation::Imported(_) = r.location
+        && let Some(namespace) = win32_namespace(&r.name)
+    {
+        return match r.name.as_str() {
+            "HRESULT" | "BSTR" | "PSTR" | "PWSTR" | "IUnknown" => {
+                format!("windows_core::{}", r.name)
+            }
+            _ => format!(
+                "windows::{}::{}",
+                namespace.trim_start_matches("Windows.").replace('.', "::"),
+                r.name
+            ),
+        };
+    }
+    if let Some(def) = lib.local(r)
+        && def.guid == IID_IUNKNOWN
+    {
+        return "windows_core::IUnknown".to_string();
+    }
+    ident(&r.name)
+}
+
+/// Whether values of `ty` can be copied bitwise, i.e. own no COM resource.
+fn is_pod(lib: &Library, ty: &Type) -> bool {
+    match ty {
+        Type::Base(vt) => !matches!(
+            *vt,
+            VT_BSTR
+                | VT_VARIANT
+                | VT_UNKNOWN
+                | VT_DISPATCH
+                | VT_STREAM
+                | VT_STREAMED_OBJECT
+                | VT_STORAGE
+                | VT_STORED_OBJECT
+        ),
+        Type::Ptr(inner) => interface_path(lib, inner).is_none(),
+        Type::SafeArray(_) => true,
+        Type::CArray { elem, .. } => is_pod(lib, elem),
+        Type::Named(r) => match lib.local(r) {
+            Some(def) => match def.kind {
+                TypeKind::Enum => true,
+                TypeKind::Record | TypeKind::Union => def.vars.iter().all(|v| is_pod(lib, &v.ty)),
+                TypeKind::Alias => def.alias_of.as_ref().is_none_or(|t| is_pod(lib, t)),
+                _ => false,
+            },
+            None => !r.is_resolved(),
+        },
+    }
+}
+
+fn is_dropless(rust_type: &str) -> bool {
+    rust_type.starts_with("core::mem::ManuallyDrop<")
+}
+
+const KEYWORDS: &[&str] = &[
+    "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern",
+    "false", "fn", "for", "gen", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut",
+    "pub", "ref", "return", "static", "struct", "super", "trait", "true", "type", "unsafe", "use",
+    "where", "while", "abstract", "become", "box", "do", "final", "macro", "override", "priv",
+    "try", "typeof", "unsized", "virtual", "yield",
+];
+
+/// `name` as a Rust identifier, raw if it is a keyword.
+fn ident(name: &str) -> String {
+    match name {
+        "self" | "Self" | "crate" | "super" => format!("{}_", name),
+        _ if KEYWORDS.contains(&name) => format!("r#{}", name),
+        _ => name.to_string(),
+    }
+}
+
+/// `GetItemCount` -> `get_item_count`, `HWND` -> `hwnd`.
+fn snake_case(name: &str) -> String {
+    let chars: Vec<char> = name.chars().collect();
+    let mut out = String::new();
+    for (i, &c) in chars.iter().enumerate() {
+        if c.is_uppercase() && i > 0 {
+            let prev = chars[i - 1];
+            let next_lower = chars.get(i + 1).is_some_and(|n| n.is_lowercase());
+            if prev != '_' && (prev.is_lowercase() || prev.is_ascii_digit() || next_lower) {
+                out.push('_');
+            }
+        }
+        out.extend(c.to_lowercase());
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{ImplType, fixture};
+
+    fn generate(lib: &Library) -> String {
+        let mut out = Vec::new();
+        write_rust(lib, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn converts_names_to_snake_case() {
+        assert_eq!(snake_case("Name"), "name");
+        assert_eq!(snake_case("GetItemCount"), "get_item_count");
+        assert_eq!(snake_case("HWND"), "hwnd");
+        assert_eq!(snake_case("ToHTMLString"), "to_html_string");
+        assert_eq!(ident("type"), "r#type");
+        assert_eq!(ident("self"), "self_");
+    }
+
+    #[test]
+    fn emits_enums_records_and_constants() {
+        let rust = generate(&fixture("simple.tlb"));
+        assert!(rust.contains("pub struct Colors(pub i32);"), "{}", rust);
+        assert!(rust.contains("    pub const Unset: Self = Self(-1);"));
+        assert!(rust.contains(
+            "#[repr(C)]\n#[derive(Clone, Copy)]\npub struct Point {\n    pub x: i32,\n    pub y: i32,\n}"
+        ));
+        assert!(rust.contains("pub type Handle = i32;"));
+        assert!(rust.contains("pub const Answer: i32 = 42;"));
+        assert!(rust.contains(
+            "pub const CLSID_Shape: windows_core::GUID = \
+             windows_core::GUID::from_u128(0x5a1e0a00_0005_4b1d_9c2e_0123456789ab);"
+        ));
+    }
+
+    #[test]
+    fn gives_bit_flags_operators() {
+        let mut lib = fixture("simple.tlb");
+        assert!(!generate(&lib).contains("impl core::ops::BitOr for Colors"));
+        let colors = lib.types.iter_mut().find(|t| t.name == "Colors").unwrap();
+        colors.bit_flags = Some(true);
+        let rust = generate(&lib);
+        assert!(rust.contains(
+            "impl core::ops::BitOr for Colors {\n    type Output = Self;\n    fn bitor(self, other: Self) -> Self { Self(self.0 | other.0) }\n}\n"
+        ));
+        assert!(rust.contains("impl core::ops::BitAnd for Colors {"));
+    }
+
+    #[test]
+    fn nests_fixed_arrays_and_maps_structured_vartypes() {
+        let rust = generate(&fixture("simple.tlb"));
+        assert!(
+            rust.contains(
+                "pub struct Matrix {\n    pub cells: [[i32; 8]; 4],\n    \
+                 pub stamp: windows::Win32::Foundation::FILETIME,\n    \
+                 pub id: windows_core::GUID,\n    \
+                 pub extra: windows::Win32::System::Com::BLOB,\n    \
+                 pub count: *mut i32,\n}"
+            ),
+            "{}",
+            rust
+        );
+    }
+
+    #[test]
+    fn imports_module_functions_from_the_dll() {
+        let rust = generate(&fixture("simple.tlb"));
+        assert!(
+            rust.contains(
+                "#[link(name = \"simple.dll\", kind = \"raw-dylib\")]\n\
+             unsafe extern \"system\" {\n\
+             \x20   /// Sounds a tone\n\
+             \x20   #[link_name = \"SimpleBeep\"]\n\
+             \x20   pub fn Beep(freq: i32, duration: i32) -> i32;\n\
+             \x20   #[link_ordinal(7)]\n\
+             \x20   pub fn Version(v: *mut i32) -> windows_core::HRESULT;\n\
+             }\n"
+            ),
+            "{}",
+            rust
+        );
+        assert!(rust.contains("unsafe extern \"C\" {\n    #[link_name = \"SimpleLog\"]\n"));
+    }
+
+    #[test]
+    fn emits_interface_traits_with_lowered_wrappers() {
+        let rust = generate(&fixture("simple.tlb"));
+        assert!(rust.contains(
+            "#[windows_core::interface(\"5A1E0A00-0003-4B1D-9C2E-0123456789AB\")]\n\
+             pub unsafe trait IShape: windows::Win32::System::Com::IDispatch {"
+        ));
+        assert!(rust.contains(
+            "    pub fn get_Name(&self, val: *mut windows_core::BSTR) -> windows_core::Result<()>;"
+        ));
+        assert!(rust.contains(
+            "    pub fn put_Name(&self, val: windows_core::Ref<windows_core::BSTR>) -> windows_core::Result<()>;"
+        ));
+        assert!(
+            rust.contains("    pub fn Move(&self, dx: i32, dy: i32) -> windows_core::Result<()>;")
+        );
+        assert!(rust.contains(
+            "    pub unsafe fn name(&self) -> windows_core::Result<windows_core::BSTR> {"
+        ));
+        assert!(rust.contains("    pub unsafe fn center(&self) -> windows_core::Result<Point> {"));
+        assert!(rust.contains("            self.get_Color(&mut value).map(|()| value)"));
+        // Pure dispinterfaces have no vtable slots of their own.
+        assert!(rust.contains(
+            "pub unsafe trait _ShapeEvents: windows::Win32::System::Com::IDispatch {\n}"
+        ));
+        // Methods MIDL numbered itself are slots like any other.
+        assert!(rust.contains(
+            "pub unsafe trait IRenderer: windows_core::IUnknown {\n    \
+             pub fn Fill(&self, color: i32) -> windows_core::Result<()>;\n    \
+             pub fn Bounds(&self, pt: *mut Point) -> windows_core::Result<()>;\n}"
+        ));
+    }
+
+    #[test]
+    fn wraps_owned_fields_and_decays_array_params() {
+        let rust = generate(&fixture("legacy_sltg.tlb"));
+        assert!(rust.contains(
+            "#[repr(C)]\npub struct Rect {\n    pub left: i32,\n    pub top: i32,\n    \
+             pub tag: core::mem::ManuallyDrop<windows_core::BSTR>,\n}"
+        ));
+        assert!(rust.contains("pub type ModePtr = *mut Mode;"));
+        assert!(
+            rust.contains("    pub fn Fill(&self, cells: *mut i32) -> windows_core::Result<()>;")
+        );
+        assert!(rust.contains(
+            "    pub unsafe fn names(&self) -> windows_core::Result<*mut windows::Win32::System::Com::SAFEARRAY> {"
+        ));
+    }
+
+    #[test]
+    fn passes_interfaces_by_reference() {
+        let mut lib = Library::default();
+        let mut base = TypeDef::new("IBase", TypeKind::Interface, Guid::from_u128(1));
+        base.impl_types.push(ImplType {
+            target: TypeRef {
+                name: "IUnknown".to_string(),
+                guid: Some(IID_IUNKNOWN),
+                kind: Some(TypeKind::Interface),
+                location: TypeLocation::Imported(0),
+                index: None,
+            },
+            flags: 0,
+            custom_data: Vec::new(),
+        });
+        let mut derived = TypeDef::new("IDerived", TypeKind::Interface, Guid::from_u128(2));
+        derived.impl_types.push(ImplType {
+            target: TypeRef::local("IBase", TypeKind::Interface, 0),
+            flags: 0,
+            custom_data: Vec::new(),
+        });
+        let base_ptr = Type::Ptr(Box::new(Type::Named(TypeRef::local(
+            "IBase",
+            TypeKind::Interface,
+            0,
+        ))));
+        derived.funcs.push(Function {
+            name: "Swap".to_string(),
+            memid: 1,
+            invoke_kind: InvokeKind::Func,
+            func_kind: FuncKind::PureVirtual,
+            call_conv: CallConv::StdCall,
+            flags: 0,
+            vtable_offset: 24,
+            params: vec![
+                Param {
+                    name: Some("type".to_string()),
+                    ty: base_ptr.clone(),
+                    flags: PARAMFLAG_FIN,
+                    default_value: None,
+                    custom_data: Vec::new(),
+                },
+                Param {
+                    name: Some("result".to_string()),
+                    ty: Type::Ptr(Box::new(base_ptr)),
+                    flags: PARAMFLAG_FOUT | PARAMFLAG_FRETVAL,
+                    default_value: None,
+                    custom_data: Vec::new(),
+                },
+            ],
+            optional_params: 0,
+            ret: Type::Base(VT_HRESULT),
+            entry: None,
+            doc: Doc::default(),
+            custom_data: Vec::new(),
+        });
+        lib.types.push(base);
+        lib.types.push(derived);
+
+        let rust = generate(&lib);
+        assert!(rust.contains("pub unsafe trait IBase: windows_core::IUnknown {"));
+        assert!(rust.contains("pub unsafe trait IDerived: IBase {"));
+        assert!(rust.contains(
+            "    pub fn Swap(&self, r#type: windows_core::Ref<IBase>, result: *mut Option<IBase>) \
+             -> windows_core::Result<()>;"
+        ));
+        assert!(rust.contains(
+            "    pub unsafe fn swap(&self, r#type: impl windows_core::Param<IBase>) \
+             -> windows_core::Result<Option<IBase>> {"
+        ));
+    }
+}
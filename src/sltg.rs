@@ -0,0 +1,981 @@
+//! Reader for the SLTG type library format, used by VB5/VB6 and the older
+//! 16/32-bit MkTypLib tool chain.
+//!
+//! Layout reference: a [`SLTG_MAGIC`] header is followed by a directory of
+//! block entries, the "CompObj"/"dir" index strings and the blocks themselves.
+//! The blocks form a chain: one per typeinfo in typeinfo order, then the
+//! library block. The library block holds the library attributes, one
+//! directory record per typeinfo (name, GUID, help context) and the offset of
+//! the name table that every other name offset points into.
+//!
+//! Each typeinfo block starts with a header giving the type kind and flags,
+//! followed by a member area (functions, variables, implemented types, type
+//! descriptions) and a tail with the member counts and their offsets.
+//! Referenced types go through a per-typeinfo ref table of `*\R<lib>*#<index>`
+//! strings.
+
+use std::collections::HashMap;
+
+use crate::error::Error;
+use crate::typelib::*;
+
+pub const SLTG_MAGIC: u32 = 0x4754_4c53; // "SLTG"
+
+const HEADER_SIZE: usize = 0x24;
+const BLOCK_ENTRY_SIZE: usize = 8;
+const MAGIC_SIZE: usize = 13;
+const INDEX_NAME_SIZE: usize = 11;
+const PAD9_SIZE: usize = 9;
+const NAME_TABLE_SKIP: usize = 0x218;
+
+const LIBBLK_MAGIC: u16 = 0x51cc;
+const TIHEADER_MAGIC: u16 = 0x0501;
+const MEMBER_HEADER_SIZE: usize = 9;
+const IMPL_MAGIC: u16 = 0x004a;
+const REF_MAGIC: u8 = 0xdf;
+const VAR_MAGIC: u8 = 0x0a;
+const VAR_WITH_FLAGS_MAGIC: u8 = 0x2a;
+const FUNCTION_FLAGS_PRESENT: u8 = 0x20;
+const FUNCTION_MAGIC: u8 = 0x4c;
+const DISPATCH_FUNCTION_MAGIC: u8 = 0xcb;
+const STATIC_FUNCTION_MAGIC: u8 = 0x8b;
+const NO_OFFSET: u16 = 0xffff;
+
+/// GUID of stdole2.tlb; its interfaces are referenced by index in SLTG files.
+const LIBID_STDOLE: Guid = Guid::from_u128(0x00020430_0000_0000_c000_000000000046);
+
+/// Returns true if `data` starts with the SLTG signature.
+pub fn is_sltg(data: &[u8]) -> bool {
+    data.len() >= 4 && u32::from_le_bytes([data[0], data[1], data[2], data[3]]) == SLTG_MAGIC
+}
+
+fn corrupt(what: &str) -> Error {
+    Error::InvalidTypeLib(format!("SLTG: {}", what))
+}
+
+/// Per-typeinfo directory record kept in the library block.
+struct DirEntry {
+    index_name: String,
+    name_offset: u16,
+    doc_string: String,
+    help_context: u32,
+    guid: Guid,
+}
+
+/// Offsets from a typeinfo's tail record.
+struct Tail {
+    c_funcs: u16,
+    c_vars: u16,
+    funcs_off: u16,
+    vars_off: u16,
+    impls_off: u16,
+    tdescalias_vt: u16,
+    simple_alias: u16,
+    cb_size_instance: u16,
+    cb_alignment: u16,
+    cb_size_vft: u16,
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    names: usize,
+    next_href: HRefType,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&self, offset: usize, len: usize) -> Result<&'a [u8], Error> {
+        offset
+            .checked_add(len)
+            .and_then(|end| self.data.get(offset..end))
+            .ok_or_else(|| corrupt(&format!("read of {} bytes at {:#x} out of range", len, offset)))
+    }
+
+    fn u8_at(&self, offset: usize) -> Result<u8, Error> {
+        Ok(self.bytes(offset, 1)?[0])
+    }
+
+    fn u16_at(&self, offset: usize) -> Result<u16, Error> {
+        let b = self.bytes(offset, 2)?;
+        Ok(u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    fn u32_at(&self, offset: usize) -> Result<u32, Error> {
+        let b = self.bytes(offset, 4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn guid_at(&self, offset: usize) -> Result<Guid, Error> {
+        let mut raw = [0u8; 16];
+        raw.copy_from_slice(self.bytes(offset, 16)?);
+        Ok(Guid::from_le_bytes(raw))
+    }
+
+    /// NUL-terminated string.
+    fn cstr_at(&self, offset: usize) -> Result<String, Error> {
+        let rest = self
+            .data
+            .get(offset..)
+            .ok_or_else(|| corrupt(&format!("string at {:#x} out of range", offset)))?;
+        let len = rest
+            .iter()
+            .position(|&b| b == 0)
+            .ok_or_else(|| corrupt("unterminated string"))?;
+        Ok(decode_ansi(&rest[..len]))
+    }
+
+    /// Length-prefixed string; a length of 0xffff means "absent". Returns the
+    /// string and the number of bytes consumed.
+    fn counted_str_at(&self, offset: usize) -> Result<(Option<String>, usize), Error> {
+        let len = self.u16_at(offset)?;
+        if len == NO_OFFSET {
+            return Ok((None, 2));
+        }
+        let s = decode_ansi(self.bytes(offset + 2, len as usize)?);
+        Ok((Some(s), 2 + len as usize))
+    }
+
+    fn name(&self, offset: u16) -> Result<String, Error> {
+        self.cstr_at(self.names + offset as usize)
+    }
+}
+
+/// Parses an SLTG type library image.
+pub fn parse(data: &[u8]) -> Result<TypeLib, Error> {
+    if !is_sltg(data) {
+        return Err(corrupt("missing SLTG signature"));
+    }
+    let mut r = Reader {
+        data,
+        names: 0,
+        next_href: 0,
+    };
+    let n_file_blks = r.u16_at(0x04)? as usize;
+    let first_blk = r.u16_at(0x0a)? as usize;
+    if n_file_blks < 2 || first_blk == 0 || first_blk >= n_file_blks {
+        return Err(corrupt("bad block directory"));
+    }
+    let type_count = n_file_blks - 2;
+
+    let entry = |i: usize| -> Result<(usize, u16, u16), Error> {
+        let p = HEADER_SIZE + i * BLOCK_ENTRY_SIZE;
+        Ok((r.u32_at(p)? as usize, r.u16_at(p + 4)?, r.u16_at(p + 6)?))
+    };
+    let magic = HEADER_SIZE + (n_file_blks - 1) * BLOCK_ENTRY_SIZE;
+    if r.u8_at(magic)? != 1 || r.bytes(magic + 1, 8)? != b"CompObj\0" {
+        return Err(corrupt("missing CompObj magic"));
+    }
+
+    // Walk the block chain: typeinfo blocks first, the library block last.
+    let mut blocks = Vec::with_capacity(type_count);
+    let mut pos = magic + MAGIC_SIZE + type_count * INDEX_NAME_SIZE + PAD9_SIZE;
+    let mut order = first_blk - 1;
+    loop {
+        let (len, index_string, next) = entry(order)?;
+        if next == 0 {
+            break;
+        }
+        if blocks.len() == type_count {
+            return Err(corrupt("block chain longer than the directory"));
+        }
+        let index_name = r.cstr_at(magic + index_string as usize)?;
+        blocks.push((pos, index_name));
+        pos = pos
+            .checked_add(len)
+            .ok_or_else(|| corrupt("block length overflow"))?;
+        order = next as usize - 1;
+        if order >= n_file_blks - 1 {
+            return Err(corrupt("block chain out of range"));
+        }
+    }
+    if blocks.len() != type_count {
+        return Err(corrupt("block chain shorter than the directory"));
+    }
+    let lib_blk = pos;
+
+    // Library block.
+    if r.u16_at(lib_blk)? != LIBBLK_MAGIC {
+        return Err(corrupt("bad library block magic"));
+    }
+    let lib_name = r.u16_at(lib_blk + 4)?;
+    let mut p = lib_blk + 6;
+    let (_, n) = r.counted_str_at(p)?;
+    p += n;
+    let (doc_string, n) = r.counted_str_at(p)?;
+    p += n;
+    let (help_file, n) = r.counted_str_at(p)?;
+    p += n;
+    let help_context = r.u32_at(p)?;
+    let syskind = r.u16_at(p + 4)? as u32;
+    let lcid = r.u16_at(p + 6)? as u32;
+    let flags = r.u16_at(p + 12)?;
+    let major_ver = r.u16_at(p + 14)?;
+    let minor_ver = r.u16_at(p + 16)?;
+    let guid = r.guid_at(p + 18)?;
+    p += 34;
+
+    // 0x40 bytes of filler, then one directory record per typeinfo.
+    p += 0x40;
+    let mut dir = Vec::with_capacity(type_count);
+    for _ in 0..type_count {
+        let (index_name, n1) = r.counted_str_at(p + 2)?;
+        let (_, n2) = r.counted_str_at(p + 2 + n1)?;
+        let q = p + 2 + n1 + n2;
+        let name_offset = r.u16_at(q + 2)?;
+        let extra = r.u16_at(q + 4)? as usize;
+        let doc = if extra > 0 {
+            let raw = r.bytes(q + 6, extra)?;
+            decode_ansi(raw.split(|&b| b == 0).next().unwrap_or_default())
+        } else {
+            String::new()
+        };
+        let q = q + 6 + extra;
+        dir.push(DirEntry {
+            index_name: index_name.unwrap_or_default(),
+            name_offset,
+            doc_string: doc,
+            help_context: r.u32_at(q + 2)?,
+            guid: r.guid_at(q + 8)?,
+        });
+        p = q + 26;
+    }
+
+    let mut name_table = lib_blk + r.u32_at(p + 2)? as usize;
+    if r.u16_at(name_table)? == 0x0200 {
+        name_table += 0x20;
+    }
+    r.names = name_table + NAME_TABLE_SKIP;
+
+    let mut lib = TypeLib {
+        attr: LibAttr {
+            guid,
+            lcid,
+            syskind,
+            major_ver,
+            minor_ver,
+            flags,
+        },
+        name: r.name(lib_name)?,
+        doc_string: doc_string.unwrap_or_default(),
+        help_context,
+        help_file: help_file.unwrap_or_default(),
+        ..Default::default()
+    };
+
+    let mut imports = HashMap::new();
+    for (i, ((blk, index_name), entry)) in blocks.iter().zip(&dir).enumerate() {
+        if *index_name != entry.index_name {
+            return Err(corrupt(&format!("index string mismatch for typeinfo {}", i)));
+        }
+        let ti = read_type_info(&mut r, &mut lib, &mut imports, *blk, entry)?;
+        lib.type_infos.push(ti);
+    }
+    Ok(lib)
+}
+
+fn read_type_info(
+    r: &mut Reader,
+    lib: &mut TypeLib,
+    imports: &mut HashMap<u32, usize>,
+    blk: usize,
+    entry: &DirEntry,
+) -> Result<TypeInfo, Error> {
+    if r.u16_at(blk)? != TIHEADER_MAGIC {
+        return Err(corrupt("bad typeinfo header magic"));
+    }
+    let href_table = r.u32_at(blk + 0x02)?;
+    let elem_table = r.u32_at(blk + 0x0a)? as usize;
+    let major_ver = r.u16_at(blk + 0x12)?;
+    let minor_ver = r.u16_at(blk + 0x14)?;
+    let typeflags1 = r.u8_at(blk + 0x1a)? as u16;
+    let typeflags2 = r.u8_at(blk + 0x1b)? as u16;
+    let stored_kind = r.u8_at(blk + 0x1d)? as u32;
+    let type_flags = (typeflags1 >> 3) | (typeflags2 << 5);
+
+    let mem = blk + elem_table;
+    let extra = r.u32_at(mem + 5)? as usize;
+    let base = mem + MEMBER_HEADER_SIZE;
+    let t = base + extra;
+    let tail = Tail {
+        c_funcs: r.u16_at(t)?,
+        c_vars: r.u16_at(t + 0x02)?,
+        funcs_off: r.u16_at(t + 0x08)?,
+        vars_off: r.u16_at(t + 0x0a)?,
+        impls_off: r.u16_at(t + 0x0c)?,
+        tdescalias_vt: r.u16_at(t + 0x14)?,
+        simple_alias: r.u16_at(t + 0x1c)?,
+        cb_size_instance: r.u16_at(t + 0x20)?,
+        cb_alignment: r.u16_at(t + 0x22)?,
+        cb_size_vft: r.u16_at(t + 0x28)?,
+    };
+
+    let refs = if href_table != u32::MAX {
+        read_refs(r, lib, imports, blk + href_table as usize)?
+    } else {
+        Vec::new()
+    };
+    let m = Members {
+        r,
+        base,
+        refs: &refs,
+    };
+
+    let mut ti = TypeInfo {
+        name: r.name(entry.name_offset)?,
+        doc_string: entry.doc_string.clone(),
+        help_context: entry.help_context,
+        help_string_context: 0,
+        attr: TypeAttr {
+            guid: entry.guid,
+            typekind: stored_kind,
+            flags: type_flags,
+            major_ver,
+            minor_ver,
+            cb_size_instance: tail.cb_size_instance as u32,
+            cb_size_vft: tail.cb_size_vft,
+            cb_alignment: tail.cb_alignment,
+            tdesc_alias: None,
+        },
+        funcs: Vec::new(),
+        vars: Vec::new(),
+        impl_types: Vec::new(),
+        dll_name: None,
+        custom_data: Vec::new(),
+    };
+
+    match stored_kind {
+        TKIND_ENUM | TKIND_RECORD | TKIND_UNION => {
+            ti.vars = m.vars(tail.vars_off, tail.c_vars)?;
+        }
+        TKIND_INTERFACE => {
+            if r.u16_at(base)? == IMPL_MAGIC {
+                ti.impl_types = m.impls(base)?;
+            }
+            ti.funcs = m.funcs(tail.funcs_off, tail.c_funcs)?;
+        }
+        TKIND_COCLASS => {
+            if r.u16_at(base)? == IMPL_MAGIC {
+                ti.impl_types = m.impls(base)?;
+            }
+        }
+        TKIND_ALIAS => {
+            ti.attr.tdesc_alias = Some(if tail.simple_alias != 0 {
+                TypeDesc::Base(tail.tdescalias_vt)
+            } else {
+                m.tdesc(base + tail.tdescalias_vt as usize)?.0
+            });
+        }
+        TKIND_DISPATCH => {
+            ti.vars = m.vars(tail.vars_off, tail.c_vars)?;
+            ti.funcs = m.funcs(tail.funcs_off, tail.c_funcs)?;
+            if tail.impls_off != NO_OFFSET {
+                ti.impl_types = m.impls(base + tail.impls_off as usize)?;
+            }
+        }
+        TKIND_MODULE => {
+            ti.vars = m.vars(tail.vars_off, tail.c_vars)?;
+            ti.funcs = m.funcs(tail.funcs_off, tail.c_funcs)?;
+        }
+        other => {
+            return Err(Error::UnsupportedTypeKind {
+                name: ti.name,
+                kind: other,
+            });
+        }
+    }
+
+    // Like MSFT, duals are exposed as their dispatch half.
+    if type_flags & TYPEFLAG_FDUAL != 0 {
+        ti.attr.typekind = TKIND_DISPATCH;
+    }
+    Ok(ti)
+}
+
+/// Reads a typeinfo's ref table and registers every entry in `lib.refs`.
+/// Returns the hreftypes in table order; type descriptions and implemented
+/// types refer to them by position.
+fn read_refs(
+    r: &mut Reader,
+    lib: &mut TypeLib,
+    imports: &mut HashMap<u32, usize>,
+    pos: usize,
+) -> Result<Vec<HRefType>, Error> {
+    if r.u8_at(pos)? != REF_MAGIC {
+        return Err(corrupt("bad ref table magic"));
+    }
+    let number = r.u32_at(pos + 0x44)? as usize;
+    let mut p = pos + 0x4f + number;
+    let mut table = Vec::with_capacity(number / 8);
+    for _ in 0..number / 8 {
+        let (text, n) = r.counted_str_at(p)?;
+        p += n;
+        let text = text.unwrap_or_default();
+        let (lib_offs, type_num) =
+            parse_ref(&text).ok_or_else(|| corrupt(&format!("bad ref string {:?}", text)))?;
+
+        let target = if lib_offs == 0xffff {
+            TypeRef::Local(type_num as usize)
+        } else {
+            let import = match imports.get(&lib_offs) {
+                Some(&import) => import,
+                None => {
+                    let spec = r.cstr_at(r.names + lib_offs as usize)?;
+                    let import_lib = parse_import(&spec)
+                        .ok_or_else(|| corrupt(&format!("bad import string {:?}", spec)))?;
+                    lib.imports.push(import_lib);
+                    imports.insert(lib_offs, lib.imports.len() - 1);
+                    lib.imports.len() - 1
+                }
+            };
+            let guid = (lib.imports[import].guid == LIBID_STDOLE)
+                .then(|| stdole_type(type_num))
+                .flatten();
+            TypeRef::External {
+                import,
+                guid,
+                index: Some(type_num),
+                name: guid
+                    .as_ref()
+                    .and_then(well_known_type)
+                    .map(|(name, _)| name.to_string()),
+                typekind: guid.as_ref().and_then(well_known_type).map(|(_, k)| k),
+            }
+        };
+
+        // Start at 4 so that no reference is 0.
+        r.next_href += 4;
+        lib.refs.insert(r.next_href, target);
+        table.push(r.next_href);
+    }
+    if r.u8_at(p)? != REF_MAGIC {
+        return Err(corrupt("bad ref table end marker"));
+    }
+    Ok(table)
+}
+
+/// Parses `*\R<hex lib offset>*#<hex type index>`.
+fn parse_ref(s: &str) -> Option<(u32, u32)> {
+    let rest = s.strip_prefix("*\\R")?;
+    let (lib, index) = rest.split_once("*#")?;
+    Some((
+        u32::from_str_radix(lib, 16).ok()?,
+        u32::from_str_radix(index, 16).ok()?,
+    ))
+}
+
+/// Parses `*\G{<libid>}#<major>.<minor>#<hex lcid>#<path>#`.
+fn parse_import(s: &str) -> Option<ImportLib> {
+    let rest = s.strip_prefix("*\\G{")?;
+    let (guid, rest) = rest.split_once("}#")?;
+    let mut fields = rest.splitn(3, '#');
+    let (major, minor) = fields.next()?.split_once('.')?;
+    let lcid = u32::from_str_radix(fields.next()?, 16).ok()?;
+    let path = fields.next()?.strip_suffix('#')?;
+    Some(ImportLib {
+        guid: Guid::parse(guid)?,
+        lcid,
+        major_ver: major.parse().ok()?,
+        minor_ver: minor.parse().ok()?,
+        // Keep only the file name, as MSFT import tables do.
+        file_name: path.rsplit(['\\', '/']).next()?.to_string(),
+    })
+}
+
+/// Well-known positions in stdole2.tlb, which SLTG refs name by index.
+fn stdole_type(index: u32) -> Option<Guid> {
+    match index {
+        3 => Some(IID_IUNKNOWN),
+        4 => Some(IID_IDISPATCH),
+        _ => None,
+    }
+}
+
+/// Member area of one typeinfo; every offset is relative to `base`.
+struct Members<'r, 'a> {
+    r: &'r Reader<'a>,
+    base: usize,
+    refs: &'r [HRefType],
+}
+
+impl Members<'_, '_> {
+    fn href(&self, index: u16) -> Result<HRefType, Error> {
+        self.refs
+            .get(index as usize)
+            .copied()
+            .ok_or_else(|| corrupt(&format!("ref {} outside the ref table", index)))
+    }
+
+    /// Decodes a type description starting at `pos`. Returns it with the
+    /// position just past it.
+    fn tdesc(&self, mut pos: usize) -> Result<(TypeDesc, usize), Error> {
+        enum Wrap {
+            Ptr,
+            SafeArray,
+            CArray(Vec<(u32, i32)>),
+        }
+        let mut wraps = Vec::new();
+        let leaf = loop {
+            if wraps.len() > 32 {
+                return Err(corrupt("type description nested too deeply"));
+            }
+            let w = self.r.u16_at(pos)?;
+            if w & 0xe00 == 0xe00 {
+                wraps.push(Wrap::Ptr);
+            }
+            match w & 0x3f {
+                VT_PTR => wraps.push(Wrap::Ptr),
+                VT_USERDEFINED => {
+                    pos += 2;
+                    let href = self.href(self.r.u16_at(pos)? / 4)?;
+                    pos += 2;
+                    break TypeDesc::UserDefined(href);
+                }
+                VT_CARRAY => {
+                    // Offset of a SAFEARRAY descriptor holding the bounds.
+                    pos += 2;
+                    let sa = self.base + self.r.u16_at(pos)? as usize;
+                    let dims = self.r.u16_at(sa)? as usize;
+                    let mut bounds = Vec::with_capacity(dims);
+                    for d in 0..dims {
+                        let b = sa + 16 + d * 8;
+                        bounds.push((self.r.u32_at(b)?, self.r.u32_at(b + 4)? as i32));
+                    }
+                    wraps.push(Wrap::CArray(bounds));
+                }
+                VT_SAFEARRAY => {
+                    pos += 2;
+                    wraps.push(Wrap::SafeArray);
+                }
+                vt => {
+                    pos += 2;
+                    break TypeDesc::Base(vt);
+                }
+            }
+            pos += 2;
+        };
+        let tdesc = wraps.into_iter().rev().fold(leaf, |inner, wrap| match wrap {
+            Wrap::Ptr => TypeDesc::Ptr(Box::new(inner)),
+            Wrap::SafeArray => TypeDesc::SafeArray(Box::new(inner)),
+            Wrap::CArray(bounds) => TypeDesc::CArray {
+                elem: Box::new(inner),
+                bounds,
+            },
+        });
+        Ok((tdesc, pos))
+    }
+
+    /// Like [`Members::tdesc`], also decoding the parameter direction bits of
+    /// the first word.
+    fn elem(&self, pos: usize) -> Result<(TypeDesc, u16, usize), Error> {
+        let w = self.r.u16_at(pos)?;
+        let mut flags = if w & 0xc000 == 0xc000 {
+            0
+        } else if w & 0x8000 != 0 {
+            PARAMFLAG_FIN | PARAMFLAG_FOUT
+        } else if w & 0x4000 != 0 {
+            PARAMFLAG_FOUT
+        } else {
+            PARAMFLAG_FIN
+        };
+        if w & 0x2000 != 0 {
+            flags |= PARAMFLAG_FLCID;
+        }
+        if w & 0x80 != 0 {
+            flags |= PARAMFLAG_FRETVAL;
+        }
+        let (tdesc, next) = self.tdesc(pos)?;
+        Ok((tdesc, flags, next))
+    }
+
+    fn vars(&self, first: u16, count: u16) -> Result<Vec<VarDesc>, Error> {
+        let r = self.r;
+        let mut vars = Vec::with_capacity(count as usize);
+        if first == NO_OFFSET {
+            return Ok(vars);
+        }
+        let mut item = self.base + first as usize;
+        for i in 0..count {
+            let magic = r.u8_at(item)?;
+            if magic != VAR_MAGIC && magic != VAR_WITH_FLAGS_MAGIC {
+                return Err(corrupt(&format!("bad variable magic {:#x}", magic)));
+            }
+            let flags = r.u8_at(item + 1)?;
+            let name_offset = r.u16_at(item + 4)?;
+            let byte_offs = r.u16_at(item + 6)?;
+            let type_field = r.u16_at(item + 8)?;
+            let memid = r.u32_at(item + 10)? as i32;
+
+            let name = match (name_offset, vars.last()) {
+                // 0xfffe repeats the previous member's name.
+                (0xfffe, Some(prev)) => prev.name.clone(),
+                _ => r.name(name_offset)?,
+            };
+            let type_pos = if flags & 0x02 != 0 {
+                item + 8
+            } else {
+                self.base + type_field as usize
+            };
+            let (tdesc, _, _) = self.elem(type_pos)?;
+
+            let mut value = None;
+            let mut oinst = 0;
+            let varkind = if flags & 0x40 != 0 {
+                VAR_DISPATCH
+            } else if flags & 0x10 != 0 {
+                value = if flags & 0x08 != 0 {
+                    Some(Variant::Int(byte_offs as i32))
+                } else {
+                    self.const_value(&tdesc, self.base + byte_offs as usize)?
+                };
+                VAR_CONST
+            } else {
+                oinst = byte_offs as u32;
+                VAR_PERINSTANCE
+            };
+
+            let mut var_flags = if magic == VAR_WITH_FLAGS_MAGIC {
+                r.u16_at(item + 18)?
+            } else {
+                0
+            };
+            if flags & 0x80 != 0 {
+                var_flags |= VARFLAG_FREADONLY;
+            }
+
+            vars.push(VarDesc {
+                memid,
+                name,
+                doc_string: String::new(),
+                help_context: 0,
+                help_string_context: 0,
+                varkind,
+                var_flags,
+                tdesc,
+                value,
+                oinst,
+                custom_data: Vec::new(),
+            });
+
+            let next = r.u16_at(item + 2)?;
+            if i + 1 < count {
+                if next == NO_OFFSET {
+                    return Err(corrupt("variable list ends early"));
+                }
+                item = self.base + next as usize;
+            }
+        }
+        Ok(vars)
+    }
+
+    fn const_value(&self, tdesc: &TypeDesc, pos: usize) -> Result<Option<Variant>, Error> {
+        Ok(match tdesc.vt() {
+            VT_LPSTR | VT_LPWSTR | VT_BSTR => {
+                let (s, _) = self.r.counted_str_at(pos)?;
+                Some(Variant::Bstr(s.unwrap_or_default()))
+            }
+            VT_I2 | VT_UI2 | VT_I4 | VT_UI4 | VT_INT | VT_UINT => {
+                Some(Variant::Int(self.r.u32_at(pos)? as i32))
+            }
+            _ => None,
+        })
+    }
+
+    fn funcs(&self, first: u16, count: u16) -> Result<Vec<FuncDesc>, Error> {
+        let r = self.r;
+        let mut funcs = Vec::with_capacity(count as usize);
+        if first == NO_OFFSET {
+            return Ok(funcs);
+        }
+        let mut item = self.base + first as usize;
+        for i in 0..count {
+            let magic = r.u8_at(item)?;
+            let funckind = match magic & !FUNCTION_FLAGS_PRESENT {
+                FUNCTION_MAGIC => FUNC_PUREVIRTUAL,
+                DISPATCH_FUNCTION_MAGIC => FUNC_DISPATCH,
+                STATIC_FUNCTION_MAGIC => FUNC_STATIC,
+                other => return Err(corrupt(&format!("bad function magic {:#x}", other))),
+            };
+            let inv = r.u8_at(item + 1)?;
+            let name = r.name(r.u16_at(item + 4)?)?;
+            let memid = r.u32_at(item + 6)? as i32;
+            let arg_off = r.u16_at(item + 0x0e)? as usize;
+            let nacc = r.u8_at(item + 0x10)?;
+            let retnextopt = r.u8_at(item + 0x11)?;
+            let rettype = r.u16_at(item + 0x12)?;
+            let vtblpos = r.u16_at(item + 0x14)?;
+            let func_flags = if magic & FUNCTION_FLAGS_PRESENT != 0 {
+                r.u16_at(item + 0x16)?
+            } else {
+                0
+            };
+
+            let ret_pos = if retnextopt & 0x80 != 0 {
+                item + 0x12
+            } else {
+                self.base + rettype as usize
+            };
+            let (ret, _, _) = self.elem(ret_pos)?;
+
+            let c_params = (nacc >> 3) as usize;
+            let c_params_opt = ((retnextopt & 0x7e) >> 1) as usize;
+            let mut params = Vec::with_capacity(c_params);
+            let mut arg = self.base + arg_off;
+            for index in 0..c_params {
+                // The name offset points at the first letter when the next
+                // word is an offset to the type, and at the second letter
+                // when the type follows inline.
+                let w = r.u16_at(arg)?;
+                let (name, inline) = match w {
+                    0xffff => (None, true),
+                    0xfffe => (None, false),
+                    _ => {
+                        let at = r.names + w as usize;
+                        let prev = r.u8_at(at - 1)?;
+                        if prev != 0 && !prev.is_ascii_alphanumeric() {
+                            (Some(r.cstr_at(at)?), false)
+                        } else {
+                            (Some(r.cstr_at(at - 1)?), true)
+                        }
+                    }
+                };
+                arg += 2;
+                let (tdesc, mut flags) = if inline {
+                    let (tdesc, flags, next) = self.elem(arg)?;
+                    arg = next;
+                    (tdesc, flags)
+                } else {
+                    let (tdesc, flags, _) = self.elem(self.base + r.u16_at(arg)? as usize)?;
+                    arg += 2;
+                    (tdesc, flags)
+                };
+                if c_params - index <= c_params_opt {
+                    flags |= PARAMFLAG_FOPT;
+                }
+                params.push(ParamDesc {
+                    name,
+                    tdesc,
+                    flags,
+                    default_value: None,
+                    custom_data: Vec::new(),
+                });
+            }
+
+            funcs.push(FuncDesc {
+                memid,
+                name,
+                doc_string: String::new(),
+                help_context: 0,
+                help_string_context: 0,
+                funckind,
+                invkind: (inv >> 4) as u32,
+                callconv: (nacc & 0x7) as u32,
+                func_flags,
+                ovft: (vtblpos & !1) as i16,
+                params,
+                c_params_opt: c_params_opt as i16,
+                ret,
+                entry: None,
+                custom_data: Vec::new(),
+            });
+
+            let next = r.u16_at(item + 2)?;
+            if i + 1 < count {
+                if next == NO_OFFSET {
+                    return Err(corrupt("function list ends early"));
+                }
+                item = self.base + next as usize;
+            }
+        }
+        Ok(funcs)
+    }
+
+    fn impls(&self, first: usize) -> Result<Vec<ImplType>, Error> {
+        let mut impls = Vec::new();
+        let mut item = first;
+        loop {
+            if impls.len() > 0xffff {
+                return Err(corrupt("implemented type list does not terminate"));
+            }
+            impls.push(ImplType {
+                href: self.href(self.r.u16_at(item + 0x0a)?)?,
+                flags: self.r.u8_at(item + 6)? as i32,
+                custom_data: Vec::new(),
+            });
+            let next = self.r.u16_at(item + 2)?;
+            if next == NO_OFFSET {
+                break;
+            }
+            item = self.base + next as usize;
+        }
+        Ok(impls)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str) -> TypeLib {
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures")
+            .join(name);
+        parse(&std::fs::read(path).unwrap()).unwrap()
+    }
+
+    fn type_info<'a>(lib: &'a TypeLib, name: &str) -> &'a TypeInfo {
+        lib.type_infos.iter().find(|t| t.name == name).unwrap()
+    }
+
+    #[test]
+    fn reads_library_attributes() {
+        let lib = fixture("legacy_sltg.tlb");
+        assert_eq!(lib.name, "LegacyLib");
+        assert_eq!(lib.doc_string, "Legacy SLTG library");
+        assert_eq!(lib.help_context, 7);
+        assert_eq!(lib.attr.lcid, 0x409);
+        assert_eq!(
+            lib.attr.guid,
+            Guid::from_u128(0x5a1e0b00_0001_4b1d_9c2e_0123456789ab)
+        );
+        assert_eq!((lib.attr.major_ver, lib.attr.minor_ver), (3, 1));
+        assert_eq!(lib.type_infos.len(), 8);
+        assert_eq!(lib.imports.len(), 1);
+        assert_eq!(lib.imports[0].file_name, "stdole2.tlb");
+        assert_eq!(lib.imports[0].guid, LIBID_STDOLE);
+    }
+
+    #[test]
+    fn reads_enums_and_records() {
+        let lib = fixture("legacy_sltg.tlb");
+        let mode = type_info(&lib, "Mode");
+        assert_eq!(mode.attr.typekind, TKIND_ENUM);
+        assert_eq!(mode.doc_string, "Counter mode");
+        let values: Vec<_> = mode
+            .vars
+            .iter()
+            .map(|v| (v.name.as_str(), v.value.as_ref().unwrap().as_i32()))
+            .collect();
+        assert_eq!(values, vec![("ModeOff", 0), ("ModeOn", 1), ("ModeAuto", -1)]);
+
+        let rect = type_info(&lib, "Rect");
+        assert_eq!(rect.attr.cb_size_instance, 12);
+        let fields: Vec<_> = rect
+            .vars
+            .iter()
+            .map(|v| (v.name.as_str(), v.oinst, v.tdesc.clone()))
+            .collect();
+        assert_eq!(
+            fields,
+            vec![
+                ("left", 0, TypeDesc::Base(VT_I4)),
+                ("top", 4, TypeDesc::Base(VT_I4)),
+                ("tag", 8, TypeDesc::Base(VT_BSTR)),
+            ]
+        );
+
+        let limits = type_info(&lib, "Limits");
+        assert_eq!(limits.vars[0].value, Some(Variant::Int(100)));
+        assert_eq!(limits.vars[1].value, Some(Variant::Bstr("hello".to_string())));
+    }
+
+    #[test]
+    fn reads_aliases_through_the_ref_table() {
+        let lib = fixture("legacy_sltg.tlb");
+        let handle = type_info(&lib, "Handle");
+        assert_eq!(handle.attr.tdesc_alias, Some(TypeDesc::Base(VT_I4)));
+        let mode_ptr = type_info(&lib, "ModePtr");
+        let Some(TypeDesc::Ptr(inner)) = &mode_ptr.attr.tdesc_alias else {
+            panic!("expected a pointer alias");
+        };
+        let TypeDesc::UserDefined(href) = **inner else {
+            panic!("expected a user-defined type");
+        };
+        assert_eq!(lib.resolve_ref(href).unwrap().name, "Mode");
+    }
+
+    #[test]
+    fn reads_dual_interface_methods() {
+        let lib = fixture("legacy_sltg.tlb");
+        let counter = type_info(&lib, "ICounter");
+        assert_eq!(counter.attr.typekind, TKIND_DISPATCH);
+        assert_ne!(counter.attr.flags & TYPEFLAG_FDUAL, 0);
+        assert_eq!(counter.doc_string, "A counter");
+        let base = lib.resolve_ref(counter.impl_types[0].href).unwrap();
+        assert_eq!(base.name, "IDispatch");
+
+        let names: Vec<_> = counter.funcs.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, vec!["Count", "Add", "Mode", "Names", "Fill"]);
+
+        let count = &counter.funcs[0];
+        assert_eq!(count.invkind, INVOKE_PROPERTYGET);
+        assert_eq!(count.ret, TypeDesc::Base(VT_HRESULT));
+        assert_eq!(count.params[0].name.as_deref(), Some("val"));
+        assert_eq!(count.params[0].flags, PARAMFLAG_FOUT | PARAMFLAG_FRETVAL);
+        assert_eq!(
+            count.params[0].tdesc,
+            TypeDesc::Ptr(Box::new(TypeDesc::Base(VT_I4)))
+        );
+
+        let add = &counter.funcs[1];
+        assert_eq!(add.memid, 2);
+        assert_eq!(add.c_params_opt, 1);
+        assert_eq!(add.params[0].flags, PARAMFLAG_FIN);
+        assert_eq!(add.params[1].flags, PARAMFLAG_FIN | PARAMFLAG_FOPT);
+        assert_eq!(add.params[1].tdesc, TypeDesc::Base(VT_VARIANT));
+        assert_eq!(add.ovft, 32);
+
+        let names = &counter.funcs[3];
+        assert_eq!(
+            names.params[0].tdesc,
+            TypeDesc::Ptr(Box::new(TypeDesc::SafeArray(Box::new(TypeDesc::Base(
+                VT_BSTR
+            )))))
+        );
+        let fill = &counter.funcs[4];
+        assert_eq!(
+            fill.params[0].tdesc,
+            TypeDesc::CArray {
+                elem: Box::new(TypeDesc::Base(VT_I4)),
+                bounds: vec![(4, 0)],
+            }
+        );
+    }
+
+    #[test]
+    fn reads_dispinterfaces_and_coclasses() {
+        let lib = fixture("legacy_sltg.tlb");
+        let events = type_info(&lib, "_CounterEvents");
+        assert_eq!(events.attr.typekind, TKIND_DISPATCH);
+        assert_eq!(events.funcs[0].funckind, FUNC_DISPATCH);
+        assert_eq!(events.funcs[0].ret, TypeDesc::Base(VT_VOID));
+        assert_eq!(
+            lib.resolve_ref(events.impl_types[0].href).unwrap().name,
+            "IDispatch"
+        );
+
+        let counter = type_info(&lib, "Counter");
+        let impls: Vec<_> = counter
+            .impl_types
+            .iter()
+            .map(|i| (lib.resolve_ref(i.href).unwrap().name, i.flags))
+            .collect();
+        assert_eq!(
+            impls,
+            vec![
+                ("ICounter".to_string(), IMPLTYPEFLAG_FDEFAULT),
+                (
+                    "_CounterEvents".to_string(),
+                    IMPLTYPEFLAG_FDEFAULT | IMPLTYPEFLAG_FSOURCE
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_truncated_files() {
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/legacy_sltg.tlb");
+        let data = std::fs::read(path).unwrap();
+        assert!(parse(&data[..data.len() / 2]).is_err());
+        assert!(parse(b"SLTG").is_err());
+    }
+}
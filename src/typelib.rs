@@ -0,0 +1,739 @@
+//! In-memory form of a loaded type library.
+//!
+//! The readers (`msft`, `sltg`) decode the on-disk format into these structures, which
+//! mirror the OLE `TLIBATTR`/`TYPEATTR`/`FUNCDESC`/`VARDESC` descriptors but own
+//! all of their data, so nothing here depends on OLE being available.
+
+use std::fmt;
+
+pub type HRefType = u32;
+
+// TYPEKIND
+pub const TKIND_ENUM: u32 = 0;
+pub const TKIND_RECORD: u32 = 1;
+pub const TKIND_MODULE: u32 = 2;
+pub const TKIND_INTERFACE: u32 = 3;
+pub const TKIND_DISPATCH: u32 = 4;
+pub const TKIND_COCLASS: u32 = 5;
+pub const TKIND_ALIAS: u32 = 6;
+pub const TKIND_UNION: u32 = 7;
+
+// SYSKIND
+/// 16-bit Windows.
+pub const SYS_WIN16: u32 = 0;
+/// 32-bit Windows.
+pub const SYS_WIN32: u32 = 1;
+/// The Macintosh.
+pub const SYS_MAC: u32 = 2;
+/// 64-bit Windows.
+pub const SYS_WIN64: u32 = 3;
+
+// TYPEFLAGS
+/// `appobject`: the application object.
+pub const TYPEFLAG_FAPPOBJECT: u16 = 0x1;
+/// Instances can be created with `ITypeInfo::CreateInstance`; a coclass without `noncreatable`.
+pub const TYPEFLAG_FCANCREATE: u16 = 0x2;
+/// `licensed`: creating instances needs a license.
+pub const TYPEFLAG_FLICENSED: u16 = 0x4;
+/// `predeclid`: the type has a predefined instance.
+pub const TYPEFLAG_FPREDECLID: u16 = 0x8;
+/// `hidden`: not shown to users.
+pub const TYPEFLAG_FHIDDEN: u16 = 0x10;
+/// `control`: a coclass of an ActiveX control.
+pub const TYPEFLAG_FCONTROL: u16 = 0x20;
+/// `dual`: callable through the vtable and through `IDispatch`.
+pub const TYPEFLAG_FDUAL: u16 = 0x40;
+/// `nonextensible`: no members are added at run time.
+pub const TYPEFLAG_FNONEXTENSIBLE: u16 = 0x80;
+/// `oleautomation`: marshaled with the OLE Automation types only.
+pub const TYPEFLAG_FOLEAUTOMATION: u16 = 0x100;
+/// `restricted`: not for macro languages.
+pub const TYPEFLAG_FRESTRICTED: u16 = 0x200;
+/// `aggregatable`: the coclass supports aggregation.
+pub const TYPEFLAG_FAGGREGATABLE: u16 = 0x400;
+/// `replaceable`: the object supports `IConnectionPointWithDefault`.
+pub const TYPEFLAG_FREPLACEABLE: u16 = 0x800;
+/// The interface derives from `IDispatch`.
+pub const TYPEFLAG_FDISPATCHABLE: u16 = 0x1000;
+/// `reversebind`: names are looked up in the base interfaces first.
+pub const TYPEFLAG_FREVERSEBIND: u16 = 0x2000;
+/// `proxy`.
+pub const TYPEFLAG_FPROXY: u16 = 0x4000;
+
+/// Names of the `TYPEFLAG_*` bits; all but `cancreate` and `dispatchable`
+/// are also their IDL attributes.
+pub const TYPE_FLAG_NAMES: [(u16, &str); 15] = [
+    (TYPEFLAG_FAPPOBJECT, "appobject"),
+    (TYPEFLAG_FCANCREATE, "cancreate"),
+    (TYPEFLAG_FLICENSED, "licensed"),
+    (TYPEFLAG_FPREDECLID, "predeclid"),
+    (TYPEFLAG_FHIDDEN, "hidden"),
+    (TYPEFLAG_FCONTROL, "control"),
+    (TYPEFLAG_FDUAL, "dual"),
+    (TYPEFLAG_FNONEXTENSIBLE, "nonextensible"),
+    (TYPEFLAG_FOLEAUTOMATION, "oleautomation"),
+    (TYPEFLAG_FRESTRICTED, "restricted"),
+    (TYPEFLAG_FAGGREGATABLE, "aggregatable"),
+    (TYPEFLAG_FREPLACEABLE, "replaceable"),
+    (TYPEFLAG_FDISPATCHABLE, "dispatchable"),
+    (TYPEFLAG_FREVERSEBIND, "reversebind"),
+    (TYPEFLAG_FPROXY, "proxy"),
+];
+
+// INVOKEKIND, but for INVOKE_FUNC (1), which the readers take as the default
+pub const INVOKE_PROPERTYGET: u32 = 2;
+pub const INVOKE_PROPERTYPUT: u32 = 4;
+pub const INVOKE_PROPERTYPUTREF: u32 = 8;
+
+// FUNCKIND
+pub const FUNC_VIRTUAL: u32 = 0;
+pub const FUNC_PUREVIRTUAL: u32 = 1;
+pub const FUNC_NONVIRTUAL: u32 = 2;
+pub const FUNC_STATIC: u32 = 3;
+pub const FUNC_DISPATCH: u32 = 4;
+
+// VARKIND
+pub const VAR_PERINSTANCE: u32 = 0;
+pub const VAR_STATIC: u32 = 1;
+pub const VAR_CONST: u32 = 2;
+pub const VAR_DISPATCH: u32 = 3;
+
+// VARFLAGS
+/// `readonly`.
+pub const VARFLAG_FREADONLY: u16 = 0x1;
+/// `source`: the property raises events.
+pub const VARFLAG_FSOURCE: u16 = 0x2;
+/// `bindable`.
+pub const VARFLAG_FBINDABLE: u16 = 0x4;
+/// `requestedit`.
+pub const VARFLAG_FREQUESTEDIT: u16 = 0x8;
+/// `displaybind`.
+pub const VARFLAG_FDISPLAYBIND: u16 = 0x10;
+/// `defaultbind`.
+pub const VARFLAG_FDEFAULTBIND: u16 = 0x20;
+/// `hidden`.
+pub const VARFLAG_FHIDDEN: u16 = 0x40;
+/// `restricted`.
+pub const VARFLAG_FRESTRICTED: u16 = 0x80;
+/// `defaultcollelem`.
+pub const VARFLAG_FDEFAULTCOLLELEM: u16 = 0x100;
+/// `uidefault`.
+pub const VARFLAG_FUIDEFAULT: u16 = 0x200;
+/// `nonbrowsable`.
+pub const VARFLAG_FNONBROWSABLE: u16 = 0x400;
+/// `replaceable`.
+pub const VARFLAG_FREPLACEABLE: u16 = 0x800;
+/// `immediatebind`.
+pub const VARFLAG_FIMMEDIATEBIND: u16 = 0x1000;
+
+// FUNCFLAGS
+/// `restricted`.
+pub const FUNCFLAG_FRESTRICTED: u16 = 0x1;
+/// `source`: the function raises events.
+pub const FUNCFLAG_FSOURCE: u16 = 0x2;
+/// `bindable`.
+pub const FUNCFLAG_FBINDABLE: u16 = 0x4;
+/// `requestedit`.
+pub const FUNCFLAG_FREQUESTEDIT: u16 = 0x8;
+/// `displaybind`.
+pub const FUNCFLAG_FDISPLAYBIND: u16 = 0x10;
+/// `defaultbind`.
+pub const FUNCFLAG_FDEFAULTBIND: u16 = 0x20;
+/// `hidden`.
+pub const FUNCFLAG_FHIDDEN: u16 = 0x40;
+/// `usesgetlasterror`.
+pub const FUNCFLAG_FUSESGETLASTERROR: u16 = 0x80;
+/// `defaultcollelem`.
+pub const FUNCFLAG_FDEFAULTCOLLELEM: u16 = 0x100;
+/// `uidefault`.
+pub const FUNCFLAG_FUIDEFAULT: u16 = 0x200;
+/// `nonbrowsable`.
+pub const FUNCFLAG_FNONBROWSABLE: u16 = 0x400;
+/// `replaceable`.
+pub const FUNCFLAG_FREPLACEABLE: u16 = 0x800;
+/// `immediatebind`.
+pub const FUNCFLAG_FIMMEDIATEBIND: u16 = 0x1000;
+
+// PARAMFLAGS
+/// `in`.
+pub const PARAMFLAG_FIN: u16 = 0x1;
+/// `out`.
+pub const PARAMFLAG_FOUT: u16 = 0x2;
+/// `lcid`: the locale of the call.
+pub const PARAMFLAG_FLCID: u16 = 0x4;
+/// `retval`: the return value of the method.
+pub const PARAMFLAG_FRETVAL: u16 = 0x8;
+/// `optional`.
+pub const PARAMFLAG_FOPT: u16 = 0x10;
+/// `defaultvalue`: the parameter has a default.
+pub const PARAMFLAG_FHASDEFAULT: u16 = 0x20;
+
+// IMPLTYPEFLAGS
+/// `default`: the default interface of the coclass.
+pub const IMPLTYPEFLAG_FDEFAULT: i32 = 0x1;
+/// `source`: an interface of events.
+pub const IMPLTYPEFLAG_FSOURCE: i32 = 0x2;
+/// `restricted`.
+pub const IMPLTYPEFLAG_FRESTRICTED: i32 = 0x4;
+/// `defaultvtable`: the default vtable interface of a source.
+pub const IMPLTYPEFLAG_FDEFAULTVTABLE: i32 = 0x8;
+
+// VARENUM
+/// No value.
+pub const VT_EMPTY: u16 = 0;
+/// SQL-style null.
+pub const VT_NULL: u16 = 1;
+/// `short`.
+pub const VT_I2: u16 = 2;
+/// `long`.
+pub const VT_I4: u16 = 3;
+/// `float`.
+pub const VT_R4: u16 = 4;
+/// `double`.
+pub const VT_R8: u16 = 5;
+/// `CURRENCY`.
+pub const VT_CY: u16 = 6;
+/// `DATE`.
+pub const VT_DATE: u16 = 7;
+/// `BSTR`.
+pub const VT_BSTR: u16 = 8;
+/// `IDispatch*`.
+pub const VT_DISPATCH: u16 = 9;
+/// `SCODE`.
+pub const VT_ERROR: u16 = 10;
+/// `VARIANT_BOOL`.
+pub const VT_BOOL: u16 = 11;
+/// `VARIANT`.
+pub const VT_VARIANT: u16 = 12;
+/// `IUnknown*`.
+pub const VT_UNKNOWN: u16 = 13;
+/// `DECIMAL`.
+pub const VT_DECIMAL: u16 = 14;
+/// `char`.
+pub const VT_I1: u16 = 16;
+/// `unsigned char`.
+pub const VT_UI1: u16 = 17;
+/// `unsigned short`.
+pub const VT_UI2: u16 = 18;
+/// `unsigned long`.
+pub const VT_UI4: u16 = 19;
+/// `int64`.
+pub const VT_I8: u16 = 20;
+/// `uint64`.
+pub const VT_UI8: u16 = 21;
+/// `int`.
+pub const VT_INT: u16 = 22;
+/// `unsigned int`.
+pub const VT_UINT: u16 = 23;
+/// `void`.
+pub const VT_VOID: u16 = 24;
+/// `HRESULT`.
+pub const VT_HRESULT: u16 = 25;
+/// A pointer.
+pub const VT_PTR: u16 = 26;
+/// A `SAFEARRAY`.
+pub const VT_SAFEARRAY: u16 = 27;
+/// A fixed-size C array.
+pub const VT_CARRAY: u16 = 28;
+/// A type defined by a library.
+pub const VT_USERDEFINED: u16 = 29;
+/// `LPSTR`, a null-terminated ANSI string.
+pub const VT_LPSTR: u16 = 30;
+/// `LPWSTR`, a null-terminated wide string.
+pub const VT_LPWSTR: u16 = 31;
+/// A user-defined record in a `VARIANT`.
+pub const VT_RECORD: u16 = 36;
+/// `INT_PTR`.
+pub const VT_INT_PTR: u16 = 37;
+/// `UINT_PTR`.
+pub const VT_UINT_PTR: u16 = 38;
+/// `FILETIME`.
+pub const VT_FILETIME: u16 = 64;
+/// A length-prefixed block of bytes.
+pub const VT_BLOB: u16 = 65;
+/// `IStream*`.
+pub const VT_STREAM: u16 = 66;
+/// `IStorage*`.
+pub const VT_STORAGE: u16 = 67;
+/// An object serialized to a stream.
+pub const VT_STREAMED_OBJECT: u16 = 68;
+/// An object serialized to a storage.
+pub const VT_STORED_OBJECT: u16 = 69;
+/// An object serialized to a blob.
+pub const VT_BLOB_OBJECT: u16 = 70;
+/// Clipboard format.
+pub const VT_CF: u16 = 71;
+/// `CLSID`.
+pub const VT_CLSID: u16 = 72;
+/// Flag: a counted array of the type.
+pub const VT_VECTOR: u16 = 0x1000;
+/// Flag: a `SAFEARRAY` of the type.
+pub const VT_ARRAY: u16 = 0x2000;
+/// Flag: a reference to the type.
+pub const VT_BYREF: u16 = 0x4000;
+/// Reserved flag.
+pub const VT_RESERVED: u16 = 0x8000;
+/// Mask of the type without its flags.
+pub const VT_TYPEMASK: u16 = 0xfff;
+
+/// A GUID, laid out like the Windows `GUID`; the default is the nil GUID.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Default, PartialOrd, Ord)]
+pub struct Guid {
+    /// The first 8 hex digits.
+    pub data1: u32,
+    /// The next 4.
+    pub data2: u16,
+    /// The next 4.
+    pub data3: u16,
+    /// The last 16, as bytes in the order they are written.
+    pub data4: [u8; 8],
+}
+
+impl Guid {
+    /// The GUID written as the hex number `v`.
+    pub const fn from_u128(v: u128) -> Self {
+        Guid {
+            data1: (v >> 96) as u32,
+            data2: (v >> 80 & 0xffff) as u16,
+            data3: (v >> 64 & 0xffff) as u16,
+            data4: (v as u64).to_be_bytes(),
+        }
+    }
+
+    /// The GUID stored as `bytes`, with `data1` to `data3` little-endian.
+    pub fn from_le_bytes(bytes: [u8; 16]) -> Self {
+        Guid {
+            data1: u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            data2: u16::from_le_bytes([bytes[4], bytes[5]]),
+            data3: u16::from_le_bytes([bytes[6], bytes[7]]),
+            data4: [
+                bytes[8], bytes[9], bytes[10], bytes[11], bytes[12], bytes[13], bytes[14],
+                bytes[15],
+            ],
+        }
+    }
+
+    /// Whether every byte is zero.
+    pub fn is_nil(&self) -> bool {
+        *self == Guid::default()
+    }
+
+    /// Parses the registry form, `5A1E0A00-0001-4B1D-9C2E-0123456789AB`.
+    pub fn parse(s: &str) -> Option<Self> {
+        let hex: String = s.chars().filter(|&c| c != '-').collect();
+        if hex.len() != 32 {
+            return None;
+        }
+        u128::from_str_radix(&hex, 16).ok().map(Guid::from_u128)
+    }
+}
+
+impl fmt::Display for Guid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:08X}-{:04X}-{:04X}-{:02X}{:02X}-{:02X}{:02X}{:02X}{:02X}{:02X}{:02X}",
+            self.data1,
+            self.data2,
+            self.data3,
+            self.data4[0],
+            self.data4[1],
+            self.data4[2],
+            self.data4[3],
+            self.data4[4],
+            self.data4[5],
+            self.data4[6],
+            self.data4[7]
+        )
+    }
+}
+
+impl fmt::Debug for Guid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+pub const IID_IUNKNOWN: Guid = Guid::from_u128(0x00000000_0000_0000_c000_000000000046);
+pub const IID_IDISPATCH: Guid = Guid::from_u128(0x00020400_0000_0000_c000_000000000046);
+
+/// A decoded VARIANT constant (enum values, module constants, parameter
+/// defaults and custom data).
+#[derive(Debug, Clone, PartialEq)]
+pub enum Variant {
+    /// `VT_EMPTY`.
+    Empty,
+    /// `VT_NULL`.
+    Null,
+    /// `VT_I1`.
+    I1(i8),
+    /// `VT_I2`.
+    I2(i16),
+    /// `VT_I4`.
+    I4(i32),
+    /// `VT_I8`.
+    I8(i64),
+    /// `VT_UI1`.
+    UI1(u8),
+    /// `VT_UI2`.
+    UI2(u16),
+    /// `VT_UI4`.
+    UI4(u32),
+    /// `VT_UI8`.
+    UI8(u64),
+    /// `VT_INT`.
+    Int(i32),
+    /// `VT_UINT`.
+    UInt(u32),
+    /// `VT_R4`.
+    R4(f32),
+    /// `VT_R8`.
+    R8(f64),
+    /// Currency, scaled by 10 000.
+    Cy(i64),
+    /// `VT_DATE`, days since 30 December 1899.
+    Date(f64),
+    /// `VT_BOOL`.
+    Bool(bool),
+    /// `VT_BSTR`.
+    Bstr(String),
+    /// `VT_ERROR`, an `SCODE`.
+    Error(i32),
+    /// `VT_DECIMAL`: the value, unscaled, and the number of decimal places.
+    Decimal(i128, u8),
+    /// A variant type the readers do not decode.
+    Unsupported(u16),
+}
+
+impl Variant {
+    /// The `VT_*` code of the value.
+    pub fn vt(&self) -> u16 {
+        match self {
+            Variant::Empty => VT_EMPTY,
+            Variant::Null => VT_NULL,
+            Variant::I1(_) => VT_I1,
+            Variant::I2(_) => VT_I2,
+            Variant::I4(_) => VT_I4,
+            Variant::I8(_) => VT_I8,
+            Variant::UI1(_) => VT_UI1,
+            Variant::UI2(_) => VT_UI2,
+            Variant::UI4(_) => VT_UI4,
+            Variant::UI8(_) => VT_UI8,
+            Variant::Int(_) => VT_INT,
+            Variant::UInt(_) => VT_UINT,
+            Variant::R4(_) => VT_R4,
+            Variant::R8(_) => VT_R8,
+            Variant::Cy(_) => VT_CY,
+            Variant::Date(_) => VT_DATE,
+            Variant::Bool(_) => VT_BOOL,
+            Variant::Bstr(_) => VT_BSTR,
+            Variant::Error(_) => VT_ERROR,
+            Variant::Decimal(..) => VT_DECIMAL,
+            Variant::Unsupported(vt) => *vt,
+        }
+    }
+
+    /// Reads the value the way OLE code reads `V_I4`, i.e. the low 32 bits.
+    pub fn as_i32(&self) -> i32 {
+        match self {
+            Variant::I1(v) => *v as i32,
+            Variant::I2(v) => *v as i32,
+            Variant::I4(v) | Variant::Int(v) | Variant::Error(v) => *v,
+            Variant::I8(v) | Variant::Cy(v) => *v as i32,
+            Variant::UI1(v) => *v as i32,
+            Variant::UI2(v) => *v as i32,
+            Variant::UI4(v) | Variant::UInt(v) => *v as i32,
+            Variant::UI8(v) => *v as i32,
+            Variant::Bool(true) => -1,
+            _ => 0,
+        }
+    }
+
+    /// The value of an integral variant, sign- or zero-extended.
+    pub fn as_i128(&self) -> Option<i128> {
+        Some(match self {
+            Variant::I1(v) => *v as i128,
+            Variant::I2(v) => *v as i128,
+            Variant::I4(v) | Variant::Int(v) | Variant::Error(v) => *v as i128,
+            Variant::I8(v) => *v as i128,
+            Variant::UI1(v) => *v as i128,
+            Variant::UI2(v) => *v as i128,
+            Variant::UI4(v) | Variant::UInt(v) => *v as i128,
+            Variant::UI8(v) => *v as i128,
+            _ => return None,
+        })
+    }
+
+    /// An integral value converted to the numeric type `vt`, truncating like
+    /// a C cast. `None` when either side is not a plain number.
+    pub fn convert(&self, vt: u16) -> Option<Variant> {
+        let v = self.as_i128()?;
+        Some(match vt {
+            VT_I1 => Variant::I1(v as i8),
+            VT_I2 => Variant::I2(v as i16),
+            VT_I4 => Variant::I4(v as i32),
+            VT_I8 => Variant::I8(v as i64),
+            VT_UI1 => Variant::UI1(v as u8),
+            VT_UI2 => Variant::UI2(v as u16),
+            VT_UI4 => Variant::UI4(v as u32),
+            VT_UI8 => Variant::UI8(v as u64),
+            VT_INT => Variant::Int(v as i32),
+            VT_UINT => Variant::UInt(v as u32),
+            VT_ERROR | VT_HRESULT => Variant::Error(v as i32),
+            VT_R4 => Variant::R4(v as f32),
+            VT_R8 => Variant::R8(v as f64),
+            _ => return None,
+        })
+    }
+}
+
+/// A `custom(guid, value)` attribute.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CustData {
+    /// The GUID naming the attribute.
+    pub guid: Guid,
+    /// Its value.
+    pub value: Variant,
+}
+
+/// A type description; `UserDefined` refers to another type through its
+/// `HRefType`, resolved with [`TypeLib::resolve_ref`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum TypeDesc {
+    Base(u16),
+    Ptr(Box<TypeDesc>),
+    SafeArray(Box<TypeDesc>),
+    CArray {
+        elem: Box<TypeDesc>,
+        /// `(cElements, lLbound)` per dimension.
+        bounds: Vec<(u32, i32)>,
+    },
+    UserDefined(HRefType),
+}
+
+impl TypeDesc {
+    pub fn vt(&self) -> u16 {
+        match self {
+            TypeDesc::Base(vt) => *vt,
+            TypeDesc::Ptr(_) => VT_PTR,
+            TypeDesc::SafeArray(_) => VT_SAFEARRAY,
+            TypeDesc::CArray { .. } => VT_CARRAY,
+            TypeDesc::UserDefined(_) => VT_USERDEFINED,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct LibAttr {
+    pub guid: Guid,
+    pub lcid: u32,
+    pub syskind: u32,
+    pub major_ver: u16,
+    pub minor_ver: u16,
+    pub flags: u16,
+}
+
+#[derive(Debug, Clone)]
+pub struct TypeAttr {
+    pub guid: Guid,
+    pub typekind: u32,
+    pub flags: u16,
+    pub major_ver: u16,
+    pub minor_ver: u16,
+    pub cb_size_instance: u32,
+    pub cb_size_vft: u16,
+    pub cb_alignment: u16,
+    pub tdesc_alias: Option<TypeDesc>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ParamDesc {
+    pub name: Option<String>,
+    pub tdesc: TypeDesc,
+    pub flags: u16,
+    pub default_value: Option<Variant>,
+    pub custom_data: Vec<CustData>,
+}
+
+/// Where a module function lives in its DLL.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DllEntry {
+    /// The name of the export.
+    Name(String),
+    /// The ordinal of the export.
+    Ordinal(u16),
+}
+
+#[derive(Debug, Clone)]
+pub struct FuncDesc {
+    pub memid: i32,
+    pub name: String,
+    pub doc_string: String,
+    pub help_context: u32,
+    pub help_string_context: u32,
+    pub funckind: u32,
+    pub invkind: u32,
+    pub callconv: u32,
+    pub func_flags: u16,
+    pub ovft: i16,
+    pub params: Vec<ParamDesc>,
+    pub c_params_opt: i16,
+    pub ret: TypeDesc,
+    pub entry: Option<DllEntry>,
+    pub custom_data: Vec<CustData>,
+}
+
+#[derive(Debug, Clone)]
+pub struct VarDesc {
+    pub memid: i32,
+    pub name: String,
+    pub doc_string: String,
+    pub help_context: u32,
+    pub help_string_context: u32,
+    pub varkind: u32,
+    pub var_flags: u16,
+    pub tdesc: TypeDesc,
+    /// Set for `VAR_CONST` members.
+    pub value: Option<Variant>,
+    /// Byte offset for `VAR_PERINSTANCE` members.
+    pub oinst: u32,
+    pub custom_data: Vec<CustData>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ImplType {
+    pub href: HRefType,
+    pub flags: i32,
+    pub custom_data: Vec<CustData>,
+}
+
+#[derive(Debug, Clone)]
+pub struct TypeInfo {
+    pub name: String,
+    pub doc_string: String,
+    pub help_context: u32,
+    pub help_string_context: u32,
+    pub attr: TypeAttr,
+    pub funcs: Vec<FuncDesc>,
+    pub vars: Vec<VarDesc>,
+    pub impl_types: Vec<ImplType>,
+    pub dll_name: Option<String>,
+    pub custom_data: Vec<CustData>,
+}
+
+/// A type library referenced through `importlib`.
+#[derive(Debug, Clone)]
+pub struct ImportLib {
+    pub guid: Guid,
+    pub lcid: u32,
+    pub major_ver: u16,
+    pub minor_ver: u16,
+    pub file_name: String,
+}
+
+/// What an `HRefType` points at.
+#[derive(Debug, Clone)]
+pub enum TypeRef {
+    /// A typeinfo of this library, by index.
+    Local(usize),
+    /// A typeinfo of an imported library, by GUID or by its position there.
+    /// Only well-known types come with a name.
+    External {
+        import: usize,
+        guid: Option<Guid>,
+        index: Option<u32>,
+        name: Option<String>,
+        typekind: Option<u32>,
+    },
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TypeLib {
+    pub attr: LibAttr,
+    pub name: String,
+    pub doc_string: String,
+    pub help_context: u32,
+    pub help_file: String,
+    pub custom_data: Vec<CustData>,
+    pub type_infos: Vec<TypeInfo>,
+    pub imports: Vec<ImportLib>,
+    pub refs: std::collections::HashMap<HRefType, TypeRef>,
+}
+
+/// The parts of a referenced type the printers need, regardless of whether it
+/// lives in this library or an imported one.
+#[derive(Debug, Clone)]
+pub struct RefInfo {
+    /// Empty for an imported type the reference gives only by GUID or
+    /// position.
+    pub name: String,
+    pub guid: Option<Guid>,
+    pub typekind: Option<u32>,
+}
+
+impl TypeLib {
+    pub fn resolve_ref(&self, href: HRefType) -> Option<RefInfo> {
+        match self.refs.get(&href)? {
+            TypeRef::Local(index) => {
+                let ti = self.type_infos.get(*index)?;
+                Some(RefInfo {
+                    name: ti.name.clone(),
+                    guid: Some(ti.attr.guid),
+                    typekind: Some(ti.attr.typekind),
+                })
+            }
+            TypeRef::External {
+                guid,
+                name,
+                typekind,
+                ..
+            } => Some(RefInfo {
+                name: name.clone().unwrap_or_default(),
+                guid: *guid,
+                typekind: *typekind,
+            }),
+        }
+    }
+}
+
+/// Decodes a string stored by a type library reader.
+pub fn decode_ansi(bytes: &[u8]) -> String {
+    match std::str::from_utf8(bytes) {
+        Ok(s) => s.to_string(),
+        // Type libraries store strings in the ANSI code page; Latin-1 is the
+        // closest portable approximation.
+        Err(_) => bytes.iter().map(|&b| b as char).collect(),
+    }
+}
+
+/// Types of stdole2.tlb, which nearly every automation library imports.
+const WELL_KNOWN: &[(u128, &str, u32)] = &[
+    (0x00000000_0000_0000_c000_000000000046, "IUnknown", TKIND_INTERFACE),
+    (0x00020400_0000_0000_c000_000000000046, "IDispatch", TKIND_INTERFACE),
+    (0x00020404_0000_0000_c000_000000000046, "IEnumVARIANT", TKIND_INTERFACE),
+    (0xbef6e002_a874_101a_8bba_00aa00300cab, "IFont", TKIND_INTERFACE),
+    (0xbef6e003_a874_101a_8bba_00aa00300cab, "IFontDisp", TKIND_DISPATCH),
+    (0x7bf80980_bf32_101a_8bbb_00aa00300cab, "IPicture", TKIND_INTERFACE),
+    (0x7bf80981_bf32_101a_8bbb_00aa00300cab, "IPictureDisp", TKIND_DISPATCH),
+    (0x4ef6100a_af88_11d0_9846_00c04fc29993, "FontEvents", TKIND_DISPATCH),
+    (0x0be35203_8f91_11ce_9de3_00aa004bb851, "StdFont", TKIND_COCLASS),
+    (0x0be35204_8f91_11ce_9de3_00aa004bb851, "StdPicture", TKIND_COCLASS),
+    (0x66504301_be0f_101a_8bbb_00aa00300cab, "OLE_COLOR", TKIND_ALIAS),
+];
+
+/// A well-known type by GUID, so no system type library needs to be
+/// opened to name it.
+pub fn well_known_type(guid: &Guid) -> Option<(&'static str, u32)> {
+    WELL_KNOWN
+        .iter()
+        .find(|(g, _, _)| Guid::from_u128(*g) == *guid)
+        .map(|(_, name, kind)| (*name, *kind))
+}
+
+/// A well-known type by name: its GUID and `TYPEKIND`.
+pub fn well_known_type_by_name(name: &str) -> Option<(Guid, u32)> {
+    WELL_KNOWN
+        .iter()
+        .find(|(_, n, _)| *n == name)
+        .map(|(guid, _, kind)| (Guid::from_u128(*guid), *kind))
+}
@@ -1,4 +1,3 @@
-use crate::idlgen::{EnumItemInfo, MethodInfo, TypeLibInfo};
 use crossterm::{
     event::{
         self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEventKind, KeyModifiers,
@@ -17,7 +16,10 @@ use ratatui::{
         ScrollbarOrientation, ScrollbarState, Table, TableState, Wrap,
     },
 };
-use std::{error::Error, io, path::PathBuf};
+use std::{collections::HashMap, error::Error, io, path::PathBuf};
+use tlb_winmd_gen::chm::Chm;
+use tlb_winmd_gen::config::Config;
+use tlb_winmd_gen::idlgen::{EnumItemInfo, MethodInfo, TypeLibInfo};
 
 #[derive(PartialEq)]
 enum ViewMode {
@@ -75,12 +77,28 @@ struct App {
     global_search_results: Vec<usize>, // Indices into all_search_items
     global_search_state: ListState,
     global_search_scroll_state: ScrollbarState, // Scrollbar for Global Search
+    // CHM help
+    help: Option<Chm>,
+    help_topics: HashMap<u32, String>, // Rendered topics by help context
+    show_help: bool,
+    help_title: String,
+    help_text: String,
+    help_scroll_offset: u16,
 }
 
 impl App {
-    fn new(tlb_path: PathBuf) -> Result<Self, Box<dyn Error>> {
+    fn new(
+        tlb_path: PathBuf,
+        resource: Option<u16>,
+        chm_path: Option<PathBuf>,
+        config: &Config,
+    ) -> Result<Self, Box<dyn Error>> {
         let mut type_lib_info = TypeLibInfo::new();
-        type_lib_info.load_type_lib(&tlb_path)?;
+        type_lib_info.load_type_lib(&tlb_path, resource, config)?;
+        let help = match chm_path {
+            Some(path) => Some(Chm::open(&path)?),
+            None => None,
+        };
 
         let count = type_lib_info.get_type_info_count();
         let mut types = Vec::new();
@@ -152,6 +170,12 @@ impl App {
             global_search_results: Vec::new(),
             global_search_state: ListState::default(),
             global_search_scroll_state: ScrollbarState::default(),
+            help,
+            help_topics: HashMap::new(),
+            show_help: false,
+            help_title: String::new(),
+            help_text: String::new(),
+            help_scroll_offset: 0,
         };
         app.update_filter();
         Ok(app)
@@ -183,38 +207,38 @@ impl App {
     }
 
     fn update_selection(&mut self) {
-        if let Some(selected_idx) = self.list_state.selected() {
-            if let Some((original_idx, _, _)) = self.filtered_types.get(selected_idx) {
-                if let Ok(idl) = self.type_lib_info.get_type_idl(*original_idx as u32) {
-                    self.current_idl = idl;
-                }
-                if let Ok(methods) = self.type_lib_info.get_type_methods(*original_idx as u32) {
-                    self.current_methods = methods;
-                } else {
-                    self.current_methods.clear();
-                }
-                if let Ok(enums) = self.type_lib_info.get_type_enums(*original_idx as u32) {
-                    self.current_enums = enums;
-                } else {
-                    self.current_enums.clear();
-                }
+        if let Some(selected_idx) = self.list_state.selected()
+            && let Some((original_idx, _, _)) = self.filtered_types.get(selected_idx)
+        {
+            if let Ok(idl) = self.type_lib_info.get_type_idl(*original_idx as u32) {
+                self.current_idl = idl;
+            }
+            if let Ok(methods) = self.type_lib_info.get_type_methods(*original_idx as u32) {
+                self.current_methods = methods;
+            } else {
+                self.current_methods.clear();
+            }
+            if let Ok(enums) = self.type_lib_info.get_type_enums(*original_idx as u32) {
+                self.current_enums = enums;
+            } else {
+                self.current_enums.clear();
+            }
 
-                // Reset content selection and scroll
-                self.method_list_state.select(None);
-                self.method_list_scroll_state = ScrollbarState::default();
-                self.details_scroll_offset = 0;
-                self.details_scroll_state = ScrollbarState::default();
-                self.idl_scroll_offset = 0;
-                self.idl_scroll_state = ScrollbarState::default();
+            // Reset content selection and scroll
+            self.method_list_state.select(None);
+            self.method_list_scroll_state = ScrollbarState::default();
+            self.details_scroll_offset = 0;
+            self.details_scroll_state = ScrollbarState::default();
+            self.idl_scroll_offset = 0;
+            self.idl_scroll_state = ScrollbarState::default();
 
-                self.content_table_state.select(None);
-                self.content_scroll_state = ScrollbarState::default();
+            self.content_table_state.select(None);
+            self.content_scroll_state = ScrollbarState::default();
 
-                if !self.current_methods.is_empty() {
-                    self.method_list_state.select(Some(0));
-                } else if !self.current_enums.is_empty() {
-                    self.content_table_state.select(Some(0));
-                }
+            if !self.current_methods.is_empty() {
+                self.method_list_state.select(Some(0));
+            } else if !self.current_enums.is_empty() {
+                self.content_table_state.select(Some(0));
             }
         }
     }
@@ -420,66 +444,171 @@ impl App {
     }
 
     fn select_global_result(&mut self) {
-        if let Some(selected_idx) = self.global_search_state.selected() {
-            if let Some(&item_idx) = self.global_search_results.get(selected_idx) {
-                let (type_index, member_name, kind) =
-                    if let Some(item) = self.all_search_items.get(item_idx) {
-                        (item.type_index, item.member_name.clone(), item.kind.clone())
-                    } else {
-                        return;
-                    };
+        if let Some(selected_idx) = self.global_search_state.selected()
+            && let Some(&item_idx) = self.global_search_results.get(selected_idx)
+        {
+            let (type_index, member_name, kind) =
+                if let Some(item) = self.all_search_items.get(item_idx) {
+                    (item.type_index, item.member_name.clone(), item.kind.clone())
+                } else {
+                    return;
+                };
 
-                self.show_global_search = false;
-                self.search_query.clear();
-                self.update_filter();
+            self.show_global_search = false;
+            self.search_query.clear();
+            self.update_filter();
 
-                if let Some(pos) = self
-                    .filtered_types
-                    .iter()
-                    .position(|(idx, _, _)| *idx == type_index)
-                {
-                    self.list_state.select(Some(pos));
-                    self.update_selection();
-                }
+            if let Some(pos) = self
+                .filtered_types
+                .iter()
+                .position(|(idx, _, _)| *idx == type_index)
+            {
+                self.list_state.select(Some(pos));
+                self.update_selection();
+            }
 
-                // If it's a method or enum value, select it in the content table
-                if kind == "Method" || kind == "EnumValue" {
-                    self.member_search_query = member_name.clone();
-                    self.search_target = SearchTarget::Members; // Switch focus to member search so user can see/clear it
-
-                    // Need to find the index of the member in the current list
-                    let member_query = member_name.to_lowercase();
-                    if !self.current_methods.is_empty() {
-                        if let Some(pos) = self
-                            .current_methods
-                            .iter()
-                            .position(|m| m.name.to_lowercase() == member_query)
-                        {
-                            self.method_list_state.select(Some(pos));
-                        }
-                    } else if !self.current_enums.is_empty() {
-                        if let Some(pos) = self
-                            .current_enums
-                            .iter()
-                            .position(|e| e.name.to_lowercase() == member_query)
-                        {
-                            self.content_table_state.select(Some(pos));
-                        }
+            // If it's a method or enum value, select it in the content table
+            if kind == "Method" || kind == "EnumValue" {
+                self.member_search_query = member_name.clone();
+                self.search_target = SearchTarget::Members; // Switch focus to member search so user can see/clear it
+
+                // Need to find the index of the member in the current list
+                let member_query = member_name.to_lowercase();
+                if !self.current_methods.is_empty() {
+                    if let Some(pos) = self
+                        .current_methods
+                        .iter()
+                        .position(|m| m.name.to_lowercase() == member_query)
+                    {
+                        self.method_list_state.select(Some(pos));
                     }
-                } else {
-                    // It's a type (Interface, Enum, Dispatch, etc.)
-                    // We already selected the type in the left panel.
-                    // Just ensure we are focusing on the type list and clear member search
-                    self.member_search_query.clear();
-                    self.search_target = SearchTarget::Types;
-                    self.focus = Focus::TypeList;
+                } else if !self.current_enums.is_empty()
+                    && let Some(pos) = self
+                        .current_enums
+                        .iter()
+                        .position(|e| e.name.to_lowercase() == member_query)
+                {
+                    self.content_table_state.select(Some(pos));
                 }
+            } else {
+                // It's a type (Interface, Enum, Dispatch, etc.)
+                // We already selected the type in the left panel.
+                // Just ensure we are focusing on the type list and clear member search
+                self.member_search_query.clear();
+                self.search_target = SearchTarget::Types;
+                self.focus = Focus::TypeList;
             }
         }
     }
+
+    fn selected_method(&self) -> Option<&MethodInfo> {
+        let selected = self.method_list_state.selected()?;
+        filter_methods(&self.current_methods, &self.member_search_query)
+            .get(selected)
+            .copied()
+    }
+
+    /// Text of the CHM topic mapped to `help_context`, rendered once and
+    /// cached. `None` when no help file is loaded.
+    fn help_topic(&mut self, help_context: u32) -> Option<&str> {
+        let chm = self.help.as_ref()?;
+        let text = self.help_topics.entry(help_context).or_insert_with(|| {
+            match chm.topic_text(help_context) {
+                Ok(Some(text)) => text,
+                Ok(None) => format!("No help topic is mapped to context {}.", help_context),
+                Err(e) => format!("Failed to read the help topic: {}", e),
+            }
+        });
+        Some(text.as_str())
+    }
+
+    /// Opens the help popup for the selected member, or for the selected
+    /// type when the member has no help context of its own.
+    fn open_help(&mut self) {
+        let Some((type_index, type_name, _)) = self
+            .list_state
+            .selected()
+            .and_then(|i| self.filtered_types.get(i))
+            .cloned()
+        else {
+            return;
+        };
+        let member = if self.focus == Focus::TypeList {
+            None
+        } else if !self.current_methods.is_empty() {
+            self.selected_method()
+                .map(|m| (m.name.clone(), m.help_context))
+        } else {
+            self.content_table_state
+                .selected()
+                .and_then(|i| {
+                    filter_enums(&self.current_enums, &self.member_search_query)
+                        .get(i)
+                        .copied()
+                })
+                .map(|e| (e.name.clone(), e.help_context))
+        };
+        let (title, help_context) = match member {
+            Some((name, context)) if context != 0 => (format!("{}.{}", type_name, name), context),
+            _ => (
+                type_name.clone(),
+                self.type_lib_info
+                    .get_type_help_context(type_index as u32)
+                    .unwrap_or(0),
+            ),
+        };
+
+        self.help_text = if help_context == 0 {
+            format!("{} has no help context.", title)
+        } else {
+            match self.help_topic(help_context) {
+                Some(text) => text.to_string(),
+                None => format!(
+                    "Help context {}. Pass --chm <file> to show the help topic.",
+                    help_context
+                ),
+            }
+        };
+        self.help_title = match self
+            .help
+            .as_ref()
+            .and_then(|h| h.topic_for_context(help_context))
+        {
+            Some(path) => format!("{} - {}", title, path),
+            None => title,
+        };
+        self.help_scroll_offset = 0;
+        self.show_help = true;
+    }
+}
+
+/// Methods whose name contains the member search query.
+fn filter_methods<'a>(methods: &'a [MethodInfo], query: &str) -> Vec<&'a MethodInfo> {
+    let query = query.to_lowercase();
+    methods
+        .iter()
+        .filter(|m| m.name.to_lowercase().contains(&query))
+        .collect()
+}
+
+/// Enum values whose name contains the member search query.
+fn filter_enums<'a>(enums: &'a [EnumItemInfo], query: &str) -> Vec<&'a EnumItemInfo> {
+    let query = query.to_lowercase();
+    enums
+        .iter()
+        .filter(|e| e.name.to_lowercase().contains(&query))
+        .collect()
 }
 
-pub fn run(tlb_path: PathBuf) -> Result<(), Box<dyn Error>> {
+pub fn run(
+    tlb_path: PathBuf,
+    resource: Option<u16>,
+    chm_path: Option<PathBuf>,
+    config: &Config,
+) -> Result<(), Box<dyn Error>> {
+    // Create app before touching the terminal, so load errors print normally
+    let app = App::new(tlb_path, resource, chm_path, config)?;
+
     // Setup terminal
     enable_raw_mode()?;
     let mut stdout = io::stdout();
@@ -487,8 +616,6 @@ pub fn run(tlb_path: PathBuf) -> Result<(), Box<dyn Error>> {
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
 
-    // Create app
-    let app = App::new(tlb_path)?;
     let res = run_app(&mut terminal, app);
 
     // Restore terminal
@@ -511,88 +638,103 @@ fn run_app<B: Backend>(terminal: &mut Terminal<B>, mut app: App) -> io::Result<(
     loop {
         terminal.draw(|f| ui(f, &mut app))?;
 
-        if let Event::Key(key) = event::read()? {
-            if key.kind == KeyEventKind::Press {
-                if app.show_global_search {
-                    match key.code {
-                        KeyCode::Esc => app.show_global_search = false,
-                        KeyCode::Down => app.next_global_result(),
-                        KeyCode::Up => app.previous_global_result(),
-                        KeyCode::Enter => app.select_global_result(),
-                        KeyCode::Char(c) => {
-                            app.global_search_query.push(c);
-                            app.update_global_search();
+        if let Event::Key(key) = event::read()?
+            && key.kind == KeyEventKind::Press
+        {
+            if app.show_help {
+                match key.code {
+                    KeyCode::Esc | KeyCode::F(1) => app.show_help = false,
+                    KeyCode::Down => {
+                        app.help_scroll_offset = app.help_scroll_offset.saturating_add(1)
+                    }
+                    KeyCode::Up => {
+                        app.help_scroll_offset = app.help_scroll_offset.saturating_sub(1)
+                    }
+                    KeyCode::PageDown => {
+                        app.help_scroll_offset = app.help_scroll_offset.saturating_add(10)
+                    }
+                    KeyCode::PageUp => {
+                        app.help_scroll_offset = app.help_scroll_offset.saturating_sub(10)
+                    }
+                    _ => {}
+                }
+            } else if app.show_global_search {
+                match key.code {
+                    KeyCode::Esc => app.show_global_search = false,
+                    KeyCode::Down => app.next_global_result(),
+                    KeyCode::Up => app.previous_global_result(),
+                    KeyCode::Enter => app.select_global_result(),
+                    KeyCode::Char(c) => {
+                        app.global_search_query.push(c);
+                        app.update_global_search();
+                    }
+                    KeyCode::Backspace => {
+                        app.global_search_query.pop();
+                        app.update_global_search();
+                    }
+                    _ => {}
+                }
+            } else {
+                match key.code {
+                    KeyCode::Char('q')
+                        if app.search_query.is_empty() && app.member_search_query.is_empty() =>
+                    {
+                        return Ok(());
+                    }
+                    KeyCode::Esc => return Ok(()),
+                    KeyCode::Down => app.next(),
+                    KeyCode::Up => app.previous(),
+                    KeyCode::Right => match app.focus {
+                        Focus::TypeList => {
+                            if app.view_mode == ViewMode::Idl {
+                                app.focus = Focus::IdlView;
+                            } else {
+                                app.focus = Focus::MethodList;
+                            }
                         }
-                        KeyCode::Backspace => {
-                            app.global_search_query.pop();
-                            app.update_global_search();
+                        Focus::MethodList if !app.current_methods.is_empty() => {
+                            app.focus = Focus::Details;
                         }
                         _ => {}
+                    },
+                    KeyCode::Left => match app.focus {
+                        Focus::Details => app.focus = Focus::MethodList,
+                        Focus::MethodList => app.focus = Focus::TypeList,
+                        Focus::IdlView => app.focus = Focus::TypeList,
+                        _ => {}
+                    },
+                    KeyCode::Tab | KeyCode::Char('v') => app.toggle_view(),
+                    KeyCode::F(1) => app.open_help(),
+                    KeyCode::Char('p') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                        app.show_global_search = true;
+                        app.global_search_query.clear();
+                        app.update_global_search();
                     }
-                } else {
-                    match key.code {
-                        KeyCode::Char('q')
-                            if app.search_query.is_empty()
-                                && app.member_search_query.is_empty() =>
-                        {
-                            return Ok(());
+                    KeyCode::Char('f') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                        app.toggle_search_target();
+                    }
+                    KeyCode::Enter => {
+                        // Enter key logic if needed, e.g. select global search result
+                    }
+                    KeyCode::Char(c) => match app.search_target {
+                        SearchTarget::Types => {
+                            app.search_query.push(c);
+                            app.update_filter();
                         }
-                        KeyCode::Esc => return Ok(()),
-                        KeyCode::Down => app.next(),
-                        KeyCode::Up => app.previous(),
-                        KeyCode::Right => match app.focus {
-                            Focus::TypeList => {
-                                if app.view_mode == ViewMode::Idl {
-                                    app.focus = Focus::IdlView;
-                                } else {
-                                    app.focus = Focus::MethodList;
-                                }
-                            }
-                            Focus::MethodList => {
-                                if !app.current_methods.is_empty() {
-                                    app.focus = Focus::Details;
-                                }
-                            }
-                            _ => {}
-                        },
-                        KeyCode::Left => match app.focus {
-                            Focus::Details => app.focus = Focus::MethodList,
-                            Focus::MethodList => app.focus = Focus::TypeList,
-                            Focus::IdlView => app.focus = Focus::TypeList,
-                            _ => {}
-                        },
-                        KeyCode::Tab | KeyCode::Char('v') => app.toggle_view(),
-                        KeyCode::Char('p') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                            app.show_global_search = true;
-                            app.global_search_query.clear();
-                            app.update_global_search();
+                        SearchTarget::Members => {
+                            app.member_search_query.push(c);
                         }
-                        KeyCode::Char('f') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                            app.toggle_search_target();
+                    },
+                    KeyCode::Backspace => match app.search_target {
+                        SearchTarget::Types => {
+                            app.search_query.pop();
+                            app.update_filter();
                         }
-                        KeyCode::Enter => {
-                            // Enter key logic if needed, e.g. select global search result
+                        SearchTarget::Members => {
+                            app.member_search_query.pop();
                         }
-                        KeyCode::Char(c) => match app.search_target {
-                            SearchTarget::Types => {
-                                app.search_query.push(c);
-                                app.update_filter();
-                            }
-                            SearchTarget::Members => {
-                                app.member_search_query.push(c);
-                            }
-                        },
-                        KeyCode::Backspace => match app.search_target {
-                            SearchTarget::Types => {
-                                app.search_query.pop();
-                                app.update_filter();
-                            }
-                            SearchTarget::Members => {
-                                app.member_search_query.pop();
-                            }
-                        },
-                        _ => {}
-                    }
+                    },
+                    _ => {}
                 }
             }
         }
@@ -655,6 +797,8 @@ fn ui(f: &mut ratatui::Frame, app: &mut App) {
         Span::raw("Switch Search "),
         Span::styled(" Ctrl+P ", Style::default().fg(Color::Cyan)),
         Span::raw("Global Search "),
+        Span::styled(" F1 ", Style::default().fg(Color::Cyan)),
+        Span::raw("Help "),
         Span::styled(" Esc ", Style::default().fg(Color::Cyan)),
         Span::raw("Exit "),
     ]);
@@ -746,6 +890,15 @@ fn ui(f: &mut ratatui::Frame, app: &mut App) {
         }
         ViewMode::Structured => {
             if !app.current_methods.is_empty() {
+                // Help for the selected method, rendered before the method
+                // list borrows the app
+                let help_context = app.selected_method().map_or(0, |m| m.help_context);
+                let help_text = if help_context != 0 {
+                    app.help_topic(help_context).map(str::to_string)
+                } else {
+                    None
+                };
+
                 // 3-Panel Layout for Methods
                 // Split right_area into Method List (Middle) and Details (Right)
                 let method_chunks = Layout::default()
@@ -754,12 +907,8 @@ fn ui(f: &mut ratatui::Frame, app: &mut App) {
                     .split(right_area);
 
                 // --- Middle Panel: Method List ---
-                let member_query = app.member_search_query.to_lowercase();
-                let filtered_methods: Vec<&MethodInfo> = app
-                    .current_methods
-                    .iter()
-                    .filter(|m| m.name.to_lowercase().contains(&member_query))
-                    .collect();
+                let filtered_methods =
+                    filter_methods(&app.current_methods, &app.member_search_query);
 
                 let method_items: Vec<ListItem> = filtered_methods
                     .iter()
@@ -819,97 +968,110 @@ fn ui(f: &mut ratatui::Frame, app: &mut App) {
                 let inner_details_area = details_block.inner(method_chunks[1]);
                 f.render_widget(details_block, method_chunks[1]);
 
-                if let Some(selected_idx) = app.method_list_state.selected() {
-                    if let Some(method) = filtered_methods.get(selected_idx) {
-                        // Render content inside details panel
-                        // We will render to a buffer or just render widgets vertically
-                        let details_layout = Layout::default()
-                            .direction(Direction::Vertical)
-                            .constraints([Constraint::Min(0)].as_ref())
-                            .split(inner_details_area);
-
-                        let mut lines = Vec::new();
-
-                        // 1. Signature
-                        lines.push(Line::from(vec![
-                            Span::styled("ƒ ", Style::default().fg(Color::Magenta)),
-                            Span::styled(
-                                &method.name,
-                                Style::default()
-                                    .fg(Color::White)
-                                    .add_modifier(Modifier::BOLD),
-                            ),
-                            Span::raw(" ("),
-                        ]));
-
-                        for param in &method.params {
-                            let mut param_spans = Vec::new();
-                            param_spans.push(Span::raw("    "));
-                            if param.flags.contains(&"in".to_string()) {
-                                param_spans
-                                    .push(Span::styled("↓ ", Style::default().fg(Color::Green)));
-                            }
-                            if param.flags.contains(&"out".to_string()) {
-                                param_spans
-                                    .push(Span::styled("↑ ", Style::default().fg(Color::Red)));
-                            }
-                            if let Some(default_val) = &param.default_value {
-                                param_spans.push(Span::styled(
-                                    format!("= {} ", default_val),
-                                    Style::default().fg(Color::Blue),
-                                ));
-                            } else if param.flags.contains(&"defaultvalue".to_string()) {
-                                param_spans
-                                    .push(Span::styled("* ", Style::default().fg(Color::Blue)));
-                            }
-                            if param.flags.contains(&"optional".to_string()) {
-                                param_spans
-                                    .push(Span::styled("? ", Style::default().fg(Color::Yellow)));
-                            }
+                if let Some(selected_idx) = app.method_list_state.selected()
+                    && let Some(method) = filtered_methods.get(selected_idx)
+                {
+                    // Render content inside details panel
+                    // We will render to a buffer or just render widgets vertically
+                    let details_layout = Layout::default()
+                        .direction(Direction::Vertical)
+                        .constraints([Constraint::Min(0)].as_ref())
+                        .split(inner_details_area);
+
+                    let mut lines = Vec::new();
+
+                    // 1. Signature
+                    lines.push(Line::from(vec![
+                        Span::styled("ƒ ", Style::default().fg(Color::Magenta)),
+                        Span::styled(
+                            &method.name,
+                            Style::default()
+                                .fg(Color::White)
+                                .add_modifier(Modifier::BOLD),
+                        ),
+                        Span::raw(" ("),
+                    ]));
+
+                    for param in &method.params {
+                        let mut param_spans = Vec::new();
+                        param_spans.push(Span::raw("    "));
+                        if param.flags.contains(&"in".to_string()) {
+                            param_spans.push(Span::styled("↓ ", Style::default().fg(Color::Green)));
+                        }
+                        if param.flags.contains(&"out".to_string()) {
+                            param_spans.push(Span::styled("↑ ", Style::default().fg(Color::Red)));
+                        }
+                        if let Some(default_val) = &param.default_value {
                             param_spans.push(Span::styled(
-                                format!("{} ", param.type_name),
-                                Style::default().fg(Color::White),
+                                format!("= {} ", default_val),
+                                Style::default().fg(Color::Blue),
                             ));
-                            param_spans.push(Span::raw(&param.name));
-                            param_spans.push(Span::raw(","));
-                            lines.push(Line::from(param_spans));
+                        } else if param.flags.contains(&"defaultvalue".to_string()) {
+                            param_spans.push(Span::styled("* ", Style::default().fg(Color::Blue)));
                         }
+                        if param.flags.contains(&"optional".to_string()) {
+                            param_spans
+                                .push(Span::styled("? ", Style::default().fg(Color::Yellow)));
+                        }
+                        param_spans.push(Span::styled(
+                            format!("{} ", param.type_name),
+                            Style::default().fg(Color::White),
+                        ));
+                        param_spans.push(Span::raw(&param.name));
+                        param_spans.push(Span::raw(","));
+                        lines.push(Line::from(param_spans));
+                    }
 
+                    lines.push(Line::from(vec![
+                        Span::raw("  ) -> "),
+                        Span::styled(&method.ret_type, Style::default().fg(Color::Green)),
+                    ]));
+                    lines.push(Line::from("")); // Spacer
+
+                    // 2. Attributes and help topic
+                    if !method.flags.is_empty() {
                         lines.push(Line::from(vec![
-                            Span::raw("  ) -> "),
-                            Span::styled(&method.ret_type, Style::default().fg(Color::Green)),
+                            Span::styled("Attributes ", Style::default().fg(Color::Yellow)),
+                            Span::raw(method.flags.join(", ")),
                         ]));
-                        lines.push(Line::from("")); // Spacer
-
-                        // Create a Paragraph with the lines
-                        // We need to handle scrolling for the details panel
-                        let total_lines = lines.len();
-                        let paragraph = Paragraph::new(lines)
-                            .wrap(Wrap { trim: false })
-                            .scroll((app.details_scroll_offset, 0));
-
-                        f.render_widget(paragraph, details_layout[0]);
-
-                        // Scrollbar for Details
-                        app.details_scroll_state =
-                            app.details_scroll_state.content_length(total_lines);
-                        app.details_scroll_state = app
-                            .details_scroll_state
-                            .position(app.details_scroll_offset as usize);
-
-                        f.render_stateful_widget(
-                            Scrollbar::default()
-                                .orientation(ScrollbarOrientation::VerticalRight)
-                                .begin_symbol(Some("↑"))
-                                .end_symbol(Some("↓")),
-                            details_layout[0],
-                            &mut app.details_scroll_state,
-                        );
                     }
+                    if method.help_context != 0 {
+                        lines.push(Line::from(vec![
+                            Span::styled("Help context ", Style::default().fg(Color::Yellow)),
+                            Span::raw(method.help_context.to_string()),
+                        ]));
+                    }
+                    if let Some(text) = &help_text {
+                        lines.push(Line::from(""));
+                        lines.extend(text.lines().map(|l| Line::from(l.to_string())));
+                    }
+
+                    // Create a Paragraph with the lines
+                    // We need to handle scrolling for the details panel
+                    let total_lines = lines.len();
+                    let paragraph = Paragraph::new(lines)
+                        .wrap(Wrap { trim: false })
+                        .scroll((app.details_scroll_offset, 0));
+
+                    f.render_widget(paragraph, details_layout[0]);
+
+                    // Scrollbar for Details
+                    app.details_scroll_state = app.details_scroll_state.content_length(total_lines);
+                    app.details_scroll_state = app
+                        .details_scroll_state
+                        .position(app.details_scroll_offset as usize);
+
+                    f.render_stateful_widget(
+                        Scrollbar::default()
+                            .orientation(ScrollbarOrientation::VerticalRight)
+                            .begin_symbol(Some("↑"))
+                            .end_symbol(Some("↓")),
+                        details_layout[0],
+                        &mut app.details_scroll_state,
+                    );
                 }
             } else if !app.current_enums.is_empty() {
                 // 2-Panel Layout for Enums (Existing logic, just ensured it fits)
-                let member_query = app.member_search_query.to_lowercase();
                 let header_cells = ["Name", "Value"]
                     .iter()
                     .map(|h| Cell::from(*h).style(Style::default().fg(Color::White)));
@@ -917,11 +1079,7 @@ fn ui(f: &mut ratatui::Frame, app: &mut App) {
                     .style(Style::default().bg(Color::Blue))
                     .height(1);
 
-                let filtered_enums: Vec<&EnumItemInfo> = app
-                    .current_enums
-                    .iter()
-                    .filter(|e| e.name.to_lowercase().contains(&member_query))
-                    .collect();
+                let filtered_enums = filter_enums(&app.current_enums, &app.member_search_query);
 
                 let rows = filtered_enums.iter().map(|item| {
                     Row::new(vec![
@@ -981,6 +1139,22 @@ fn ui(f: &mut ratatui::Frame, app: &mut App) {
         }
     }
 
+    // Help Popup
+    if app.show_help {
+        let area = centered_rect(70, 70, f.area());
+        f.render_widget(Clear, area);
+        let help_paragraph = Paragraph::new(app.help_text.as_str())
+            .block(
+                Block::default()
+                    .title(format!("Help: {} (Esc to close)", app.help_title))
+                    .borders(Borders::ALL)
+                    .style(Style::default().bg(Color::Black)),
+            )
+            .wrap(Wrap { trim: false })
+            .scroll((app.help_scroll_offset, 0));
+        f.render_widget(help_paragraph, area);
+    }
+
     // Global Search Popup (Keep this)
     if app.show_global_search {
         let area = centered_rect(60, 50, f.area());
@@ -0,0 +1,609 @@
+//! Round-trip verification of the IDL decompiler.
+//!
+//! The library is printed with [`idlgen`](crate::idlgen), the IDL is read
+//! back with [`idl`](crate::idl), and the two libraries are compared
+//! member by member: GUIDs, DISPIDs, flags, vtable order, parameter types
+//! and flags, default values, enum values and record layouts. Every
+//! difference is something the IDL does not carry faithfully, so that
+//! compiling it with MIDL would not give back the same type library.
+//!
+//! The IDL spells some things differently without changing them: a pure
+//! dispinterface's properties and methods are written as the
+//! `propget`/`propput` methods of an interface, and `FUNC_DISPATCH`
+//! members return an HRESULT. Signatures are compared in the dispatch
+//! shape when either side has it, so only the kind and flags of such a
+//! type are reported.
+
+use std::collections::BTreeSet;
+use std::io::Write;
+
+use crate::diagnostics::Diagnostics;
+use crate::error::Error;
+use crate::idlgen::{
+    dispatch_properties, function_flags, print_library, type_to_string, variant_to_string,
+};
+use crate::jsongen::Json;
+use crate::model::*;
+use crate::typelib::*;
+
+/// Version of the `--format json` layout.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Something of a library its IDL does not read back the same.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Discrepancy {
+    /// `Library`, `Type`, `Type.Member` or `Type.Member(param)`; properties
+    /// are named `Member[propget]` and the like.
+    pub path: String,
+    /// How the read-back item differs.
+    pub message: String,
+}
+
+/// Prints `lib` as IDL, parses it back and compares the result with `lib`.
+/// IDL that does not parse is an [`Error::InvalidIdl`].
+pub fn verify_library(lib: &Library) -> Result<Vec<Discrepancy>, Error> {
+    let mut idl = Vec::new();
+    print_library(lib, &mut idl, &mut Diagnostics::new()).map_err(Error::IoError)?;
+    let parsed = crate::idl::parse(&String::from_utf8_lossy(&idl))?;
+    Ok(compare(lib, &parsed))
+}
+
+/// What differs between `lib` and `parsed`, the library read back from its
+/// IDL, in the order of `lib`.
+pub fn compare(lib: &Library, parsed: &Library) -> Vec<Discrepancy> {
+    let mut c = Comparer::default();
+    let path = lib.name.as_str();
+    c.check(path, "library name", &lib.name, &parsed.name);
+    c.check(path, "library GUID", lib.guid, parsed.guid);
+    c.check(
+        path,
+        "library version",
+        version(lib.version),
+        version(parsed.version),
+    );
+    c.check(
+        path,
+        "LCID",
+        format!("{:#x}", lib.lcid),
+        format!("{:#x}", parsed.lcid),
+    );
+    c.check(path, "help file", &lib.help_file, &parsed.help_file);
+    c.doc(path, &lib.doc, &parsed.doc);
+    c.custom_data(path, &lib.custom_data, &parsed.custom_data);
+    let imports = |lib: &Library| {
+        let names: BTreeSet<_> = lib.imports.iter().map(|i| i.file_name.as_str()).collect();
+        list(names)
+    };
+    c.check(path, "imports", imports(lib), imports(parsed));
+
+    // IUnknown and IDispatch are printed as bases only.
+    let printed = |ty: &&TypeDef| ty.guid != IID_IUNKNOWN && ty.guid != IID_IDISPATCH;
+    for ty in lib.types.iter().filter(printed) {
+        match parsed.find(&ty.name) {
+            Some(other) => c.type_def(ty, other),
+            None => c.report(
+                &ty.name,
+                format!("{} is missing from the IDL", ty.kind.name()),
+            ),
+        }
+    }
+    for ty in parsed.types.iter().filter(printed) {
+        if lib.find(&ty.name).is_none() {
+            c.report(
+                &ty.name,
+                format!("{} in the IDL is not in the library", ty.kind.name()),
+            );
+        }
+    }
+    c.found
+}
+
+#[derive(Default)]
+struct Comparer {
+    found: Vec<Discrepancy>,
+}
+
+impl Comparer {
+    fn report(&mut self, path: &str, message: String) {
+        self.found.push(Discrepancy {
+            path: path.to_string(),
+            message,
+        });
+    }
+
+    fn check<T>(&mut self, path: &str, what: &str, expected: T, actual: T)
+    where
+        T: PartialEq + std::fmt::Display,
+    {
+        if expected != actual {
+            self.report(
+                path,
+                format!(
+                    "{} is {} in the library but {} in the IDL",
+                    what, expected, actual
+                ),
+            );
+        }
+    }
+
+    fn doc(&mut self, path: &str, expected: &Doc, actual: &Doc) {
+        self.check(
+            path,
+            "help string",
+            quoted(&expected.help_string),
+            quoted(&actual.help_string),
+        );
+        self.check(
+            path,
+            "help context",
+            expected.help_context,
+            actual.help_context,
+        );
+        self.check(
+            path,
+            "help string context",
+            expected.help_string_context,
+            actual.help_string_context,
+        );
+    }
+
+    fn custom_data(&mut self, path: &str, expected: &[CustData], actual: &[CustData]) {
+        let render = |data: &[CustData]| {
+            list(
+                data.iter()
+                    .map(|d| format!("{} = {}", d.guid, variant_to_string(&d.value))),
+            )
+        };
+        self.check(path, "custom data", render(expected), render(actual));
+    }
+
+    fn type_def(&mut self, ty: &TypeDef, other: &TypeDef) {
+        let path = ty.name.as_str();
+        self.check(path, "kind", ty.kind.name(), other.kind.name());
+        self.check(path, "GUID", ty.guid, other.guid);
+        self.check(path, "version", version(ty.version), version(other.version));
+        self.check(path, "flags", type_flags(ty.flags), type_flags(other.flags));
+        self.doc(path, &ty.doc, &other.doc);
+        self.custom_data(path, &ty.custom_data, &other.custom_data);
+        if ty.kind != other.kind
+            && !matches!(
+                (ty.kind, other.kind),
+                (TypeKind::Dispatch, TypeKind::Interface)
+            )
+        {
+            return;
+        }
+        match ty.kind {
+            TypeKind::Enum => self.enum_values(path, ty, other),
+            TypeKind::Record | TypeKind::Union => self.fields(path, ty, other),
+            TypeKind::Alias => {
+                let target =
+                    |ty: &TypeDef| ty.alias_of.as_ref().map_or(String::new(), type_to_string);
+                self.check(path, "aliased type", target(ty), target(other));
+            }
+            TypeKind::Interface | TypeKind::Dispatch => {
+                let base = |ty: &TypeDef| ty.base().map_or("none".to_string(), |b| b.name.clone());
+                self.check(path, "base interface", base(ty), base(other));
+                self.members(path, &members(ty), &members(other));
+            }
+            TypeKind::CoClass => self.impl_types(path, ty, other),
+            TypeKind::Module => {
+                self.check(
+                    path,
+                    "DLL name",
+                    ty.dll_name.as_deref().unwrap_or("none"),
+                    other.dll_name.as_deref().unwrap_or("none"),
+                );
+                self.constants(path, ty, other);
+                self.members(path, &ty.funcs, &other.funcs);
+            }
+        }
+    }
+
+    fn enum_values(&mut self, path: &str, ty: &TypeDef, other: &TypeDef) {
+        let value = |var: &Variable| {
+            var.typed_value()
+                .map_or(String::new(), |v| variant_to_string(&v))
+        };
+        for var in &ty.vars {
+            let path = format!("{}.{}", path, var.name);
+            match other.vars.iter().find(|v| v.name == var.name) {
+                Some(found) => self.check(&path, "value", value(var), value(found)),
+                None => self.report(&path, "value is missing from the IDL".to_string()),
+            }
+        }
+        for var in &other.vars {
+            if !ty.vars.iter().any(|v| v.name == var.name) {
+                self.report(
+                    &format!("{}.{}", path, var.name),
+                    "value in the IDL is not in the library".to_string(),
+                );
+            }
+        }
+    }
+
+    /// Record and union fields, by position.
+    fn fields(&mut self, path: &str, ty: &TypeDef, other: &TypeDef) {
+        self.check(path, "size", ty.size, other.size);
+        self.check(path, "alignment", ty.alignment, other.alignment);
+        self.check(path, "field count", ty.vars.len(), other.vars.len());
+        for (var, found) in ty.vars.iter().zip(&other.vars) {
+            let path = format!("{}.{}", path, var.name);
+            self.check(&path, "field name", &var.name, &found.name);
+            self.check(
+                &path,
+                "type",
+                type_to_string(&var.ty),
+                type_to_string(&found.ty),
+            );
+            self.check(&path, "offset", var.offset, found.offset);
+        }
+    }
+
+    fn constants(&mut self, path: &str, ty: &TypeDef, other: &TypeDef) {
+        let value = |var: &Variable| {
+            var.typed_value()
+                .map_or(String::new(), |v| variant_to_string(&v))
+        };
+        for var in &ty.vars {
+            let path = format!("{}.{}", path, var.name);
+            match other.vars.iter().find(|v| v.name == var.name) {
+                Some(found) => {
+                    self.check(
+                        &path,
+                        "type",
+                        type_to_string(&var.ty),
+                        type_to_string(&found.ty),
+                    );
+                    self.check(&path, "value", value(var), value(found));
+                }
+                None => self.report(&path, "constant is missing from the IDL".to_string()),
+            }
+        }
+        for var in &other.vars {
+            if !ty.vars.iter().any(|v| v.name == var.name) {
+                self.report(
+                    &format!("{}.{}", path, var.name),
+                    "constant in the IDL is not in the library".to_string(),
+                );
+            }
+        }
+    }
+
+    fn impl_types(&mut self, path: &str, ty: &TypeDef, other: &TypeDef) {
+        self.check(
+            path,
+            "implemented interface count",
+            ty.impl_types.len(),
+            other.impl_types.len(),
+        );
+        for (implemented, found) in ty.impl_types.iter().zip(&other.impl_types) {
+            let path = format!("{}.{}", path, implemented.target.name);
+            self.check(
+                &path,
+                "implemented interface",
+                &implemented.target.name,
+                &found.target.name,
+            );
+            self.check(
+                &path,
+                "flags",
+                impl_flags(implemented.flags),
+                impl_flags(found.flags),
+            );
+        }
+    }
+
+    /// Methods paired by name and invoke kind, then the vtable order of
+    /// those both sides have in the vtable.
+    fn members(&mut self, path: &str, funcs: &[Function], others: &[Function]) {
+        for func in funcs {
+            match others.iter().find(|f| key(f) == key(func)) {
+                Some(found) => self.function(&format!("{}.{}", path, key(func)), func, found),
+                None => self.report(
+                    &format!("{}.{}", path, key(func)),
+                    "member is missing from the IDL".to_string(),
+                ),
+            }
+        }
+        for func in others {
+            if !funcs.iter().any(|f| key(f) == key(func)) {
+                self.report(
+                    &format!("{}.{}", path, key(func)),
+                    "member in the IDL is not in the library".to_string(),
+                );
+            }
+        }
+
+        let in_vtable = |funcs: &[Function], others: &[Function]| {
+            let mut slots: Vec<_> = funcs
+                .iter()
+                .filter(|f| f.func_kind != FuncKind::Dispatch)
+                .filter(|f| {
+                    others
+                        .iter()
+                        .any(|o| key(o) == key(f) && o.func_kind != FuncKind::Dispatch)
+                })
+                .collect();
+            slots.sort_by_key(|f| f.vtable_offset);
+            list(slots.iter().map(|f| key(f)))
+        };
+        self.check(
+            path,
+            "vtable order",
+            in_vtable(funcs, others),
+            in_vtable(others, funcs),
+        );
+    }
+
+    fn function(&mut self, path: &str, func: &Function, other: &Function) {
+        self.check(
+            path,
+            "DISPID",
+            format!("{:#x}", func.memid),
+            format!("{:#x}", other.memid),
+        );
+        self.check(
+            path,
+            "flags",
+            list(function_flags(func)),
+            list(function_flags(other)),
+        );
+        self.doc(path, &func.doc, &other.doc);
+        self.custom_data(path, &func.custom_data, &other.custom_data);
+        if func.func_kind == FuncKind::Static || other.func_kind == FuncKind::Static {
+            self.check(path, "entry point", entry(func), entry(other));
+            self.check(
+                path,
+                "calling convention",
+                format!("{:?}", func.call_conv),
+                format!("{:?}", other.call_conv),
+            );
+        }
+
+        let dispatch =
+            func.func_kind == FuncKind::Dispatch || other.func_kind == FuncKind::Dispatch;
+        let (ret, params) = signature(func, dispatch);
+        let (other_ret, other_params) = signature(other, dispatch);
+        self.check(path, "return type", ret, other_ret);
+        self.check(path, "parameter count", params.len(), other_params.len());
+        for (param, found) in params.iter().zip(&other_params) {
+            let name = |p: &Param| p.name.clone().unwrap_or_else(|| "unnamed".to_string());
+            let path = format!("{}({})", path, name(param));
+            self.check(&path, "parameter name", name(param), name(found));
+            self.check(
+                &path,
+                "type",
+                type_to_string(&param.ty),
+                type_to_string(&found.ty),
+            );
+            self.check(
+                &path,
+                "flags",
+                param_flags(param.flags),
+                param_flags(found.flags),
+            );
+            let default = |p: &Param| {
+                p.default_value
+                    .as_ref()
+                    .map_or("none".to_string(), variant_to_string)
+            };
+            self.check(&path, "default value", default(param), default(found));
+        }
+    }
+}
+
+/// What the IDL prints for an interface: the properties of a pure
+/// dispinterface as accessors, then the methods without the IUnknown and
+/// IDispatch ones.
+fn members(ty: &TypeDef) -> Vec<Function> {
+    let mut funcs = if ty.kind == TypeKind::Dispatch && !ty.has_flag(TYPEFLAG_FDUAL) {
+        dispatch_properties(ty)
+    } else {
+        Vec::new()
+    };
+    funcs.extend(
+        ty.funcs
+            .iter()
+            .filter(|f| !f.is_dispatch_plumbing())
+            .cloned(),
+    );
+    funcs
+}
+
+/// `Name`, or `Name[propget]` and the like for property accessors.
+fn key(func: &Function) -> String {
+    match func.invoke_kind.idl_attribute() {
+        Some(attribute) => format!("{}[{}]", func.name, attribute),
+        None => func.name.clone(),
+    }
+}
+
+/// Return type and parameters; in the dispatch shape, an HRESULT and an
+/// `[out, retval]` parameter become the type it points to, and a bare
+/// HRESULT becomes `void`.
+fn signature(func: &Function, dispatch: bool) -> (String, Vec<Param>) {
+    let mut params = func.params.clone();
+    let mut ret = func.ret.clone();
+    if dispatch && ret == Type::Base(VT_HRESULT) {
+        ret = match func.retval_index() {
+            Some(index) => match params.remove(index).ty {
+                Type::Ptr(pointee) => *pointee,
+                ty => ty,
+            },
+            None => Type::Base(VT_VOID),
+        };
+    }
+    (type_to_string(&ret), params)
+}
+
+fn entry(func: &Function) -> String {
+    match &func.entry {
+        Some(DllEntry::Name(name)) => quoted(name),
+        Some(DllEntry::Ordinal(ordinal)) => ordinal.to_string(),
+        None => "none".to_string(),
+    }
+}
+
+fn type_flags(flags: u16) -> String {
+    list(
+        TYPE_FLAG_NAMES
+            .iter()
+            .filter(|&&(flag, _)| flags & flag != 0)
+            .map(|&(_, name)| name),
+    )
+}
+
+fn param_flags(flags: u16) -> String {
+    list(
+        [
+            (PARAMFLAG_FIN, "in"),
+            (PARAMFLAG_FOUT, "out"),
+            (PARAMFLAG_FLCID, "lcid"),
+            (PARAMFLAG_FRETVAL, "retval"),
+            (PARAMFLAG_FOPT, "optional"),
+            (PARAMFLAG_FHASDEFAULT, "defaultvalue"),
+        ]
+        .into_iter()
+        .filter(|&(flag, _)| flags & flag != 0)
+        .map(|(_, name)| name),
+    )
+}
+
+fn impl_flags(flags: i32) -> String {
+    list(
+        [
+            (IMPLTYPEFLAG_FDEFAULT, "default"),
+            (IMPLTYPEFLAG_FSOURCE, "source"),
+            (IMPLTYPEFLAG_FRESTRICTED, "restricted"),
+            (IMPLTYPEFLAG_FDEFAULTVTABLE, "defaultvtable"),
+        ]
+        .into_iter()
+        .filter(|&(flag, _)| flags & flag != 0)
+        .map(|(_, name)| name),
+    )
+}
+
+fn version(version: (u16, u16)) -> String {
+    format!("{}.{}", version.0, version.1)
+}
+
+fn quoted(s: &str) -> String {
+    format!("{:?}", s)
+}
+
+/// Comma-separated items, or `none`.
+fn list<I>(items: I) -> String
+where
+    I: IntoIterator,
+    I::Item: AsRef<str>,
+{
+    let items: Vec<String> = items.into_iter().map(|i| i.as_ref().to_string()).collect();
+    if items.is_empty() {
+        "none".to_string()
+    } else {
+        items.join(", ")
+    }
+}
+
+/// Prints the discrepancies as aligned text, one per line, with a count.
+pub fn write_text<W>(
+    lib: &Library,
+    discrepancies: &[Discrepancy],
+    mut out: W,
+) -> std::io::Result<()>
+where
+    W: Write,
+{
+    writeln!(out, "Verifying {} {}", lib.name, version(lib.version))?;
+    if discrepancies.is_empty() {
+        writeln!(out, "No discrepancies.")?;
+        return Ok(());
+    }
+    writeln!(out)?;
+    let width = discrepancies
+        .iter()
+        .map(|d| d.path.len())
+        .max()
+        .unwrap_or(0);
+    for d in discrepancies {
+        writeln!(out, "{:<width$}  {}", d.path, d.message, width = width)?;
+    }
+    writeln!(out)?;
+    writeln!(out, "{} discrepancies", discrepancies.len())?;
+    Ok(())
+}
+
+/// The discrepancies as a JSON document.
+pub fn to_json(lib: &Library, discrepancies: &[Discrepancy]) -> String {
+    Json::Object(vec![
+        ("schema_version", SCHEMA_VERSION.into()),
+        (
+            "library",
+            Json::Object(vec![
+                ("name", lib.name.as_str().into()),
+                ("guid", lib.guid.to_string().into()),
+                ("version", version(lib.version).into()),
+            ]),
+        ),
+        ("count", discrepancies.len().into()),
+        (
+            "discrepancies",
+            Json::array(discrepancies, |d| {
+                Json::Object(vec![
+                    ("path", d.path.as_str().into()),
+                    ("message", d.message.as_str().into()),
+                ])
+            }),
+        ),
+    ])
+    .render()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::fixture;
+
+    fn round_trip(lib: &Library) -> Library {
+        let mut idl = Vec::new();
+        print_library(lib, &mut idl, &mut Diagnostics::new()).unwrap();
+        crate::idl::parse(&String::from_utf8(idl).unwrap()).unwrap()
+    }
+
+    fn type_mut<'a>(lib: &'a mut Library, name: &str) -> &'a mut TypeDef {
+        lib.types.iter_mut().find(|t| t.name == name).unwrap()
+    }
+
+    #[test]
+    fn reports_what_the_idl_changes() {
+        let lib = fixture("simple.tlb");
+        let mut parsed = round_trip(&lib);
+        let shape = type_mut(&mut parsed, "IShape");
+        shape.funcs[2].memid = 9;
+        shape.funcs[2].params[1].flags &= !PARAMFLAG_FOPT;
+        let first = shape.funcs[0].vtable_offset;
+        shape.funcs[0].vtable_offset = shape.funcs[3].vtable_offset;
+        shape.funcs[3].vtable_offset = first;
+        type_mut(&mut parsed, "Colors").vars[2].value = Some(Variant::I4(3));
+        type_mut(&mut parsed, "Packet").vars[1].offset = 4;
+
+        let found: Vec<_> = compare(&lib, &parsed)
+            .into_iter()
+            .map(|d| format!("{}: {}", d.path, d.message))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                "Colors.Blue: value is 2 in the library but 3 in the IDL",
+                "IShape.Move: DISPID is 0x3 in the library but 0x9 in the IDL",
+                "IShape.Move(dy): flags is in, optional, defaultvalue in the library \
+                 but in, defaultvalue in the IDL",
+                "IShape: vtable order is Name[propget], Name[propput], Move, Color[propget], \
+                 Center in the library but Color[propget], Name[propput], Move, \
+                 Name[propget], Center in the IDL",
+                "Packet.value: offset is 1 in the library but 4 in the IDL",
+            ]
+        );
+    }
+}
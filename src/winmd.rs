@@ -0,0 +1,2300 @@
+//! Native writer for Windows metadata (.winmd) files.
+//!
+//! The output follows the conventions windows-bindgen expects from the Win32
+//! metadata: COM interfaces are abstract `ComImport` interfaces carrying a
+//! `GuidAttribute`, enums are value types deriving from `System.Enum` (with
+//! `[Flags]` when they are bit flags),
+//! aliases are `NativeTypedef` structs with a single `Value` field, and
+//! module constants and coclass CLSIDs live on a static `Apis` class.
+//! Module functions become static P/Invoke methods of `Apis` whose `ImplMap`
+//! rows name the DLL and entry point, so they bind as `extern` functions;
+//! `usesgetlasterror` becomes `SupportsLastError`.
+//! Fundamental COM types (`HRESULT`, `BSTR`, `VARIANT`, `IUnknown`, ...) are
+//! referenced from the `Windows.Win32` namespaces rather than redefined;
+//! types of other imported libraries are referenced from the .winmd files
+//! given as [`References`], or from a namespace named after the import.
+//!
+//! Several libraries can share one .winmd, each in its own namespace (a
+//! [`Partition`]). Imports between them resolve to their TypeDefs, and a
+//! type more than one of them defines is written once.
+//!
+//! The metadata (ECMA-335 partition II) is wrapped in the smallest PE image
+//! readers accept: a single section holding the CLI header and the metadata.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::diagnostics::Diagnostics;
+use crate::error::Error;
+use crate::model::TypeRef;
+use crate::model::*;
+use crate::reference::References;
+use crate::typelib::*;
+
+// Metadata table numbers.
+const MODULE: usize = 0x00;
+const TYPE_REF: usize = 0x01;
+const TYPE_DEF: usize = 0x02;
+const FIELD: usize = 0x04;
+const METHOD_DEF: usize = 0x06;
+const PARAM: usize = 0x08;
+const INTERFACE_IMPL: usize = 0x09;
+const MEMBER_REF: usize = 0x0a;
+const CONSTANT: usize = 0x0b;
+const CUSTOM_ATTRIBUTE: usize = 0x0c;
+const CLASS_LAYOUT: usize = 0x0f;
+const FIELD_LAYOUT: usize = 0x10;
+const MODULE_REF: usize = 0x1a;
+const IMPL_MAP: usize = 0x1c;
+const ASSEMBLY: usize = 0x20;
+const ASSEMBLY_REF: usize = 0x23;
+const TABLE_COUNT: usize = 0x2d;
+
+/// `output` of the diagnostics of the .winmd.
+const WINMD: &str = "winmd";
+
+// Signature element types.
+const ELEMENT_TYPE_VOID: u8 = 0x01;
+const ELEMENT_TYPE_BOOLEAN: u8 = 0x02;
+const ELEMENT_TYPE_I1: u8 = 0x04;
+const ELEMENT_TYPE_U1: u8 = 0x05;
+const ELEMENT_TYPE_I2: u8 = 0x06;
+const ELEMENT_TYPE_U2: u8 = 0x07;
+const ELEMENT_TYPE_I4: u8 = 0x08;
+const ELEMENT_TYPE_U4: u8 = 0x09;
+const ELEMENT_TYPE_I8: u8 = 0x0a;
+const ELEMENT_TYPE_U8: u8 = 0x0b;
+const ELEMENT_TYPE_R4: u8 = 0x0c;
+const ELEMENT_TYPE_R8: u8 = 0x0d;
+const ELEMENT_TYPE_STRING: u8 = 0x0e;
+const ELEMENT_TYPE_PTR: u8 = 0x0f;
+const ELEMENT_TYPE_VALUETYPE: u8 = 0x11;
+const ELEMENT_TYPE_CLASS: u8 = 0x12;
+const ELEMENT_TYPE_ARRAY: u8 = 0x14;
+const ELEMENT_TYPE_I: u8 = 0x18;
+const ELEMENT_TYPE_U: u8 = 0x19;
+
+const SIG_DEFAULT: u8 = 0x00;
+const SIG_FIELD: u8 = 0x06;
+const SIG_HASTHIS: u8 = 0x20;
+
+// TypeAttributes
+const TD_PUBLIC: u32 = 0x1;
+const TD_SEQUENTIAL_LAYOUT: u32 = 0x8;
+const TD_EXPLICIT_LAYOUT: u32 = 0x10;
+const TD_INTERFACE: u32 = 0x20;
+const TD_ABSTRACT: u32 = 0x80;
+const TD_SEALED: u32 = 0x100;
+const TD_IMPORT: u32 = 0x1000;
+const TD_BEFORE_FIELD_INIT: u32 = 0x10_0000;
+
+// FieldAttributes
+const FD_PUBLIC: u16 = 0x6;
+const FD_STATIC: u16 = 0x10;
+const FD_LITERAL: u16 = 0x40;
+const FD_SPECIAL_NAME: u16 = 0x200;
+const FD_RT_SPECIAL_NAME: u16 = 0x400;
+const FD_HAS_DEFAULT: u16 = 0x8000;
+
+// MethodAttributes
+const MD_PUBLIC: u16 = 0x6;
+const MD_STATIC: u16 = 0x10;
+const MD_VIRTUAL: u16 = 0x40;
+const MD_HIDE_BY_SIG: u16 = 0x80;
+const MD_NEW_SLOT: u16 = 0x100;
+const MD_ABSTRACT: u16 = 0x400;
+const MD_PINVOKE_IMPL: u16 = 0x2000;
+
+// MethodImplAttributes
+const MI_PRESERVE_SIG: u16 = 0x80;
+
+// PInvokeAttributes
+const PI_NO_MANGLE: u16 = 0x1;
+const PI_SUPPORTS_LAST_ERROR: u16 = 0x40;
+const PI_CALL_CONV_WINAPI: u16 = 0x100;
+const PI_CALL_CONV_CDECL: u16 = 0x200;
+const PI_CALL_CONV_FASTCALL: u16 = 0x500;
+
+// ParamAttributes
+const PD_IN: u16 = 0x1;
+const PD_OUT: u16 = 0x2;
+const PD_OPTIONAL: u16 = 0x10;
+const PD_HAS_DEFAULT: u16 = 0x1000;
+
+/// A coded index: which of the candidate tables, and the 1-based row.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct Coded {
+    tag: u32,
+    row: u32,
+}
+
+/// The tables a coded index can point at, by tag.
+struct CodedKind {
+    bits: u32,
+    tables: &'static [usize],
+}
+
+const TYPE_DEF_OR_REF: CodedKind = CodedKind {
+    bits: 2,
+    tables: &[TYPE_DEF, TYPE_REF, 0x1b],
+};
+const HAS_CONSTANT: CodedKind = CodedKind {
+    bits: 2,
+    tables: &[FIELD, PARAM, 0x17],
+};
+const HAS_CUSTOM_ATTRIBUTE: CodedKind = CodedKind {
+    bits: 5,
+    tables: &[
+        METHOD_DEF,
+        FIELD,
+        TYPE_REF,
+        TYPE_DEF,
+        PARAM,
+        INTERFACE_IMPL,
+        MEMBER_REF,
+        MODULE,
+        0x0e,
+        0x17,
+        0x14,
+        0x11,
+        MODULE_REF,
+        0x1b,
+        ASSEMBLY,
+        ASSEMBLY_REF,
+        0x26,
+        0x27,
+        0x28,
+        0x2a,
+        0x2c,
+        0x2b,
+    ],
+};
+const MEMBER_REF_PARENT: CodedKind = CodedKind {
+    bits: 3,
+    tables: &[TYPE_DEF, TYPE_REF, MODULE_REF, METHOD_DEF, 0x1b],
+};
+const MEMBER_FORWARDED: CodedKind = CodedKind {
+    bits: 1,
+    tables: &[FIELD, METHOD_DEF],
+};
+const CUSTOM_ATTRIBUTE_TYPE: CodedKind = CodedKind {
+    bits: 3,
+    tables: &[METHOD_DEF, MEMBER_REF],
+};
+const RESOLUTION_SCOPE: CodedKind = CodedKind {
+    bits: 2,
+    tables: &[MODULE, MODULE_REF, ASSEMBLY_REF, TYPE_REF],
+};
+
+fn type_def(row: u32) -> Coded {
+    Coded { tag: 0, row }
+}
+
+fn type_ref(row: u32) -> Coded {
+    Coded { tag: 1, row }
+}
+
+const NULL: Coded = Coded { tag: 0, row: 0 };
+
+struct TypeDefRow {
+    flags: u32,
+    name: u32,
+    namespace: u32,
+    extends: Coded,
+    field_list: u32,
+    method_list: u32,
+}
+
+struct FieldRow {
+    flags: u16,
+    name: u32,
+    signature: u32,
+}
+
+struct MethodDefRow {
+    impl_flags: u16,
+    flags: u16,
+    name: u32,
+    signature: u32,
+    param_list: u32,
+}
+
+struct ParamRow {
+    flags: u16,
+    sequence: u16,
+    name: u32,
+}
+
+struct ConstantRow {
+    ty: u8,
+    parent: Coded,
+    value: u32,
+}
+
+struct CustomAttributeRow {
+    parent: Coded,
+    ty: Coded,
+    value: u32,
+}
+
+struct ClassLayoutRow {
+    packing_size: u16,
+    class_size: u32,
+    parent: u32,
+}
+
+struct ImplMapRow {
+    flags: u16,
+    member: Coded,
+    import_name: u32,
+    scope: u32,
+}
+
+struct AssemblyRefRow {
+    version: [u16; 4],
+    public_key_token: u32,
+    name: u32,
+}
+
+#[derive(Default)]
+struct Heaps {
+    strings: Vec<u8>,
+    string_index: HashMap<String, u32>,
+    blobs: Vec<u8>,
+    blob_index: HashMap<Vec<u8>, u32>,
+    guids: Vec<Guid>,
+}
+
+impl Heaps {
+    fn new() -> Self {
+        Heaps {
+            strings: vec![0],
+            blobs: vec![0],
+            ..Default::default()
+        }
+    }
+
+    fn string(&mut self, s: &str) -> u32 {
+        if s.is_empty() {
+            return 0;
+        }
+        if let Some(&index) = self.string_index.get(s) {
+            return index;
+        }
+        let index = self.strings.len() as u32;
+        self.strings.extend_from_slice(s.as_bytes());
+        self.strings.push(0);
+        self.string_index.insert(s.to_string(), index);
+        index
+    }
+
+    fn blob(&mut self, data: &[u8]) -> u32 {
+        if data.is_empty() {
+            return 0;
+        }
+        if let Some(&index) = self.blob_index.get(data) {
+            return index;
+        }
+        let index = self.blobs.len() as u32;
+        compress(data.len() as u32, &mut self.blobs);
+        self.blobs.extend_from_slice(data);
+        self.blob_index.insert(data.to_vec(), index);
+        index
+    }
+
+    fn guid(&mut self, guid: Guid) -> u32 {
+        self.guids.push(guid);
+        self.guids.len() as u32
+    }
+}
+
+/// ECMA-335 compressed unsigned integer.
+fn compress(value: u32, out: &mut Vec<u8>) {
+    if value < 0x80 {
+        out.push(value as u8);
+    } else if value < 0x4000 {
+        out.extend_from_slice(&(0x8000 | value as u16).to_be_bytes());
+    } else {
+        out.extend_from_slice(&(0xc000_0000 | value).to_be_bytes());
+    }
+}
+
+fn guid_bytes(guid: &Guid) -> Vec<u8> {
+    let mut b = Vec::with_capacity(16);
+    b.extend_from_slice(&guid.data1.to_le_bytes());
+    b.extend_from_slice(&guid.data2.to_le_bytes());
+    b.extend_from_slice(&guid.data3.to_le_bytes());
+    b.extend_from_slice(&guid.data4);
+    b
+}
+
+/// Namespace of a type the Win32 metadata already defines.
+pub fn win32_namespace(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "HRESULT" | "BSTR" | "VARIANT_BOOL" | "PSTR" | "PWSTR" | "DECIMAL" | "FILETIME" => {
+            "Windows.Win32.Foundation"
+        }
+        "IUnknown" | "IDispatch" | "CY" | "SAFEARRAY" | "BLOB" | "CLIPDATA" | "IStream" => {
+            "Windows.Win32.System.Com"
+        }
+        "IStorage" => "Windows.Win32.System.Com.StructuredStorage",
+        "VARIANT" => "Windows.Win32.System.Variant",
+        "IEnumVARIANT" | "IFont" | "IFontDisp" | "IPicture" | "IPictureDisp" => {
+            "Windows.Win32.System.Ole"
+        }
+        "GuidAttribute" | "NativeTypedefAttribute" => "Windows.Win32.Foundation.Metadata",
+        _ => return None,
+    })
+}
+
+/// The Win32 metadata spelling of a fundamental VARTYPE with no element
+/// type of its own.
+fn win32_base_type(vt: u16) -> Option<&'static str> {
+    Some(match vt {
+        VT_HRESULT => "HRESULT",
+        VT_BSTR => "BSTR",
+        VT_BOOL => "VARIANT_BOOL",
+        VT_LPSTR => "PSTR",
+        VT_LPWSTR => "PWSTR",
+        VT_DECIMAL => "DECIMAL",
+        VT_CY => "CY",
+        VT_VARIANT => "VARIANT",
+        VT_FILETIME => "FILETIME",
+        VT_BLOB | VT_BLOB_OBJECT => "BLOB",
+        _ => return None,
+    })
+}
+
+/// A library of a .winmd and the namespace its types are written to.
+#[derive(Debug, Clone)]
+pub struct Partition<'a> {
+    /// The library.
+    pub lib: &'a Library,
+    /// Namespace of its types.
+    pub namespace: String,
+}
+
+/// What makes types of different libraries the same type: their GUID, or
+/// for types without one their kind, name, size and member names.
+#[derive(PartialEq, Eq, Hash)]
+enum SharedKey {
+    Guid(Guid),
+    Shape(TypeKind, String, u32, Vec<String>),
+}
+
+fn shared_key(ty: &TypeDef) -> SharedKey {
+    if !ty.guid.is_nil() {
+        return SharedKey::Guid(ty.guid);
+    }
+    let members = ty
+        .vars
+        .iter()
+        .map(|v| v.name.clone())
+        .chain(ty.funcs.iter().map(|f| f.name.clone()))
+        .collect();
+    SharedKey::Shape(ty.kind, ty.name.clone(), ty.size, members)
+}
+
+struct Writer<'a> {
+    partitions: Vec<Partition<'a>>,
+    /// Index in `partitions` of the library being written.
+    current: usize,
+    references: &'a References,
+    assembly: String,
+    version: [u16; 4],
+    heaps: Heaps,
+    assembly_refs: Vec<AssemblyRefRow>,
+    type_refs: Vec<(Coded, u32, u32)>,
+    type_ref_index: HashMap<(String, String), u32>,
+    type_defs: Vec<TypeDefRow>,
+    fields: Vec<FieldRow>,
+    methods: Vec<MethodDefRow>,
+    params: Vec<ParamRow>,
+    interface_impls: Vec<(u32, Coded)>,
+    member_refs: Vec<(Coded, u32, u32)>,
+    constants: Vec<ConstantRow>,
+    custom_attributes: Vec<CustomAttributeRow>,
+    field_layouts: Vec<(u32, u32)>,
+    class_layouts: Vec<ClassLayoutRow>,
+    /// `#Strings` index of each imported DLL name.
+    module_refs: Vec<u32>,
+    impl_maps: Vec<ImplMapRow>,
+    /// TypeDef row of each emitted library type, by partition and index in
+    /// `lib.types`.
+    local_rows: HashMap<(usize, usize), u32>,
+    guid_ctor: Option<u32>,
+    native_typedef_ctor: Option<u32>,
+    flags_ctor: Option<u32>,
+    diagnostics: Diagnostics,
+    /// What the last encoded types lost, until [`Writer::report`] files it.
+    degraded: Vec<String>,
+}
+
+/// Writes several libraries as one .winmd image named after `assembly`.
+/// The first partition gives the assembly its MVID, and its version unless
+/// `version` is given. What cannot be written faithfully is recorded in
+/// `diagnostics`; there must be at least one partition.
+pub fn write_partitions(
+    partitions: Vec<Partition>,
+    assembly: &str,
+    version: Option<[u16; 4]>,
+    references: &References,
+    diagnostics: &mut Diagnostics,
+) -> Result<Vec<u8>, Error> {
+    if partitions.is_empty() {
+        return Err(Error::InvalidConfig("no type libraries given".to_string()));
+    }
+    let mut w = Writer::new(partitions, assembly, references);
+    if let Some(version) = version {
+        w.version = version;
+    }
+    w.diagnostics = std::mem::take(diagnostics);
+    w.build();
+    *diagnostics = std::mem::take(&mut w.diagnostics);
+    Ok(pe_image(&w.metadata()))
+}
+
+impl<'a> Writer<'a> {
+    fn new(partitions: Vec<Partition<'a>>, assembly: &str, references: &'a References) -> Self {
+        let (major, minor) = partitions[0].lib.version;
+        Writer {
+            partitions,
+            current: 0,
+            references,
+            assembly: assembly.to_string(),
+            version: [major, minor, 0, 0],
+            heaps: Heaps::new(),
+            assembly_refs: Vec::new(),
+            type_refs: Vec::new(),
+            type_ref_index: HashMap::new(),
+            type_defs: Vec::new(),
+            fields: Vec::new(),
+            methods: Vec::new(),
+            params: Vec::new(),
+            interface_impls: Vec::new(),
+            member_refs: Vec::new(),
+            constants: Vec::new(),
+            custom_attributes: Vec::new(),
+            field_layouts: Vec::new(),
+            class_layouts: Vec::new(),
+            module_refs: Vec::new(),
+            impl_maps: Vec::new(),
+            local_rows: HashMap::new(),
+            guid_ctor: None,
+            native_typedef_ctor: None,
+            flags_ctor: None,
+            diagnostics: Diagnostics::new(),
+            degraded: Vec::new(),
+        }
+    }
+
+    /// The library being written.
+    fn lib(&self) -> &'a Library {
+        self.partitions[self.current].lib
+    }
+
+    fn warn(&mut self, path: String, message: String) {
+        self.diagnostics.set_library(&self.lib().name);
+        self.diagnostics.warn(WINMD, path, message);
+    }
+
+    /// Files what the types encoded since the last call lost under `path`.
+    fn report(&mut self, path: &str) {
+        for message in std::mem::take(&mut self.degraded) {
+            self.warn(path.to_string(), message);
+        }
+    }
+
+    fn build(&mut self) {
+        self.type_defs.push(TypeDefRow {
+            flags: 0,
+            name: self.heaps.string("<Module>"),
+            namespace: 0,
+            extends: NULL,
+            field_list: 1,
+            method_list: 1,
+        });
+
+        // Rows are assigned up front so types can refer to each other in
+        // any order, across libraries too. A type an earlier library
+        // already defines maps to that row instead of getting its own.
+        let mut shared = HashMap::new();
+        let mut emitted = Vec::new();
+        for p in 0..self.partitions.len() {
+            let lib = self.partitions[p].lib;
+            for (index, ty) in lib.types.iter().enumerate() {
+                if !self.has_type_def(ty) {
+                    continue;
+                }
+                let row = *shared.entry(shared_key(ty)).or_insert_with(|| {
+                    emitted.push((p, ty));
+                    emitted.len() as u32 + 1
+                });
+                self.local_rows.insert((p, index), row);
+            }
+        }
+
+        for (p, ty) in emitted {
+            self.current = p;
+            self.add_type(ty);
+        }
+        let mut namespaces: Vec<String> = Vec::new();
+        for partition in &self.partitions {
+            if !namespaces.contains(&partition.namespace) {
+                namespaces.push(partition.namespace.clone());
+            }
+        }
+        let mut clsids = HashSet::new();
+        for namespace in namespaces {
+            self.add_apis(&namespace, &mut clsids);
+        }
+    }
+
+    fn has_type_def(&self, ty: &TypeDef) -> bool {
+        ty.kind != TypeKind::Module
+            && ty.kind != TypeKind::CoClass
+            && ty.guid != IID_IUNKNOWN
+            && ty.guid != IID_IDISPATCH
+    }
+
+    fn assembly_ref(&mut self, name: &str) -> Coded {
+        let name_index = self.heaps.string(name);
+        let row = match self.assembly_refs.iter().position(|r| r.name == name_index) {
+            Some(pos) => pos as u32 + 1,
+            None => {
+                let (version, token) = if name == "netstandard" {
+                    (
+                        [2, 1, 0, 0],
+                        self.heaps
+                            .blob(&[0xcc, 0x7b, 0x13, 0xff, 0xcd, 0x2d, 0xdd, 0x51]),
+                    )
+                } else {
+                    ([0, 0, 0, 0], 0)
+                };
+                self.assembly_refs.push(AssemblyRefRow {
+                    version,
+                    public_key_token: token,
+                    name: name_index,
+                });
+                self.assembly_refs.len() as u32
+            }
+        };
+        Coded { tag: 2, row }
+    }
+
+    fn type_ref(&mut self, namespace: &str, name: &str) -> Coded {
+        let assembly = if namespace == "System" {
+            "netstandard"
+        } else if namespace.starts_with("Windows.Win32") {
+            "Windows.Win32"
+        } else {
+            namespace
+        };
+        self.assembly_type_ref(assembly, namespace, name)
+    }
+
+    fn assembly_type_ref(&mut self, assembly: &str, namespace: &str, name: &str) -> Coded {
+        let key = (namespace.to_string(), name.to_string());
+        if let Some(&row) = self.type_ref_index.get(&key) {
+            return type_ref(row);
+        }
+        let scope = self.assembly_ref(assembly);
+        let name_index = self.heaps.string(name);
+        let namespace_index = self.heaps.string(namespace);
+        self.type_refs.push((scope, name_index, namespace_index));
+        let row = self.type_refs.len() as u32;
+        self.type_ref_index.insert(key, row);
+        type_ref(row)
+    }
+
+    fn win32_type_ref(&mut self, name: &str) -> Coded {
+        let namespace = win32_namespace(name).unwrap_or("Windows.Win32.Foundation");
+        self.type_ref(namespace, name)
+    }
+
+    fn attribute_ctor(&mut self, class: Coded, signature: &[u8]) -> u32 {
+        let class = Coded {
+            // MemberRefParent tags TypeRef as 1, like TypeDefOrRef.
+            tag: 1,
+            row: class.row,
+        };
+        let name = self.heaps.string(".ctor");
+        let signature = self.heaps.blob(signature);
+        self.member_refs.push((class, name, signature));
+        self.member_refs.len() as u32
+    }
+
+    /// Attaches `[Guid(...)]` to a TypeDef or Field.
+    fn guid_attribute(&mut self, parent: Coded, guid: &Guid) {
+        let ctor = match self.guid_ctor {
+            Some(ctor) => ctor,
+            None => {
+                let mut sig = vec![SIG_HASTHIS, 11, ELEMENT_TYPE_VOID];
+                sig.extend_from_slice(&[ELEMENT_TYPE_U4, ELEMENT_TYPE_U2, ELEMENT_TYPE_U2]);
+                sig.extend_from_slice(&[ELEMENT_TYPE_U1; 8]);
+                let class = self.win32_type_ref("GuidAttribute");
+                let ctor = self.attribute_ctor(class, &sig);
+                self.guid_ctor = Some(ctor);
+                ctor
+            }
+        };
+        let mut value = vec![1, 0];
+        value.extend(guid_bytes(guid));
+        value.extend_from_slice(&[0, 0]);
+        self.custom_attribute(parent, ctor, &value);
+    }
+
+    fn native_typedef_attribute(&mut self, parent: Coded) {
+        let ctor = match self.native_typedef_ctor {
+            Some(ctor) => ctor,
+            None => {
+                let sig = [SIG_HASTHIS, 0, ELEMENT_TYPE_VOID];
+                let class = self.win32_type_ref("NativeTypedefAttribute");
+                let ctor = self.attribute_ctor(class, &sig);
+                self.native_typedef_ctor = Some(ctor);
+                ctor
+            }
+        };
+        self.custom_attribute(parent, ctor, &[1, 0, 0, 0]);
+    }
+
+    /// `[Flags]`, which makes windows-bindgen give an enum bit operators.
+    fn flags_attribute(&mut self, parent: Coded) {
+        let ctor = match self.flags_ctor {
+            Some(ctor) => ctor,
+            None => {
+                let sig = [SIG_HASTHIS, 0, ELEMENT_TYPE_VOID];
+                let class = self.type_ref("System", "FlagsAttribute");
+                let ctor = self.attribute_ctor(class, &sig);
+                self.flags_ctor = Some(ctor);
+                ctor
+            }
+        };
+        self.custom_attribute(parent, ctor, &[1, 0, 0, 0]);
+    }
+
+    fn custom_attribute(&mut self, parent: Coded, ctor: u32, value: &[u8]) {
+        let value = self.heaps.blob(value);
+        self.custom_attributes.push(CustomAttributeRow {
+            parent,
+            ty: Coded { tag: 3, row: ctor },
+            value,
+        });
+    }
+
+    fn start_type_def(&mut self, flags: u32, name: &str, extends: Coded) -> u32 {
+        let name = self.heaps.string(name);
+        let namespace = self.heaps.string(&self.partitions[self.current].namespace);
+        self.type_defs.push(TypeDefRow {
+            flags,
+            name,
+            namespace,
+            extends,
+            field_list: self.fields.len() as u32 + 1,
+            method_list: self.methods.len() as u32 + 1,
+        });
+        self.type_defs.len() as u32
+    }
+
+    fn add_field(&mut self, flags: u16, name: &str, signature: &[u8]) -> u32 {
+        let name = self.heaps.string(name);
+        let signature = self.heaps.blob(signature);
+        self.fields.push(FieldRow {
+            flags,
+            name,
+            signature,
+        });
+        self.fields.len() as u32
+    }
+
+    fn add_constant(&mut self, parent: Coded, value: &Variant) -> bool {
+        let Some((ty, bytes)) = constant_value(value) else {
+            return false;
+        };
+        let value = self.heaps.blob(&bytes);
+        self.constants.push(ConstantRow { ty, parent, value });
+        true
+    }
+
+    fn add_type(&mut self, ty: &TypeDef) {
+        match ty.kind {
+            TypeKind::Enum => self.add_enum(ty),
+            TypeKind::Record | TypeKind::Union => self.add_struct(ty),
+            TypeKind::Alias => self.add_alias(ty),
+            TypeKind::Interface | TypeKind::Dispatch => self.add_interface(ty),
+            TypeKind::Module | TypeKind::CoClass => {}
+        }
+    }
+
+    fn add_enum(&mut self, ty: &TypeDef) {
+        let extends = self.type_ref("System", "Enum");
+        let row = self.start_type_def(TD_PUBLIC | TD_SEALED, &ty.name, extends);
+        if ty.is_bit_flags() {
+            self.flags_attribute(Coded { tag: 3, row });
+        }
+        self.add_field(
+            FD_PUBLIC | FD_SPECIAL_NAME | FD_RT_SPECIAL_NAME,
+            "value__",
+            &[SIG_FIELD, ELEMENT_TYPE_I4],
+        );
+        let mut sig = vec![SIG_FIELD, ELEMENT_TYPE_VALUETYPE];
+        compress(type_def_or_ref_encoded(type_def(row)), &mut sig);
+        for var in &ty.vars {
+            let field = self.add_field(
+                FD_PUBLIC | FD_STATIC | FD_LITERAL | FD_HAS_DEFAULT,
+                &var.name,
+                &sig,
+            );
+            let value = match var.typed_value() {
+                Some(value) => value.as_i32(),
+                None => {
+                    self.warn(
+                        format!("{}.{}", ty.name, var.name),
+                        "enum member without a value, written as 0".to_string(),
+                    );
+                    0
+                }
+            };
+            self.add_constant(Coded { tag: 0, row: field }, &Variant::I4(value));
+        }
+    }
+
+    /// Unions and records whose offsets no packing explains get explicit
+    /// field offsets; packed records get a `ClassLayout` row, which
+    /// windows-bindgen turns into `#[repr(C, packed(N))]`.
+    fn add_struct(&mut self, ty: &TypeDef) {
+        let extends = self.type_ref("System", "ValueType");
+        let layout = self.lib().layout(ty);
+        let explicit = ty.kind == TypeKind::Union || layout == Layout::Explicit;
+        let flags = if explicit {
+            TD_EXPLICIT_LAYOUT
+        } else {
+            TD_SEQUENTIAL_LAYOUT
+        };
+        let row = self.start_type_def(TD_PUBLIC | TD_SEALED | flags, &ty.name, extends);
+        for var in &ty.vars {
+            let mut sig = vec![SIG_FIELD];
+            self.encode_type(&var.ty, &mut sig);
+            self.report(&format!("{}.{}", ty.name, var.name));
+            let field = self.add_field(FD_PUBLIC, &var.name, &sig);
+            if explicit {
+                self.field_layouts.push((var.offset, field));
+            }
+        }
+        if let Layout::Packed(pack) = layout {
+            self.class_layouts.push(ClassLayoutRow {
+                packing_size: pack as u16,
+                class_size: ty.size,
+                parent: row,
+            });
+        }
+    }
+
+    fn add_alias(&mut self, ty: &TypeDef) {
+        let extends = self.type_ref("System", "ValueType");
+        let row = self.start_type_def(
+            TD_PUBLIC | TD_SEALED | TD_SEQUENTIAL_LAYOUT,
+            &ty.name,
+            extends,
+        );
+        let mut sig = vec![SIG_FIELD];
+        let aliased = ty.alias_of.clone().unwrap_or_else(|| {
+            self.degraded
+                .push("alias without a target, written as void".to_string());
+            Type::Base(VT_VOID)
+        });
+        self.encode_type(&aliased, &mut sig);
+        self.report(&ty.name);
+        self.add_field(FD_PUBLIC, "Value", &sig);
+        self.native_typedef_attribute(Coded { tag: 3, row });
+    }
+
+    fn add_interface(&mut self, ty: &TypeDef) {
+        let row = self.start_type_def(
+            TD_PUBLIC | TD_INTERFACE | TD_ABSTRACT | TD_IMPORT,
+            &ty.name,
+            NULL,
+        );
+        self.guid_attribute(Coded { tag: 3, row }, &ty.guid);
+
+        let is_dispinterface = ty.kind == TypeKind::Dispatch && !ty.has_flag(TYPEFLAG_FDUAL);
+        let base = if is_dispinterface {
+            Some(self.win32_type_ref("IDispatch"))
+        } else {
+            ty.base().and_then(|b| self.named_type(b))
+        };
+        match (base, ty.base()) {
+            (Some(base), _) => self.interface_impls.push((row, base)),
+            (None, Some(b)) => self.warn(
+                ty.name.clone(),
+                format!("base interface {} does not resolve", describe_ref(b)),
+            ),
+            (None, None) => {}
+        }
+
+        // Members of a pure dispinterface are only reachable through
+        // IDispatch::Invoke; they are not vtable slots, so none are emitted.
+        if is_dispinterface {
+            return;
+        }
+        // Dispatch functions, the inherited IDispatch members among them,
+        // are not slots of this vtable either.
+        for func in &ty.funcs {
+            if func.func_kind == FuncKind::Dispatch {
+                continue;
+            }
+            self.add_method(&ty.name, func);
+        }
+    }
+
+    fn add_method(&mut self, type_name: &str, func: &Function) {
+        self.push_method(
+            &format!("{}.{}", type_name, func.name),
+            &func.vtable_name(),
+            func,
+            MD_PUBLIC | MD_VIRTUAL | MD_HIDE_BY_SIG | MD_NEW_SLOT | MD_ABSTRACT,
+            0,
+            SIG_HASTHIS,
+        );
+    }
+
+    /// A module function: a static P/Invoke method importing `func` from
+    /// `dll_name`, declared by the module `module`.
+    fn add_function(&mut self, module: &str, func: &Function, dll_name: &str) {
+        let row = self.push_method(
+            &format!("{}.{}", module, func.name),
+            &func.name,
+            func,
+            MD_PUBLIC | MD_HIDE_BY_SIG | MD_STATIC | MD_PINVOKE_IMPL,
+            MI_PRESERVE_SIG,
+            SIG_DEFAULT,
+        );
+        let import_name = match &func.entry {
+            Some(DllEntry::Name(name)) => name.clone(),
+            Some(DllEntry::Ordinal(ordinal)) => format!("#{}", ordinal),
+            None => func.name.clone(),
+        };
+        let call_conv = match func.call_conv {
+            CallConv::CDecl | CallConv::MpwCDecl => PI_CALL_CONV_CDECL,
+            CallConv::FastCall => PI_CALL_CONV_FASTCALL,
+            _ => PI_CALL_CONV_WINAPI,
+        };
+        // `usesgetlasterror` is the only FUNCFLAG windows-bindgen can act on.
+        let last_error = if func.flags & FUNCFLAG_FUSESGETLASTERROR != 0 {
+            PI_SUPPORTS_LAST_ERROR
+        } else {
+            0
+        };
+        let import_name = self.heaps.string(&import_name);
+        let scope = self.module_ref(dll_name);
+        self.impl_maps.push(ImplMapRow {
+            flags: PI_NO_MANGLE | call_conv | last_error,
+            member: Coded { tag: 1, row },
+            import_name,
+            scope,
+        });
+    }
+
+    fn module_ref(&mut self, name: &str) -> u32 {
+        let name = self.heaps.string(name);
+        match self.module_refs.iter().position(|&r| r == name) {
+            Some(pos) => pos as u32 + 1,
+            None => {
+                self.module_refs.push(name);
+                self.module_refs.len() as u32
+            }
+        }
+    }
+
+    /// Appends a MethodDef row and its Param rows, returning the method row.
+    /// `path` names the function in diagnostics.
+    fn push_method(
+        &mut self,
+        path: &str,
+        name: &str,
+        func: &Function,
+        flags: u16,
+        impl_flags: u16,
+        calling_convention: u8,
+    ) -> u32 {
+        let mut sig = vec![calling_convention];
+        compress(func.params.len() as u32, &mut sig);
+        self.encode_type(&func.ret, &mut sig);
+        self.report(path);
+        for (i, param) in func.params.iter().enumerate() {
+            // Array parameters decay to pointers, as in C.
+            match &param.ty {
+                Type::CArray { elem, .. } => {
+                    sig.push(ELEMENT_TYPE_PTR);
+                    self.encode_type(elem, &mut sig);
+                }
+                ty => self.encode_type(ty, &mut sig),
+            }
+            self.report(&param_path(path, param, i));
+        }
+
+        let name = self.heaps.string(name);
+        let signature = self.heaps.blob(&sig);
+        self.methods.push(MethodDefRow {
+            impl_flags,
+            flags,
+            name,
+            signature,
+            param_list: self.params.len() as u32 + 1,
+        });
+        let method = self.methods.len() as u32;
+
+        for (i, param) in func.params.iter().enumerate() {
+            let mut flags = 0;
+            if param.flags & PARAMFLAG_FIN != 0 {
+                flags |= PD_IN;
+            }
+            if param.flags & PARAMFLAG_FOUT != 0 {
+                flags |= PD_OUT;
+            }
+            if param.flags & PARAMFLAG_FOPT != 0 {
+                flags |= PD_OPTIONAL;
+            }
+            let name = match &param.name {
+                Some(name) => name.clone(),
+                None => format!("arg{}", i),
+            };
+            let name = self.heaps.string(&name);
+            self.params.push(ParamRow {
+                flags,
+                sequence: i as u16 + 1,
+                name,
+            });
+            let row = self.params.len() as u32;
+            if param.flags & PARAMFLAG_FHASDEFAULT != 0
+                && let Some(value) = &param.default_value
+            {
+                if self.add_constant(Coded { tag: 1, row }, value) {
+                    self.params[row as usize - 1].flags |= PD_HAS_DEFAULT;
+                } else {
+                    self.warn(
+                        param_path(path, param, i),
+                        format!("default value of VARTYPE {} is left out", value.vt()),
+                    );
+                }
+            }
+        }
+        method
+    }
+
+    /// Module constants and coclass CLSIDs of the libraries in `namespace`,
+    /// as static fields of its `Apis` class, and module functions as its
+    /// methods. Members the namespace or, for CLSIDs, the whole .winmd
+    /// already has are left out.
+    fn add_apis(&mut self, namespace: &str, clsids: &mut HashSet<Guid>) {
+        let libs: Vec<(usize, &'a Library)> = self
+            .partitions
+            .iter()
+            .enumerate()
+            .filter(|(_, partition)| partition.namespace == namespace)
+            .map(|(p, partition)| (p, partition.lib))
+            .collect();
+        let modules = libs.iter().flat_map(|&(p, lib)| {
+            lib.types
+                .iter()
+                .filter(|t| t.kind == TypeKind::Module)
+                .map(move |t| (p, t))
+        });
+        let mut names = HashSet::new();
+        let mut consts: Vec<(&str, Variant)> = Vec::new();
+        let mut functions: Vec<(usize, &str, &Function, &str)> = Vec::new();
+        for (p, t) in modules {
+            self.current = p;
+            for v in &t.vars {
+                let path = format!("{}.{}", t.name, v.name);
+                match v.typed_value() {
+                    Some(value) if constant_value(&value).is_some() => {
+                        if names.insert(v.name.as_str()) {
+                            consts.push((&v.name, value));
+                        }
+                    }
+                    Some(value) => self.warn(
+                        path,
+                        format!("constant of VARTYPE {} is left out", value.vt()),
+                    ),
+                    None => self.warn(path, "constant without a value is left out".to_string()),
+                }
+            }
+            // Without a DLL name there is nothing to import from.
+            let Some(dll) = t.dll_name.as_deref() else {
+                for f in &t.funcs {
+                    self.warn(
+                        format!("{}.{}", t.name, f.name),
+                        "module without a DLL name; function left out".to_string(),
+                    );
+                }
+                continue;
+            };
+            for f in &t.funcs {
+                if names.insert(&f.name) {
+                    functions.push((p, &t.name, f, dll));
+                }
+            }
+        }
+        let coclasses: Vec<&TypeDef> = libs
+            .iter()
+            .flat_map(|(_, lib)| &lib.types)
+            .filter(|t| t.kind == TypeKind::CoClass && clsids.insert(t.guid))
+            .collect();
+        if consts.is_empty() && coclasses.is_empty() && functions.is_empty() {
+            return;
+        }
+
+        self.current = libs[0].0;
+        let extends = self.type_ref("System", "Object");
+        self.start_type_def(
+            TD_PUBLIC | TD_ABSTRACT | TD_SEALED | TD_BEFORE_FIELD_INIT,
+            "Apis",
+            extends,
+        );
+        for (name, value) in consts {
+            let (ty, _) = constant_value(&value).unwrap();
+            let field = self.add_field(
+                FD_PUBLIC | FD_STATIC | FD_LITERAL | FD_HAS_DEFAULT,
+                name,
+                &[SIG_FIELD, ty],
+            );
+            self.add_constant(Coded { tag: 0, row: field }, &value);
+        }
+        for coclass in coclasses {
+            let mut sig = vec![SIG_FIELD, ELEMENT_TYPE_VALUETYPE];
+            let guid = self.type_ref("System", "Guid");
+            compress(type_def_or_ref_encoded(guid), &mut sig);
+            let field = self.add_field(
+                FD_PUBLIC | FD_STATIC,
+                &format!("CLSID_{}", coclass.name),
+                &sig,
+            );
+            self.guid_attribute(Coded { tag: 1, row: field }, &coclass.guid);
+        }
+        for (p, module, func, dll_name) in functions {
+            self.current = p;
+            self.add_function(module, func, dll_name);
+        }
+    }
+
+    /// The TypeDef or TypeRef a named type resolves to.
+    fn named_type(&mut self, r: &TypeRef) -> Option<Coded> {
+        if let Some(guid) = r.guid
+            && (guid == IID_IUNKNOWN || guid == IID_IDISPATCH)
+        {
+            return Some(self.win32_type_ref(&r.name));
+        }
+        match r.location {
+            TypeLocation::Local(index) => self
+                .local_rows
+                .get(&(self.current, index))
+                .map(|&row| type_def(row)),
+            TypeLocation::Imported(import) => {
+                let import = self.lib().imports.get(import);
+                if let Some(row) = import.and_then(|i| self.partition_row(i, r)) {
+                    return Some(type_def(row));
+                }
+                if !r.is_resolved() {
+                    return None;
+                }
+                if win32_namespace(&r.name).is_some() {
+                    return Some(self.win32_type_ref(&r.name));
+                }
+                if let Some((assembly, namespace)) =
+                    import.and_then(|i| self.references.resolve(i, &r.name))
+                {
+                    return Some(self.assembly_type_ref(assembly, namespace, &r.name));
+                }
+                let namespace = import
+                    .map(|i| import_namespace(&i.file_name))
+                    .unwrap_or_default();
+                Some(self.type_ref(&namespace, &r.name))
+            }
+            TypeLocation::Unresolved => None,
+        }
+    }
+
+    /// The partition and position of `r` when `import` is another library
+    /// of the .winmd, matched by GUID. The type is found by its GUID, else by
+    /// its position as the reference table gives it, else, as IDL sources
+    /// only give that, by name.
+    fn partition_type(&self, import: &Import, r: &TypeRef) -> Option<(usize, usize)> {
+        if import.guid.is_nil() {
+            return None;
+        }
+        let p = self
+            .partitions
+            .iter()
+            .position(|partition| partition.lib.guid == import.guid)?;
+        let types = &self.partitions[p].lib.types;
+        let index = match (r.guid.filter(|guid| !guid.is_nil()), r.index) {
+            (Some(guid), _) => types.iter().position(|t| t.guid == guid)?,
+            (None, Some(index)) => index,
+            (None, None) => types.iter().position(|t| t.name == r.name)?,
+        };
+        Some((p, index))
+    }
+
+    /// The TypeDef row of `r` when another library of the .winmd defines it.
+    fn partition_row(&self, import: &Import, r: &TypeRef) -> Option<u32> {
+        let key = self.partition_type(import, r)?;
+        self.local_rows.get(&key).copied()
+    }
+
+    /// The kind of `r`, from the library of the .winmd defining it when the
+    /// reference does not tell.
+    fn kind_of(&self, r: &TypeRef) -> Option<TypeKind> {
+        r.kind.or_else(|| {
+            let TypeLocation::Imported(import) = r.location else {
+                return None;
+            };
+            let (p, index) = self.partition_type(self.lib().imports.get(import)?, r)?;
+            Some(self.partitions[p].lib.types.get(index)?.kind)
+        })
+    }
+
+    fn encode_type(&mut self, ty: &Type, out: &mut Vec<u8>) {
+        match ty {
+            Type::Base(vt) => self.encode_base_type(*vt, out),
+            Type::Ptr(inner) => {
+                // Interface pointers are object references in metadata.
+                if let Type::Named(r) = inner.as_ref()
+                    && is_interface(self.kind_of(r))
+                {
+                    self.encode_type(inner, out);
+                } else {
+                    out.push(ELEMENT_TYPE_PTR);
+                    self.encode_type(inner, out);
+                }
+            }
+            Type::SafeArray(_) => {
+                out.push(ELEMENT_TYPE_PTR);
+                self.encode_value_type_ref("SAFEARRAY", out);
+            }
+            // One rank-1 array per dimension, outermost first: windows-bindgen
+            // reads `long f[4][8]` back as `[[i32; 8]; 4]`.
+            Type::CArray { elem, bounds } => match bounds.split_first() {
+                Some((&(count, lower), rest)) => {
+                    if lower != 0 {
+                        self.degraded
+                            .push(format!("array lower bound {} is left out", lower));
+                    }
+                    out.push(ELEMENT_TYPE_ARRAY);
+                    if rest.is_empty() {
+                        self.encode_type(elem, out);
+                    } else {
+                        let inner = Type::CArray {
+                            elem: elem.clone(),
+                            bounds: rest.to_vec(),
+                        };
+                        self.encode_type(&inner, out);
+                    }
+                    // Rank 1, one size, no lower bounds.
+                    out.extend_from_slice(&[1, 1]);
+                    compress(count, out);
+                    compress(0, out);
+                }
+                None => self.encode_type(elem, out),
+            },
+            Type::Named(r) => match self.named_type(r) {
+                Some(coded) => {
+                    out.push(if is_interface(self.kind_of(r)) {
+                        ELEMENT_TYPE_CLASS
+                    } else {
+                        ELEMENT_TYPE_VALUETYPE
+                    });
+                    compress(type_def_or_ref_encoded(coded), out);
+                }
+                None => {
+                    self.degraded.push(format!(
+                        "type {} does not resolve, written as void*",
+                        describe_ref(r)
+                    ));
+                    out.extend_from_slice(&[ELEMENT_TYPE_PTR, ELEMENT_TYPE_VOID]);
+                }
+            },
+        }
+    }
+
+    fn encode_value_type_ref(&mut self, name: &str, out: &mut Vec<u8>) {
+        let coded = self.win32_type_ref(name);
+        out.push(ELEMENT_TYPE_VALUETYPE);
+        compress(type_def_or_ref_encoded(coded), out);
+    }
+
+    fn encode_base_type(&mut self, vt: u16, out: &mut Vec<u8>) {
+        let element = match vt {
+            VT_I1 => ELEMENT_TYPE_I1,
+            VT_UI1 => ELEMENT_TYPE_U1,
+            VT_I2 => ELEMENT_TYPE_I2,
+            VT_UI2 => ELEMENT_TYPE_U2,
+            VT_I4 | VT_INT | VT_ERROR => ELEMENT_TYPE_I4,
+            VT_UI4 | VT_UINT => ELEMENT_TYPE_U4,
+            VT_I8 => ELEMENT_TYPE_I8,
+            VT_UI8 => ELEMENT_TYPE_U8,
+            VT_R4 => ELEMENT_TYPE_R4,
+            VT_R8 | VT_DATE => ELEMENT_TYPE_R8,
+            VT_INT_PTR => ELEMENT_TYPE_I,
+            VT_UINT_PTR => ELEMENT_TYPE_U,
+            VT_VOID => ELEMENT_TYPE_VOID,
+            VT_UNKNOWN | VT_DISPATCH => {
+                let name = if vt == VT_UNKNOWN {
+                    "IUnknown"
+                } else {
+                    "IDispatch"
+                };
+                let coded = self.win32_type_ref(name);
+                out.push(ELEMENT_TYPE_CLASS);
+                compress(type_def_or_ref_encoded(coded), out);
+                return;
+            }
+            VT_CLSID => {
+                let coded = self.type_ref("System", "Guid");
+                out.push(ELEMENT_TYPE_VALUETYPE);
+                compress(type_def_or_ref_encoded(coded), out);
+                return;
+            }
+            VT_STREAM | VT_STREAMED_OBJECT | VT_STORAGE | VT_STORED_OBJECT => {
+                let name = if matches!(vt, VT_STREAM | VT_STREAMED_OBJECT) {
+                    "IStream"
+                } else {
+                    "IStorage"
+                };
+                let coded = self.win32_type_ref(name);
+                out.push(ELEMENT_TYPE_CLASS);
+                compress(type_def_or_ref_encoded(coded), out);
+                return;
+            }
+            VT_CF => {
+                out.push(ELEMENT_TYPE_PTR);
+                self.encode_value_type_ref("CLIPDATA", out);
+                return;
+            }
+            other => {
+                match win32_base_type(other) {
+                    Some(name) => self.encode_value_type_ref(name, out),
+                    // No faithful equivalent: keep the ABI of an opaque pointer.
+                    None => {
+                        self.degraded.push(format!(
+                            "VARTYPE {} has no metadata equivalent, written as void*",
+                            other
+                        ));
+                        out.extend_from_slice(&[ELEMENT_TYPE_PTR, ELEMENT_TYPE_VOID]);
+                    }
+                }
+                return;
+            }
+        };
+        out.push(element);
+    }
+
+    fn metadata(&mut self) -> Vec<u8> {
+        let mut rows = [0u32; TABLE_COUNT];
+        rows[MODULE] = 1;
+        rows[TYPE_REF] = self.type_refs.len() as u32;
+        rows[TYPE_DEF] = self.type_defs.len() as u32;
+        rows[FIELD] = self.fields.len() as u32;
+        rows[METHOD_DEF] = self.methods.len() as u32;
+        rows[PARAM] = self.params.len() as u32;
+        rows[INTERFACE_IMPL] = self.interface_impls.len() as u32;
+        rows[MEMBER_REF] = self.member_refs.len() as u32;
+        rows[CONSTANT] = self.constants.len() as u32;
+        rows[CUSTOM_ATTRIBUTE] = self.custom_attributes.len() as u32;
+        rows[CLASS_LAYOUT] = self.class_layouts.len() as u32;
+        rows[FIELD_LAYOUT] = self.field_layouts.len() as u32;
+        rows[MODULE_REF] = self.module_refs.len() as u32;
+        rows[IMPL_MAP] = self.impl_maps.len() as u32;
+        rows[ASSEMBLY] = 1;
+        rows[ASSEMBLY_REF] = self.assembly_refs.len() as u32;
+
+        // Everything that goes in the heaps has to be there before the
+        // index sizes are known.
+        let module_name = self.heaps.string(&format!("{}.winmd", self.assembly));
+        let assembly_name = self.heaps.string(&self.assembly);
+        let mvid = self.heaps.guid(self.partitions[0].lib.guid);
+
+        // Tables flagged as sorted must be ordered by their key column.
+        self.constants
+            .sort_by_key(|c| encode(c.parent, &HAS_CONSTANT));
+        self.custom_attributes
+            .sort_by_key(|c| encode(c.parent, &HAS_CUSTOM_ATTRIBUTE));
+        self.class_layouts.sort_by_key(|r| r.parent);
+        self.field_layouts.sort_by_key(|&(_, field)| field);
+        self.impl_maps
+            .sort_by_key(|r| encode(r.member, &MEMBER_FORWARDED));
+
+        let t = TableWriter {
+            rows,
+            wide_strings: self.heaps.strings.len() >= 0x10000,
+            wide_guids: self.heaps.guids.len() >= 0x10000,
+            wide_blobs: self.heaps.blobs.len() >= 0x10000,
+        };
+        let mut out = Vec::new();
+
+        // Module
+        t.u16(&mut out, 0);
+        t.string(&mut out, module_name);
+        t.guid(&mut out, mvid);
+        t.guid(&mut out, 0);
+        t.guid(&mut out, 0);
+
+        for &(scope, name, namespace) in &self.type_refs {
+            t.coded(&mut out, scope, &RESOLUTION_SCOPE);
+            t.string(&mut out, name);
+            t.string(&mut out, namespace);
+        }
+
+        for r in &self.type_defs {
+            t.u32(&mut out, r.flags);
+            t.string(&mut out, r.name);
+            t.string(&mut out, r.namespace);
+            t.coded(&mut out, r.extends, &TYPE_DEF_OR_REF);
+            t.index(&mut out, r.field_list, FIELD);
+            t.index(&mut out, r.method_list, METHOD_DEF);
+        }
+
+        for r in &self.fields {
+            t.u16(&mut out, r.flags);
+            t.string(&mut out, r.name);
+            t.blob(&mut out, r.signature);
+        }
+
+        for r in &self.methods {
+            t.u32(&mut out, 0);
+            t.u16(&mut out, r.impl_flags);
+            t.u16(&mut out, r.flags);
+            t.string(&mut out, r.name);
+            t.blob(&mut out, r.signature);
+            t.index(&mut out, r.param_list, PARAM);
+        }
+
+        for r in &self.params {
+            t.u16(&mut out, r.flags);
+            t.u16(&mut out, r.sequence);
+            t.string(&mut out, r.name);
+        }
+
+        for &(class, interface) in &self.interface_impls {
+            t.index(&mut out, class, TYPE_DEF);
+            t.coded(&mut out, interface, &TYPE_DEF_OR_REF);
+        }
+
+        for &(class, name, signature) in &self.member_refs {
+            t.coded(&mut out, class, &MEMBER_REF_PARENT);
+            t.string(&mut out, name);
+            t.blob(&mut out, signature);
+        }
+
+        for r in &self.constants {
+            out.push(r.ty);
+            out.push(0);
+            t.coded(&mut out, r.parent, &HAS_CONSTANT);
+            t.blob(&mut out, r.value);
+        }
+
+        for r in &self.custom_attributes {
+            t.coded(&mut out, r.parent, &HAS_CUSTOM_ATTRIBUTE);
+            t.coded(&mut out, r.ty, &CUSTOM_ATTRIBUTE_TYPE);
+            t.blob(&mut out, r.value);
+        }
+
+        for r in &self.class_layouts {
+            t.u16(&mut out, r.packing_size);
+            t.u32(&mut out, r.class_size);
+            t.index(&mut out, r.parent, TYPE_DEF);
+        }
+
+        for &(offset, field) in &self.field_layouts {
+            t.u32(&mut out, offset);
+            t.index(&mut out, field, FIELD);
+        }
+
+        for &name in &self.module_refs {
+            t.string(&mut out, name);
+        }
+
+        for r in &self.impl_maps {
+            t.u16(&mut out, r.flags);
+            t.coded(&mut out, r.member, &MEMBER_FORWARDED);
+            t.string(&mut out, r.import_name);
+            t.index(&mut out, r.scope, MODULE_REF);
+        }
+
+        // Assembly
+        t.u32(&mut out, 0x8004); // SHA1
+        for v in self.version {
+            t.u16(&mut out, v);
+        }
+        t.u32(&mut out, 0);
+        t.blob(&mut out, 0);
+        t.string(&mut out, assembly_name);
+        t.string(&mut out, 0);
+
+        for r in &self.assembly_refs {
+            for v in r.version {
+                t.u16(&mut out, v);
+            }
+            t.u32(&mut out, 0);
+            t.blob(&mut out, r.public_key_token);
+            t.string(&mut out, r.name);
+            t.string(&mut out, 0);
+            t.blob(&mut out, 0);
+        }
+
+        let mut tables = Vec::new();
+        tables.extend_from_slice(&0u32.to_le_bytes());
+        tables.extend_from_slice(&[2, 0]);
+        let heap_sizes =
+            t.wide_strings as u8 | (t.wide_guids as u8) << 1 | (t.wide_blobs as u8) << 2;
+        tables.extend_from_slice(&[heap_sizes, 1]);
+        let valid = rows
+            .iter()
+            .enumerate()
+            .filter(|&(_, &n)| n > 0)
+            .fold(0u64, |mask, (i, _)| mask | 1 << i);
+        tables.extend_from_slice(&valid.to_le_bytes());
+        tables.extend_from_slice(&0x0000_1600_3301_fa00u64.to_le_bytes());
+        for &n in rows.iter().filter(|&&n| n > 0) {
+            tables.extend_from_slice(&n.to_le_bytes());
+        }
+        tables.extend(out);
+
+        let guids: Vec<u8> = self.heaps.guids.iter().flat_map(guid_bytes).collect();
+        metadata_root(&[
+            ("#~", tables),
+            ("#Strings", std::mem::take(&mut self.heaps.strings)),
+            ("#US", vec![0]),
+            ("#GUID", guids),
+            ("#Blob", std::mem::take(&mut self.heaps.blobs)),
+        ])
+    }
+}
+
+/// `Method(param)`, naming unnamed parameters as the Param rows do.
+fn param_path(method: &str, param: &Param, index: usize) -> String {
+    match &param.name {
+        Some(name) => format!("{}({})", method, name),
+        None => format!("{}(arg{})", method, index),
+    }
+}
+
+/// A referenced type by name, for diagnostics.
+fn describe_ref(r: &TypeRef) -> String {
+    if r.name.is_empty() {
+        "of unknown name".to_string()
+    } else {
+        r.name.clone()
+    }
+}
+
+fn is_interface(kind: Option<TypeKind>) -> bool {
+    matches!(
+        kind,
+        Some(TypeKind::Interface | TypeKind::Dispatch | TypeKind::CoClass)
+    )
+}
+
+/// `stdole` for `stdole2.tlb`. Import paths are Windows paths, whatever the
+/// host.
+fn import_namespace(file_name: &str) -> String {
+    let name = file_name.rsplit(['\\', '/']).next().unwrap_or(file_name);
+    let stem = name.rsplit_once('.').map_or(name, |(stem, _)| stem);
+    stem.trim_end_matches(|c: char| c.is_ascii_digit())
+        .to_string()
+}
+
+fn encode(coded: Coded, kind: &CodedKind) -> u32 {
+    coded.row << kind.bits | coded.tag
+}
+
+fn type_def_or_ref_encoded(coded: Coded) -> u32 {
+    encode(coded, &TYPE_DEF_OR_REF)
+}
+
+/// The element type and blob of a `Constant` row.
+fn constant_value(value: &Variant) -> Option<(u8, Vec<u8>)> {
+    Some(match value {
+        Variant::I1(v) => (ELEMENT_TYPE_I1, v.to_le_bytes().to_vec()),
+        Variant::UI1(v) => (ELEMENT_TYPE_U1, v.to_le_bytes().to_vec()),
+        Variant::I2(v) => (ELEMENT_TYPE_I2, v.to_le_bytes().to_vec()),
+        Variant::UI2(v) => (ELEMENT_TYPE_U2, v.to_le_bytes().to_vec()),
+        Variant::I4(v) | Variant::Int(v) | Variant::Error(v) => {
+            (ELEMENT_TYPE_I4, v.to_le_bytes().to_vec())
+        }
+        Variant::UI4(v) | Variant::UInt(v) => (ELEMENT_TYPE_U4, v.to_le_bytes().to_vec()),
+        Variant::I8(v) => (ELEMENT_TYPE_I8, v.to_le_bytes().to_vec()),
+        Variant::UI8(v) => (ELEMENT_TYPE_U8, v.to_le_bytes().to_vec()),
+        Variant::R4(v) => (ELEMENT_TYPE_R4, v.to_le_bytes().to_vec()),
+        Variant::R8(v) | Variant::Date(v) => (ELEMENT_TYPE_R8, v.to_le_bytes().to_vec()),
+        Variant::Bool(v) => (ELEMENT_TYPE_BOOLEAN, vec![*v as u8]),
+        Variant::Bstr(s) => (
+            ELEMENT_TYPE_STRING,
+            s.encode_utf16().flat_map(u16::to_le_bytes).collect(),
+        ),
+        _ => return None,
+    })
+}
+
+/// Column writer that knows the width of every index.
+struct TableWriter {
+    rows: [u32; TABLE_COUNT],
+    wide_strings: bool,
+    wide_guids: bool,
+    wide_blobs: bool,
+}
+
+impl TableWriter {
+    fn u16(&self, out: &mut Vec<u8>, v: u16) {
+        out.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn u32(&self, out: &mut Vec<u8>, v: u32) {
+        out.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn sized(&self, out: &mut Vec<u8>, v: u32, wide: bool) {
+        if wide {
+            self.u32(out, v);
+        } else {
+            self.u16(out, v as u16);
+        }
+    }
+
+    fn string(&self, out: &mut Vec<u8>, index: u32) {
+        self.sized(out, index, self.wide_strings);
+    }
+
+    fn guid(&self, out: &mut Vec<u8>, index: u32) {
+        self.sized(out, index, self.wide_guids);
+    }
+
+    fn blob(&self, out: &mut Vec<u8>, index: u32) {
+        self.sized(out, index, self.wide_blobs);
+    }
+
+    fn index(&self, out: &mut Vec<u8>, row: u32, table: usize) {
+        self.sized(out, row, self.rows[table] >= 0x10000);
+    }
+
+    fn coded(&self, out: &mut Vec<u8>, coded: Coded, kind: &CodedKind) {
+        let max = kind.tables.iter().map(|&t| self.rows[t]).max().unwrap_or(0);
+        self.sized(out, encode(coded, kind), max >= 1 << (16 - kind.bits));
+    }
+}
+
+fn align4(out: &mut Vec<u8>) {
+    while !out.len().is_multiple_of(4) {
+        out.push(0);
+    }
+}
+
+/// The metadata root (II.24.2.1) followed by the streams.
+fn metadata_root(streams: &[(&str, Vec<u8>)]) -> Vec<u8> {
+    const VERSION: &[u8] = b"WindowsRuntime 1.4\0\0";
+
+    let header_len = 16
+        + VERSION.len()
+        + 4
+        + streams
+            .iter()
+            .map(|(name, _)| 8 + (name.len() + 1).next_multiple_of(4))
+            .sum::<usize>();
+
+    let mut out = Vec::new();
+    out.extend_from_slice(&0x424a_5342u32.to_le_bytes());
+    out.extend_from_slice(&1u16.to_le_bytes());
+    out.extend_from_slice(&1u16.to_le_bytes());
+    out.extend_from_slice(&0u32.to_le_bytes());
+    out.extend_from_slice(&(VERSION.len() as u32).to_le_bytes());
+    out.extend_from_slice(VERSION);
+    out.extend_from_slice(&0u16.to_le_bytes());
+    out.extend_from_slice(&(streams.len() as u16).to_le_bytes());
+
+    let mut offset = header_len;
+    for (name, data) in streams {
+        let size = data.len().next_multiple_of(4);
+        out.extend_from_slice(&(offset as u32).to_le_bytes());
+        out.extend_from_slice(&(size as u32).to_le_bytes());
+        out.extend_from_slice(name.as_bytes());
+        out.push(0);
+        align4(&mut out);
+        offset += size;
+    }
+    for (_, data) in streams {
+        out.extend_from_slice(data);
+        align4(&mut out);
+    }
+    out
+}
+
+/// Wraps metadata in a PE32 DLL with a CLI header (II.25).
+fn pe_image(metadata: &[u8]) -> Vec<u8> {
+    const FILE_ALIGNMENT: usize = 0x200;
+    const SECTION_ALIGNMENT: u32 = 0x1000;
+    const TEXT_RVA: u32 = 0x1000;
+    const CLI_HEADER_SIZE: u32 = 72;
+
+    let text_size = CLI_HEADER_SIZE as usize + metadata.len();
+    let raw_size = text_size.div_ceil(FILE_ALIGNMENT) * FILE_ALIGNMENT;
+    let image_size = (TEXT_RVA + text_size as u32).div_ceil(SECTION_ALIGNMENT) * SECTION_ALIGNMENT;
+
+    let mut out = vec![0u8; 0x80];
+    out[0..2].copy_from_slice(b"MZ");
+    out[0x3c..0x40].copy_from_slice(&0x80u32.to_le_bytes());
+
+    // PE signature and COFF header.
+    out.extend_from_slice(b"PE\0\0");
+    out.extend_from_slice(&0x14cu16.to_le_bytes()); // i386
+    out.extend_from_slice(&1u16.to_le_bytes()); // sections
+    out.extend_from_slice(&0u32.to_le_bytes()); // timestamp, kept fixed
+    out.extend_from_slice(&0u32.to_le_bytes());
+    out.extend_from_slice(&0u32.to_le_bytes());
+    out.extend_from_slice(&224u16.to_le_bytes()); // optional header size
+    out.extend_from_slice(&0x2102u16.to_le_bytes()); // DLL | 32-bit | executable
+
+    // PE32 optional header.
+    let u16s = |out: &mut Vec<u8>, v: u16| out.extend_from_slice(&v.to_le_bytes());
+    let u32s = |out: &mut Vec<u8>, v: u32| out.extend_from_slice(&v.to_le_bytes());
+    u16s(&mut out, 0x10b);
+    out.extend_from_slice(&[11, 0]); // linker version
+    u32s(&mut out, raw_size as u32); // SizeOfCode
+    u32s(&mut out, 0);
+    u32s(&mut out, 0);
+    u32s(&mut out, 0); // AddressOfEntryPoint
+    u32s(&mut out, TEXT_RVA); // BaseOfCode
+    u32s(&mut out, 0); // BaseOfData
+    u32s(&mut out, 0x1000_0000); // ImageBase
+    u32s(&mut out, SECTION_ALIGNMENT);
+    u32s(&mut out, FILE_ALIGNMENT as u32);
+    for v in [4, 0, 0, 0, 4, 0] {
+        u16s(&mut out, v); // OS, image and subsystem versions
+    }
+    u32s(&mut out, 0);
+    u32s(&mut out, image_size);
+    u32s(&mut out, FILE_ALIGNMENT as u32); // SizeOfHeaders
+    u32s(&mut out, 0); // CheckSum
+    u16s(&mut out, 3); // console subsystem
+    u16s(&mut out, 0x8540); // NX, no SEH, dynamic base, TS aware
+    for v in [0x10_0000, 0x1000, 0x10_0000, 0x1000] {
+        u32s(&mut out, v); // stack and heap
+    }
+    u32s(&mut out, 0);
+    u32s(&mut out, 16);
+    for i in 0..16 {
+        if i == 14 {
+            u32s(&mut out, TEXT_RVA);
+            u32s(&mut out, CLI_HEADER_SIZE);
+        } else {
+            u32s(&mut out, 0);
+            u32s(&mut out, 0);
+        }
+    }
+
+    // Section table.
+    out.extend_from_slice(b".text\0\0\0");
+    u32s(&mut out, text_size as u32);
+    u32s(&mut out, TEXT_RVA);
+    u32s(&mut out, raw_size as u32);
+    u32s(&mut out, FILE_ALIGNMENT as u32);
+    u32s(&mut out, 0);
+    u32s(&mut out, 0);
+    u16s(&mut out, 0);
+    u16s(&mut out, 0);
+    u32s(&mut out, 0x6000_0020); // code | execute | read
+    out.resize(FILE_ALIGNMENT, 0);
+
+    // CLI header, then the metadata.
+    u32s(&mut out, CLI_HEADER_SIZE);
+    u16s(&mut out, 2);
+    u16s(&mut out, 5);
+    u32s(&mut out, TEXT_RVA + CLI_HEADER_SIZE);
+    u32s(&mut out, metadata.len() as u32);
+    u32s(&mut out, 1); // IL only
+    out.resize(FILE_ALIGNMENT + CLI_HEADER_SIZE as usize, 0);
+    out.extend_from_slice(metadata);
+    out.resize(FILE_ALIGNMENT + raw_size, 0);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::fixture;
+    use crate::reference::Metadata;
+
+    fn no_references() -> &'static References {
+        Box::leak(Box::default())
+    }
+
+    fn partition<'a>(lib: &'a Library, namespace: &str) -> Partition<'a> {
+        Partition {
+            lib,
+            namespace: namespace.to_string(),
+        }
+    }
+
+    fn built(lib: &Library) -> Writer<'_> {
+        let mut w = Writer::new(vec![partition(lib, &lib.name)], &lib.name, no_references());
+        w.build();
+        w
+    }
+
+    fn string(w: &Writer, index: u32) -> String {
+        let s = &w.heaps.strings[index as usize..];
+        String::from_utf8(s[..s.iter().position(|&b| b == 0).unwrap()].to_vec()).unwrap()
+    }
+
+    fn type_def_names(w: &Writer) -> Vec<String> {
+        w.type_defs.iter().map(|t| string(w, t.name)).collect()
+    }
+
+    #[test]
+    fn compresses_integers() {
+        let encode = |v| {
+            let mut out = Vec::new();
+            compress(v, &mut out);
+            out
+        };
+        assert_eq!(encode(0x03), [0x03]);
+        assert_eq!(encode(0x7f), [0x7f]);
+        assert_eq!(encode(0x80), [0x80, 0x80]);
+        assert_eq!(encode(0x2e57), [0xae, 0x57]);
+        assert_eq!(encode(0x4000), [0xc0, 0x00, 0x40, 0x00]);
+    }
+
+    #[test]
+    fn emits_one_type_def_per_type() {
+        let lib = fixture("simple.tlb");
+        let w = built(&lib);
+        assert_eq!(
+            type_def_names(&w),
+            vec![
+                "<Module>",
+                "Colors",
+                "Point",
+                "Handle",
+                "IShape",
+                "_ShapeEvents",
+                "Matrix",
+                "Value",
+                "Packet",
+                "IRenderer",
+                "Apis"
+            ]
+        );
+        let apis = w.type_defs.last().unwrap();
+        let fields: Vec<_> = w.fields[apis.field_list as usize - 1..]
+            .iter()
+            .map(|f| string(&w, f.name))
+            .collect();
+        // Currency has no literal form in metadata.
+        assert_eq!(
+            fields,
+            vec![
+                "Answer",
+                "Pi",
+                "Greeting",
+                "Failure",
+                "Big",
+                "Tiny",
+                "CLSID_Shape"
+            ]
+        );
+    }
+
+    #[test]
+    fn lays_out_unions_and_packed_records() {
+        let lib = fixture("simple.tlb");
+        let w = built(&lib);
+        let row = |w: &Writer, name: &str| {
+            w.type_defs
+                .iter()
+                .position(|t| string(w, t.name) == name)
+                .unwrap() as u32
+                + 1
+        };
+        let (value, packet) = (row(&w, "Value"), row(&w, "Packet"));
+        let layout = |row: u32| w.type_defs[row as usize - 1].flags & 0x18;
+        assert_eq!(layout(value), TD_EXPLICIT_LAYOUT);
+        assert_eq!(layout(packet), TD_SEQUENTIAL_LAYOUT);
+        assert_eq!(layout(row(&w, "Point")), TD_SEQUENTIAL_LAYOUT);
+
+        let first = w.type_defs[value as usize - 1].field_list;
+        assert_eq!(
+            w.field_layouts,
+            vec![(0, first), (0, first + 1), (0, first + 2)]
+        );
+        let layouts: Vec<_> = w
+            .class_layouts
+            .iter()
+            .map(|r| (r.packing_size, r.class_size, r.parent))
+            .collect();
+        assert_eq!(layouts, vec![(1, 7, packet)]);
+    }
+
+    #[test]
+    fn marks_bit_flag_enums() {
+        let mut lib = fixture("simple.tlb");
+        let w = built(&lib);
+        assert!(w.flags_ctor.is_none());
+
+        let colors = lib.types.iter_mut().find(|t| t.name == "Colors").unwrap();
+        colors.bit_flags = Some(true);
+        let w = built(&lib);
+        let row = type_def_names(&w)
+            .iter()
+            .position(|n| n == "Colors")
+            .unwrap() as u32
+            + 1;
+        let ctor = w.flags_ctor.unwrap();
+        assert!(
+            w.custom_attributes
+                .iter()
+                .any(|a| a.parent == Coded { tag: 3, row } && a.ty == Coded { tag: 3, row: ctor })
+        );
+        let (class, _, _) = w.member_refs[ctor as usize - 1];
+        let (_, name, namespace) = w.type_refs[class.row as usize - 1];
+        assert_eq!(
+            (string(&w, namespace), string(&w, name)),
+            ("System".into(), "FlagsAttribute".into())
+        );
+    }
+
+    #[test]
+    fn types_module_constants() {
+        let lib = fixture("simple.tlb");
+        let w = built(&lib);
+        let apis = w.type_defs.last().unwrap();
+        let first = apis.field_list;
+        let constant = |field: u32| {
+            let c = w
+                .constants
+                .iter()
+                .find(|c| c.parent == Coded { tag: 0, row: field })
+                .unwrap();
+            let blob = &w.heaps.blobs[c.value as usize..];
+            (c.ty, blob[1..1 + blob[0] as usize].to_vec())
+        };
+        assert_eq!(
+            constant(first + 1),
+            (ELEMENT_TYPE_R8, 3.25f64.to_le_bytes().to_vec())
+        );
+        assert_eq!(constant(first + 2).0, ELEMENT_TYPE_STRING);
+        assert_eq!(
+            constant(first + 3),
+            (ELEMENT_TYPE_I4, 0x80004005u32.to_le_bytes().to_vec())
+        );
+        assert_eq!(
+            constant(first + 4),
+            (ELEMENT_TYPE_U8, (1u64 << 40).to_le_bytes().to_vec())
+        );
+        assert_eq!(constant(first + 5), (ELEMENT_TYPE_U1, vec![200]));
+    }
+
+    #[test]
+    fn emits_vtable_methods_with_params() {
+        let lib = fixture("simple.tlb");
+        let w = built(&lib);
+        let names: Vec<_> = w.methods.iter().map(|m| string(&w, m.name)).collect();
+        assert_eq!(
+            names[..5],
+            ["get_Name", "put_Name", "Move", "get_Color", "Center"]
+        );
+
+        // Move([in] long dx, [in, optional, defaultvalue(5)] long dy)
+        let mov = &w.methods[2];
+        let dy = &w.params[mov.param_list as usize];
+        assert_eq!(string(&w, dy.name), "dy");
+        assert_eq!(dy.sequence, 2);
+        assert_eq!(dy.flags, PD_IN | PD_OPTIONAL | PD_HAS_DEFAULT);
+        assert_eq!(w.constants.iter().filter(|c| c.parent.tag == 1).count(), 1);
+
+        // IShape derives from IDispatch and carries its IID.
+        let ishape = type_def_names(&w)
+            .iter()
+            .position(|n| n == "IShape")
+            .unwrap() as u32
+            + 1;
+        assert_eq!(w.interface_impls.len(), 3);
+        assert_eq!(w.interface_impls[0].0, ishape);
+        assert!(w.custom_attributes.iter().any(|c| c.parent
+            == Coded {
+                tag: 3,
+                row: ishape
+            }));
+    }
+
+    #[test]
+    fn emits_the_methods_of_interfaces_with_automatic_memids() {
+        let lib = fixture("simple.tlb");
+        let w = built(&lib);
+        let row = type_def_names(&w)
+            .iter()
+            .position(|n| n == "IRenderer")
+            .unwrap();
+        let methods = w.type_defs[row].method_list as usize - 1
+            ..w.type_defs[row + 1].method_list as usize - 1;
+        let names: Vec<_> = w.methods[methods]
+            .iter()
+            .map(|m| string(&w, m.name))
+            .collect();
+        assert_eq!(names, ["Fill", "Bounds"]);
+    }
+
+    #[test]
+    fn imports_module_functions() {
+        let lib = fixture("simple.tlb");
+        let w = built(&lib);
+        let apis = w.type_defs.last().unwrap();
+        let functions = &w.methods[apis.method_list as usize - 1..];
+        let names: Vec<_> = functions.iter().map(|m| string(&w, m.name)).collect();
+        assert_eq!(names, ["Beep", "Log", "Version"]);
+        for m in functions {
+            assert_eq!(
+                m.flags,
+                MD_PUBLIC | MD_HIDE_BY_SIG | MD_STATIC | MD_PINVOKE_IMPL
+            );
+            assert_eq!(m.impl_flags, MI_PRESERVE_SIG);
+        }
+        // Static: no HASTHIS, two int parameters, returns int.
+        let sig = &w.heaps.blobs[functions[0].signature as usize..];
+        assert_eq!(
+            sig[..6],
+            [
+                5,
+                SIG_DEFAULT,
+                2,
+                ELEMENT_TYPE_I4,
+                ELEMENT_TYPE_I4,
+                ELEMENT_TYPE_I4
+            ]
+        );
+
+        assert_eq!(w.module_refs.len(), 1);
+        assert_eq!(string(&w, w.module_refs[0]), "simple.dll");
+        let imports: Vec<_> = w
+            .impl_maps
+            .iter()
+            .map(|r| (r.flags, string(&w, r.import_name), r.scope))
+            .collect();
+        assert_eq!(
+            imports,
+            [
+                (
+                    PI_NO_MANGLE | PI_CALL_CONV_WINAPI,
+                    "SimpleBeep".to_string(),
+                    1
+                ),
+                (
+                    PI_NO_MANGLE | PI_CALL_CONV_CDECL,
+                    "SimpleLog".to_string(),
+                    1
+                ),
+                (
+                    PI_NO_MANGLE | PI_CALL_CONV_WINAPI | PI_SUPPORTS_LAST_ERROR,
+                    "#7".to_string(),
+                    1
+                ),
+            ]
+        );
+        let first = apis.method_list;
+        assert_eq!(
+            w.impl_maps.iter().map(|r| r.member).collect::<Vec<_>>(),
+            (first..first + 3)
+                .map(|row| Coded { tag: 1, row })
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn resolves_imported_types_through_references() {
+        let mut record = TypeDef::new("Window", TypeKind::Record, Guid::default());
+        record.vars.push(Variable {
+            name: "bounds".to_string(),
+            memid: 0,
+            kind: VarKind::PerInstance,
+            flags: 0,
+            ty: Type::Named(TypeRef {
+                name: "Rect".to_string(),
+                guid: None,
+                kind: Some(TypeKind::Record),
+                location: TypeLocation::Imported(0),
+                index: None,
+            }),
+            value: None,
+            offset: 0,
+            doc: Doc::default(),
+            custom_data: Vec::new(),
+        });
+        let lib = Library {
+            name: "WindowLib".to_string(),
+            imports: vec![Import {
+                guid: Guid::default(),
+                version: (3, 1),
+                lcid: 0,
+                file_name: "C:\\Libs\\Legacy.tlb".to_string(),
+            }],
+            types: vec![record],
+            ..Default::default()
+        };
+        let type_ref = |w: &Writer| {
+            let &(scope, name, namespace) = w.type_refs.last().unwrap();
+            let assembly = w.assembly_refs[scope.row as usize - 1].name;
+            (string(w, name), string(w, namespace), string(w, assembly))
+        };
+
+        // Without a reference the namespace is guessed from the file name.
+        let w = built(&lib);
+        assert_eq!(
+            type_ref(&w),
+            ("Rect".into(), "Legacy".into(), "Legacy".into())
+        );
+
+        let legacy = fixture("legacy_sltg.tlb");
+        let image = write_partitions(
+            vec![partition(&legacy, "Contoso.Legacy")],
+            "Contoso.Legacy",
+            None,
+            no_references(),
+            &mut Diagnostics::new(),
+        )
+        .unwrap();
+        let mut references = References::default();
+        references.insert("legacy.tlb", Metadata::parse(&image).unwrap());
+        let mut w = Writer::new(vec![partition(&lib, "WindowLib")], "WindowLib", &references);
+        w.build();
+        assert_eq!(
+            type_ref(&w),
+            (
+                "Rect".into(),
+                "Contoso.Legacy".into(),
+                "Contoso.Legacy".into()
+            )
+        );
+    }
+
+    #[test]
+    fn merges_libraries_into_partitions() {
+        let guid = |data1| Guid {
+            data1,
+            ..Default::default()
+        };
+        let field = |name: &str, location| Variable {
+            name: name.to_string(),
+            memid: 0,
+            kind: VarKind::PerInstance,
+            flags: 0,
+            ty: Type::Named(TypeRef {
+                name: "Point".to_string(),
+                guid: None,
+                kind: Some(TypeKind::Record),
+                location,
+                index: None,
+            }),
+            value: None,
+            offset: 0,
+            doc: Doc::default(),
+            custom_data: Vec::new(),
+        };
+        let mut point = TypeDef::new("Point", TypeKind::Record, Guid::default());
+        point.size = 8;
+        let shape = TypeDef::new("Shape", TypeKind::CoClass, guid(20));
+        let shapes = Library {
+            name: "Shapes".to_string(),
+            guid: guid(1),
+            types: vec![point.clone(), shape.clone()],
+            ..Default::default()
+        };
+        // Canvas carries its own copy of Point and of the Shape coclass.
+        let mut frame = TypeDef::new("Frame", TypeKind::Record, Guid::default());
+        frame.vars = vec![
+            field("origin", TypeLocation::Imported(0)),
+            field("corner", TypeLocation::Local(0)),
+        ];
+        let canvas = Library {
+            name: "Canvas".to_string(),
+            guid: guid(2),
+            imports: vec![Import {
+                guid: guid(1),
+                version: (1, 0),
+                lcid: 0,
+                file_name: "shapes.tlb".to_string(),
+            }],
+            types: vec![point, frame, shape],
+            ..Default::default()
+        };
+
+        let partitions = vec![
+            partition(&shapes, "Contoso.Shapes"),
+            partition(&canvas, "Contoso.Canvas"),
+        ];
+        let mut w = Writer::new(partitions, "Contoso", no_references());
+        w.build();
+        let names: Vec<_> = w
+            .type_defs
+            .iter()
+            .map(|t| (string(&w, t.namespace), string(&w, t.name)))
+            .collect();
+        assert_eq!(
+            names,
+            [
+                ("", "<Module>"),
+                ("Contoso.Shapes", "Point"),
+                ("Contoso.Canvas", "Frame"),
+                ("Contoso.Shapes", "Apis"),
+            ]
+            .map(|(ns, name)| (ns.to_string(), name.to_string()))
+        );
+        // Both fields of Frame are the one Point TypeDef, row 2.
+        let point_sig = [SIG_FIELD, ELEMENT_TYPE_VALUETYPE, 2 << 2];
+        let frame = w.type_defs[2].field_list as usize - 1;
+        for f in &w.fields[frame..frame + 2] {
+            let blob = &w.heaps.blobs[f.signature as usize..];
+            assert_eq!(&blob[1..1 + blob[0] as usize], point_sig);
+        }
+        assert!(
+            w.type_refs
+                .iter()
+                .all(|&(_, name, _)| string(&w, name) != "Point")
+        );
+    }
+
+    #[test]
+    fn matches_imported_types_to_partitions_by_guid_or_position() {
+        let simple = fixture("simple.tlb");
+        // Without simple.tlb next to it, the SLTG library knows the types it
+        // imports only by position.
+        let dir = std::env::temp_dir().join(format!("partitions-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let sltg = dir.join("consumer_sltg.tlb");
+        std::fs::copy(
+            std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("tests/fixtures/consumer_sltg.tlb"),
+            &sltg,
+        )
+        .unwrap();
+        let unnamed = Library::load(&sltg, None).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        for consumer in [fixture("consumer.tlb"), unnamed] {
+            let partitions = vec![
+                partition(&simple, "Contoso.Simple"),
+                partition(&consumer, "Contoso.Consumer"),
+            ];
+            let mut w = Writer::new(partitions, "Contoso", no_references());
+            w.build();
+            assert_eq!(w.degraded, Vec::<String>::new());
+            let names = type_def_names(&w);
+            let row = |name| names.iter().position(|n| n == name).unwrap() as u8 + 1;
+            let canvas = names.iter().position(|n| n == "ICanvas").unwrap();
+            let methods = &w.methods[w.type_defs[canvas].method_list as usize - 1..];
+            let expected = [
+                [ELEMENT_TYPE_CLASS, row("IShape") << 2],
+                [ELEMENT_TYPE_VALUETYPE, row("Point") << 2],
+                [ELEMENT_TYPE_VALUETYPE, row("Colors") << 2],
+            ];
+            for (m, expected) in methods.iter().zip(expected) {
+                let blob = &w.heaps.blobs[m.signature as usize..];
+                let sig = &blob[1..1 + blob[0] as usize];
+                assert!(
+                    sig.windows(2).any(|pair| pair == expected),
+                    "{}: {:?}",
+                    string(&w, m.name),
+                    sig
+                );
+            }
+            assert!(w.type_refs.iter().all(|&(_, name, _)| {
+                !["IShape", "Point", "Colors", ""].contains(&string(&w, name).as_str())
+            }));
+        }
+    }
+
+    #[test]
+    fn encodes_signatures() {
+        let lib = fixture("legacy_sltg.tlb");
+        let mut w = Writer::new(
+            vec![partition(&lib, "LegacyLib")],
+            "LegacyLib",
+            no_references(),
+        );
+        let mut sig = Vec::new();
+        w.encode_type(&Type::Base(VT_I4), &mut sig);
+        assert_eq!(sig, [ELEMENT_TYPE_I4]);
+
+        let mut sig = Vec::new();
+        w.encode_type(&Type::Ptr(Box::new(Type::Base(VT_BSTR))), &mut sig);
+        assert_eq!(sig, [ELEMENT_TYPE_PTR, ELEMENT_TYPE_VALUETYPE, 0x05]);
+        assert_eq!(w.type_refs.len(), 1);
+
+        let mut sig = Vec::new();
+        let array = Type::CArray {
+            elem: Box::new(Type::Base(VT_UI1)),
+            bounds: vec![(16, 0)],
+        };
+        w.encode_type(&array, &mut sig);
+        assert_eq!(sig, [ELEMENT_TYPE_ARRAY, ELEMENT_TYPE_U1, 1, 1, 16, 0]);
+
+        // Multi-dimensional arrays nest, outermost dimension first.
+        let mut sig = Vec::new();
+        let matrix = Type::CArray {
+            elem: Box::new(Type::Base(VT_I4)),
+            bounds: vec![(4, 0), (8, 0)],
+        };
+        w.encode_type(&matrix, &mut sig);
+        assert_eq!(
+            sig,
+            [
+                ELEMENT_TYPE_ARRAY,
+                ELEMENT_TYPE_ARRAY,
+                ELEMENT_TYPE_I4,
+                1,
+                1,
+                8,
+                0,
+                1,
+                1,
+                4,
+                0
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_an_empty_partition_list() {
+        let error = write_partitions(
+            Vec::new(),
+            "Contoso",
+            None,
+            no_references(),
+            &mut Diagnostics::new(),
+        )
+        .unwrap_err();
+        assert!(matches!(error, Error::InvalidConfig(_)), "{:?}", error);
+    }
+
+    #[test]
+    fn writes_a_cli_image() {
+        let lib = fixture("legacy_sltg.tlb");
+        let image = write_partitions(
+            vec![partition(&lib, "LegacyLib")],
+            "LegacyLib",
+            None,
+            no_references(),
+            &mut Diagnostics::new(),
+        )
+        .unwrap();
+        let u32_at = |o: usize| u32::from_le_bytes(image[o..o + 4].try_into().unwrap());
+
+        assert_eq!(&image[..2], b"MZ");
+        let pe = u32_at(0x3c) as usize;
+        assert_eq!(&image[pe..pe + 4], b"PE\0\0");
+        // The CLI header data directory points at the start of .text, which
+        // is the first file-aligned block.
+        let cli = u32_at(pe + 24 + 96 + 14 * 8);
+        assert_eq!(cli, 0x1000);
+        assert_eq!(u32_at(0x200), 72);
+        let metadata = 0x200 + (u32_at(0x208) - 0x1000) as usize;
+        assert_eq!(u32_at(metadata), 0x424a_5342);
+        assert!(image.len().is_multiple_of(0x200));
+    }
+}
@@ -0,0 +1,235 @@
+//! The library API, driven as a build script or another tool would.
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use tlb_winmd_gen::diagnostics::Diagnostics;
+use tlb_winmd_gen::model::TypeKind;
+use tlb_winmd_gen::reference::{Metadata, References};
+use tlb_winmd_gen::{Config, Error, Library, diff, error, idlgen, jsongen, rustgen, verify, winmd};
+
+fn fixture(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name)
+}
+
+#[test]
+fn prints_idl() {
+    let lib = Library::load(&fixture("simple.tlb"), None).unwrap();
+    let mut idl = Vec::new();
+    let mut diagnostics = Diagnostics::new();
+    idlgen::print_library(&lib, &mut idl, &mut diagnostics).unwrap();
+    assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+    let idl = String::from_utf8(idl).unwrap();
+    assert!(idl.contains("library SimpleLib"));
+    assert!(idl.contains("importlib(\"stdole2.tlb\");"));
+    assert!(idl.contains("interface IShape : IDispatch"));
+}
+
+#[test]
+fn writes_a_winmd() {
+    let config = Config::parse("namespace = \"Contoso.Simple\"").unwrap();
+    let lib = config.load_library(&fixture("simple.tlb"), None).unwrap();
+    let partition = winmd::Partition {
+        lib: &lib,
+        namespace: config.namespace.clone().unwrap(),
+    };
+    let image = winmd::write_partitions(
+        vec![partition],
+        "Contoso.Simple",
+        None,
+        &References::default(),
+        &mut Diagnostics::new(),
+    )
+    .unwrap();
+    let metadata = Metadata::parse(&image).unwrap();
+    assert_eq!(metadata.assembly, "Contoso.Simple");
+    assert_eq!(
+        metadata.namespaces.get("IShape").map(String::as_str),
+        Some("Contoso.Simple")
+    );
+}
+
+#[test]
+fn reports_what_is_degraded() {
+    let config = Config::parse("types.exclude = [\"Colors\"]").unwrap();
+    let lib = config.load_library(&fixture("simple.tlb"), None).unwrap();
+    let mut diagnostics = Diagnostics::new();
+    idlgen::print_library(&lib, std::io::sink(), &mut diagnostics).unwrap();
+    let partition = winmd::Partition {
+        lib: &lib,
+        namespace: "Simple".to_string(),
+    };
+    winmd::write_partitions(
+        vec![partition],
+        "Simple",
+        None,
+        &References::default(),
+        &mut diagnostics,
+    )
+    .unwrap();
+    let found: Vec<_> = diagnostics
+        .entries()
+        .iter()
+        .map(|d| {
+            (
+                d.output,
+                d.library.as_str(),
+                d.path.as_str(),
+                d.message.as_str(),
+            )
+        })
+        .collect();
+    assert!(
+        found.contains(&(
+            "idl",
+            "SimpleLib",
+            "IShape.Color(val)",
+            "type Colors does not resolve, written as UnknownUserDefined"
+        )),
+        "{:?}",
+        found
+    );
+    assert!(
+        found
+            .iter()
+            .any(|&(output, _, path, _)| output == "winmd" && path == "IShape.Color(val)"),
+        "{:?}",
+        found
+    );
+}
+
+#[test]
+fn dumps_and_compares() {
+    let lib = Library::load(&fixture("legacy_sltg.tlb"), None).unwrap();
+    assert!(diff::diff_libraries(&lib, &lib).is_empty());
+    assert!(jsongen::to_json(&lib).contains("\"schema_version\""));
+    let mut rust = Vec::new();
+    rustgen::write_rust(&lib, &mut rust).unwrap();
+    assert!(String::from_utf8(rust).unwrap().contains("ICounter"));
+}
+
+/// The IDL printed from each fixture gives the library back, and between
+/// them the fixtures have a type of every kind with a GUID and a help
+/// string.
+#[test]
+fn round_trips_through_idl() {
+    let mut covered = HashSet::new();
+    for name in ["simple.tlb", "legacy_sltg.tlb"] {
+        let lib = Library::load(&fixture(name), None).unwrap();
+        covered.extend(
+            lib.types
+                .iter()
+                .filter(|t| !t.guid.is_nil() && !t.doc.help_string.is_empty())
+                .map(|t| t.kind),
+        );
+        let found: Vec<_> = verify::verify_library(&lib)
+            .unwrap()
+            .into_iter()
+            .map(|d| format!("{}: {}", d.path, d.message))
+            .collect();
+        assert_eq!(found, Vec::<String>::new(), "{}", name);
+    }
+    let kinds = [
+        TypeKind::Enum,
+        TypeKind::Record,
+        TypeKind::Module,
+        TypeKind::Interface,
+        TypeKind::Dispatch,
+        TypeKind::CoClass,
+        TypeKind::Alias,
+        TypeKind::Union,
+    ];
+    assert_eq!(covered, HashSet::from(kinds));
+}
+
+/// simple.tlb as widl compiles it from simple.idl, rather than as
+/// gen_fixtures.py writes it: the source, the library and its IDL agree.
+#[test]
+#[ignore = "needs tests/fixtures/widl/simple.tlb, built by tests/fixtures/build_real.sh"]
+fn reads_a_library_compiled_by_widl() {
+    let widl = Library::load(&fixture("widl/simple.tlb"), None).unwrap();
+    let source = Library::load(&fixture("simple.idl"), None).unwrap();
+    let found: Vec<_> = diff::diff_libraries(&source, &widl)
+        .into_iter()
+        .map(|c| format!("{}: {}", c.path, c.message))
+        .collect();
+    assert_eq!(found, Vec::<String>::new());
+    assert_eq!(verify::verify_library(&widl).unwrap(), []);
+}
+
+/// legacy_sltg.tlb as `widl --oldtlb` compiles it from legacy_sltg.idl, to
+/// check the SLTG reader against a producer other than gen_fixtures.py.
+#[test]
+#[ignore = "needs tests/fixtures/widl/legacy_sltg.tlb, built by tests/fixtures/build_real.sh"]
+fn reads_an_sltg_library_compiled_by_widl() {
+    let widl = Library::load(&fixture("widl/legacy_sltg.tlb"), None).unwrap();
+    let source = Library::load(&fixture("legacy_sltg.idl"), None).unwrap();
+    let found: Vec<_> = diff::diff_libraries(&source, &widl)
+        .into_iter()
+        .map(|c| format!("{}: {}", c.path, c.message))
+        .collect();
+    assert_eq!(found, Vec::<String>::new());
+    assert_eq!(verify::verify_library(&widl).unwrap(), []);
+}
+
+/// The sources of the fixtures give the libraries back.
+#[test]
+fn loads_idl_sources() {
+    for name in ["simple", "legacy_sltg"] {
+        let tlb = Library::load(&fixture(&format!("{}.tlb", name)), None).unwrap();
+        let idl = Library::load(&fixture(&format!("{}.idl", name)), None).unwrap();
+        let found: Vec<_> = diff::diff_libraries(&tlb, &idl)
+            .into_iter()
+            .map(|c| format!("{}: {}", c.path, c.message))
+            .collect();
+        assert_eq!(found, Vec::<String>::new(), "{}", name);
+    }
+}
+
+#[test]
+fn reports_invalid_libraries() {
+    let error = Library::load(&fixture("help.chm"), None).unwrap_err();
+    let Error::LoadFailed {
+        path,
+        hresult,
+        source,
+    } = &error
+    else {
+        panic!("{:?}", error);
+    };
+    assert_eq!(path, &fixture("help.chm"));
+    assert_eq!(*hresult, error::TYPE_E_CANTLOADLIBRARY);
+    assert!(matches!(**source, Error::InvalidTypeLib(_)), "{:?}", source);
+
+    let broken = std::env::temp_dir().join(format!("broken-{}.idl", std::process::id()));
+    std::fs::write(&broken, "library Broken\n{\n    struct S { long };\n};\n").unwrap();
+    let error = Library::load(&broken, None).unwrap_err();
+    std::fs::remove_file(&broken).unwrap();
+    let Error::LoadFailed {
+        hresult, source, ..
+    } = &error
+    else {
+        panic!("{:?}", error);
+    };
+    assert_eq!(*hresult, error::TYPE_E_CANTLOADLIBRARY);
+    assert!(
+        matches!(**source, Error::InvalidIdl { line: 3, .. }),
+        "{:?}",
+        source
+    );
+
+    let error = Library::load(&fixture("missing.tlb"), None).unwrap_err();
+    assert!(
+        matches!(
+            error,
+            Error::LoadFailed {
+                hresult: error::STG_E_FILENOTFOUND,
+                ..
+            }
+        ),
+        "{:?}",
+        error
+    );
+}
@@ -0,0 +1,64 @@
+#!/bin/sh
+# Builds the fixtures that come from the real tools rather than from
+# gen_fixtures.py, so the readers are checked against what those tools
+# write and not only against our own reading of the formats:
+#
+#   widl/simple.tlb         widl, MSFT format, from simple.idl
+#   widl/legacy_sltg.tlb    widl --oldtlb, SLTG format, from legacy_sltg.idl
+#   hhc/help.chm            hhc.exe, from the hhc/help.hhp project
+#
+# widl needs the Wine IDL files for oaidl.idl (WINE_INCLUDE) and
+# stdole2.tlb for the importlib (STDOLE_DIR). HHC runs the HTML Help
+# Workshop compiler, through Wine off Windows:
+#
+#     HHC="wine 'C:/Program Files (x86)/HTML Help Workshop/hhc.exe'"
+#
+# Run from the repository root and check the output in; the tests that
+# read it are ignored until then:
+#
+#     sh tests/fixtures/build_real.sh
+#     cargo test -- --ignored
+
+set -eu
+
+WIDL=${WIDL:-widl}
+WINE_INCLUDE=${WINE_INCLUDE:-/usr/include/wine/windows}
+STDOLE_DIR=${STDOLE_DIR:-/usr/lib/wine/x86_64-windows}
+HHC=${HHC:-hhc.exe}
+
+cd tests/fixtures
+mkdir -p widl
+
+# compile NAME [WIDL OPTION...] builds widl/NAME.tlb from NAME.idl. The
+# fixture sources leave the declarations of IDispatch and the OLE
+# Automation types to the importlib, as the built-in IDL reader does;
+# widl needs them imported.
+compile() {
+    name=$1
+    shift
+    { echo 'import "oaidl.idl";'; cat "$name.idl"; } > "widl/$name.idl"
+    "$WIDL" --win32 -t "$@" -I "$WINE_INCLUDE" -L "$STDOLE_DIR" \
+        -o "widl/$name.tlb" "widl/$name.idl"
+    rm "widl/$name.idl"
+    echo "wrote widl/$name.tlb"
+}
+
+compile simple
+compile legacy_sltg --oldtlb
+
+# A topic large enough to span several LZX frames and a reset point, so the
+# topic after it is decoded from a later reset.
+i=1
+{
+    echo '<html><head><title>Filler</title></head><body><pre>'
+    while [ $i -le 3000 ]; do
+        echo "Line $i of the filler, which moves the shape by its default offset."
+        i=$((i + 1))
+    done
+    echo '</pre></body></html>'
+} > hhc/topics/filler.htm
+rm -f hhc/help.chm
+# hhc exits with 1 when it succeeds.
+(cd hhc && eval "$HHC help.hhp") || test -f hhc/help.chm
+rm hhc/topics/filler.htm
+echo "wrote hhc/help.chm"
@@ -0,0 +1,1226 @@
+#!/usr/bin/env python3
+"""Writes the type library fixtures used by the test suite.
+
+MIDL is not available on the Linux build agents, so the fixtures are produced by
+small writers for the two on-disk formats: MSFT, laid out the way widl's
+write_msft.c does, and the legacy SLTG format, laid out the way widl's
+write_sltg.c does. The equivalent IDL for each library is kept next to it as
+<name>.idl for reference. typelibs.dll is a minimal PE image embedding both
+libraries as TYPELIB resources 1 and 2. help.chm is a compiled HTML Help file
+whose MSCompressed section is produced by a small LZX encoder covering all
+three block types. Run from the repository root:
+
+    python3 tests/fixtures/gen_fixtures.py
+
+As these writers share our reading of the formats, build_real.sh builds the
+same libraries with widl and a help file with hhc.exe, for tests that check
+the readers against the real tools.
+"""
+
+import heapq
+import os
+import struct
+import uuid
+
+HERE = os.path.dirname(os.path.abspath(__file__))
+
+# TYPEKIND
+TKIND_ENUM, TKIND_RECORD, TKIND_MODULE, TKIND_INTERFACE = 0, 1, 2, 3
+TKIND_DISPATCH, TKIND_COCLASS, TKIND_ALIAS, TKIND_UNION = 4, 5, 6, 7
+
+# VARENUM
+VT_EMPTY, VT_NULL, VT_I2, VT_I4, VT_R4, VT_R8, VT_CY, VT_DATE = 0, 1, 2, 3, 4, 5, 6, 7
+VT_BSTR, VT_DISPATCH, VT_ERROR, VT_BOOL, VT_VARIANT, VT_UNKNOWN = 8, 9, 10, 11, 12, 13
+VT_DECIMAL, VT_I1, VT_UI1, VT_UI2, VT_UI4, VT_I8, VT_UI8 = 14, 16, 17, 18, 19, 20, 21
+VT_INT, VT_UINT, VT_VOID, VT_HRESULT, VT_PTR, VT_SAFEARRAY = 22, 23, 24, 25, 26, 27
+VT_CARRAY, VT_USERDEFINED, VT_LPSTR, VT_LPWSTR = 28, 29, 30, 31
+VT_FILETIME, VT_BLOB, VT_CLSID, VT_BYREF = 64, 65, 72, 0x4000
+
+INVOKE_FUNC, INVOKE_PROPERTYGET, INVOKE_PROPERTYPUT, INVOKE_PROPERTYPUTREF = 1, 2, 4, 8
+FUNC_PUREVIRTUAL, FUNC_STATIC, FUNC_DISPATCH = 1, 3, 4
+CC_CDECL, CC_STDCALL = 1, 4
+VAR_PERINSTANCE, VAR_CONST, VAR_DISPATCH = 0, 2, 3
+VARFLAG_FREADONLY = 0x1
+FUNCFLAG_FRESTRICTED, FUNCFLAG_FBINDABLE, FUNCFLAG_FDISPLAYBIND = 0x1, 0x4, 0x10
+FUNCFLAG_FHIDDEN, FUNCFLAG_FUSESGETLASTERROR, FUNCFLAG_FNONBROWSABLE = 0x40, 0x80, 0x400
+
+PARAMFLAG_FIN, PARAMFLAG_FOUT, PARAMFLAG_FLCID = 0x1, 0x2, 0x4
+PARAMFLAG_FRETVAL, PARAMFLAG_FOPT, PARAMFLAG_FHASDEFAULT = 0x8, 0x10, 0x20
+
+TYPEFLAG_FHIDDEN, TYPEFLAG_FDUAL, TYPEFLAG_FNONEXTENSIBLE = 0x10, 0x40, 0x80
+TYPEFLAG_FOLEAUTOMATION, TYPEFLAG_FRESTRICTED = 0x100, 0x200
+TYPEFLAG_FCANCREATE, TYPEFLAG_FDISPATCHABLE = 0x2, 0x1000
+
+IMPLTYPEFLAG_FDEFAULT, IMPLTYPEFLAG_FSOURCE = 0x1, 0x2
+
+IID_IDISPATCH = "00020400-0000-0000-C000-000000000046"
+IID_IUNKNOWN = "00000000-0000-0000-C000-000000000046"
+STDOLE2 = "00020430-0000-0000-C000-000000000046"
+
+
+def simple(vt):
+    """Type descriptions that fit in the encoding itself."""
+    return ("simple", vt)
+
+
+def ptr(inner):
+    return ("ptr", inner)
+
+
+def safearray(inner):
+    return ("safearray", inner)
+
+
+def carray(inner, dims):
+    return ("carray", inner, dims)
+
+
+def userdef(name):
+    """A reference to a typeinfo by name, local or imported."""
+    return ("user", name)
+
+
+class Func:
+    def __init__(self, name, memid, ret=simple(VT_HRESULT), params=(), invkind=INVOKE_FUNC,
+                 funckind=FUNC_PUREVIRTUAL, callconv=CC_STDCALL, flags=0, helpstring=None,
+                 helpcontext=0, entry=None, custdata=(), nopt=0):
+        self.name, self.memid, self.ret, self.params = name, memid, ret, list(params)
+        self.invkind, self.funckind, self.callconv, self.flags = invkind, funckind, callconv, flags
+        self.helpstring, self.helpcontext, self.entry = helpstring, helpcontext, entry
+        self.custdata, self.nopt = list(custdata), nopt
+
+
+class Param:
+    def __init__(self, name, type_, flags=PARAMFLAG_FIN, default=None):
+        self.name, self.type, self.flags, self.default = name, type_, flags, default
+
+
+class Var:
+    def __init__(self, name, type_, value=None, oinst=0, memid=None, kind=None, flags=0,
+                 helpstring=None, helpcontext=0):
+        self.name, self.type, self.value, self.oinst = name, type_, value, oinst
+        self.memid, self.flags, self.helpstring, self.helpcontext = memid, flags, helpstring, helpcontext
+        self.kind = kind if kind is not None else (VAR_CONST if value is not None else VAR_PERINSTANCE)
+
+
+class TypeInfo:
+    def __init__(self, kind, name, guid=None, flags=0, funcs=(), vars=(), impls=(), base=None,
+                 alias=None, size=4, align=4, helpstring=None, helpcontext=0, dllname=None,
+                 custdata=(), version=(0, 0)):
+        self.kind, self.name, self.guid, self.flags = kind, name, guid, flags
+        self.funcs, self.vars, self.impls = list(funcs), list(vars), list(impls)
+        self.base, self.alias, self.size, self.align = base, alias, size, align
+        self.helpstring, self.helpcontext, self.dllname = helpstring, helpcontext, dllname
+        self.custdata, self.version = list(custdata), version
+
+
+class Library:
+    def __init__(self, name, guid, version=(1, 0), helpstring=None, helpcontext=0, helpfile=None,
+                 lcid=0, custdata=(), imports=()):
+        self.name, self.guid, self.version = name, guid, version
+        self.helpstring, self.helpcontext, self.helpfile = helpstring, helpcontext, helpfile
+        self.lcid, self.custdata = lcid, list(custdata)
+        # imports: list of (file, libguid, (major, minor), [(typename, guid, tkind, index)])
+        self.imports = list(imports)
+        self.typeinfos = []
+
+    def add(self, ti):
+        self.typeinfos.append(ti)
+        return ti
+
+    def inherited_slots(self, ti):
+        """Vtable slots before the first function of `ti`: MIDL numbers the
+        functions of interfaces and dispinterfaces after those of IUnknown
+        (3) or IDispatch (7) and every base in between."""
+        if ti.kind == TKIND_DISPATCH and ti.base is None:
+            return 7
+        if ti.kind not in (TKIND_INTERFACE, TKIND_DISPATCH) or ti.base is None:
+            return 0
+        known = {"IUnknown": 3, "IDispatch": 7}
+        if ti.base in known:
+            return known[ti.base]
+        base = next(t for t in self.typeinfos if t.name == ti.base)
+        return self.inherited_slots(base) + len(base.funcs)
+
+    def vft_size(self, ti):
+        if ti.kind not in (TKIND_INTERFACE, TKIND_DISPATCH):
+            return 0
+        return (self.inherited_slots(ti) + len(ti.funcs)) * 4
+
+
+def pad4(b, fill=b"\x57"):
+    return b + fill * ((4 - len(b) % 4) % 4)
+
+
+class Writer:
+    def __init__(self, lib):
+        self.lib = lib
+        self.seg = {k: bytearray() for k in (
+            "typeinfo", "impinfo", "impfiles", "reftab", "guidhash", "guid", "namehash",
+            "name", "string", "typedesc", "arraydesc", "custdata", "cdguids")}
+        self.names = {}
+        self.strings = {}
+        self.guids = {}
+        self.typedescs = {}
+        self.hrefs = {}
+        self.seg["guidhash"] = bytearray(b"\xff" * 0x80)
+        self.seg["namehash"] = bytearray(b"\xff" * 0x200)
+
+    def alloc(self, seg, data):
+        off = len(self.seg[seg])
+        self.seg[seg] += data
+        return off
+
+    def guid(self, text, hreftype=-1):
+        if text is None:
+            return -1
+        if text in self.guids:
+            return self.guids[text]
+        off = self.alloc("guid", uuid.UUID(text).bytes_le + struct.pack("<ii", hreftype, -1))
+        self.guids[text] = off
+        return off
+
+    def name(self, text, hreftype=-1):
+        if text is None:
+            return -1
+        if text in self.names:
+            return self.names[text]
+        raw = text.encode("latin-1")
+        entry = struct.pack("<iiI", hreftype, -1, len(raw) | 0x3800) + pad4(raw)
+        off = self.alloc("name", entry)
+        self.names[text] = off
+        return off
+
+    def string(self, text):
+        if text is None:
+            return -1
+        if text in self.strings:
+            return self.strings[text]
+        raw = text.encode("latin-1")
+        entry = pad4(struct.pack("<H", len(raw)) + raw)
+        if len(entry) < 8:
+            entry += b"\x57" * (8 - len(entry))
+        off = self.alloc("string", entry)
+        self.strings[text] = off
+        return off
+
+    def value(self, v):
+        """Encodes a (vt, value) constant; small non-negative ints are packed."""
+        vt, val = v
+        if vt in (VT_I2, VT_I4, VT_UI2, VT_UI4, VT_INT, VT_UINT, VT_BOOL, VT_ERROR, VT_I1, VT_UI1) \
+                and isinstance(val, int) and 0 <= val < 0x4000000:
+            return -0x80000000 | (vt << 26) | val
+        if vt == VT_BSTR:
+            raw = val.encode("latin-1")
+            return self.alloc("custdata", pad4(struct.pack("<Hi", vt, len(raw)) + raw))
+        fmt = {VT_I2: "<Hh", VT_I4: "<Hi", VT_INT: "<Hi", VT_ERROR: "<Hi", VT_I1: "<Hb",
+               VT_UI1: "<HB", VT_UI2: "<HH", VT_UI4: "<HI", VT_UINT: "<HI", VT_BOOL: "<Hh",
+               VT_R4: "<Hf", VT_R8: "<Hd", VT_DATE: "<Hd", VT_I8: "<Hq", VT_UI8: "<HQ",
+               VT_CY: "<Hq"}[vt]
+        payload = struct.pack("<" + fmt[2], val)
+        data = struct.pack("<H", vt) + payload.ljust(4 if len(payload) <= 4 else 8, b"\x00")
+        return self.alloc("custdata", pad4(data))
+
+    def custdata(self, items):
+        """Builds the (guid, value, next) list; returns the head offset."""
+        head = -1
+        for guid, v in reversed(items):
+            head = self.alloc("cdguids", struct.pack("<iii", self.guid(guid), self.value(v), head))
+        return head
+
+    def encode(self, t):
+        kind = t[0]
+        if kind == "simple":
+            return -0x80000000 | (t[1] << 16) | t[1]
+        key = repr(t)
+        if key in self.typedescs:
+            return self.typedescs[key]
+        if kind in ("ptr", "safearray"):
+            target = self.encode(t[1])
+            vt = VT_PTR if kind == "ptr" else VT_SAFEARRAY
+            mix = (target >> 16) & 0xfff if target < 0 else 0x7ffe
+            data = struct.pack("<Ii", (mix << 16) | vt, target)
+        elif kind == "user":
+            data = struct.pack("<Ii", (0x7fff << 16) | VT_USERDEFINED, self.hrefs[t[1]])
+        elif kind == "carray":
+            target = self.encode(t[1])
+            dims = t[2]
+            total = 1
+            for count, _ in dims:
+                total *= count
+            arr = struct.pack("<iI", target, len(dims) | (total << 16))
+            for count, lbound in dims:
+                arr += struct.pack("<Ii", count, lbound)
+            data = struct.pack("<Ii", (0x7ffe << 16) | VT_CARRAY, self.alloc("arraydesc", arr))
+        else:
+            raise ValueError(kind)
+        off = self.alloc("typedesc", data)
+        self.typedescs[key] = off
+        return off
+
+    def build(self):
+        lib = self.lib
+        # Header-level entries first, as widl does.
+        lib_guid = self.guid(lib.guid, -2)
+        lib_name = self.name(lib.name)
+
+        # Imports: one import file per library, one impinfo per type.
+        for fname, libguid, (major, minor), types in lib.imports:
+            file_off = len(self.seg["impfiles"])
+            raw = fname.encode("latin-1")
+            entry = struct.pack("<iIHHH", self.guid(libguid, 2), 0, major, minor,
+                                (len(raw) << 2) | 1) + raw
+            self.alloc("impfiles", pad4(entry, b"\x57"))
+            # Types with a GUID are imported by it, the others by position.
+            for tname, tguid, tkind, index in types:
+                off = len(self.seg["impinfo"])
+                self.hrefs[tname] = off | 1
+                if tguid:
+                    flags, target = (tkind << 24) | 0x10000 | 1, self.guid(tguid, off | 1)
+                else:
+                    flags, target = (tkind << 24) | 1, index
+                self.alloc("impinfo", struct.pack("<iii", flags, file_off, target))
+
+        for i, ti in enumerate(lib.typeinfos):
+            self.hrefs[ti.name] = i * 0x64
+
+        blocks = []
+        typeinfo_data = []
+        for i, ti in enumerate(lib.typeinfos):
+            name_off = self.name(ti.name, i * 0x64)
+            guid_off = self.guid(ti.guid, i * 0x64) if ti.guid else -1
+            block = self.members(ti)
+            blocks.append(block)
+            datatype1 = -1
+            if ti.kind == TKIND_ALIAS:
+                datatype1 = self.encode(ti.alias)
+            elif ti.kind == TKIND_COCLASS:
+                head = -1
+                refs = []
+                for href_name, flags in ti.impls:
+                    refs.append((self.hrefs[href_name], flags))
+                # Allocate in order so each record points at the next.
+                start = len(self.seg["reftab"])
+                for j, (href, flags) in enumerate(refs):
+                    nxt = start + (j + 1) * 16 if j + 1 < len(refs) else -1
+                    self.alloc("reftab", struct.pack("<iiii", href, flags, -1, nxt))
+                head = start if refs else -1
+                datatype1 = head
+            elif ti.kind == TKIND_MODULE:
+                datatype1 = self.string(ti.dllname)
+            elif ti.base is not None:
+                datatype1 = self.hrefs[ti.base]
+            n_impls = len(ti.impls) if ti.kind == TKIND_COCLASS else (1 if ti.base else 0)
+            typeinfo_data.append(dict(
+                kind=ti.kind | (ti.align << 11), celement=(len(ti.vars) << 16) | len(ti.funcs),
+                guid=guid_off, flags=ti.flags, name=name_off,
+                version=ti.version[0] | (ti.version[1] << 16),
+                doc=self.string(ti.helpstring), helpcontext=ti.helpcontext,
+                custdata=self.custdata(ti.custdata), impls=n_impls, vft=lib.vft_size(ti),
+                size=ti.size,
+                datatype1=datatype1))
+
+        helpstring = self.string(lib.helpstring)
+        helpfile = self.string(lib.helpfile)
+        lib_custdata = self.custdata(lib.custdata)
+        dispatch = self.hrefs.get("IDispatch", -1)
+
+        n = len(lib.typeinfos)
+        seg_order = ["typeinfo", "impinfo", "impfiles", "reftab", "guidhash", "guid",
+                     "namehash", "name", "string", "typedesc", "arraydesc", "custdata",
+                     "cdguids", None, None]
+        header_len = 0x54 + 4 * n + 15 * 16
+        # Typeinfo table goes first; its size is known up front.
+        self.seg["typeinfo"] = bytearray(0x64 * n)
+
+        offsets = {}
+        pos = header_len
+        for key in seg_order:
+            if key is None or len(self.seg[key]) == 0:
+                continue
+            offsets[key] = pos
+            pos += len(self.seg[key])
+        mem_offsets = []
+        for block in blocks:
+            mem_offsets.append(pos if block else -1)
+            pos += len(block)
+
+        for i, d in enumerate(typeinfo_data):
+            self.seg["typeinfo"][i * 0x64:(i + 1) * 0x64] = struct.pack(
+                "<" + "i" * 19 + "hH" + "i" * 5,
+                d["kind"], mem_offsets[i], 0, -1, 3, 0, d["celement"], 0, 0, 0, 0, d["guid"],
+                d["flags"], d["name"], d["version"], d["doc"], 0, d["helpcontext"],
+                d["custdata"], d["impls"], d["vft"], d["size"], d["datatype1"], 0, 0, -1)
+
+        flags = 0x41
+        header = struct.pack(
+            "<I" + "i" * 19 + "I",
+            0x5446534D, 0x00010002, lib_guid, lib.lcid, lib.lcid, flags,
+            lib.version[0] | (lib.version[1] << 16), 0, n, helpstring, 0, lib.helpcontext,
+            len(self.names), sum(len(k) for k in self.names), lib_name, helpfile,
+            lib_custdata, 0x20, 0x80, dispatch, len(self.seg["impinfo"]) // 12)
+        out = bytearray(header)
+        out += b"".join(struct.pack("<i", i * 0x64) for i in range(n))
+        for key in seg_order:
+            if key is None or len(self.seg[key]) == 0:
+                out += struct.pack("<iiii", -1, 0, -1, 0x0F)
+            else:
+                out += struct.pack("<iiii", offsets[key], len(self.seg[key]), -1, 0x0F)
+        assert len(out) == header_len
+        for key in seg_order:
+            if key is not None:
+                out += self.seg[key]
+        for block in blocks:
+            out += block
+        return bytes(out)
+
+    def members(self, ti):
+        if not ti.funcs and not ti.vars:
+            return b""
+        records = bytearray()
+        memids, names, recoffs = [], [], []
+        slots = self.lib.inherited_slots(ti)
+        for i, f in enumerate(ti.funcs):
+            has_defaults = any(p.default is not None for p in f.params)
+            extra = []
+            if f.helpcontext or f.helpstring or f.entry is not None or f.custdata:
+                extra = [f.helpcontext, self.string(f.helpstring)]
+            if f.entry is not None or f.custdata:
+                if isinstance(f.entry, int):
+                    entry = f.entry
+                else:
+                    entry = self.string(f.entry)
+                extra += [entry, -1, -1, 0]
+            if f.custdata:
+                extra += [self.custdata(f.custdata)] + [-1] * len(f.params)
+            fkccic = f.funckind | (f.invkind << 3) | (f.callconv << 8)
+            if has_defaults:
+                fkccic |= 0x1000
+            if isinstance(f.entry, int):
+                fkccic |= 0x2000
+            if any(p.flags & PARAMFLAG_FRETVAL for p in f.params):
+                fkccic |= 0x4000
+            if f.custdata:
+                fkccic |= 0x80
+            body = struct.pack("<iihhihh", self.encode(f.ret), f.flags, (slots + i) * 4, 0,
+                               fkccic, len(f.params), f.nopt)
+            body += b"".join(struct.pack("<i", x) for x in extra)
+            if has_defaults:
+                body += b"".join(struct.pack("<i", self.value(p.default) if p.default else -1)
+                                 for p in f.params)
+            for p in f.params:
+                body += struct.pack("<iii", self.encode(p.type), self.name(p.name), p.flags)
+            rec = struct.pack("<i", (len(body) + 4) | (i << 16)) + body
+            recoffs.append(len(records))
+            records += rec
+            memids.append(f.memid)
+            names.append(self.name(f.name))
+        for i, v in enumerate(ti.vars):
+            extra = []
+            if v.helpcontext or v.helpstring:
+                extra = [v.helpcontext, self.string(v.helpstring)]
+            offs = self.value(v.value) if v.kind == VAR_CONST else v.oinst
+            body = struct.pack("<iihhi", self.encode(v.type), v.flags, v.kind, 0, offs)
+            body += b"".join(struct.pack("<i", x) for x in extra)
+            rec = struct.pack("<i", (len(body) + 4) | (i << 16)) + body
+            recoffs.append(len(records))
+            records += rec
+            memids.append(v.memid if v.memid is not None else 0x40000000 + i)
+            names.append(self.name(v.name))
+        out = struct.pack("<i", len(records)) + records
+        out += b"".join(struct.pack("<i", x) for x in memids)
+        out += b"".join(struct.pack("<i", x) for x in names)
+        out += b"".join(struct.pack("<i", x) for x in recoffs)
+        return out
+
+
+class SltgWriter:
+    """Writes the SLTG format: header, block directory, one block per typeinfo
+    and the library block holding the typeinfo directory and name table."""
+
+    def __init__(self, lib):
+        self.lib = lib
+        self.names = bytearray()
+        self.name_offsets = {}
+
+    def name(self, s):
+        if s not in self.name_offsets:
+            self.name_offsets[s] = len(self.names)
+            self.names += b"\xff" * 8 + s.encode("latin-1") + b"\x00"
+            if len(self.names) % 2:
+                self.names += b"\x00"
+        return self.name_offsets[s]
+
+    def build(self):
+        lib = self.lib
+        lib_name = self.name(lib.name)
+        index_names = ["%010d" % (i + 1) for i in range(len(lib.typeinfos))]
+        index = b"\x01CompObj\x00dir\x00"
+        index_offsets = []
+        for n in index_names:
+            index_offsets.append(len(index))
+            index += n.encode() + b"\x00"
+        blocks = [self.typeinfo_block(ti) for ti in lib.typeinfos]
+
+        def counted(s):
+            if s is None:
+                return struct.pack("<H", 0xffff)
+            b = s.encode("latin-1")
+            return struct.pack("<H", len(b)) + b
+
+        libblk = struct.pack("<HHHH", 0x51cc, 3, lib_name, 0xffff)
+        libblk += counted(lib.helpstring) + counted(lib.helpfile)
+        libblk += struct.pack("<IHHIHHH", lib.helpcontext, 1, lib.lcid, 0, 0, *lib.version)
+        libblk += uuid.UUID(lib.guid).bytes_le
+        libblk += b"\xff" * 0x40
+        for i, ti in enumerate(lib.typeinfos):
+            doc = (ti.helpstring or "").encode("latin-1")
+            libblk += struct.pack("<H", i) + counted(index_names[i]) + counted(index_names[i])
+            libblk += struct.pack("<HHH", 0xffff, self.name(ti.name), len(doc)) + doc
+            libblk += struct.pack("<HIH", 0xffff, ti.helpcontext, 0xffff)
+            libblk += uuid.UUID(ti.guid or "00000000-0000-0000-0000-000000000000").bytes_le
+            libblk += struct.pack("<H", ti.kind)
+        name_table_offset = len(libblk) + 6
+        libblk += struct.pack("<HI", 0xffff, name_table_offset)
+        libblk += struct.pack("<6H", 0xffff, 1, 2, 0xff00, 0xffff, 0xffff) + b"\xff" * 0x200
+        libblk += struct.pack("<I", len(self.names)) + bytes(self.names)
+        libblk += b"\x00" * 12
+
+        out = struct.pack("<IHHHH", 0x47544c53, len(blocks) + 2, 9, len(index), 1)
+        out += uuid.UUID("00020402-0000-0000-C000-000000000046").bytes_le
+        out += struct.pack("<II", 0x44, 0xffff0000)
+        for i, b in enumerate(blocks):
+            out += struct.pack("<IHH", len(b), index_offsets[i], i + 2)
+        out += struct.pack("<IHH", len(libblk), 9, 0)
+        out += index + b"\x00" * 9
+        for b in blocks:
+            out += b
+        return out + libblk
+
+    def ref_index(self, refs, name):
+        """Index of `name` in the typeinfo's ref table, adding it if needed."""
+        local = [t.name for t in self.lib.typeinfos]
+        if name in local:
+            text = "*\\Rffff*#%x" % local.index(name)
+        else:
+            for fname, libguid, (major, minor), types in self.lib.imports:
+                for pos, t in enumerate(types):
+                    if t[0] == name:
+                        spec = "*\\G{%s}#%d.%d#0#%s#" % (libguid.lower(), major, minor, fname)
+                        text = "*\\R%x*#%x" % (self.name(spec), t[3])
+                        break
+                else:
+                    continue
+                break
+            else:
+                raise KeyError(name)
+        if text not in refs:
+            refs.append(text)
+        return refs.index(text)
+
+    def tdesc_words(self, t, refs, aux, aux_base):
+        kind = t[0]
+        if kind == "simple":
+            return [t[1]]
+        if kind == "ptr":
+            return [VT_PTR] + self.tdesc_words(t[1], refs, aux, aux_base)
+        if kind == "safearray":
+            return [VT_SAFEARRAY, 0] + self.tdesc_words(t[1], refs, aux, aux_base)
+        if kind == "carray":
+            off = aux_base + len(aux)
+            aux += struct.pack("<HHIII", len(t[2]), 0, 0, 0, 0)
+            for count, lbound in t[2]:
+                aux += struct.pack("<Ii", count, lbound)
+            return [VT_CARRAY, off] + self.tdesc_words(t[1], refs, aux, aux_base)
+        if kind == "user":
+            return [VT_USERDEFINED, self.ref_index(refs, t[1]) * 4]
+        raise ValueError(kind)
+
+    @staticmethod
+    def elem_flags(flags):
+        direction = flags & (PARAMFLAG_FIN | PARAMFLAG_FOUT)
+        w = {PARAMFLAG_FIN: 0, PARAMFLAG_FOUT: 0x4000,
+             PARAMFLAG_FIN | PARAMFLAG_FOUT: 0x8000, 0: 0xc000}[direction]
+        if flags & PARAMFLAG_FLCID:
+            w |= 0x2000
+        if flags & PARAMFLAG_FRETVAL:
+            w |= 0x80
+        return w
+
+    def typeinfo_block(self, ti):
+        refs = []
+        impls = ti.impls if ti.kind == TKIND_COCLASS else (
+            [(ti.base, 0)] if ti.base else [])
+        var_size = [20 if v.flags else 18 for v in ti.vars]
+        func_size = [24 if f.flags else 22 for f in ti.funcs]
+        impls_off = 0
+        vars_off = 22 * len(impls)
+        funcs_off = vars_off + sum(var_size)
+        aux_base = funcs_off + sum(func_size)
+        aux = bytearray()
+        area = bytearray()
+        slots = self.lib.inherited_slots(ti)
+
+        for i, (name, flags) in enumerate(impls):
+            nxt = 22 * (i + 1) if i + 1 < len(impls) else 0xffff
+            area += struct.pack("<HHHBBHHHHHHH", 0x004a, nxt, 0xffff, flags, 0x80, 0x12,
+                                self.ref_index(refs, name), 0x4000, 0xfffe, 0xffff, 0x1d, i * 4)
+
+        pos = vars_off
+        for i, v in enumerate(ti.vars):
+            words = self.tdesc_words(v.type, refs, aux, aux_base)
+            flags = 0
+            if len(words) == 1:
+                flags |= 0x02
+                type_field = words[0]
+            else:
+                type_field = aux_base + len(aux)
+                aux += struct.pack("<%dH" % len(words), *words)
+            if v.kind == VAR_CONST:
+                flags |= 0x10
+                vt, value = v.value
+                if vt == VT_BSTR:
+                    byte_offs = aux_base + len(aux)
+                    b = value.encode("latin-1")
+                    aux += struct.pack("<H", len(b)) + b
+                    if len(aux) % 2:
+                        aux += b"\x00"
+                elif 0 <= value < 0xffff:
+                    flags |= 0x08
+                    byte_offs = value
+                else:
+                    byte_offs = aux_base + len(aux)
+                    aux += struct.pack("<i", value)
+            elif v.kind == VAR_DISPATCH:
+                flags |= 0x40
+                byte_offs = 0
+            else:
+                byte_offs = v.oinst
+            pos += var_size[i]
+            nxt = pos if i + 1 < len(ti.vars) else 0xffff
+            memid = v.memid if v.memid is not None else 0x40000000 + i
+            area += struct.pack("<BBHHHHiHH", 0x2a if v.flags else 0x0a, flags, nxt,
+                                self.name(v.name), byte_offs, type_field, memid, 0, 0xffff)
+            if v.flags:
+                area += struct.pack("<H", v.flags)
+
+        pos = funcs_off
+        for i, f in enumerate(ti.funcs):
+            magic = {FUNC_PUREVIRTUAL: 0x4c, FUNC_DISPATCH: 0xcb, FUNC_STATIC: 0x8b}[f.funckind]
+            if f.flags:
+                magic |= 0x20
+            ret = self.tdesc_words(f.ret, refs, aux, aux_base)
+            retnextopt = f.nopt << 1
+            if len(ret) == 1:
+                retnextopt |= 0x80
+                rettype = ret[0]
+            else:
+                rettype = aux_base + len(aux)
+                aux += struct.pack("<%dH" % len(ret), *ret)
+            # Parameters are written inline: the name offset points at the
+            # second letter to say the type follows.
+            args = []
+            for p in f.params:
+                words = self.tdesc_words(p.type, refs, aux, aux_base)
+                words[0] |= self.elem_flags(p.flags)
+                args += [self.name(p.name) + 1] + words
+            arg_off = aux_base + len(aux)
+            aux += struct.pack("<%dH" % len(args), *args)
+            pos += func_size[i]
+            nxt = pos if i + 1 < len(ti.funcs) else 0xffff
+            area += struct.pack("<BBHHiHHHBBHH", magic, (f.invkind << 4) | 2, nxt,
+                                self.name(f.name), f.memid, 0, 0xffff, arg_off,
+                                (len(f.params) << 3) | f.callconv, retnextopt, rettype,
+                                (slots + i) * 4)
+            if f.flags:
+                area += struct.pack("<H", f.flags)
+
+        tdescalias_vt, simple_alias = 0, 0
+        if ti.kind == TKIND_ALIAS:
+            words = self.tdesc_words(ti.alias, refs, aux, aux_base)
+            if len(words) == 1:
+                tdescalias_vt, simple_alias = words[0], 1
+            else:
+                tdescalias_vt = aux_base + len(aux)
+                aux += struct.pack("<%dH" % len(words), *words)
+        assert len(area) == aux_base
+        area += aux
+
+        dispatch = ti.kind == TKIND_DISPATCH and not ti.flags & TYPEFLAG_FDUAL
+        kind = TKIND_INTERFACE if ti.flags & TYPEFLAG_FDUAL else ti.kind
+        tail = struct.pack(
+            "<27H", len(ti.funcs), len(ti.vars), len(impls), 0,
+            funcs_off if ti.funcs else 0xffff, vars_off if ti.vars else 0xffff,
+            impls_off if impls and dispatch else 0xffff,
+            sum(func_size), sum(var_size), 22 * len(impls), tdescalias_vt, 0xffff, 0, 0,
+            simple_alias, 0, ti.size, ti.align, 0, 0, self.lib.vft_size(ti), 0xffff, 0xffff,
+            0xffff, 0xffff,
+            0, 0)
+
+        ref_table = b""
+        if refs:
+            ref_table = b"\xdf\x00" + b"\xff" * 64 + struct.pack("<HI", 0xffff, 8 * len(refs))
+            for text in refs:
+                ref_table += struct.pack("<HBBHH", 1, 2, 0x40 if "ffff" in text else 0, 0xffff, 0)
+            ref_table += struct.pack("<HBI", 0xffff, 1, 0)
+            for text in refs:
+                ref_table += struct.pack("<H", len(text)) + text.encode()
+            ref_table += b"\xdf"
+
+        header_size = 0x22
+        elem_table = header_size + len(ref_table)
+        header = struct.pack("<HIIIIHHIBBBBI", 0x0501, header_size if refs else 0xffffffff,
+                             0xffffffff, elem_table, 0xffffffff, *ti.version, 0xfffe0000,
+                             0x02 | ((ti.flags & 0x1f) << 3), ti.flags >> 5, 0x02, kind,
+                             0xffffffff)
+        member_header = struct.pack("<HHBI", 1, 0xffff, 1, len(area))
+        return header + ref_table + member_header + bytes(area) + tail
+
+
+def stdole_import(*names):
+    # (guid, kind, position in stdole2.tlb)
+    known = {
+        "IUnknown": (IID_IUNKNOWN, TKIND_INTERFACE, 3),
+        "IDispatch": (IID_IDISPATCH, TKIND_INTERFACE, 4),
+    }
+    return ("stdole2.tlb", STDOLE2, (2, 0), [(n,) + known[n] for n in names])
+
+
+def simple_import(*names):
+    # (guid, kind, position in simple.tlb)
+    known = {
+        "Colors": ("5A1E0A00-0002-4B1D-9C2E-0123456789AB", TKIND_ENUM, 0),
+        "Point": (None, TKIND_RECORD, 1),
+        "IShape": ("5A1E0A00-0003-4B1D-9C2E-0123456789AB", TKIND_DISPATCH, 3),
+    }
+    return ("simple.tlb", "5A1E0A00-0001-4B1D-9C2E-0123456789AB", (1, 2),
+            [(n,) + known[n] for n in names])
+
+
+def simple_tlb():
+    lib = Library("SimpleLib", "5A1E0A00-0001-4B1D-9C2E-0123456789AB", version=(1, 2),
+                  helpstring="Simple test library",
+                  custdata=[("DE77BA64-517C-11D1-A2DA-0000F8773CE9", (VT_BSTR, "Simple fixture"))],
+                  imports=[stdole_import("IDispatch", "IUnknown")])
+    lib.add(TypeInfo(TKIND_ENUM, "Colors", guid="5A1E0A00-0002-4B1D-9C2E-0123456789AB",
+                     vars=[Var("Red", simple(VT_I4), (VT_I4, 0)),
+                           Var("Green", simple(VT_I4), (VT_I4, 1)),
+                           Var("Blue", simple(VT_I4), (VT_I4, 2)),
+                           Var("Unset", simple(VT_I4), (VT_I4, -1))]))
+    lib.add(TypeInfo(TKIND_RECORD, "Point", size=8,
+                     vars=[Var("x", simple(VT_I4), oinst=0), Var("y", simple(VT_I4), oinst=4)]))
+    lib.add(TypeInfo(TKIND_ALIAS, "Handle", guid="5A1E0A00-000A-4B1D-9C2E-0123456789AB",
+                     alias=simple(VT_I4), flags=0, helpstring="An opaque handle"))
+    lib.add(TypeInfo(
+        TKIND_DISPATCH, "IShape", guid="5A1E0A00-0003-4B1D-9C2E-0123456789AB",
+        flags=TYPEFLAG_FDUAL | TYPEFLAG_FDISPATCHABLE | TYPEFLAG_FOLEAUTOMATION,
+        base="IDispatch", helpstring="A drawable shape",
+        funcs=[
+            Func("Name", 1, invkind=INVOKE_PROPERTYGET, helpstring="The shape name",
+                 params=[Param("val", ptr(simple(VT_BSTR)), PARAMFLAG_FOUT | PARAMFLAG_FRETVAL)]),
+            Func("Name", 1, invkind=INVOKE_PROPERTYPUT,
+                 params=[Param("val", simple(VT_BSTR))]),
+            Func("Move", 3, params=[
+                Param("dx", simple(VT_I4)),
+                Param("dy", simple(VT_I4), PARAMFLAG_FIN | PARAMFLAG_FOPT | PARAMFLAG_FHASDEFAULT,
+                      (VT_I4, 5))]),
+            Func("Color", 4, invkind=INVOKE_PROPERTYGET, helpcontext=0x20,
+                 flags=FUNCFLAG_FBINDABLE | FUNCFLAG_FDISPLAYBIND | FUNCFLAG_FNONBROWSABLE,
+                 params=[Param("val", ptr(userdef("Colors")), PARAMFLAG_FOUT | PARAMFLAG_FRETVAL)]),
+            Func("Center", 5, flags=FUNCFLAG_FRESTRICTED | FUNCFLAG_FHIDDEN,
+                 custdata=[("5A1E0A00-00FF-4B1D-9C2E-0123456789AB", (VT_BSTR, "center"))],
+                 params=[
+                Param("pt", ptr(userdef("Point")), PARAMFLAG_FOUT | PARAMFLAG_FRETVAL)]),
+        ]))
+    lib.add(TypeInfo(
+        TKIND_DISPATCH, "_ShapeEvents", guid="5A1E0A00-0004-4B1D-9C2E-0123456789AB",
+        flags=TYPEFLAG_FDISPATCHABLE, base="IDispatch",
+        vars=[Var("Visible", simple(VT_BOOL), memid=2, kind=VAR_DISPATCH,
+                  helpstring="Whether the shape is drawn"),
+              Var("Id", simple(VT_I4), memid=3, kind=VAR_DISPATCH, flags=VARFLAG_FREADONLY)],
+        funcs=[Func("Moved", 1, ret=simple(VT_VOID), funckind=FUNC_DISPATCH,
+                    params=[Param("dx", simple(VT_I4)), Param("dy", simple(VT_I4))])]))
+    lib.add(TypeInfo(
+        TKIND_COCLASS, "Shape", guid="5A1E0A00-0005-4B1D-9C2E-0123456789AB",
+        flags=TYPEFLAG_FCANCREATE, helpstring="Shape object",
+        impls=[("IShape", IMPLTYPEFLAG_FDEFAULT),
+               ("_ShapeEvents", IMPLTYPEFLAG_FDEFAULT | IMPLTYPEFLAG_FSOURCE)]))
+    lib.add(TypeInfo(
+        TKIND_MODULE, "Constants", guid="5A1E0A00-0006-4B1D-9C2E-0123456789AB",
+        dllname="simple.dll", helpstring="Values and entry points of simple.dll",
+        vars=[Var("Answer", simple(VT_I4), (VT_I4, 42)),
+              Var("Pi", simple(VT_R8), (VT_R8, 3.25)),
+              Var("Greeting", simple(VT_BSTR), (VT_BSTR, 'say "hi"\n')),
+              Var("Price", simple(VT_CY), (VT_CY, 123400)),
+              Var("Failure", simple(VT_ERROR), (VT_ERROR, -2147467259)),
+              Var("Big", simple(VT_UI8), (VT_UI8, 1 << 40)),
+              Var("Tiny", simple(VT_UI1), (VT_UI1, 200))],
+        funcs=[
+            Func("Beep", 0x60000000, ret=simple(VT_I4), funckind=FUNC_STATIC,
+                 entry="SimpleBeep", helpstring="Sounds a tone",
+                 params=[Param("freq", simple(VT_I4)), Param("duration", simple(VT_I4))]),
+            Func("Log", 0x60000001, ret=simple(VT_VOID), funckind=FUNC_STATIC,
+                 callconv=CC_CDECL, entry="SimpleLog", nopt=-1,
+                 params=[Param("format", simple(VT_LPSTR)),
+                         Param("args", safearray(simple(VT_VARIANT)))]),
+            Func("Version", 0x60000002, funckind=FUNC_STATIC, entry=7,
+                 flags=FUNCFLAG_FUSESGETLASTERROR, params=[
+                Param("v", ptr(simple(VT_I4)), PARAMFLAG_FOUT | PARAMFLAG_FRETVAL)]),
+        ]))
+    lib.add(TypeInfo(TKIND_RECORD, "Matrix", guid="5A1E0A00-0008-4B1D-9C2E-0123456789AB",
+                     helpstring="A grid of cells", size=164,
+                     vars=[Var("cells", carray(simple(VT_I4), [(4, 0), (8, 0)]), oinst=0),
+                           Var("stamp", simple(VT_FILETIME), oinst=128),
+                           Var("id", simple(VT_CLSID), oinst=136),
+                           Var("extra", simple(VT_BLOB), oinst=152),
+                           Var("count", simple(VT_BYREF | VT_I4), oinst=160)]))
+    lib.add(TypeInfo(TKIND_UNION, "Value", guid="5A1E0A00-0009-4B1D-9C2E-0123456789AB",
+                     helpstring="One of three values", size=8, align=8,
+                     vars=[Var("l", simple(VT_I4), oinst=0),
+                           Var("d", simple(VT_R8), oinst=0),
+                           Var("s", simple(VT_BSTR), oinst=0)]))
+    lib.add(TypeInfo(TKIND_RECORD, "Packet", size=7, align=1,
+                     vars=[Var("tag", simple(VT_UI1), oinst=0),
+                           Var("value", simple(VT_I4), oinst=1),
+                           Var("extra", simple(VT_I2), oinst=5)]))
+    # Without [id], MIDL numbers the members of an interface from
+    # 0x60000000 plus 0x10000 per ancestor, here IUnknown.
+    lib.add(TypeInfo(
+        TKIND_INTERFACE, "IRenderer", guid="5A1E0A00-0007-4B1D-9C2E-0123456789AB",
+        base="IUnknown", helpstring="Draws shapes",
+        funcs=[Func("Fill", 0x60010000, params=[Param("color", simple(VT_I4))]),
+               Func("Bounds", 0x60010001, params=[
+                   Param("pt", ptr(userdef("Point")), PARAMFLAG_FOUT | PARAMFLAG_FRETVAL)])]))
+    return lib
+
+
+def legacy_tlb():
+    lib = Library("LegacyLib", "5A1E0B00-0001-4B1D-9C2E-0123456789AB", version=(3, 1),
+                  helpstring="Legacy SLTG library", helpcontext=7, lcid=0x409,
+                  imports=[stdole_import("IDispatch")])
+    lib.add(TypeInfo(TKIND_ENUM, "Mode", guid="5A1E0B00-0002-4B1D-9C2E-0123456789AB",
+                     helpstring="Counter mode",
+                     vars=[Var("ModeOff", simple(VT_I4), (VT_I4, 0)),
+                           Var("ModeOn", simple(VT_I4), (VT_I4, 1)),
+                           Var("ModeAuto", simple(VT_I4), (VT_I4, -1))]))
+    lib.add(TypeInfo(TKIND_RECORD, "Rect", guid="5A1E0B00-0007-4B1D-9C2E-0123456789AB",
+                     helpstring="A rectangle", size=12,
+                     vars=[Var("left", simple(VT_I4), oinst=0),
+                           Var("top", simple(VT_I4), oinst=4),
+                           Var("tag", simple(VT_BSTR), oinst=8)]))
+    lib.add(TypeInfo(TKIND_ALIAS, "Handle", guid="5A1E0B00-0008-4B1D-9C2E-0123456789AB",
+                     alias=simple(VT_I4), helpstring="A legacy handle"))
+    lib.add(TypeInfo(TKIND_ALIAS, "ModePtr", alias=ptr(userdef("Mode"))))
+    lib.add(TypeInfo(
+        TKIND_DISPATCH, "ICounter", guid="5A1E0B00-0003-4B1D-9C2E-0123456789AB",
+        flags=TYPEFLAG_FDUAL | TYPEFLAG_FDISPATCHABLE | TYPEFLAG_FOLEAUTOMATION,
+        base="IDispatch", helpstring="A counter",
+        funcs=[
+            Func("Count", 1, invkind=INVOKE_PROPERTYGET,
+                 params=[Param("val", ptr(simple(VT_I4)), PARAMFLAG_FOUT | PARAMFLAG_FRETVAL)]),
+            Func("Add", 2, nopt=1, params=[
+                Param("n", simple(VT_I4)),
+                Param("step", simple(VT_VARIANT), PARAMFLAG_FIN | PARAMFLAG_FOPT)]),
+            Func("Mode", 3, invkind=INVOKE_PROPERTYGET, params=[
+                Param("val", ptr(userdef("Mode")), PARAMFLAG_FOUT | PARAMFLAG_FRETVAL)]),
+            Func("Names", 4, params=[
+                Param("names", ptr(safearray(simple(VT_BSTR))),
+                      PARAMFLAG_FOUT | PARAMFLAG_FRETVAL)]),
+            Func("Fill", 5, params=[Param("cells", carray(simple(VT_I4), [(4, 0)]))]),
+        ]))
+    lib.add(TypeInfo(
+        TKIND_DISPATCH, "_CounterEvents", guid="5A1E0B00-0004-4B1D-9C2E-0123456789AB",
+        flags=TYPEFLAG_FDISPATCHABLE, base="IDispatch",
+        funcs=[Func("Changed", 1, ret=simple(VT_VOID), funckind=FUNC_DISPATCH,
+                    params=[Param("value", simple(VT_I4))])]))
+    lib.add(TypeInfo(
+        TKIND_COCLASS, "Counter", guid="5A1E0B00-0005-4B1D-9C2E-0123456789AB",
+        flags=TYPEFLAG_FCANCREATE,
+        impls=[("ICounter", IMPLTYPEFLAG_FDEFAULT),
+               ("_CounterEvents", IMPLTYPEFLAG_FDEFAULT | IMPLTYPEFLAG_FSOURCE)]))
+    lib.add(TypeInfo(
+        TKIND_MODULE, "Limits", guid="5A1E0B00-0006-4B1D-9C2E-0123456789AB",
+        vars=[Var("MaxCount", simple(VT_I4), (VT_I4, 100)),
+              Var("Greeting", simple(VT_BSTR), (VT_BSTR, "hello"))]))
+    return lib
+
+
+def consumer_tlb():
+    """A library using types of simple.tlb, which sits next to it."""
+    lib = Library("ConsumerLib", "5A1E0C00-0001-4B1D-9C2E-0123456789AB",
+                  imports=[stdole_import("IUnknown"), simple_import("IShape", "Point", "Colors")])
+    lib.add(TypeInfo(
+        TKIND_INTERFACE, "ICanvas", guid="5A1E0C00-0002-4B1D-9C2E-0123456789AB",
+        base="IUnknown",
+        funcs=[Func("Draw", 0x60010000, params=[Param("shape", ptr(userdef("IShape")))]),
+               Func("Origin", 0x60010001, params=[
+                   Param("pt", ptr(userdef("Point")), PARAMFLAG_FOUT | PARAMFLAG_FRETVAL)]),
+               Func("Tint", 0x60010002, params=[Param("color", userdef("Colors"))])]))
+    return lib
+
+
+def pe_with_typelibs(typelibs):
+    """A minimal PE32 DLL whose only section holds TYPELIB resources 1..n."""
+    section_rva, section_offset = 0x1000, 0x200
+    n = len(typelibs)
+    root_size = 16 + 8
+    type_dir = root_size
+    lang_dirs = type_dir + 16 + 8 * n
+    data_entries = lang_dirs + (16 + 8) * n
+    name_str = data_entries + 16 * n
+    name = "TYPELIB".encode("utf-16-le")
+    blobs = (name_str + 2 + len(name) + 3) & ~3
+
+    rsrc = struct.pack("<IIHHHH", 0, 0, 0, 0, 1, 0)
+    rsrc += struct.pack("<II", 0x80000000 | name_str, 0x80000000 | type_dir)
+    rsrc += struct.pack("<IIHHHH", 0, 0, 0, 0, 0, n)
+    for i in range(n):
+        rsrc += struct.pack("<II", i + 1, 0x80000000 | (lang_dirs + 24 * i))
+    for i in range(n):
+        rsrc += struct.pack("<IIHHHH", 0, 0, 0, 0, 0, 1)
+        rsrc += struct.pack("<II", 0, data_entries + 16 * i)
+    pos = blobs
+    for data in typelibs:
+        rsrc += struct.pack("<IIII", section_rva + pos, len(data), 0, 0)
+        pos = (pos + len(data) + 3) & ~3
+    rsrc += struct.pack("<H", len(name) // 2) + name
+    rsrc = rsrc.ljust(blobs, b"\x00")
+    for data in typelibs:
+        rsrc += data
+        rsrc = rsrc.ljust((len(rsrc) + 3) & ~3, b"\x00")
+
+    dos = b"MZ".ljust(0x3c, b"\x00") + struct.pack("<I", 0x40)
+    coff = b"PE\x00\x00" + struct.pack("<HHIIIHH", 0x14c, 1, 0, 0, 0, 224, 0x2102)
+    opt = bytearray(224)
+    struct.pack_into("<H", opt, 0, 0x10b)
+    struct.pack_into("<II", opt, 32, 0x1000, 0x200)  # section / file alignment
+    struct.pack_into("<I", opt, 92, 16)
+    struct.pack_into("<II", opt, 96 + 2 * 8, section_rva, len(rsrc))
+    section = struct.pack("<8sIIIIIIHHI", b".rsrc", len(rsrc), section_rva,
+                          (len(rsrc) + 0x1ff) & ~0x1ff, section_offset, 0, 0, 0, 0, 0x40000040)
+    headers = (dos + coff + bytes(opt) + section).ljust(section_offset, b"\x00")
+    return headers + rsrc.ljust((len(rsrc) + 0x1ff) & ~0x1ff, b"\x00")
+
+
+# --- LZX / CHM -------------------------------------------------------------
+
+LZX_FRAME = 0x8000
+LZX_WINDOW_BITS = 16
+LZX_POSITION_SLOTS = 32
+LZX_RESET_FRAMES = 2
+LZX_EXTRA_BITS = [0, 0, 0, 0] + [min(i // 2 - 1, 17) for i in range(4, 51)]
+LZX_POSITION_BASE = [sum(1 << b for b in LZX_EXTRA_BITS[:i]) for i in range(51)]
+
+
+class BitWriter:
+    """LZX bit order: 16-bit little-endian words, filled MSB first."""
+
+    def __init__(self):
+        self.out = bytearray()
+        self.acc = 0
+        self.n = 0
+
+    def put(self, value, bits):
+        for i in reversed(range(bits)):
+            self.acc = (self.acc << 1) | ((value >> i) & 1)
+            self.n += 1
+            if self.n == 16:
+                self.out += struct.pack("<H", self.acc)
+                self.acc = self.n = 0
+
+    def align(self):
+        if self.n:
+            self.put(0, 16 - self.n)
+
+
+def huffman_lengths(freqs, limit):
+    """Code lengths for `freqs`; at least two symbols always get a code."""
+    used = [i for i, f in enumerate(freqs) if f]
+    while len(used) < 2:
+        used.append(next(i for i in range(len(freqs)) if i not in used))
+    heap = [(freqs[i] or 1, i, [i]) for i in used]
+    heapq.heapify(heap)
+    lens = [0] * len(freqs)
+    while len(heap) > 1:
+        fa, ia, a = heapq.heappop(heap)
+        fb, ib, b = heapq.heappop(heap)
+        for sym in a + b:
+            lens[sym] += 1
+        heapq.heappush(heap, (fa + fb, min(ia, ib), a + b))
+    assert max(lens) <= limit
+    return lens
+
+
+def canonical_codes(lens):
+    codes, code = {}, 0
+    for length in range(1, max(lens) + 1):
+        for sym, l in enumerate(lens):
+            if l == length:
+                codes[sym] = (code, length)
+                code += 1
+        code <<= 1
+    return codes
+
+
+def write_lengths(w, old, new):
+    """Sends `new` as pretree-coded deltas against `old`, using run codes
+    17/18 for zeros and 19 for short runs of a repeated length."""
+    ops, i = [], 0
+    while i < len(new):
+        run = 1
+        while i + run < len(new) and new[i + run] == new[i]:
+            run += 1
+        if new[i] == 0 and run >= 20:
+            ops.append((18, min(run, 51) - 20, 5))
+            i += min(run, 51)
+        elif new[i] == 0 and run >= 4:
+            ops.append((17, min(run, 19) - 4, 4))
+            i += min(run, 19)
+        elif run >= 4:
+            n = min(run, 5)
+            ops.append((19, n - 4, 1, (old[i] - new[i]) % 17))
+            i += n
+        else:
+            ops.append(((old[i] - new[i]) % 17,))
+            i += 1
+    freqs = [0] * 20
+    for op in ops:
+        freqs[op[0]] += 1
+        if op[0] == 19:
+            freqs[op[3]] += 1
+    lens = huffman_lengths(freqs, 15)
+    codes = canonical_codes(lens)
+    for l in lens:
+        w.put(l, 4)
+    for op in ops:
+        w.put(*codes[op[0]])
+        if op[0] in (17, 18):
+            w.put(op[1], op[2])
+        elif op[0] == 19:
+            w.put(op[1], op[2])
+            w.put(*codes[op[3]])
+    old[:len(new)] = new
+
+
+def lzx_tokens(data, start, end, floor):
+    """Greedy LZ77 over data[start:end]; matches never reach before `floor`
+    (the last reset point) or past `end` (the frame boundary)."""
+    tokens, heads, i = [], {}, start
+    for j in range(floor, start - 2):
+        heads[data[j:j + 3]] = j
+    while i < end:
+        key = data[i:i + 3]
+        cand = heads.get(key)
+        length = 0
+        if cand is not None and i - cand <= LZX_FRAME and i + 3 <= end:
+            while (length < 257 and i + length < end
+                   and data[cand + length] == data[i + length]):
+                length += 1
+        if length >= 3:
+            tokens.append(("match", length, i - cand))
+            for j in range(i, i + length):
+                heads[data[j:j + 3]] = j
+            i += length
+        else:
+            tokens.append(("lit", data[i]))
+            heads[key] = i
+            i += 1
+    return tokens
+
+
+def lzx_slot(offset):
+    formatted = offset + 2
+    slot = max(s for s in range(3, 51) if LZX_POSITION_BASE[s] <= formatted)
+    return slot, formatted - LZX_POSITION_BASE[slot]
+
+
+def lzx_compress(data):
+    """Compresses `data` as CHM does: one block per 32K frame, a reset every
+    LZX_RESET_FRAMES frames. Blocks cycle through verbatim, aligned offset and
+    uncompressed. Returns the stream and the compressed offset of each frame."""
+    w = BitWriter()
+    frames = []
+    main_symbols = 256 + LZX_POSITION_SLOTS * 8
+    for frame, start in enumerate(range(0, len(data), LZX_FRAME)):
+        end = min(start + LZX_FRAME, len(data))
+        frames.append(len(w.out))
+        if frame % LZX_RESET_FRAMES == 0:
+            floor = start
+            main_lens, length_lens = [0] * main_symbols, [0] * 249
+            w.put(0, 1)  # no E8 translation
+        block_type = (1, 2, 3)[frame % 3]
+        w.put(block_type, 3)
+        w.put((end - start) >> 8, 16)
+        w.put((end - start) & 0xFF, 8)
+        if block_type == 3:
+            if w.n == 0:
+                w.put(0, 16)
+            w.align()
+            w.out += struct.pack("<III", 1, 1, 1) + data[start:end]
+            continue
+
+        tokens = lzx_tokens(data, start, end, floor)
+        symbols = []
+        for t in tokens:
+            if t[0] == "lit":
+                symbols.append((t[1], None, None))
+                continue
+            _, length, offset = t
+            slot, extra = lzx_slot(offset)
+            header = min(length - 2, 7)
+            footer = length - 2 - 7 if header == 7 else None
+            symbols.append((256 + slot * 8 + header, footer, (slot, extra)))
+        main_freq, length_freq, aligned_freq = [0] * main_symbols, [0] * 249, [0] * 8
+        for main, footer, off in symbols:
+            main_freq[main] += 1
+            if footer is not None:
+                length_freq[footer] += 1
+            if off and block_type == 2 and LZX_EXTRA_BITS[off[0]] >= 3:
+                aligned_freq[off[1] & 7] += 1
+
+        if block_type == 2:
+            aligned_lens = huffman_lengths(aligned_freq, 7)
+            for l in aligned_lens:
+                w.put(l, 3)
+            aligned_codes = canonical_codes(aligned_lens)
+        new_main = huffman_lengths(main_freq, 16)
+        write_lengths(w, main_lens, new_main[:256])
+        write_lengths(w, main_lens[256:], new_main[256:])
+        main_lens[256:] = new_main[256:]
+        new_length = huffman_lengths(length_freq, 16) if any(length_freq) else [0] * 249
+        write_lengths(w, length_lens, new_length)
+        main_codes, length_codes = canonical_codes(new_main), canonical_codes(new_length)
+
+        for main, footer, off in symbols:
+            w.put(*main_codes[main])
+            if footer is not None:
+                w.put(*length_codes[footer])
+            if off is None:
+                continue
+            slot, extra = off
+            bits = LZX_EXTRA_BITS[slot]
+            if block_type == 2 and bits >= 3:
+                w.put(extra >> 3, bits - 3)
+                w.put(*aligned_codes[extra & 7])
+            else:
+                w.put(extra, bits)
+        w.align()
+    return bytes(w.out), frames
+
+
+def encint(n):
+    out = [n & 0x7F]
+    n >>= 7
+    while n:
+        out.append(0x80 | (n & 0x7F))
+        n >>= 7
+    return bytes(reversed(out))
+
+
+def chm_file(files0, files1):
+    """An ITSF v3 container. `files0` are stored as is in section 0, `files1`
+    LZX-compressed in section 1; both map names to contents."""
+    content1 = b""
+    entries = []
+    for name, data in files1.items():
+        entries.append((name, 1, len(content1), len(data)))
+        content1 += data
+    compressed, frames = lzx_compress(content1)
+    storage = "::DataSpace/Storage/MSCompressed/"
+    files0 = dict(files0)
+    files0[storage + "Content"] = compressed
+    files0[storage + "ControlData"] = struct.pack(
+        "<I4sIIIII", 6, b"LZXC", 2, LZX_RESET_FRAMES, (1 << LZX_WINDOW_BITS) // LZX_FRAME,
+        LZX_RESET_FRAMES, 0)
+    files0[storage + "SpanInfo"] = struct.pack("<Q", len(content1))
+    files0[storage + "Transform/{7FC28940-9D31-11D0-9B27-00A0C91E9C7C}/InstanceData/"
+           "ResetTable"] = struct.pack(
+        "<IIIIQQQ", 2, len(frames), 8, 0x28, len(content1), len(compressed), LZX_FRAME
+    ) + b"".join(struct.pack("<Q", f) for f in frames)
+    content0 = b""
+    for name, data in files0.items():
+        entries.append((name, 0, len(content0), len(data)))
+        content0 += data
+
+    chunk_size = 0x1000
+    listing = b"".join(encint(len(name.encode())) + name.encode() + encint(section)
+                       + encint(offset) + encint(length)
+                       for name, section, offset, length in
+                       sorted(entries, key=lambda e: e[0].lower()))
+    free = chunk_size - 0x14 - len(listing)
+    assert free >= 2
+    chunk = struct.pack("<4sIIii", b"PMGL", free, 0, -1, -1) + listing
+    chunk = chunk.ljust(chunk_size - 2, b"\x00") + struct.pack("<H", len(entries))
+    itsp = struct.pack("<4sIIIIIIiiiiII16sIiii", b"ITSP", 1, 0x54, 10, chunk_size, 2, 1, -1,
+                       0, 0, -1, 1, 0x409,
+                       uuid.UUID("5D02926A-212E-11D0-9DF9-00A0C922E6EC").bytes_le,
+                       0x54, -1, -1, -1)
+    directory = itsp + chunk
+    header_size, section0_size = 0x60, 0x18
+    dir_offset = header_size + section0_size
+    content_offset = dir_offset + len(directory)
+    total = content_offset + len(content0)
+    itsf = struct.pack("<4sIIIII16s16sQQQQQ", b"ITSF", 3, header_size, 1, 0, 0x409,
+                       uuid.UUID("7C01FD10-7BAA-11D0-9E0C-00A0C922E6EC").bytes_le,
+                       uuid.UUID("7C01FD11-7BAA-11D0-9E0C-00A0C922E6EC").bytes_le,
+                       header_size, section0_size, dir_offset, len(directory),
+                       content_offset)
+    section0 = struct.pack("<IIQII", 0x1FE, 0, total, 0, 0)
+    return itsf + section0 + directory + content0
+
+
+def topic(title, body):
+    return ("<html><head><title>%s</title></head><body>\n<h1>%s</h1>\n%s\n</body></html>\n"
+            % (title, title, body)).encode()
+
+
+def help_chm():
+    # Deterministic filler text large enough to span several frames and a
+    # reset point, so later topics are decoded from the second reset.
+    words = ["shape", "color", "point", "moves", "the", "by", "of", "returns", "name",
+             "center", "event", "value", "default", "optional"]
+    state, filler = 12345, []
+    for i in range(13000):
+        state = (state * 1103515245 + 12345) & 0x7FFFFFFF
+        filler.append(words[state % len(words)] + ("\n" if i % 12 == 11 else " "))
+    topics = {
+        "/topics/shape.htm": topic("IShape interface", "<p>A drawable shape.</p>"),
+        "/topics/move.htm": topic("Move method", "<p>Moves the shape by dx, dy.</p>\n"
+                                  "<ul><li>dx: horizontal offset</li>"
+                                  "<li>dy: vertical offset</li></ul>"),
+        "/topics/colors.htm": topic("Colors enumeration", "<p>Shape colors.</p>"),
+        "/topics/filler.htm": topic("Filler", "<pre>" + "".join(filler) + "</pre>"),
+        "/topics/late.htm": topic("Late topic", "<p>Topic after the filler.</p>"),
+    }
+    contexts = [(100, "topics/shape.htm"), (110, "topics/move.htm"),
+                (200, "topics\\colors.htm"), (300, "topics/late.htm")]
+    strings, ivb = b"\x00", b""
+    for context, path in contexts:
+        ivb += struct.pack("<II", context, len(strings))
+        strings += path.encode() + b"\x00"
+    files1 = {"/#IVB": struct.pack("<I", len(ivb)) + ivb, "/#STRINGS": strings}
+    files1.update(topics)
+    return chm_file({}, files1)
+
+
+FIXTURES = {
+    "simple.tlb": lambda: Writer(simple_tlb()).build(),
+    "legacy_sltg.tlb": lambda: SltgWriter(legacy_tlb()).build(),
+    "typelibs.dll": lambda: pe_with_typelibs([Writer(simple_tlb()).build(),
+                                              SltgWriter(legacy_tlb()).build()]),
+    "consumer.tlb": lambda: Writer(consumer_tlb()).build(),
+    "consumer_sltg.tlb": lambda: SltgWriter(consumer_tlb()).build(),
+    "help.chm": help_chm,
+}
+
+
+def main():
+    for name, make in FIXTURES.items():
+        data = make()
+        with open(os.path.join(HERE, name), "wb") as f:
+            f.write(data)
+        print("wrote %s (%d bytes)" % (name, len(data)))
+
+
+if __name__ == "__main__":
+    main()